@@ -0,0 +1,107 @@
+//! Opt-in audit log of the commands bridge sends to remote hosts, for `audit_log`.
+//! Each command is appended as a JSON line before it is sent, so commands that fail,
+//! or never finish, are on record too. A command that can't be logged isn't sent.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::env;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::config::Host;
+
+/// Log file for this invocation, set when the config is loaded.
+static LOG: OnceLock<PathBuf> = OnceLock::new();
+
+#[derive(Serialize)]
+struct Entry<'a> {
+    /// Local time with its UTC offset (RFC 3339)
+    time: String,
+    /// Local user who ran bridge
+    user: String,
+    /// SSH host, or pod for Kubernetes hosts
+    host: &'a str,
+    /// How the command was sent: ssh, mosh, or kubectl
+    via: &'a str,
+    /// Local directory bridge ran in
+    dir: PathBuf,
+    /// The command line exactly as sent
+    command: &'a str,
+}
+
+/// Log every remote command to `log` from now on. `~/` is the home directory, and
+/// relative paths resolve against the project root. The first call wins.
+pub fn enable(log: &str, project_root: &Path) {
+    let _ = LOG.set(resolve(log, project_root));
+}
+
+fn resolve(log: &str, project_root: &Path) -> PathBuf {
+    match log.strip_prefix("~/") {
+        Some(rest) => env::var_os("HOME").map(PathBuf::from).unwrap_or_default().join(rest),
+        None => project_root.join(log),
+    }
+}
+
+/// Append `command` to the audit log, if there is one. Called right before the
+/// command is sent; an error means it must not be.
+pub fn record(host: &Host, via: &str, command: &str) -> Result<()> {
+    match LOG.get() {
+        Some(path) => append(path, host, via, command),
+        None => Ok(()),
+    }
+}
+
+fn append(path: &Path, host: &Host, via: &str, command: &str) -> Result<()> {
+    let entry = Entry {
+        time: chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, false),
+        user: env::var("USER").or_else(|_| env::var("USERNAME")).unwrap_or_else(|_| "unknown".to_string()),
+        host: &host.hostname,
+        via,
+        dir: env::current_dir().unwrap_or_default(),
+        command,
+    };
+    let mut line = serde_json::to_string(&entry)?;
+    line.push('\n');
+
+    // Commands may carry substituted secrets, so the log is private to its owner
+    let mut options = OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all(line.as_bytes()))
+        .with_context(|| format!("Failed to write audit log {}; not sending the command", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn commands_are_appended_as_json_lines() {
+        let dir = TempDir::new().unwrap();
+        let log = resolve("audit.jsonl", dir.path());
+        assert_eq!(log, dir.path().join("audit.jsonl"));
+
+        let host: Host = toml::from_str("hostname = \"gpu\"\npath = \"/srv/app\"").unwrap();
+        append(&log, &host, "ssh", "cd '/srv/app' && make").unwrap();
+        append(&log, &host, "ssh", "rm -rf build").unwrap();
+
+        let content = std::fs::read_to_string(&log).unwrap();
+        let entries: Vec<serde_json::Value> = content.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["host"], "gpu");
+        assert_eq!(entries[0]["command"], "cd '/srv/app' && make");
+        assert_eq!(entries[1]["command"], "rm -rf build");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(std::fs::metadata(&log).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+    }
+}
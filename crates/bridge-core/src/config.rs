@@ -0,0 +1,1711 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+
+use crate::audit;
+use crate::env_subst;
+use crate::paths;
+
+/// Project config file names, in the order they are looked for in each directory.
+const CONFIG_FILENAMES: [&str; 4] = ["bridge.toml", "bridge.yaml", "bridge.yml", "bridge.json"];
+
+/// Profile selected with `--profile` for this invocation.
+static PROFILE: OnceLock<String> = OnceLock::new();
+
+#[derive(Debug, Deserialize, Serialize, Default)]
+pub struct Config {
+    pub default_host: Option<String>,
+    #[serde(default)]
+    pub hosts: HashMap<String, Host>,
+    #[serde(default)]
+    pub sync: SyncConfig,
+    #[serde(default)]
+    pub upload: UploadConfig,
+    #[serde(default)]
+    pub watch: WatchConfig,
+    #[serde(default)]
+    pub notifications: NotificationsConfig,
+    /// File every command sent to a remote host is appended to before it is sent
+    pub audit_log: Option<String>,
+    /// Named sets of hosts for `--group`
+    #[serde(default)]
+    pub groups: HashMap<String, Vec<String>>,
+    /// Host settings every host inherits unless it sets them itself
+    #[serde(default, skip_serializing_if = "toml::Table::is_empty")]
+    pub host_defaults: toml::Table,
+    /// Named overlays applied on top of the config with `--profile <name>`
+    #[serde(default, skip_serializing_if = "toml::Table::is_empty")]
+    pub profiles: toml::Table,
+    /// Further config files merged underneath this one, in order (later files win)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Host {
+    /// SSH host. Optional for `kubernetes` hosts, where it defaults to the pod name.
+    #[serde(default)]
+    pub hostname: String,
+    pub path: String,
+    /// Another host whose settings this one inherits (instead of `[host_defaults]`)
+    pub extends: Option<String>,
+    #[serde(default)]
+    pub shell: Shell,
+    /// Send commands base64-encoded and decode them on the remote, so quotes, `$` and
+    /// backticks reach the remote shell untouched (not supported for cmd). Default: false.
+    #[serde(default)]
+    pub encode_command: bool,
+    /// WSL distribution to run commands in with `shell = "wsl"`. Unset uses the
+    /// host's default distribution.
+    pub wsl_distro: Option<String>,
+    /// Docker container on the host to run commands in (`docker exec`), for dev
+    /// environments that live in a container.
+    pub container: Option<String>,
+    /// Working directory inside the container. Default: the remote path, as when the
+    /// project is bind-mounted at the same place.
+    pub container_path: Option<String>,
+    /// If true, each sync also copies the project into the container (`docker cp`), for
+    /// containers without a bind mount. Default: false.
+    #[serde(default)]
+    pub container_sync: bool,
+    /// Run in a Kubernetes pod with `kubectl exec` and `kubectl cp` instead of over SSH.
+    pub kubernetes: Option<KubernetesTarget>,
+    /// Sync method: "tar" (default) or "rsync" (incremental, deletes removed files)
+    #[serde(default)]
+    pub sync_method: SyncMethod,
+    /// If true, a sync that would delete many remote files asks before doing so.
+    /// Default: true.
+    #[serde(default = "default_true")]
+    pub confirm_delete: bool,
+    /// Sync excludes for this host, added to `sync.exclude`
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// If true, linked git worktrees use path-worktree_name as the remote path. Default: true.
+    #[serde(default = "default_true")]
+    pub worktree_rename: bool,
+    /// Optional command wrapper template. Use `{}` as placeholder for the command.
+    /// Supports ${VAR} syntax for local environment variable substitution.
+    pub wrapper: Option<String>,
+    /// If true, fail when ${VAR} references cannot be resolved. Default: true.
+    #[serde(default = "default_true")]
+    pub strict_env: bool,
+    /// Additional env files to load after .env (which is loaded by default).
+    /// Files are loaded in order; later files override earlier ones.
+    #[serde(default)]
+    pub env_files: Vec<String>,
+    /// If true, every variable loaded from the env files is also exported to the remote
+    /// command's environment, not only used for `${VAR}` substitution. Default: false.
+    #[serde(default)]
+    pub export_env: bool,
+    /// Variables to export to the remote command's environment, resolved like `${VAR}`
+    /// (process environment, then env files).
+    #[serde(default)]
+    pub remote_env: Vec<String>,
+    /// Command to run after reconnecting from an unexpected SSH disconnect.
+    /// If not set, reconnect behavior is disabled.
+    pub reconnect_command: Option<String>,
+    /// What `bridge run` does after a lost connection comes back: "rerun", "command"
+    /// (run `reconnect_command`), or "none". Unset, "command" if there is a
+    /// `reconnect_command`, otherwise "none".
+    pub reconnect_action: Option<ReconnectAction>,
+    /// How many times `reconnect_action = "rerun"` runs a command again before giving
+    /// up on a host that keeps dropping the connection. Default: 3.
+    #[serde(default = "default_max_reruns")]
+    pub max_reruns: u32,
+    /// Seconds to wait for reconnection before giving up. Default: 90.
+    #[serde(default = "default_reconnect_timeout")]
+    pub reconnect_timeout: u64,
+    /// Seconds between checks while waiting to reconnect (the first wait, with
+    /// exponential backoff). Default: 5.
+    #[serde(default = "default_reconnect_interval")]
+    pub reconnect_interval: u64,
+    /// "fixed" (default) waits `reconnect_interval` between checks; "exponential"
+    /// doubles the wait after each failed check, up to a minute.
+    #[serde(default)]
+    pub reconnect_backoff: ReconnectBackoff,
+    /// Give up reconnecting after this many failed checks, even before
+    /// `reconnect_timeout`. Unset, only the timeout applies.
+    pub reconnect_attempts: Option<u32>,
+    /// Times a transfer is retried after its connection fails (ssh or scp exit 255,
+    /// rsync exit 12 or 30). Default: 0.
+    #[serde(default)]
+    pub retries: u32,
+    /// Seconds to wait before the first retry; each later retry waits twice as long.
+    /// Default: 2.
+    #[serde(default = "default_retry_delay")]
+    pub retry_delay: u64,
+    /// Lock configuration: false (default), true (lock with default name), or string (named lock)
+    #[serde(default)]
+    pub lock: LockSetting,
+    /// Seconds to wait for lock acquisition before giving up. Default: 600.
+    #[serde(default = "default_lock_timeout")]
+    pub lock_timeout: u64,
+    /// Address family for SSH connections: "any" (default), "inet" (IPv4), or "inet6" (IPv6)
+    #[serde(default)]
+    pub address_family: AddressFamily,
+    /// Optional address to connect to instead of resolving `hostname` via DNS.
+    /// The hostname is still used for ~/.ssh/config and known_hosts lookups.
+    pub resolve: Option<String>,
+    /// SSH login user, for hosts not described in ~/.ssh/config.
+    pub user: Option<String>,
+    /// SSH port, for hosts not described in ~/.ssh/config.
+    pub port: Option<u16>,
+    /// Private key file for SSH authentication (`~` is expanded by ssh).
+    pub identity_file: Option<String>,
+    /// Jump host(s) to connect through, as for `ssh -J` (e.g. `bastion` or `user@bastion:22`).
+    pub proxy_jump: Option<String>,
+    /// Seconds to wait for an SSH connection to be set up (`ConnectTimeout`). Unset,
+    /// connection checks give up after 5 seconds and other connections wait as long
+    /// as ssh does.
+    pub connect_timeout: Option<u64>,
+    /// Seconds of silence after which ssh checks that the server is still there
+    /// (`ServerAliveInterval`). Default: 5 for remote commands, 15 for the master
+    /// connection and mounts.
+    pub server_alive_interval: Option<u64>,
+    /// Unanswered checks after which ssh drops the connection (`ServerAliveCountMax`).
+    /// Default: 3.
+    pub server_alive_count: Option<u64>,
+    /// Use mosh instead of ssh for `bridge ssh` and for `bridge run` in a terminal,
+    /// when mosh is installed on both ends. Default: false.
+    #[serde(default)]
+    pub mosh: bool,
+    /// Forward the local SSH agent to `bridge run` and `bridge ssh` sessions (`ssh -A`),
+    /// e.g. for `git pull` on the remote. Default: false.
+    #[serde(default)]
+    pub forward_agent: bool,
+    /// Forward X11 to `bridge run` and `bridge ssh` sessions (`ssh -X`) so remote GUI
+    /// tools can open windows locally. Default: false.
+    #[serde(default)]
+    pub forward_x11: bool,
+    /// Use trusted X11 forwarding (`ssh -Y`), which some applications need. Default: false.
+    #[serde(default)]
+    pub x11_trusted: bool,
+    /// Show a desktop notification when `bridge run` finishes, with the exit status and
+    /// how long it took. Default: false.
+    #[serde(default)]
+    pub notify: bool,
+    /// Authenticate with a password (via sshpass) instead of keys. The password comes
+    /// from `$SSH_PASSWORD` or is asked for once per invocation. Default: false.
+    #[serde(default)]
+    pub password_auth: bool,
+    /// Host key verification policy: "strict", "accept-new", or "off". Unset leaves it
+    /// to ~/.ssh/config.
+    pub host_key_checking: Option<HostKeyChecking>,
+    /// Extra arguments passed to every ssh and scp invocation (and to rsync's remote
+    /// shell), e.g. `["-o", "Compression=yes"]`.
+    #[serde(default)]
+    pub ssh_options: Vec<String>,
+    /// Named log files for `bridge tail <name>`, relative to the remote path or absolute.
+    #[serde(default)]
+    pub logs: HashMap<String, String>,
+    /// If set, write the merged env vars to this file on the remote after each sync.
+    /// Relative to the remote path. Created with 600 permissions.
+    pub render_env: Option<String>,
+    /// Local command that must succeed before upload/sync to this host.
+    /// Receives the file manifest (absolute paths, one per line) on stdin.
+    pub transfer_hook: Option<String>,
+    /// Local command (e.g. a build step) to run in the project root before each sync.
+    /// A non-zero exit aborts the sync.
+    pub pre_sync_local: Option<String>,
+    /// Local command to run in the project root before each run. A non-zero exit aborts the run.
+    pub pre_run_local: Option<String>,
+    /// Local command to run in the project root when a run or tail loses its connection,
+    /// before waiting for the host to come back.
+    pub on_disconnect_local: Option<String>,
+    /// Local command to run in the project root once a lost connection is back, before
+    /// the reconnect action.
+    pub on_reconnect_local: Option<String>,
+    /// Local command asked for `${VAR}` secrets that aren't otherwise set. Invoked as
+    /// `<helper> get` with `host=`, `hostname=`, `name=` lines on stdin; prints the value.
+    pub credential_helper: Option<String>,
+    /// Seconds to cache credential helper answers (0 disables caching). Default: 300.
+    #[serde(default = "default_credential_ttl")]
+    pub credential_ttl: u64,
+    /// Hosts to fail over to, in order, when this host fails a connection check.
+    #[serde(default)]
+    pub fallback_hosts: Vec<String>,
+    /// Free-form labels for selecting sets of hosts with `--tag`.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Object store URL (`s3://bucket/prefix` or `gs://bucket/prefix`) the remote uploads
+    /// artifacts to with `bridge artifacts push`, using its own `aws`/`gsutil` credentials.
+    pub artifact_store: Option<String>,
+    /// Why `${VAR}` in the hostname, path, or env_files couldn't be expanded. Loading
+    /// the config doesn't fail over it; using this host does (see [`Config::get_host`]).
+    #[serde(skip)]
+    pub expand_error: Option<String>,
+}
+
+/// The pod a `kubernetes` host runs in. Unset fields use kubectl's current context
+/// and namespace, and the pod's default container.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct KubernetesTarget {
+    pub pod: String,
+    pub namespace: Option<String>,
+    pub context: Option<String>,
+    pub container: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncMethod {
+    #[default]
+    Tar,
+    Rsync,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum HostKeyChecking {
+    /// Only connect to hosts whose key is already in known_hosts
+    Strict,
+    /// Trust and record keys of new hosts, but refuse changed keys
+    AcceptNew,
+    /// Skip verification entirely, e.g. for frequently reimaged VMs
+    Off,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum ReconnectAction {
+    /// Sync again if the run synced, then run the interrupted command again
+    Rerun,
+    /// Run `reconnect_command`
+    Command,
+    /// Exit with 255 without waiting for the host
+    None,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReconnectBackoff {
+    /// The same wait between every check
+    #[default]
+    Fixed,
+    /// Twice the wait after each failed check
+    Exponential,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AddressFamily {
+    #[default]
+    Any,
+    Inet,
+    Inet6,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_reconnect_timeout() -> u64 {
+    90
+}
+
+fn default_reconnect_interval() -> u64 {
+    5
+}
+
+fn default_max_reruns() -> u32 {
+    3
+}
+
+fn default_retry_delay() -> u64 {
+    2
+}
+
+fn default_lock_timeout() -> u64 {
+    600
+}
+
+fn default_credential_ttl() -> u64 {
+    300
+}
+
+/// Lock configuration: off (default), on with default name, or named lock.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum LockSetting {
+    /// Lock not configured
+    #[default]
+    Off,
+    /// lock = true → uses "default" lock name
+    Default,
+    /// lock = "kernel" → uses "kernel" lock name
+    Named(String),
+}
+
+impl Serialize for LockSetting {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self {
+            LockSetting::Off => serializer.serialize_bool(false),
+            LockSetting::Default => serializer.serialize_bool(true),
+            LockSetting::Named(name) => serializer.serialize_str(name),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for LockSetting {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        use serde::de;
+
+        struct LockSettingVisitor;
+
+        impl<'de> de::Visitor<'de> for LockSettingVisitor {
+            type Value = LockSetting;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a boolean or a string")
+            }
+
+            fn visit_bool<E: de::Error>(self, v: bool) -> std::result::Result<LockSetting, E> {
+                if v {
+                    Ok(LockSetting::Default)
+                } else {
+                    Ok(LockSetting::Off)
+                }
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> std::result::Result<LockSetting, E> {
+                Ok(LockSetting::Named(v.to_string()))
+            }
+
+            fn visit_string<E: de::Error>(self, v: String) -> std::result::Result<LockSetting, E> {
+                Ok(LockSetting::Named(v))
+            }
+        }
+
+        deserializer.deserialize_any(LockSettingVisitor)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum Shell {
+    #[default]
+    Bash,
+    Zsh,
+    Fish,
+    /// A plain POSIX shell such as dash or ash
+    Sh,
+    Nushell,
+    /// bash in WSL on a Windows host, reached through `wsl.exe`
+    Wsl,
+    Powershell,
+    Cmd,
+}
+
+impl Shell {
+    /// The executable that starts this shell.
+    pub fn program(&self) -> &'static str {
+        match self {
+            Shell::Bash => "bash",
+            Shell::Zsh => "zsh",
+            Shell::Fish => "fish",
+            Shell::Sh => "sh",
+            Shell::Nushell => "nu",
+            Shell::Wsl => "bash",
+            Shell::Powershell => "powershell",
+            Shell::Cmd => "cmd",
+        }
+    }
+
+    /// Whether the remote is a Windows host (PowerShell or cmd).
+    pub fn is_windows(&self) -> bool {
+        matches!(self, Shell::Powershell | Shell::Cmd)
+    }
+}
+
+impl std::fmt::Display for Shell {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Shell::Bash => write!(f, "bash"),
+            Shell::Zsh => write!(f, "zsh"),
+            Shell::Fish => write!(f, "fish"),
+            Shell::Sh => write!(f, "sh"),
+            Shell::Nushell => write!(f, "nushell"),
+            Shell::Wsl => write!(f, "wsl"),
+            Shell::Powershell => write!(f, "powershell"),
+            Shell::Cmd => write!(f, "cmd"),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Default)]
+pub struct SyncConfig {
+    #[serde(default = "default_excludes")]
+    pub exclude: Vec<String>,
+    /// Line endings text files are converted to on the way to the remote: "lf",
+    /// "crlf", or "none" (default, files are sent as they are)
+    #[serde(default)]
+    pub convert_line_endings: LineEndings,
+    /// Permission fixes applied on the remote after each sync, as "<mode> <pattern>"
+    /// (e.g. "+x scripts/*.sh"), for executable bits lost on the way
+    #[serde(default)]
+    pub chmod: Vec<String>,
+    /// rsync filter rules (e.g. "- /data/", "+ /data/small/**") passed as `--filter`
+    /// ahead of the excludes. rsync hosts only.
+    #[serde(default)]
+    pub filters: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEndings {
+    #[default]
+    None,
+    Lf,
+    Crlf,
+}
+
+#[derive(Debug, Deserialize, Serialize, Default)]
+pub struct UploadConfig {
+    /// If true, single-file uploads keep their project-relative path on the remote
+    /// (as `--relative`). Default: false.
+    #[serde(default)]
+    pub relative: bool,
+}
+
+/// Settings for `run --watch`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct WatchConfig {
+    /// Quiet period (milliseconds) after a change before rerunning. Default: 300.
+    #[serde(default = "default_debounce_ms")]
+    pub debounce_ms: u64,
+    /// Patterns whose changes never trigger a rerun, on top of the sync excludes
+    #[serde(default)]
+    pub ignore: Vec<String>,
+    /// Scan the project for changes instead of relying on OS notifications, which
+    /// network filesystems and some VMs don't deliver. Default: false.
+    #[serde(default)]
+    pub poll: bool,
+    /// Milliseconds between scans when polling. Default: 1000.
+    #[serde(default = "default_poll_interval_ms")]
+    pub poll_interval_ms: u64,
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        WatchConfig {
+            debounce_ms: default_debounce_ms(),
+            ignore: Vec::new(),
+            poll: false,
+            poll_interval_ms: default_poll_interval_ms(),
+        }
+    }
+}
+
+/// Webhook posted after runs, for teams keeping an eye on long remote jobs.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct NotificationsConfig {
+    /// URL to POST to. Supports ${VAR} substitution and the placeholders of [`crate::notify::Finished`].
+    pub webhook: Option<String>,
+    /// JSON body template. Default: a Slack-style `{"text": ...}` message.
+    pub body: Option<String>,
+    /// Post after runs that take at least this many seconds, whatever their result.
+    /// Unset posts only on failure.
+    pub min_duration: Option<u64>,
+    /// Post after failed runs, however short. Default: true.
+    #[serde(default = "default_true")]
+    pub on_failure: bool,
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        NotificationsConfig { webhook: None, body: None, min_duration: None, on_failure: true }
+    }
+}
+
+fn default_debounce_ms() -> u64 {
+    300
+}
+
+fn default_poll_interval_ms() -> u64 {
+    1000
+}
+
+fn default_excludes() -> Vec<String> {
+    vec![
+        ".git".to_string(),
+        "target".to_string(),
+        "node_modules".to_string(),
+        "__pycache__".to_string(),
+    ]
+}
+
+/// Auto-excludes for Mac-specific files that cause issues on remote systems
+pub fn auto_excludes() -> Vec<String> {
+    vec![
+        ".DS_Store".to_string(),
+        "._*".to_string(),
+    ]
+}
+
+/// Return the remote path Bridge should use for this checkout. WSL hosts get it in
+/// the form WSL sees it.
+pub fn effective_remote_path(host: &Host, project_root: &Path) -> String {
+    let path = checkout_remote_path(host, project_root);
+    if host.shell == Shell::Wsl {
+        paths::wsl_path(&path)
+    } else {
+        path
+    }
+}
+
+fn checkout_remote_path(host: &Host, project_root: &Path) -> String {
+    if !host.worktree_rename || !is_linked_worktree(project_root) {
+        return host.path.clone();
+    }
+
+    let Some(worktree_name) = worktree_name(project_root) else {
+        return host.path.clone();
+    };
+
+    remote_path_with_worktree_suffix(&host.path, &worktree_name)
+}
+
+fn is_linked_worktree(project_root: &Path) -> bool {
+    let Some(git_dir) = git_output(project_root, &["rev-parse", "--git-dir"]) else {
+        return false;
+    };
+    let Some(common_dir) = git_output(project_root, &["rev-parse", "--git-common-dir"]) else {
+        return false;
+    };
+
+    git_dir != common_dir
+}
+
+fn worktree_name(project_root: &Path) -> Option<String> {
+    let worktree_root = git_output(project_root, &["rev-parse", "--show-toplevel"])?;
+    Path::new(&worktree_root)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .map(|name| name.to_string())
+}
+
+fn remote_path_with_worktree_suffix(remote_path: &str, worktree_name: &str) -> String {
+    let base = remote_path.trim_end_matches(['/', '\\']);
+    let base = if base.is_empty() { remote_path } else { base };
+    format!("{}-{}", base, worktree_name)
+}
+
+fn git_output(project_root: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(project_root)
+        .args(args)
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    let stdout = String::from_utf8(output.stdout).ok()?;
+    let value = stdout.trim();
+    if value.is_empty() {
+        None
+    } else {
+        Some(value.to_string())
+    }
+}
+
+impl Config {
+    /// Find and load config by walking up from current directory
+    pub fn find_and_load() -> Result<(Config, PathBuf)> {
+        let config_path = find_config_file()?;
+        let config = load_config(&config_path)?;
+        if let Some(ref log) = config.audit_log {
+            audit::enable(log, &Config::project_root(&config_path));
+        }
+        Ok((config, config_path))
+    }
+
+    /// Get the project root directory (where bridge.toml is located)
+    pub fn project_root(config_path: &Path) -> PathBuf {
+        config_path.parent().unwrap_or(config_path).to_path_buf()
+    }
+
+    /// Get a host by name, or the default host
+    pub fn get_host(&self, name: Option<&str>) -> Result<(&String, &Host)> {
+        let host_name = match name {
+            Some(n) => n.to_string(),
+            None => self
+                .default_host
+                .clone()
+                .context("No default host configured. Use --host or set default_host in bridge.toml")?,
+        };
+
+        let host = self
+            .hosts
+            .get(&host_name)
+            .with_context(|| format!("Host '{}' not found in configuration", host_name))?;
+        if let Some(ref error) = host.expand_error {
+            anyhow::bail!("{}", error);
+        }
+
+        // Return a reference to the key in the map
+        let key = self.hosts.keys()
+            .find(|k| *k == &host_name)
+            .expect("host key must exist after successful get");
+        Ok((key, host))
+    }
+
+    /// Names of the hosts selected by `--group` and `--tag`, sorted by name: members
+    /// of any of the groups (or all hosts when no group is given) that carry every tag.
+    pub fn select_hosts(&self, groups: &[String], tags: &[String]) -> Result<Vec<&String>> {
+        let mut names: Vec<&String> = if groups.is_empty() {
+            self.hosts.keys().collect()
+        } else {
+            let mut members = Vec::new();
+            for group in groups {
+                let hosts = self
+                    .groups
+                    .get(group)
+                    .with_context(|| format!("Group '{}' not found in configuration", group))?;
+                for name in hosts {
+                    let (key, _) = self
+                        .hosts
+                        .get_key_value(name)
+                        .with_context(|| format!("Group '{}' references unknown host '{}'", group, name))?;
+                    members.push(key);
+                }
+            }
+            members
+        };
+
+        names.retain(|name| tags.iter().all(|tag| self.hosts[*name].tags.contains(tag)));
+        names.sort();
+        names.dedup();
+
+        if names.is_empty() {
+            let mut selectors: Vec<String> = groups.iter().map(|g| format!("group {}", g)).collect();
+            selectors.extend(tags.iter().map(|t| format!("tag {}", t)));
+            anyhow::bail!("No hosts match: {}", selectors.join(", "));
+        }
+
+        Ok(names)
+    }
+}
+
+/// No config file in the current directory or any parent. Commands that can do
+/// without a project check for it with `downcast_ref`.
+#[derive(Debug)]
+pub struct ConfigNotFound;
+
+impl std::fmt::Display for ConfigNotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(
+            "No bridge.toml (or bridge.yaml/bridge.json) found in current directory or any parent. Run 'bridge init' to create one.",
+        )
+    }
+}
+
+impl std::error::Error for ConfigNotFound {}
+
+/// Find config file by walking up directory tree
+fn find_config_file() -> Result<PathBuf> {
+    let current_dir = env::current_dir().context("Failed to get current directory")?;
+    let mut dir = current_dir.as_path();
+
+    loop {
+        let found: Vec<PathBuf> = CONFIG_FILENAMES.iter().map(|name| dir.join(name)).filter(|p| p.exists()).collect();
+        match found.as_slice() {
+            [] => {}
+            [config_path] => return Ok(config_path.clone()),
+            _ => anyhow::bail!(
+                "Multiple config files in {}: {}. Keep only one.",
+                dir.display(),
+                found.iter().filter_map(|p| p.file_name()).map(|n| n.to_string_lossy()).collect::<Vec<_>>().join(", ")
+            ),
+        }
+
+        match dir.parent() {
+            Some(parent) => dir = parent,
+            None => return Err(ConfigNotFound.into()),
+        }
+    }
+}
+
+/// Load and parse config from a file, merged over the global config
+fn load_config(path: &Path) -> Result<Config> {
+    from_layers(&config_layers(path)?)
+}
+
+/// Raw config layers, highest priority first, each labelled with where it came from:
+/// the project file and the files it includes, then the global config (and its
+/// includes) if present. Keys missing from every layer take their built-in defaults.
+pub fn config_layers(config_path: &Path) -> Result<Vec<(String, toml::Value)>> {
+    let mut layers = Vec::new();
+    push_layer(config_path, &mut layers, &mut Vec::new())?;
+
+    if let Some(global) = global_config_path().filter(|p| p.exists()) {
+        push_layer(&global, &mut layers, &mut Vec::new())?;
+    }
+
+    Ok(layers)
+}
+
+/// Push a file's layer, followed by the files named in its `include` list. Later
+/// includes take priority over earlier ones, and the including file over all of them.
+fn push_layer(path: &Path, layers: &mut Vec<(String, toml::Value)>, chain: &mut Vec<PathBuf>) -> Result<()> {
+    let canonical = fs::canonicalize(path).with_context(|| format!("Config file not found: {}", path.display()))?;
+    if chain.contains(&canonical) {
+        anyhow::bail!("Config include cycle: {} includes itself", path.display());
+    }
+
+    let value = read_layer(path)?;
+    let includes = include_paths(path, &value)?;
+    layers.push((path.display().to_string(), value));
+
+    chain.push(canonical);
+    for include in includes.iter().rev() {
+        push_layer(include, layers, chain)
+            .with_context(|| format!("Failed to include {} from {}", include.display(), path.display()))?;
+    }
+    chain.pop();
+    Ok(())
+}
+
+/// Paths listed in a layer's `include`, resolved against the including file's directory.
+fn include_paths(path: &Path, value: &toml::Value) -> Result<Vec<PathBuf>> {
+    let Some(include) = value.get("include") else {
+        return Ok(Vec::new());
+    };
+    let entries = include
+        .as_array()
+        .and_then(|entries| entries.iter().map(|e| e.as_str()).collect::<Option<Vec<_>>>())
+        .with_context(|| format!("include in {} must be a list of file paths", path.display()))?;
+
+    let base = path.parent().unwrap_or(Path::new("."));
+    Ok(entries
+        .into_iter()
+        .map(|entry| match entry.strip_prefix("~/") {
+            Some(rest) => env::var_os("HOME").map(PathBuf::from).unwrap_or_default().join(rest),
+            None => base.join(entry),
+        })
+        .collect())
+}
+
+/// Build the effective config from raw layers (highest priority first).
+pub fn from_layers(layers: &[(String, toml::Value)]) -> Result<Config> {
+    let mut merged = toml::Value::Table(toml::Table::new());
+    for (_, layer) in layers.iter().rev() {
+        merge_values(&mut merged, layer.clone());
+    }
+
+    let sources: Vec<&str> = layers.iter().map(|(label, _)| label.as_str()).collect();
+    if let Some(profile) = active_profile() {
+        apply_profile(&mut merged, &profile)?;
+    }
+    apply_host_inheritance(&mut merged)
+        .with_context(|| format!("Invalid config file: {}", sources.join(" + ")))?;
+    let mut config: Config = merged
+        .try_into()
+        .with_context(|| format!("Failed to parse config file: {}", sources.join(" + ")))?;
+    substitute_host_vars(&mut config);
+    check_hosts(&mut config)?;
+    Ok(config)
+}
+
+/// Fill in and check settings that depend on each other: a hostname is required
+/// unless the host is a Kubernetes pod, which can only be reached with a Unix shell.
+fn check_hosts(config: &mut Config) -> Result<()> {
+    for (name, host) in config.hosts.iter_mut() {
+        let Some(ref target) = host.kubernetes else {
+            if host.hostname.is_empty() {
+                anyhow::bail!("hosts.{}.hostname is required", name);
+            }
+            continue;
+        };
+        if host.hostname.is_empty() {
+            host.hostname = target.pod.clone();
+        }
+        if host.shell.is_windows() || host.shell == Shell::Wsl {
+            anyhow::bail!("hosts.{}: kubernetes hosts need a Unix shell (got \"{}\")", name, host.shell);
+        }
+        if host.container.is_some() {
+            anyhow::bail!("hosts.{}: use kubernetes.container to pick a container in the pod", name);
+        }
+    }
+    Ok(())
+}
+
+/// Expand `${VAR}` in each host's hostname, path, and env_files from the local
+/// environment, so one config can hold per-developer values. (The wrapper is expanded
+/// when a command runs, since it can also use variables from the env files.) A host
+/// whose variables aren't set keeps its settings as written and its `expand_error`,
+/// so only commands that use it fail.
+fn substitute_host_vars(config: &mut Config) {
+    let no_env_files = HashMap::new();
+    for (name, host) in config.hosts.iter_mut() {
+        let expand = |field: &str, value: &str| {
+            env_subst::substitute_env_vars(value, host.strict_env, &no_env_files)
+                .with_context(|| format!("Failed to expand hosts.{}.{}", name, field))
+        };
+        let expanded = (|| -> Result<_> {
+            let env_files = host.env_files.iter().map(|file| expand("env_files", file)).collect::<Result<_>>()?;
+            Ok((expand("hostname", &host.hostname)?, expand("path", &host.path)?, env_files))
+        })();
+        match expanded {
+            Ok((hostname, path, env_files)) => {
+                host.hostname = hostname;
+                host.path = path;
+                host.env_files = env_files;
+            }
+            Err(e) => host.expand_error = Some(format!("{:#}", e)),
+        }
+    }
+}
+
+/// Personal config merged underneath every project's bridge.toml:
+/// `$XDG_CONFIG_HOME/bridge/config.toml` or `~/.config/bridge/config.toml`
+/// (`config.yaml`/`config.json` are accepted too).
+pub fn global_config_path() -> Option<PathBuf> {
+    let dir = match env::var_os("XDG_CONFIG_HOME").filter(|d| !d.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    }
+    .join("bridge");
+
+    let candidates = ["config.toml", "config.yaml", "config.yml", "config.json"].map(|name| dir.join(name));
+    let existing = candidates.iter().find(|p| p.exists()).cloned();
+    existing.or_else(|| Some(dir.join("config.toml")))
+}
+
+/// Parse a config file into a TOML value, whatever its format, so every layer merges alike.
+fn read_layer(path: &Path) -> Result<toml::Value> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+    parse_layer(path, &content).with_context(|| format!("Failed to parse config file: {}", path.display()))
+}
+
+fn parse_layer(path: &Path, content: &str) -> Result<toml::Value> {
+    let value: toml::Value = match path.extension().and_then(|e| e.to_str()) {
+        Some("yaml" | "yml") => {
+            // An empty YAML document is null, which has no TOML equivalent
+            if content.trim().is_empty() {
+                return Ok(toml::Value::Table(toml::Table::new()));
+            }
+            serde_yaml::from_str(content)?
+        }
+        Some("json") => serde_json::from_str(content)?,
+        _ => toml::from_str(content)?,
+    };
+    if !value.is_table() {
+        anyhow::bail!("expected a table of settings at the top level");
+    }
+    Ok(value)
+}
+
+/// Select the profile for this invocation (`--profile`). Without one, `$BRIDGE_PROFILE`
+/// is used if set.
+pub fn set_profile(name: Option<String>) {
+    if let Some(name) = name.or_else(|| env::var("BRIDGE_PROFILE").ok().filter(|p| !p.is_empty())) {
+        let _ = PROFILE.set(name);
+    }
+}
+
+pub fn active_profile() -> Option<String> {
+    PROFILE.get().cloned()
+}
+
+/// Merge `[profiles.<name>]` over the rest of the config.
+fn apply_profile(config: &mut toml::Value, name: &str) -> Result<()> {
+    let profiles = config.get("profiles").and_then(|p| p.as_table());
+    let Some(overlay) = profiles.and_then(|p| p.get(name)).cloned() else {
+        let mut available: Vec<&str> = profiles.map(|p| p.keys().map(String::as_str).collect()).unwrap_or_default();
+        available.sort();
+        if available.is_empty() {
+            anyhow::bail!("Unknown profile '{}' (no [profiles] are configured)", name);
+        }
+        anyhow::bail!("Unknown profile '{}' (available: {})", name, available.join(", "));
+    };
+    if !overlay.is_table() {
+        anyhow::bail!("profiles.{} must be a table", name);
+    }
+    merge_values(config, overlay);
+    Ok(())
+}
+
+/// Fill in each host's unset keys from the host it `extends`, or from `[host_defaults]`.
+fn apply_host_inheritance(config: &mut toml::Value) -> Result<()> {
+    let defaults = config.get("host_defaults").cloned().unwrap_or(toml::Value::Table(toml::Table::new()));
+    let Some(toml::Value::Table(hosts)) = config.get("hosts") else {
+        return Ok(());
+    };
+
+    let mut resolved = HashMap::new();
+    for name in hosts.keys() {
+        resolve_host(name, hosts, &defaults, &mut resolved, &mut Vec::new())?;
+    }
+
+    if let Some(toml::Value::Table(hosts)) = config.get_mut("hosts") {
+        for (name, host) in resolved {
+            hosts.insert(name, host);
+        }
+    }
+    Ok(())
+}
+
+fn resolve_host(
+    name: &str,
+    hosts: &toml::Table,
+    defaults: &toml::Value,
+    resolved: &mut HashMap<String, toml::Value>,
+    chain: &mut Vec<String>,
+) -> Result<toml::Value> {
+    if let Some(host) = resolved.get(name) {
+        return Ok(host.clone());
+    }
+    if chain.iter().any(|n| n == name) {
+        chain.push(name.to_string());
+        anyhow::bail!("Host inheritance cycle: {}", chain.join(" -> "));
+    }
+
+    let own = hosts[name].clone();
+    let mut host = match own.get("extends") {
+        Some(toml::Value::String(parent)) => {
+            if !hosts.contains_key(parent) {
+                anyhow::bail!("Host '{}' extends unknown host '{}'", name, parent);
+            }
+            chain.push(name.to_string());
+            let parent = resolve_host(parent, hosts, defaults, resolved, chain)?;
+            chain.pop();
+            parent
+        }
+        Some(_) => anyhow::bail!("Host '{}': extends must be a host name", name),
+        None => defaults.clone(),
+    };
+    merge_values(&mut host, own);
+
+    resolved.insert(name.to_string(), host.clone());
+    Ok(host)
+}
+
+/// Raw config paths that can supply `path`, in priority order: the key itself, then for
+/// host settings the same key on each host it `extends`, then `[host_defaults]`. With a
+/// profile active, each is preceded by its `[profiles.<name>]` override.
+pub fn inherited_key_paths(config: &Config, path: &[&str]) -> Vec<Vec<String>> {
+    let paths = host_key_paths(config, path);
+    match active_profile() {
+        Some(profile) => paths
+            .into_iter()
+            .flat_map(|p| {
+                let overridden = ["profiles".to_string(), profile.clone()].into_iter().chain(p.clone()).collect();
+                [overridden, p]
+            })
+            .collect(),
+        None => paths,
+    }
+}
+
+fn host_key_paths(config: &Config, path: &[&str]) -> Vec<Vec<String>> {
+    let mut paths = vec![path.iter().map(|p| p.to_string()).collect::<Vec<_>>()];
+    let ["hosts", name, rest @ ..] = path else {
+        return paths;
+    };
+    if rest.is_empty() || rest[0] == "extends" {
+        return paths;
+    }
+
+    let mut current = config.hosts.get(*name);
+    let mut seen = vec![name.to_string()];
+    while let Some(parent) = current.and_then(|h| h.extends.as_ref()) {
+        if seen.contains(parent) {
+            break;
+        }
+        paths.push(["hosts", parent.as_str()].iter().chain(rest).map(|p| p.to_string()).collect());
+        seen.push(parent.clone());
+        current = config.hosts.get(parent);
+    }
+
+    paths.push(std::iter::once("host_defaults").chain(rest.iter().copied()).map(|p| p.to_string()).collect());
+    paths
+}
+
+/// Merge `overlay` into `base`: tables merge key by key, anything else is replaced.
+fn merge_values(base: &mut toml::Value, overlay: toml::Value) {
+    match (base, overlay) {
+        (toml::Value::Table(base), toml::Value::Table(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_values(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Project types `bridge init --template` tailors the excludes and wrapper example to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum ProjectTemplate {
+    Rust,
+    Python,
+    Node,
+    /// Python ML projects: conda environment, datasets, checkpoints, and experiment logs
+    Ml,
+}
+
+impl ProjectTemplate {
+    fn excludes(self) -> &'static [&'static str] {
+        match self {
+            ProjectTemplate::Rust => &[".git", "target"],
+            ProjectTemplate::Python => &[
+                ".git", ".venv", "venv", "__pycache__", ".pytest_cache", ".mypy_cache", ".ruff_cache", "*.egg-info", "dist", "build",
+            ],
+            ProjectTemplate::Node => &[".git", "node_modules", "dist", "build", ".next", ".cache", "coverage"],
+            ProjectTemplate::Ml => &[
+                ".git", ".venv", "__pycache__", ".ipynb_checkpoints", "data", "datasets", "checkpoints", "wandb", "mlruns", "lightning_logs", ".cache",
+            ],
+        }
+    }
+
+    /// Example wrapper line (commented out) for the host section.
+    fn wrapper_example(self) -> &'static str {
+        match self {
+            ProjectTemplate::Rust => r#"# wrapper = "source ~/.cargo/env && {}"  # Put cargo on PATH for non-login shells"#,
+            ProjectTemplate::Python => r#"# wrapper = "source .venv/bin/activate && {}"  # Run inside the project's virtualenv"#,
+            ProjectTemplate::Node => r#"# wrapper = "source ~/.nvm/nvm.sh && nvm use && {}"  # Node version from .nvmrc"#,
+            ProjectTemplate::Ml => r#"# wrapper = "source ~/miniconda3/bin/activate ml && {}"  # Run inside the conda env"#,
+        }
+    }
+}
+
+const TEMPLATE_WRAPPER_LINE: &str = r#"# wrapper = "source ~/.profile && {}"  # Optional: wrap all commands"#;
+const TEMPLATE_EXCLUDE_LINE: &str = r#"exclude = [".git", "target", "node_modules", "__pycache__"]"#;
+
+/// Generate a template config file, tailored to a project type if one is given
+pub fn generate_template(template: Option<ProjectTemplate>) -> String {
+    let Some(template) = template else {
+        return base_template();
+    };
+    let excludes: Vec<String> = template.excludes().iter().map(|e| format!("\"{}\"", e)).collect();
+    base_template()
+        .replace(TEMPLATE_WRAPPER_LINE, template.wrapper_example())
+        .replace(TEMPLATE_EXCLUDE_LINE, &format!("exclude = [{}]", excludes.join(", ")))
+}
+
+fn base_template() -> String {
+    r#"default_host = "dev-server"
+# audit_log = "~/.local/state/bridge/audit.jsonl"  # Record every remote command before it is sent
+
+[hosts.dev-server]
+hostname = "dev-server"        # SSH alias (from ~/.ssh/config) or IP
+path = "/home/user/projects/myproject"
+# shell = "bash"               # Login shell: bash (default), zsh, fish, sh, nushell, powershell, cmd, or wsl
+# encode_command = true        # Ship commands base64-encoded to avoid quoting problems
+# wsl_distro = "Ubuntu"        # With shell = "wsl": distribution to use (default: WSL's default)
+# container = "devbox"         # Run commands inside this Docker container on the host
+# container_path = "/app"      # Working directory in the container (default: path)
+# container_sync = true        # Also docker cp the project into the container after each sync
+# kubernetes = { pod = "api-0", namespace = "dev" }  # Run in a pod via kubectl instead of SSH
+# sync_method = "rsync"        # tar (default) or rsync (incremental, deletes removed files)
+# confirm_delete = false       # Don't ask before a sync deletes many remote files
+# exclude = ["data/"]          # Extra sync excludes for this host (added to sync.exclude)
+# worktree_rename = true       # Linked git worktrees use path-worktree_name (default: true)
+# wrapper = "source ~/.profile && {}"  # Optional: wrap all commands
+# strict_env = true            # Fail on missing ${VAR} references (default: true)
+# env_files = [".env.prod"]    # Additional env files to load after .env
+# export_env = true            # Also export env file variables to the remote command
+# remote_env = ["API_KEY"]     # Or export just these (from the local environment or env files)
+# render_env = ".env.remote"   # Write merged env vars to this remote file after sync
+# transfer_hook = "./scripts/sign.sh"  # Must succeed before upload/sync; file list on stdin
+# pre_sync_local = "npm run build"     # Local command run before each sync (failure aborts)
+# pre_run_local = "cargo fmt --check"  # Local command run before each run (failure aborts)
+# on_disconnect_local = "notify-send 'dev is down'"  # Local command run when a connection drops
+# on_reconnect_local = "notify-send 'dev is back'"   # Local command run when it comes back
+# credential_helper = "bridge-cred-op"  # Supplies ${VAR} secrets not set in env or .env files
+# credential_ttl = 300         # Seconds to cache helper answers (0 disables caching)
+# tags = ["linux", "gpu"]      # Select hosts with --tag gpu
+# fallback_hosts = ["dev-2"]   # Used in order when this host is unreachable
+# artifact_store = "s3://bucket/runs"  # Remote uploads artifacts here (bridge artifacts push)
+# reconnect_command = "get-crash-dump.sh"  # Run after SSH reconnects from unexpected disconnect
+# reconnect_action = "rerun"   # Or sync again and rerun the interrupted command (or "none")
+# max_reruns = 3               # Give up after rerunning this many times (default: 3)
+# reconnect_timeout = 90       # Seconds to wait for reconnection (default: 90)
+# reconnect_interval = 5       # Seconds between reconnection checks (default: 5)
+# reconnect_backoff = "exponential"  # Double the wait after each failed check (default: fixed)
+# reconnect_attempts = 10      # Give up after this many failed checks
+# retries = 3                  # Retry transfers whose connection fails (default: 0)
+# retry_delay = 2              # Seconds before the first retry, doubling after (default: 2)
+# lock = true                  # Acquire exclusive lock before running commands
+# lock = "kernel"              # Named lock (only blocks commands with same lock name)
+# lock_timeout = 600           # Seconds to wait for lock (default: 600)
+# address_family = "inet6"     # any (default), inet (IPv4 only), or inet6 (IPv6 only)
+# resolve = "2001:db8::10"     # Connect to this address instead of resolving hostname
+# user = "deploy"              # SSH login user (if not set in ~/.ssh/config)
+# port = 2222                  # SSH port (if not set in ~/.ssh/config)
+# identity_file = "~/.ssh/id_dev"  # SSH private key (if not set in ~/.ssh/config)
+# proxy_jump = "bastion"       # Connect through a jump host (ssh -J)
+# connect_timeout = 30         # Seconds to wait for a connection (checks default to 5)
+# server_alive_interval = 15   # Seconds between keepalives (default: 5)
+# server_alive_count = 3       # Missed keepalives before the connection counts as lost (default: 3)
+# forward_agent = true        # Forward the local SSH agent to run/ssh sessions
+# forward_x11 = true          # Forward X11 to run/ssh sessions (x11_trusted = true for ssh -Y)
+# notify = true                # Desktop notification when a run finishes
+# password_auth = true        # Password login via sshpass ($SSH_PASSWORD or prompt)
+# host_key_checking = "accept-new"  # strict, accept-new, or off (for frequently reimaged VMs)
+# ssh_options = ["-o", "Compression=yes"]  # Extra ssh/scp arguments
+# mosh = true                  # Interactive sessions over mosh (falls back to ssh)
+
+# [hosts.dev-server.logs]      # Shortcuts for `bridge tail <name>`
+# server = "logs/server.log"
+
+# Staging box sharing everything but its path with dev-server:
+# [hosts.staging]
+# extends = "dev-server"
+# path = "/srv/staging/myproject"
+
+# Windows example with environment loading:
+# [hosts.windows-pc]
+# hostname = "192.168.1.100"
+# path = "C:/Users/name/dev/myproject"
+# shell = "powershell"
+# wrapper = "net use \\\\server\\share /user:${DOMAIN_USER} ${DOMAIN_PASS:-}; {}"
+
+# Conda environment example:
+# [hosts.ml-server]
+# hostname = "ml-box"
+# path = "/home/user/ml-project"
+# wrapper = "source ~/miniconda3/bin/activate ml && {}"
+
+[sync]
+exclude = [".git", "target", "node_modules", "__pycache__"]
+# convert_line_endings = "lf"  # Rewrite text files' line endings on the way: lf, crlf, or none (default)
+# chmod = ["+x scripts/*.sh"]  # Permission fixes applied on the remote after each sync
+# filters = ["+ /data/small/**", "- /data/"]  # rsync filter rules, for rsync hosts
+
+# [host_defaults]              # Settings every host inherits unless it sets its own
+# wrapper = "source ~/.profile && {}"
+# env_files = [".env.remote"]
+# reconnect_timeout = 120
+
+# [upload]
+# relative = true              # Single-file uploads keep their relative path (like --relative)
+
+# [watch]                      # run --watch
+# debounce_ms = 300            # Wait for changes to settle before rerunning
+# ignore = ["*.log", "docs/"]  # Changes that don't trigger a rerun (sync excludes never do)
+# poll = true                  # Scan for changes (for network filesystems), every poll_interval_ms
+
+# [notifications]              # Webhook posted after long or failed runs
+# webhook = "https://hooks.slack.com/services/${SLACK_HOOK}"
+# min_duration = 300           # Post after runs that take at least this many seconds
+# on_failure = true            # Post after failed runs, however short
+"#
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn worktree_rename_defaults_to_true() {
+        let config: Config = toml::from_str(
+            r#"
+default_host = "dev"
+
+[hosts.dev]
+hostname = "dev"
+path = "/home/user/project"
+"#,
+        )
+        .unwrap();
+
+        let host = config.hosts.get("dev").unwrap();
+        assert!(host.worktree_rename);
+    }
+
+    #[test]
+    fn worktree_rename_can_be_disabled() {
+        let config: Config = toml::from_str(
+            r#"
+default_host = "dev"
+
+[hosts.dev]
+hostname = "dev"
+path = "/home/user/project"
+worktree_rename = false
+"#,
+        )
+        .unwrap();
+
+        let host = config.hosts.get("dev").unwrap();
+        assert!(!host.worktree_rename);
+    }
+
+    #[test]
+    fn address_family_and_resolve_parse() {
+        let config: Config = toml::from_str(
+            r#"
+[hosts.lab]
+hostname = "lab-3"
+path = "/home/user/project"
+address_family = "inet6"
+resolve = "2001:db8::10"
+"#,
+        )
+        .unwrap();
+
+        let host = config.hosts.get("lab").unwrap();
+        assert_eq!(host.address_family, AddressFamily::Inet6);
+        assert_eq!(host.resolve.as_deref(), Some("2001:db8::10"));
+    }
+
+    #[test]
+    fn project_layer_overrides_global_layer() {
+        let global: toml::Value = toml::from_str(
+            "default_host = \"home\"\n[hosts.home]\nhostname = \"home-box\"\npath = \"/home/me/p\"\nlock_timeout = 30\n",
+        )
+        .unwrap();
+        let project: toml::Value =
+            toml::from_str("[hosts.home]\npath = \"/srv/p\"\n[hosts.ci]\nhostname = \"ci\"\npath = \"/ci\"\n").unwrap();
+
+        let config = from_layers(&[("project".to_string(), project), ("global".to_string(), global)]).unwrap();
+        assert_eq!(config.default_host.as_deref(), Some("home"));
+        assert_eq!(config.hosts["home"].hostname, "home-box");
+        assert_eq!(config.hosts["home"].path, "/srv/p");
+        assert_eq!(config.hosts["home"].lock_timeout, 30);
+        assert_eq!(config.hosts.len(), 2);
+    }
+
+    #[test]
+    fn hosts_inherit_defaults_and_extends() {
+        let value: toml::Value = toml::from_str(
+            r#"
+[host_defaults]
+wrapper = "source ~/.profile && {}"
+reconnect_timeout = 120
+
+[hosts.dev]
+hostname = "dev"
+path = "/srv/app"
+env_files = [".env.dev"]
+
+[hosts.staging]
+extends = "dev"
+path = "/srv/staging"
+reconnect_timeout = 30
+
+[hosts.win]
+hostname = "win"
+path = "C:/app"
+wrapper = "{}"
+"#,
+        )
+        .unwrap();
+
+        let config = from_layers(&[("project".to_string(), value)]).unwrap();
+        let staging = &config.hosts["staging"];
+        assert_eq!(staging.hostname, "dev");
+        assert_eq!(staging.path, "/srv/staging");
+        assert_eq!(staging.env_files, vec![".env.dev"]);
+        assert_eq!(staging.wrapper.as_deref(), Some("source ~/.profile && {}"));
+        assert_eq!(staging.reconnect_timeout, 30);
+        assert_eq!(config.hosts["dev"].reconnect_timeout, 120);
+        assert_eq!(config.hosts["win"].wrapper.as_deref(), Some("{}"));
+
+        assert_eq!(
+            inherited_key_paths(&config, &["hosts", "staging", "wrapper"]),
+            vec![vec!["hosts", "staging", "wrapper"], vec!["hosts", "dev", "wrapper"], vec!["host_defaults", "wrapper"]]
+        );
+    }
+
+    #[test]
+    fn extends_rejects_cycles_and_unknown_hosts() {
+        let cycle: toml::Value = toml::from_str(
+            "[hosts.a]\nextends = \"b\"\n[hosts.b]\nextends = \"a\"\nhostname = \"b\"\npath = \"/b\"\n",
+        )
+        .unwrap();
+        let err = from_layers(&[("project".to_string(), cycle)]).unwrap_err();
+        assert!(format!("{:#}", err).contains("cycle"));
+
+        let unknown: toml::Value = toml::from_str("[hosts.a]\nextends = \"nope\"\n").unwrap();
+        let err = from_layers(&[("project".to_string(), unknown)]).unwrap_err();
+        assert!(format!("{:#}", err).contains("unknown host 'nope'"));
+    }
+
+    #[test]
+    fn profile_overlays_config() {
+        let mut value: toml::Value = toml::from_str(
+            r#"
+default_host = "dev"
+
+[hosts.dev]
+hostname = "dev"
+path = "/srv/app"
+
+[profiles.prod]
+default_host = "prod"
+sync = { exclude = ["data/"] }
+host_defaults = { env_files = [".env.prod"] }
+
+[profiles.prod.hosts.prod]
+hostname = "prod"
+path = "/srv/prod"
+wrapper = "sudo -u app {}"
+"#,
+        )
+        .unwrap();
+
+        apply_profile(&mut value, "prod").unwrap();
+        apply_host_inheritance(&mut value).unwrap();
+        let config: Config = value.clone().try_into().unwrap();
+        assert_eq!(config.default_host.as_deref(), Some("prod"));
+        assert_eq!(config.sync.exclude, vec!["data/"]);
+        assert_eq!(config.hosts["prod"].wrapper.as_deref(), Some("sudo -u app {}"));
+        assert_eq!(config.hosts["dev"].env_files, vec![".env.prod"]);
+
+        let err = apply_profile(&mut value, "qa").unwrap_err();
+        assert_eq!(err.to_string(), "Unknown profile 'qa' (available: prod)");
+    }
+
+    #[test]
+    fn yaml_and_json_parse_like_toml() {
+        let toml_value = parse_layer(
+            Path::new("bridge.toml"),
+            "default_host = \"dev\"\n[hosts.dev]\nhostname = \"dev\"\npath = \"/srv\"\nport = 2222\ntags = [\"gpu\"]\n",
+        )
+        .unwrap();
+        let yaml_value = parse_layer(
+            Path::new("bridge.yaml"),
+            "default_host: dev\nhosts:\n  dev:\n    hostname: dev\n    path: /srv\n    port: 2222\n    tags: [gpu]\n",
+        )
+        .unwrap();
+        let json_value = parse_layer(
+            Path::new("bridge.json"),
+            r#"{"default_host": "dev", "hosts": {"dev": {"hostname": "dev", "path": "/srv", "port": 2222, "tags": ["gpu"]}}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(yaml_value, toml_value);
+        assert_eq!(json_value, toml_value);
+        assert!(parse_layer(Path::new("bridge.json"), "[1, 2]").is_err());
+    }
+
+    #[test]
+    fn host_fields_expand_local_env_vars() {
+        env::set_var("BRIDGE_TEST_SCRATCH_USER", "alice");
+        let value: toml::Value = toml::from_str(
+            r#"
+[hosts.hpc]
+hostname = "${BRIDGE_TEST_LOGIN_NODE:-login1}.cluster"
+path = "/scratch/${BRIDGE_TEST_SCRATCH_USER}/project"
+env_files = [".env.${BRIDGE_TEST_SCRATCH_USER}"]
+wrapper = "module load ${MODULES} && {}"
+"#,
+        )
+        .unwrap();
+
+        let config = from_layers(&[("project".to_string(), value)]).unwrap();
+        let host = &config.hosts["hpc"];
+        assert_eq!(host.hostname, "login1.cluster");
+        assert_eq!(host.path, "/scratch/alice/project");
+        assert_eq!(host.env_files, vec![".env.alice"]);
+        assert_eq!(host.wrapper.as_deref(), Some("module load ${MODULES} && {}"));
+
+        // An unset variable only gets in the way of using that host
+        let missing: toml::Value = toml::from_str(
+            "[hosts.hpc]\nhostname = \"hpc\"\npath = \"/scratch/${BRIDGE_TEST_UNSET_VAR}\"\n\
+             [hosts.dev]\nhostname = \"dev\"\npath = \"/srv\"\n",
+        )
+        .unwrap();
+        let config = from_layers(&[("project".to_string(), missing)]).unwrap();
+        let err = config.get_host(Some("hpc")).unwrap_err();
+        assert!(format!("{:#}", err).contains("hosts.hpc.path"));
+        assert!(config.get_host(Some("dev")).is_ok());
+    }
+
+    #[test]
+    fn includes_merge_under_the_including_file() {
+        let dir = TempDir::new().unwrap();
+        fs::write(
+            dir.path().join("bridge.toml"),
+            "include = [\"hosts.toml\", \"shared/team.toml\"]\ndefault_host = \"gpu\"\n[hosts.gpu]\npath = \"/mine\"\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("hosts.toml"), "[hosts.gpu]\nhostname = \"gpu-1\"\npath = \"/srv\"\nlock_timeout = 10\n").unwrap();
+        fs::create_dir(dir.path().join("shared")).unwrap();
+        fs::write(dir.path().join("shared/team.toml"), "[hosts.gpu]\nlock_timeout = 20\n").unwrap();
+
+        let layers = config_layers(&dir.path().join("bridge.toml")).unwrap();
+        let names: Vec<String> = layers
+            .iter()
+            .take(3)
+            .map(|(label, _)| Path::new(label).file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names, vec!["bridge.toml", "team.toml", "hosts.toml"]);
+
+        let config = from_layers(&layers).unwrap();
+        assert_eq!(config.hosts["gpu"].hostname, "gpu-1");
+        assert_eq!(config.hosts["gpu"].path, "/mine");
+        assert_eq!(config.hosts["gpu"].lock_timeout, 20);
+
+        fs::write(dir.path().join("hosts.toml"), "include = [\"bridge.toml\"]\n").unwrap();
+        let err = config_layers(&dir.path().join("bridge.toml")).unwrap_err();
+        assert!(format!("{:#}", err).contains("include cycle"));
+    }
+
+    #[test]
+    fn init_templates_are_valid_configs() {
+        let plain: Config = toml::from_str(&generate_template(None)).unwrap();
+        assert_eq!(plain.sync.exclude, default_excludes());
+
+        for template in [ProjectTemplate::Rust, ProjectTemplate::Python, ProjectTemplate::Node, ProjectTemplate::Ml] {
+            let text = generate_template(Some(template));
+            let config: Config = toml::from_str(&text).unwrap();
+            assert_eq!(config.sync.exclude, template.excludes());
+            assert!(text.contains(template.wrapper_example()));
+        }
+    }
+
+    #[test]
+    fn wsl_hosts_use_mounted_drive_paths() {
+        let temp = TempDir::new().unwrap();
+        let mut host = test_host(true);
+        host.path = r"D:\work\app".to_string();
+        assert_eq!(effective_remote_path(&host, temp.path()), r"D:\work\app");
+
+        host.shell = Shell::Wsl;
+        assert_eq!(effective_remote_path(&host, temp.path()), "/mnt/d/work/app");
+    }
+
+    #[test]
+    fn kubernetes_hosts_need_no_hostname() {
+        let value: toml::Value = toml::from_str(
+            "[hosts.pod]\npath = \"/app\"\nkubernetes = { pod = \"api-0\", namespace = \"dev\" }\n",
+        )
+        .unwrap();
+        let config = from_layers(&[("project".to_string(), value)]).unwrap();
+        let host = &config.hosts["pod"];
+        assert_eq!(host.hostname, "api-0");
+        assert_eq!(host.kubernetes.as_ref().unwrap().namespace.as_deref(), Some("dev"));
+
+        let value: toml::Value = toml::from_str("[hosts.dev]\npath = \"/app\"\n").unwrap();
+        let err = from_layers(&[("project".to_string(), value)]).unwrap_err();
+        assert!(format!("{:#}", err).contains("hosts.dev.hostname is required"));
+    }
+
+    #[test]
+    fn upload_relative_defaults_off() {
+        let config: Config = toml::from_str("").unwrap();
+        assert!(!config.upload.relative);
+
+        let config: Config = toml::from_str("[upload]\nrelative = true\n").unwrap();
+        assert!(config.upload.relative);
+    }
+
+    #[test]
+    fn watch_settings_have_defaults() {
+        let config: Config = toml::from_str("").unwrap();
+        assert_eq!((config.watch.debounce_ms, config.watch.poll), (300, false));
+
+        let config: Config = toml::from_str("[watch]\nignore = [\"*.log\"]\npoll = true\n").unwrap();
+        assert_eq!(config.watch.ignore, vec!["*.log"]);
+        assert_eq!((config.watch.poll, config.watch.poll_interval_ms), (true, 1000));
+    }
+
+    #[test]
+    fn select_hosts_by_groups_and_tags() {
+        let config: Config = toml::from_str(
+            r#"
+[hosts.ml-1]
+hostname = "ml-1"
+path = "/p"
+tags = ["gpu", "linux"]
+
+[hosts.ml-2]
+hostname = "ml-2"
+path = "/p"
+tags = ["gpu", "lab-3"]
+
+[hosts.build]
+hostname = "build"
+path = "/p"
+tags = ["linux"]
+
+[groups]
+gpu = ["ml-1", "ml-2"]
+ci = ["build", "ml-1"]
+broken = ["missing"]
+"#,
+        )
+        .unwrap();
+
+        let s = |v: &[&str]| v.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(config.select_hosts(&[], &s(&["gpu"])).unwrap(), vec!["ml-1", "ml-2"]);
+        assert_eq!(config.select_hosts(&[], &s(&["gpu", "linux"])).unwrap(), vec!["ml-1"]);
+        assert!(config.select_hosts(&[], &s(&["windows"])).is_err());
+
+        assert_eq!(config.select_hosts(&s(&["gpu", "ci"]), &[]).unwrap(), vec!["build", "ml-1", "ml-2"]);
+        assert_eq!(config.select_hosts(&s(&["ci"]), &s(&["gpu"])).unwrap(), vec!["ml-1"]);
+        assert!(config.select_hosts(&s(&["nope"]), &[]).is_err());
+        assert!(config.select_hosts(&s(&["broken"]), &[]).is_err());
+    }
+
+    #[test]
+    fn remote_path_suffix_handles_unix_windows_and_trailing_separators() {
+        assert_eq!(
+            remote_path_with_worktree_suffix("/home/user/project", "project-fix"),
+            "/home/user/project-project-fix"
+        );
+        assert_eq!(
+            remote_path_with_worktree_suffix("/home/user/project/", "project-fix"),
+            "/home/user/project-project-fix"
+        );
+        assert_eq!(
+            remote_path_with_worktree_suffix("C:/Users/name/project", "project-fix"),
+            "C:/Users/name/project-project-fix"
+        );
+        assert_eq!(
+            remote_path_with_worktree_suffix(r"C:\Users\name\project\\", "project-fix"),
+            r"C:\Users\name\project-project-fix"
+        );
+    }
+
+    #[test]
+    fn primary_worktree_uses_configured_path() {
+        let dir = TempDir::new().unwrap();
+        git(dir.path(), &["init"]);
+
+        let host = test_host(true);
+        assert_eq!(effective_remote_path(&host, dir.path()), "/remote/project");
+    }
+
+    #[test]
+    fn linked_worktree_uses_worktree_directory_suffix() {
+        let dir = TempDir::new().unwrap();
+        let main = dir.path().join("repo");
+        let linked = dir.path().join("repo-codex-1");
+
+        fs::create_dir(&main).unwrap();
+        git(&main, &["init"]);
+        git(&main, &["config", "user.email", "bridge@example.com"]);
+        git(&main, &["config", "user.name", "Bridge Tests"]);
+        git(&main, &["commit", "--allow-empty", "-m", "init"]);
+        git(&main, &["worktree", "add", linked.to_str().unwrap()]);
+
+        let host = test_host(true);
+        assert_eq!(
+            effective_remote_path(&host, &linked),
+            "/remote/project-repo-codex-1"
+        );
+    }
+
+    #[test]
+    fn disabled_worktree_rename_uses_configured_path_in_linked_worktree() {
+        let dir = TempDir::new().unwrap();
+        let main = dir.path().join("repo");
+        let linked = dir.path().join("repo-codex-2");
+
+        fs::create_dir(&main).unwrap();
+        git(&main, &["init"]);
+        git(&main, &["config", "user.email", "bridge@example.com"]);
+        git(&main, &["config", "user.name", "Bridge Tests"]);
+        git(&main, &["commit", "--allow-empty", "-m", "init"]);
+        git(&main, &["worktree", "add", linked.to_str().unwrap()]);
+
+        let host = test_host(false);
+        assert_eq!(effective_remote_path(&host, &linked), "/remote/project");
+    }
+
+    fn test_host(worktree_rename: bool) -> Host {
+        Host {
+            hostname: "dev".to_string(),
+            path: "/remote/project".to_string(),
+            extends: None,
+            shell: Shell::Bash,
+            encode_command: false,
+            wsl_distro: None,
+            container: None,
+            container_path: None,
+            container_sync: false,
+            kubernetes: None,
+            sync_method: SyncMethod::Tar,
+            confirm_delete: true,
+            exclude: Vec::new(),
+            worktree_rename,
+            wrapper: None,
+            strict_env: true,
+            env_files: Vec::new(),
+            export_env: false,
+            remote_env: Vec::new(),
+            reconnect_command: None,
+            reconnect_action: None,
+            max_reruns: 3,
+            reconnect_timeout: default_reconnect_timeout(),
+            reconnect_interval: 5,
+            reconnect_backoff: ReconnectBackoff::Fixed,
+            reconnect_attempts: None,
+            retries: 0,
+            retry_delay: default_retry_delay(),
+            lock: LockSetting::Off,
+            lock_timeout: default_lock_timeout(),
+            address_family: AddressFamily::Any,
+            resolve: None,
+            user: None,
+            port: None,
+            identity_file: None,
+            proxy_jump: None,
+            connect_timeout: None,
+            server_alive_interval: None,
+            server_alive_count: None,
+            mosh: false,
+            forward_agent: false,
+            forward_x11: false,
+            x11_trusted: false,
+            notify: false,
+            password_auth: false,
+            host_key_checking: None,
+            ssh_options: Vec::new(),
+            logs: HashMap::new(),
+            render_env: None,
+            transfer_hook: None,
+            pre_sync_local: None,
+            pre_run_local: None,
+            on_disconnect_local: None,
+            on_reconnect_local: None,
+            credential_helper: None,
+            credential_ttl: default_credential_ttl(),
+            fallback_hosts: Vec::new(),
+            tags: Vec::new(),
+            artifact_store: None,
+            expand_error: None,
+        }
+    }
+
+    fn git(cwd: &Path, args: &[&str]) {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(cwd)
+            .args(args)
+            .output()
+            .unwrap();
+
+        assert!(
+            output.status.success(),
+            "git -C {} {} failed\nstdout:\n{}\nstderr:\n{}",
+            cwd.display(),
+            args.join(" "),
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+}
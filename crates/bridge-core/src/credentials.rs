@@ -0,0 +1,161 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use tracing::{info, warn};
+
+use crate::config::Host;
+use crate::env_subst;
+use crate::state;
+
+/// Secrets cached on disk, keyed by `<host name>/<variable>`.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Cache {
+    secrets: HashMap<String, CachedSecret>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct CachedSecret {
+    value: String,
+    /// Unix timestamp (seconds) after which the helper is asked again
+    expires_at: u64,
+}
+
+/// Ask the host's credential helper for every `${VAR}` referenced in `texts` that is
+/// not already set in the process environment or `env_vars`, adding the answers to
+/// `env_vars`. Variables the helper doesn't know are left for the usual
+/// default/strict handling.
+pub fn fill_missing(
+    host_name: &str,
+    host: &Host,
+    texts: &[&str],
+    env_vars: &mut HashMap<String, String>,
+) -> Result<()> {
+    let Some(ref helper) = host.credential_helper else {
+        return Ok(());
+    };
+
+    let mut missing = Vec::new();
+    for text in texts {
+        for name in env_subst::referenced_vars(text) {
+            if env::var_os(&name).is_none() && !env_vars.contains_key(&name) && !missing.contains(&name) {
+                missing.push(name);
+            }
+        }
+    }
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    let now = state::now_secs();
+    let mut cache = load_cache();
+    cache.secrets.retain(|_, secret| secret.expires_at > now);
+    let mut cache_changed = false;
+
+    for name in missing {
+        let key = format!("{}/{}", host_name, name);
+        if let Some(secret) = cache.secrets.get(&key) {
+            info!("Using cached credential: {}", name);
+            env_vars.insert(name, secret.value.clone());
+            continue;
+        }
+
+        let Some(value) = query_helper(helper, host_name, host, &name)? else {
+            continue;
+        };
+
+        if host.credential_ttl > 0 {
+            cache.secrets.insert(
+                key,
+                CachedSecret {
+                    value: value.clone(),
+                    expires_at: now + host.credential_ttl,
+                },
+            );
+            cache_changed = true;
+        }
+        env_vars.insert(name, value);
+    }
+
+    if cache_changed {
+        if let Err(e) = store_cache(&cache) {
+            warn!("Failed to cache credentials: {:#}", e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Run `<helper> get` with the request on stdin (`host=`, `hostname=`, `name=` lines).
+/// The helper prints the secret on stdout and exits 0, or exits non-zero if it has none.
+fn query_helper(helper: &str, host_name: &str, host: &Host, name: &str) -> Result<Option<String>> {
+    info!("Requesting credential {} from helper: {}", name, helper);
+
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} get", helper))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .with_context(|| format!("Failed to run credential helper: {}", helper))?;
+
+    let request = format!("host={}\nhostname={}\nname={}\n", host_name, host.hostname, name);
+    let mut stdin = child.stdin.take().context("Failed to get credential helper stdin")?;
+    // A helper that doesn't read its stdin closes the pipe early; that is not an error
+    let _ = stdin.write_all(request.as_bytes());
+    drop(stdin);
+
+    let output = child.wait_with_output().context("Failed to wait for credential helper")?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+
+    let value = String::from_utf8(output.stdout).context("Credential helper printed invalid UTF-8")?;
+    Ok(Some(value.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+/// Cache file location. Prefers `$XDG_RUNTIME_DIR` (per-user tmpfs, cleared at logout).
+fn cache_path() -> Option<PathBuf> {
+    let dir = match env::var_os("XDG_RUNTIME_DIR").filter(|d| !d.is_empty()) {
+        Some(dir) => PathBuf::from(dir).join("bridge"),
+        None => state::state_dir()?,
+    };
+    Some(dir.join("credentials.json"))
+}
+
+fn load_cache() -> Cache {
+    cache_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Persist the cache readable by the current user only.
+fn store_cache(cache: &Cache) -> Result<()> {
+    let path = cache_path().context("Cannot determine credential cache directory (HOME is not set)")?;
+    let dir = path.parent().context("Cache file has no parent directory")?;
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+
+    let temp = path.with_extension(format!("json.{}", std::process::id()));
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options
+        .open(&temp)
+        .with_context(|| format!("Failed to write credential cache: {}", temp.display()))?;
+    file.write_all(serde_json::to_string(cache)?.as_bytes())?;
+    drop(file);
+
+    fs::rename(&temp, &path).with_context(|| format!("Failed to write credential cache: {}", path.display()))?;
+    Ok(())
+}
@@ -1,7 +1,10 @@
 use anyhow::{Context, Result};
 use std::collections::HashMap;
+use std::env;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+use crate::env_subst::substitute_env_vars;
 
 const DEFAULT_ENV_FILE: &str = ".env";
 
@@ -40,6 +43,57 @@ pub fn load_env_files(project_root: &Path, additional_files: &[String]) -> Resul
     Ok(env_vars)
 }
 
+/// The files [`load_env_files`] reads, in order: `.env` if it exists, then
+/// `additional_files`.
+pub fn env_file_paths(project_root: &Path, additional_files: &[String]) -> Vec<PathBuf> {
+    let default_env_path = project_root.join(DEFAULT_ENV_FILE);
+    default_env_path
+        .exists()
+        .then_some(default_env_path)
+        .into_iter()
+        .chain(additional_files.iter().map(|file| project_root.join(file)))
+        .collect()
+}
+
+/// Render loaded env vars as a dotenv file for tools on the remote that read env files.
+///
+/// Values follow the same priority as `${VAR}` substitution (process environment
+/// overrides file values), and `${VAR}` references inside values are substituted.
+/// Keys are sorted so the output is stable between syncs.
+pub fn render_env_file(env_vars: &HashMap<String, String>, strict: bool) -> Result<String> {
+    let mut keys: Vec<&String> = env_vars.keys().collect();
+    keys.sort();
+
+    let mut rendered = String::new();
+    for key in keys {
+        let raw = env::var(key).unwrap_or_else(|_| env_vars[key].clone());
+        let value = substitute_env_vars(&raw, strict, env_vars)
+            .with_context(|| format!("Failed to substitute environment variables in {}", key))?;
+        rendered.push_str(&format!("{}={}\n", key, quote_env_value(&value)));
+    }
+
+    Ok(rendered)
+}
+
+/// Double-quote values that would otherwise be misread by dotenv parsers.
+fn quote_env_value(value: &str) -> String {
+    let needs_quotes = value.is_empty()
+        || value != value.trim()
+        || value.contains(['#', '"', '\'', '\\', '\n', '$', '`']);
+
+    if !needs_quotes {
+        return value.to_string();
+    }
+
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('$', "\\$")
+        .replace('`', "\\`");
+    format!("\"{}\"", escaped)
+}
+
 /// Parse a single .env file into a HashMap.
 ///
 /// Supported syntax:
@@ -237,6 +291,28 @@ KEY2=value2
         assert_eq!(vars.get("ONLY_PROD"), Some(&"yes".to_string()));
     }
 
+    #[test]
+    fn test_render_env_file() {
+        let mut vars = HashMap::new();
+        vars.insert("BRIDGE_RENDER_B".to_string(), "plain".to_string());
+        vars.insert("BRIDGE_RENDER_A".to_string(), "has space and # hash".to_string());
+        vars.insert("BRIDGE_RENDER_C".to_string(), "${BRIDGE_RENDER_B}-suffix".to_string());
+
+        let rendered = render_env_file(&vars, true).unwrap();
+        assert_eq!(
+            rendered,
+            "BRIDGE_RENDER_A=\"has space and # hash\"\nBRIDGE_RENDER_B=plain\nBRIDGE_RENDER_C=plain-suffix\n"
+        );
+    }
+
+    #[test]
+    fn test_quote_env_value_escapes() {
+        assert_eq!(quote_env_value("simple"), "simple");
+        assert_eq!(quote_env_value(""), "\"\"");
+        assert_eq!(quote_env_value("a\"b"), "\"a\\\"b\"");
+        assert_eq!(quote_env_value("$HOME"), "\"\\$HOME\"");
+    }
+
     #[test]
     fn test_is_valid_env_key() {
         assert!(is_valid_env_key("KEY"));
@@ -25,7 +25,7 @@ pub fn substitute_env_vars(
     strict: bool,
     env_vars: &HashMap<String, String>,
 ) -> Result<String> {
-    let re = Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)(?::-([^}]*))?\}").expect("valid regex");
+    let re = var_pattern();
 
     let mut result = input.to_string();
     let mut missing_vars = Vec::new();
@@ -81,6 +81,22 @@ pub fn substitute_env_vars(
     Ok(result)
 }
 
+/// Names of the variables referenced by ${VAR} patterns (escaped $${VAR} excluded).
+pub fn referenced_vars(input: &str) -> Vec<String> {
+    let unescaped = input.replace("$${", "");
+    let mut names: Vec<String> = Vec::new();
+    for cap in var_pattern().captures_iter(&unescaped) {
+        if !names.iter().any(|n| n == &cap[1]) {
+            names.push(cap[1].to_string());
+        }
+    }
+    names
+}
+
+fn var_pattern() -> Regex {
+    Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)(?::-([^}]*))?\}").expect("valid regex")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -175,6 +191,14 @@ mod tests {
         env::remove_var("BRIDGE_PRIORITY_TEST");
     }
 
+    #[test]
+    fn test_referenced_vars() {
+        assert_eq!(
+            referenced_vars("echo ${A} ${B:-x} $${C} && {}"),
+            vec!["A".to_string(), "B".to_string()]
+        );
+    }
+
     #[test]
     fn test_fallback_to_hashmap() {
         // BRIDGE_UNIQUE_VAR_XYZ should not exist in process env
@@ -0,0 +1,115 @@
+use anyhow::{Context, Result};
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use tracing::info;
+
+/// Run a local preparation command, such as a build step, before a sync or run.
+///
+/// The command runs via `sh -c` in `cwd` with output passed through, and with
+/// `BRIDGE_OPERATION` ("sync" or "run") and `BRIDGE_HOST` set.
+/// A non-zero exit aborts the operation.
+pub fn run_local_hook(hook: &str, operation: &str, host_name: &str, cwd: &Path) -> Result<()> {
+    info!("Running local hook: {}", hook);
+
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(hook)
+        .current_dir(cwd)
+        .env("BRIDGE_OPERATION", operation)
+        .env("BRIDGE_HOST", host_name)
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .status()
+        .with_context(|| format!("Failed to run local hook: {}", hook))?;
+
+    if !status.success() {
+        anyhow::bail!(
+            "Local pre-{} hook failed (exit code {}): {}",
+            operation,
+            status.code().unwrap_or(1),
+            hook
+        );
+    }
+
+    Ok(())
+}
+
+/// Run a local command when a connection drops or comes back, for
+/// `on_disconnect_local` and `on_reconnect_local`.
+///
+/// The command runs via `sh -c` in `cwd` with output passed through, and with
+/// `BRIDGE_EVENT` ("disconnect" or "reconnect"), `BRIDGE_OPERATION` ("run" or
+/// "tail") and `BRIDGE_HOST` set. Bridge waits for it to finish.
+pub fn run_connection_hook(hook: &str, event: &str, operation: &str, host_name: &str, cwd: &Path) -> Result<()> {
+    info!("Running local {} hook: {}", event, hook);
+
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(hook)
+        .current_dir(cwd)
+        .env("BRIDGE_EVENT", event)
+        .env("BRIDGE_OPERATION", operation)
+        .env("BRIDGE_HOST", host_name)
+        .stdin(Stdio::null())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .status()
+        .with_context(|| format!("Failed to run local {} hook: {}", event, hook))?;
+
+    if !status.success() {
+        anyhow::bail!("Local {} hook failed (exit code {}): {}", event, status.code().unwrap_or(1), hook);
+    }
+
+    Ok(())
+}
+
+/// Run a local gate command before files are transferred to a host.
+///
+/// The command runs via `sh -c` in `cwd` and receives the file manifest on stdin,
+/// one absolute path per line. `BRIDGE_OPERATION` ("upload" or "sync"),
+/// `BRIDGE_HOST`, and `BRIDGE_REMOTE_PATH` describe the pending transfer.
+/// A non-zero exit aborts the transfer.
+pub fn run_transfer_hook(
+    hook: &str,
+    operation: &str,
+    host_name: &str,
+    remote_path: &str,
+    cwd: &Path,
+    manifest: &[String],
+) -> Result<()> {
+    info!("Running transfer hook: {} ({} files)", hook, manifest.len());
+
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(hook)
+        .current_dir(cwd)
+        .env("BRIDGE_OPERATION", operation)
+        .env("BRIDGE_HOST", host_name)
+        .env("BRIDGE_REMOTE_PATH", remote_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .with_context(|| format!("Failed to run transfer hook: {}", hook))?;
+
+    let mut stdin = child.stdin.take().context("Failed to get transfer hook stdin")?;
+    let mut contents = manifest.join("\n");
+    contents.push('\n');
+    // A hook that doesn't read its stdin closes the pipe early; that is not an error
+    let _ = stdin.write_all(contents.as_bytes());
+    drop(stdin);
+
+    let status = child.wait().context("Failed to wait for transfer hook")?;
+    if !status.success() {
+        anyhow::bail!(
+            "Transfer hook rejected the {} (exit code {}): {}",
+            operation,
+            status.code().unwrap_or(1),
+            hook
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,33 @@
+//! Core of the `bridge` CLI: config loading, the SSH/scp/rsync transport, project sync,
+//! and environment handling, for tools and editor plugins that want to sync and run
+//! commands on a configured host without shelling out to `bridge`. Diagnostics are
+//! `tracing` events; install a subscriber to see them.
+//!
+//! ```no_run
+//! use bridge_core::{config::{self, Config}, env_loader, ssh};
+//!
+//! let (config, config_path) = Config::find_and_load()?;
+//! let (_, host) = config.get_host(None)?;
+//! let project_root = Config::project_root(&config_path);
+//! let remote_path = config::effective_remote_path(host, &project_root);
+//! let env_vars = env_loader::load_env_files(&project_root, &host.env_files)?;
+//! let exit_code = ssh::run_remote_command(host, &remote_path, "cargo test", &env_vars, &[], false)?.code();
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+pub mod audit;
+pub mod config;
+pub mod credentials;
+pub mod env_loader;
+pub mod env_subst;
+pub mod hooks;
+pub mod line_endings;
+pub mod lock;
+pub mod manifest;
+pub mod notify;
+pub mod output;
+pub mod paths;
+pub mod run_id;
+pub mod runtime;
+pub mod ssh;
+pub mod state;
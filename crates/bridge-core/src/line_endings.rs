@@ -0,0 +1,121 @@
+//! Line-ending conversion for `[sync] convert_line_endings`. The files a sync would
+//! ship are copied into a staging directory with text files rewritten, and the sync
+//! sends that directory instead of the project.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use crate::config::LineEndings;
+
+/// How much of a file is checked for NUL bytes, the same heuristic git uses to
+/// tell binary files from text.
+const BINARY_CHECK_LEN: usize = 8000;
+
+/// Whether `contents` looks binary: a NUL byte near the start.
+pub fn is_binary(contents: &[u8]) -> bool {
+    contents[..contents.len().min(BINARY_CHECK_LEN)].contains(&0)
+}
+
+/// `contents` with every line ending rewritten to `endings`, or `None` if the file is
+/// binary or already uses them. Lone `\r`s are left alone.
+pub fn convert(contents: &[u8], endings: LineEndings) -> Option<Vec<u8>> {
+    if endings == LineEndings::None || is_binary(contents) {
+        return None;
+    }
+
+    let mut converted = Vec::with_capacity(contents.len());
+    for (i, &byte) in contents.iter().enumerate() {
+        if byte == b'\r' && contents.get(i + 1) == Some(&b'\n') {
+            continue;
+        }
+        if byte == b'\n' && endings == LineEndings::Crlf {
+            converted.push(b'\r');
+        }
+        converted.push(byte);
+    }
+
+    (converted != contents).then_some(converted)
+}
+
+/// Copy `files` (`/`-separated, relative to `root`) into `staging`, converting text
+/// files. Returns how many were converted. Permissions and modification times are
+/// kept, so rsync still skips files that haven't changed since the last sync.
+pub fn stage(root: &Path, files: &[String], endings: LineEndings, staging: &Path) -> Result<usize> {
+    let mut converted = 0;
+    for rel in files {
+        let from = root.join(rel);
+        let to = staging.join(rel);
+        if let Some(dir) = to.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+        }
+
+        let metadata = fs::symlink_metadata(&from).with_context(|| format!("Failed to read {}", from.display()))?;
+        if metadata.file_type().is_symlink() && copy_symlink(&from, &to)? {
+            continue;
+        }
+
+        let contents = fs::read(&from).with_context(|| format!("Failed to read {}", from.display()))?;
+        let text = convert(&contents, endings);
+        if text.is_some() {
+            converted += 1;
+        }
+
+        // Times go on before permissions, which may make the copy read-only
+        let mut file = fs::File::create(&to).with_context(|| format!("Failed to create {}", to.display()))?;
+        file.write_all(text.as_deref().unwrap_or(&contents))
+            .and_then(|()| file.set_modified(metadata.modified()?))
+            .with_context(|| format!("Failed to write {}", to.display()))?;
+        drop(file);
+        fs::set_permissions(&to, metadata.permissions())
+            .with_context(|| format!("Failed to set permissions of {}", to.display()))?;
+    }
+    Ok(converted)
+}
+
+/// Recreate a symlink as a symlink, as tar and rsync would send it. Returns false
+/// where that isn't possible, so the file it points to is copied instead.
+#[cfg(unix)]
+fn copy_symlink(from: &Path, to: &Path) -> Result<bool> {
+    let target = fs::read_link(from).with_context(|| format!("Failed to read link {}", from.display()))?;
+    std::os::unix::fs::symlink(target, to).with_context(|| format!("Failed to create link {}", to.display()))?;
+    Ok(true)
+}
+
+#[cfg(not(unix))]
+fn copy_symlink(_from: &Path, _to: &Path) -> Result<bool> {
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn converts_text_and_leaves_binaries() {
+        assert_eq!(convert(b"a\r\nb\n", LineEndings::Lf).unwrap(), b"a\nb\n");
+        assert_eq!(convert(b"a\r\nb\n", LineEndings::Crlf).unwrap(), b"a\r\nb\r\n");
+        assert_eq!(convert(b"a\rb\n", LineEndings::Lf), None);
+        assert_eq!(convert(b"a\r\n", LineEndings::None), None);
+        assert_eq!(convert(b"\x7fELF\0\r\n", LineEndings::Lf), None);
+    }
+
+    #[test]
+    fn staging_keeps_modification_times() {
+        let root = TempDir::new().unwrap();
+        let staging = TempDir::new().unwrap();
+        fs::create_dir(root.path().join("scripts")).unwrap();
+        fs::write(root.path().join("scripts/run.sh"), "#!/bin/sh\r\necho hi\r\n").unwrap();
+        fs::write(root.path().join("logo.png"), b"\x89PNG\0\r\n").unwrap();
+
+        let files = vec!["logo.png".to_string(), "scripts/run.sh".to_string()];
+        assert_eq!(stage(root.path(), &files, LineEndings::Lf, staging.path()).unwrap(), 1);
+        assert_eq!(fs::read(staging.path().join("scripts/run.sh")).unwrap(), b"#!/bin/sh\necho hi\n");
+        assert_eq!(fs::read(staging.path().join("logo.png")).unwrap(), b"\x89PNG\0\r\n");
+
+        let modified = |path: &Path| fs::metadata(path).unwrap().modified().unwrap();
+        assert_eq!(modified(&staging.path().join("scripts/run.sh")), modified(&root.path().join("scripts/run.sh")));
+    }
+}
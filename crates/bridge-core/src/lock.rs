@@ -1,15 +1,38 @@
 use std::fs::{self, File};
 use std::thread;
 use std::time::{Duration, Instant};
+use tracing::info;
 
 use anyhow::{Context, Result};
 use fs2::FileExt;
 
+use crate::status;
+
 /// Guard that holds an exclusive file lock. The lock is released when dropped.
 pub struct LockGuard {
     _file: File,
 }
 
+/// Path of the lock file for a hostname and lock name.
+fn lock_path(hostname: &str, lock_name: &str) -> String {
+    format!("/tmp/bridge-{}-{}.lock", hostname, lock_name)
+}
+
+/// Check whether another process currently holds the lock, without waiting.
+pub fn is_held(hostname: &str, lock_name: &str) -> bool {
+    let Ok(file) = File::open(lock_path(hostname, lock_name)) else {
+        return false;
+    };
+
+    match file.try_lock_shared() {
+        Ok(()) => {
+            let _ = file.unlock();
+            false
+        }
+        Err(_) => true,
+    }
+}
+
 /// Acquire an exclusive lock for the given hostname and lock name.
 ///
 /// Lock file is stored at `/tmp/bridge-{hostname}-{lock_name}.lock`.
@@ -18,26 +41,24 @@ pub fn acquire_lock(
     hostname: &str,
     lock_name: &str,
     timeout: Duration,
-    verbose: bool,
 ) -> Result<LockGuard> {
-    let lock_path = format!("/tmp/bridge-{}-{}.lock", hostname, lock_name);
+    let lock_path = lock_path(hostname, lock_name);
 
     // Ensure the lock file exists
     let file = fs::OpenOptions::new()
         .create(true)
+        .truncate(false)
         .write(true)
         .open(&lock_path)
         .with_context(|| format!("Failed to open lock file: {}", lock_path))?;
 
     // Try non-blocking lock first
     if file.try_lock_exclusive().is_ok() {
-        if verbose {
-            eprintln!("Acquired lock '{}' on {}", lock_name, hostname);
-        }
+        info!("Acquired lock '{}' on {}", lock_name, hostname);
         return Ok(LockGuard { _file: file });
     }
 
-    eprintln!(
+    status!(
         "Waiting for lock '{}' on {}...",
         lock_name, hostname
     );
@@ -58,7 +79,7 @@ pub fn acquire_lock(
         thread::sleep(poll_interval);
 
         if file.try_lock_exclusive().is_ok() {
-            eprintln!("Acquired lock '{}' on {}", lock_name, hostname);
+            status!("Acquired lock '{}' on {}", lock_name, hostname);
             return Ok(LockGuard { _file: file });
         }
     }
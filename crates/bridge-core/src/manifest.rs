@@ -0,0 +1,291 @@
+use anyhow::{Context, Result};
+use glob::{MatchOptions, Pattern};
+use std::fs;
+use std::path::Path;
+
+/// List files under `root` that a sync would ship, as `/`-separated paths relative to root.
+///
+/// Exclude patterns follow tar's unanchored `--exclude` semantics: a pattern matches
+/// if it matches the full relative path or any trailing run of path components,
+/// so `target` excludes both `./target` and `crates/foo/target`. Excluded
+/// directories are pruned entirely, unless a `!` pattern re-includes something
+/// inside them (see [`negations`]). Results are sorted.
+pub fn collect_files(root: &Path, excludes: &[String]) -> Result<Vec<String>> {
+    let patterns = compile_patterns(excludes)?;
+    let negations = negations(excludes);
+    let mut files = Vec::new();
+    walk(root, "", &patterns, &negations, false, &mut files)?;
+    files.sort();
+    Ok(files)
+}
+
+/// Compile the exclude patterns, leaving out `!` re-includes.
+pub fn compile_patterns(excludes: &[String]) -> Result<Vec<Pattern>> {
+    excludes
+        .iter()
+        .filter(|e| !e.starts_with('!'))
+        .map(|e| Pattern::new(e.trim_end_matches('/')).with_context(|| format!("Invalid exclude pattern: {}", e)))
+        .collect()
+}
+
+/// A `!pattern` entry of an exclude list, which syncs what the other patterns
+/// exclude. One containing a `/` (or starting with one) names a path from the project
+/// root and can reach into excluded directories (`!target/config.json`); one without
+/// matches a name anywhere outside them (`!keep.log` next to `*.log`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Negation {
+    pub pattern: String,
+    pub rooted: bool,
+}
+
+/// The `!` entries of an exclude list.
+pub fn negations(excludes: &[String]) -> Vec<Negation> {
+    excludes
+        .iter()
+        .filter_map(|e| e.strip_prefix('!'))
+        .map(|n| n.trim_end_matches('/'))
+        .filter(|n| !n.trim_start_matches('/').is_empty())
+        .map(|n| Negation { pattern: n.trim_start_matches('/').to_string(), rooted: n.contains('/') })
+        .collect()
+}
+
+fn walk(
+    dir: &Path,
+    prefix: &str,
+    patterns: &[Pattern],
+    negations: &[Negation],
+    excluded: bool,
+    files: &mut Vec<String>,
+) -> Result<()> {
+    let entries = fs::read_dir(dir).with_context(|| format!("Failed to read directory: {}", dir.display()))?;
+
+    for entry in entries {
+        let entry = entry.with_context(|| format!("Failed to read directory: {}", dir.display()))?;
+        let Some(name) = entry.file_name().to_str().map(|s| s.to_string()) else {
+            continue;
+        };
+        let rel = if prefix.is_empty() { name } else { format!("{}/{}", prefix, name) };
+
+        let excluded = (excluded || is_excluded(&rel, patterns)) && !is_reincluded(&rel, negations, excluded);
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            if !excluded || negations.iter().any(|n| may_contain(n, &rel)) {
+                walk(&entry.path(), &rel, patterns, negations, excluded, files)?;
+            }
+        } else if !excluded {
+            files.push(rel);
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether a `!` pattern names this path (or, without a `/`, its file name). Inside
+/// an excluded directory only rooted patterns count.
+fn is_reincluded(rel_path: &str, negations: &[Negation], in_excluded_dir: bool) -> bool {
+    let name = rel_path.rsplit('/').next().unwrap_or(rel_path);
+    negations.iter().any(|negation| match Pattern::new(&negation.pattern) {
+        Ok(pattern) if negation.rooted => pattern.matches_with(rel_path, NEGATION_OPTIONS),
+        Ok(pattern) if !in_excluded_dir => pattern.matches(name),
+        _ => false,
+    })
+}
+
+/// `*` in a `!` pattern stays within one directory, as it does in rsync rules.
+const NEGATION_OPTIONS: MatchOptions =
+    MatchOptions { case_sensitive: true, require_literal_separator: true, require_literal_leading_dot: false };
+
+/// Whether a rooted `!` pattern could name something inside the directory `dir`.
+fn may_contain(negation: &Negation, dir: &str) -> bool {
+    if !negation.rooted {
+        return false;
+    }
+    let mut parts = negation.pattern.split('/');
+    for component in dir.split('/') {
+        match parts.next() {
+            Some("**") => return true,
+            Some(part) if Pattern::new(part).is_ok_and(|p| p.matches(component)) => {}
+            _ => return false,
+        }
+    }
+    parts.next().is_some()
+}
+
+/// An exclude list compiled once, for checking many paths that may not exist locally
+/// the way [`collect_files`] would.
+pub struct Excludes {
+    patterns: Vec<Pattern>,
+    negations: Vec<Negation>,
+}
+
+impl Excludes {
+    pub fn new(excludes: &[String]) -> Result<Self> {
+        Ok(Self { patterns: compile_patterns(excludes)?, negations: negations(excludes) })
+    }
+
+    /// Whether a sync leaves out the path `rel_path`.
+    pub fn contains(&self, rel_path: &str) -> bool {
+        let mut excluded = false;
+        let mut prefix = String::new();
+        let mut components = rel_path.split('/').peekable();
+        while let Some(component) = components.next() {
+            if !prefix.is_empty() {
+                prefix.push('/');
+            }
+            prefix.push_str(component);
+            excluded = (excluded || is_excluded(&prefix, &self.patterns))
+                && !is_reincluded(&prefix, &self.negations, excluded);
+            // collect_files doesn't look inside an excluded directory no `!` pattern reaches into
+            if excluded && components.peek().is_some() && !self.negations.iter().any(|n| may_contain(n, &prefix)) {
+                return true;
+            }
+        }
+        excluded
+    }
+}
+
+/// rsync arguments filtering by `excludes`, with rooted patterns anchored under `root`
+/// within the transfer. Each `!` pattern becomes include rules for it and its parent
+/// directories, which come first since rsync's first matching rule wins; parents that
+/// are themselves excluded then get their other contents excluded, so only the named
+/// path gets through.
+pub fn rsync_filter_args(excludes: &[String], root: &str) -> Result<Vec<String>> {
+    let root = if root.is_empty() { String::new() } else { format!("/{}", root.trim_matches('/')) };
+    let patterns = compile_patterns(excludes)?;
+    let mut includes: Vec<String> = Vec::new();
+    let mut sibling_excludes: Vec<String> = Vec::new();
+    let mut name_includes: Vec<String> = Vec::new();
+    for Negation { pattern, rooted } in negations(excludes) {
+        if !rooted {
+            name_includes.push(format!("--include={}", pattern));
+            continue;
+        }
+        let parts: Vec<&str> = pattern.split('/').collect();
+        for depth in 1..parts.len() {
+            let parent = parts[..depth].join("/");
+            includes.push(format!("--include={}/{}/", root, parent));
+            if is_within_excluded(&parent, &patterns) {
+                sibling_excludes.insert(0, format!("--exclude={}/{}/*", root, parent));
+            }
+        }
+        includes.push(format!("--include={}/{}", root, pattern));
+        includes.push(format!("--include={}/{}/***", root, pattern));
+    }
+
+    let mut args: Vec<String> = Vec::new();
+    // Name includes come after the sibling excludes, so they don't reach into the
+    // excluded directories a rooted `!` pattern opens up
+    for arg in includes.into_iter().chain(sibling_excludes).chain(name_includes) {
+        if !args.contains(&arg) {
+            args.push(arg);
+        }
+    }
+    args.extend(excludes.iter().filter(|e| !e.starts_with('!')).map(|e| format!("--exclude={}", e)));
+    Ok(args)
+}
+
+/// Check a relative path against exclude patterns, matching every trailing component run.
+pub fn is_excluded(rel_path: &str, patterns: &[Pattern]) -> bool {
+    let mut suffix = rel_path;
+    loop {
+        if patterns.iter().any(|p| p.matches(suffix)) {
+            return true;
+        }
+        match suffix.split_once('/') {
+            Some((_, rest)) => suffix = rest,
+            None => return false,
+        }
+    }
+}
+
+/// Whether a relative path is excluded itself or lies inside an excluded directory.
+pub fn is_within_excluded(rel_path: &str, patterns: &[Pattern]) -> bool {
+    let mut end = 0;
+    for component in rel_path.split('/') {
+        end += component.len();
+        if is_excluded(&rel_path[..end], patterns) {
+            return true;
+        }
+        end += 1;
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn touch(root: &Path, rel: &str) {
+        let path = root.join(rel);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, "").unwrap();
+    }
+
+    #[test]
+    fn collect_files_honors_unanchored_excludes() {
+        let dir = TempDir::new().unwrap();
+        touch(dir.path(), "src/main.rs");
+        touch(dir.path(), "target/debug/app");
+        touch(dir.path(), "crates/foo/target/out");
+        touch(dir.path(), "crates/foo/lib.rs");
+        touch(dir.path(), ".DS_Store");
+        touch(dir.path(), "docs/._notes.md");
+
+        let excludes = vec!["target".to_string(), ".DS_Store".to_string(), "._*".to_string()];
+        let files = collect_files(dir.path(), &excludes).unwrap();
+        assert_eq!(files, vec!["crates/foo/lib.rs", "src/main.rs"]);
+    }
+
+    #[test]
+    fn path_patterns_match_relative_paths() {
+        let patterns = compile_patterns(&["data/raw".to_string(), "*.log".to_string()]).unwrap();
+        assert!(is_excluded("data/raw", &patterns));
+        assert!(is_excluded("sub/data/raw", &patterns));
+        assert!(!is_excluded("data/processed", &patterns));
+        assert!(is_excluded("logs/server.log", &patterns));
+    }
+
+    #[test]
+    fn paths_inside_excluded_directories_are_excluded() {
+        let patterns = compile_patterns(&["target".to_string(), "data/raw/".to_string()]).unwrap();
+        assert!(is_within_excluded("crates/foo/target/debug/app", &patterns));
+        assert!(is_within_excluded("data/raw/a.csv", &patterns));
+        assert!(!is_within_excluded("src/target.rs", &patterns));
+    }
+
+    #[test]
+    fn negations_reinclude_paths_inside_excluded_directories() {
+        let dir = TempDir::new().unwrap();
+        touch(dir.path(), "target/config.json");
+        touch(dir.path(), "target/debug/app");
+        touch(dir.path(), "target/keep.log");
+        touch(dir.path(), "logs/a.log");
+        touch(dir.path(), "logs/keep.log");
+        touch(dir.path(), "src/main.rs");
+
+        let excludes: Vec<String> =
+            ["target", "*.log", "!target/config.json", "!keep.log"].iter().map(|e| e.to_string()).collect();
+        let files = collect_files(dir.path(), &excludes).unwrap();
+        assert_eq!(files, vec!["logs/keep.log", "src/main.rs", "target/config.json"]);
+        let compiled = Excludes::new(&excludes).unwrap();
+        assert!(compiled.contains("target/debug/app"));
+        assert!(compiled.contains("logs/debug.log"));
+        assert!(compiled.contains("target/keep.log"));
+        assert!(!compiled.contains("target/config.json"));
+        assert!(!compiled.contains("src/lib.rs"));
+
+        assert_eq!(
+            rsync_filter_args(&excludes, "").unwrap(),
+            [
+                "--include=/target/",
+                "--include=/target/config.json",
+                "--include=/target/config.json/***",
+                "--exclude=/target/*",
+                "--include=keep.log",
+                "--exclude=target",
+                "--exclude=*.log",
+            ]
+        );
+    }
+}
@@ -0,0 +1,208 @@
+//! Notifications about finished runs. Desktop notifications, for `run --notify`, go
+//! through the platform's own tool: `osascript` on macOS, PowerShell on Windows, and
+//! `notify-send` elsewhere. Webhooks, for `[notifications]`, are posted with `curl`.
+
+use anyhow::{Context, Result};
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use crate::config::NotificationsConfig;
+use crate::output;
+
+/// Body posted when `[notifications] body` isn't set; Slack, Mattermost and Rocket.Chat
+/// all accept it.
+const DEFAULT_BODY: &str = r#"{"text": "Run on {host} {status}: `{command}` (exit code {exit_code}, took {duration})"}"#;
+
+/// Seconds a webhook gets to answer before bridge gives up on it.
+const WEBHOOK_TIMEOUT: u64 = 10;
+
+/// A finished run, as webhook templates see it. Each field has a placeholder of the
+/// same name (`{host}`, `{exit_code}`, ...), plus `{status}` ("succeeded", "failed", or
+/// "disconnected"), `{duration}` ("4m 12s") and `{duration_secs}`.
+pub struct Finished<'a> {
+    pub host: &'a str,
+    pub hostname: &'a str,
+    pub command: &'a str,
+    pub exit_code: i32,
+    /// The SSH connection was lost, rather than the command failing by itself
+    pub connection_lost: bool,
+    pub duration: Duration,
+    pub run_id: &'a str,
+}
+
+impl Finished<'_> {
+    /// `template` with its placeholders filled in, each value passed through `escape`.
+    fn render(&self, template: &str, escape: impl Fn(&str) -> String) -> String {
+        let status = match (self.exit_code, self.connection_lost) {
+            (_, true) => "disconnected",
+            (0, false) => "succeeded",
+            _ => "failed",
+        };
+        let values = [
+            ("{host}", self.host.to_string()),
+            ("{hostname}", self.hostname.to_string()),
+            ("{command}", self.command.to_string()),
+            ("{exit_code}", self.exit_code.to_string()),
+            ("{connection_lost}", self.connection_lost.to_string()),
+            ("{status}", status.to_string()),
+            ("{duration}", output::format_duration(self.duration)),
+            ("{duration_secs}", self.duration.as_secs().to_string()),
+            ("{run_id}", self.run_id.to_string()),
+        ];
+        // One pass, so placeholders inside the values (a command's own braces) stay as they are
+        let mut rendered = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            rendered.push_str(&rest[..start]);
+            rest = &rest[start..];
+            match values.iter().find(|(name, _)| rest.starts_with(name)) {
+                Some((name, value)) => {
+                    rendered.push_str(&escape(value));
+                    rest = &rest[name.len()..];
+                }
+                None => {
+                    rendered.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        rendered.push_str(rest);
+        rendered
+    }
+}
+
+/// Whether `[notifications]` asks for a webhook after this run.
+pub fn wants_webhook(config: &NotificationsConfig, run: &Finished) -> bool {
+    config.webhook.is_some()
+        && ((run.exit_code != 0 && config.on_failure)
+            || config.min_duration.is_some_and(|secs| run.duration.as_secs() >= secs))
+}
+
+/// POST the `[notifications]` webhook for a finished run. `url` has had its `${VAR}`s
+/// substituted already.
+pub fn webhook(config: &NotificationsConfig, url: &str, run: &Finished) -> Result<()> {
+    let url = run.render(url, percent_encode);
+    let body = run.render(config.body.as_deref().unwrap_or(DEFAULT_BODY), json_escape);
+
+    let mut child = Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "--max-time", &WEBHOOK_TIMEOUT.to_string()])
+        .args(["--header", "Content-Type: application/json", "--data-binary", "@-", &url])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .context("Failed to run curl (needed for webhook notifications)")?;
+    child.stdin.take().expect("stdin is piped").write_all(body.as_bytes())?;
+    let status = child.wait().context("Failed to wait for curl")?;
+    if !status.success() {
+        anyhow::bail!("curl failed with exit code: {}", status.code().unwrap_or(1));
+    }
+    Ok(())
+}
+
+/// `text` as the inside of a JSON string.
+fn json_escape(text: &str) -> String {
+    let quoted = serde_json::to_string(text).expect("strings always serialize");
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+/// `text` safe to put anywhere in a URL.
+fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Show a desktop notification.
+pub fn desktop(title: &str, body: &str) -> Result<()> {
+    let (program, mut cmd) = if cfg!(target_os = "macos") {
+        let script = format!("display notification {} with title {}", applescript_string(body), applescript_string(title));
+        let mut cmd = Command::new("osascript");
+        cmd.args(["-e", &script]);
+        ("osascript", cmd)
+    } else if cfg!(windows) {
+        let mut cmd = Command::new("powershell");
+        cmd.args(["-NoProfile", "-NonInteractive", "-Command", &balloon_script(title, body)]);
+        ("powershell", cmd)
+    } else {
+        let mut cmd = Command::new("notify-send");
+        cmd.args(["--app-name=bridge", title, body]);
+        ("notify-send", cmd)
+    };
+
+    cmd.stdin(Stdio::null()).stdout(Stdio::null());
+    // The balloon has to stay up for a while; don't hold the command up meanwhile
+    if cfg!(windows) {
+        cmd.spawn().with_context(|| format!("Failed to run {} (needed for desktop notifications)", program))?;
+        return Ok(());
+    }
+
+    let status = cmd
+        .status()
+        .with_context(|| format!("Failed to run {} (needed for desktop notifications)", program))?;
+    if !status.success() {
+        anyhow::bail!("{} failed with exit code: {}", program, status.code().unwrap_or(1));
+    }
+    Ok(())
+}
+
+/// `text` as an AppleScript string literal.
+fn applescript_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// PowerShell that shows a tray balloon, which works without extra modules.
+fn balloon_script(title: &str, body: &str) -> String {
+    let quote = |text: &str| format!("'{}'", text.replace('\'', "''"));
+    format!(
+        "Add-Type -AssemblyName System.Windows.Forms; $n = New-Object System.Windows.Forms.NotifyIcon; \
+         $n.Icon = [System.Drawing.SystemIcons]::Information; $n.Visible = $true; \
+         $n.ShowBalloonTip(10000, {}, {}, 'Info'); Start-Sleep -Seconds 5; $n.Dispose()",
+        quote(title),
+        quote(body)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notification_text_is_quoted_for_each_platform() {
+        assert_eq!(applescript_string(r#"say "hi" \ bye"#), r#""say \"hi\" \\ bye""#);
+        assert!(balloon_script("dev: done", "it's built").contains("'dev: done', 'it''s built'"));
+    }
+
+    #[test]
+    fn webhooks_fill_in_escaped_placeholders() {
+        let run = Finished {
+            host: "gpu",
+            hostname: "gpu.lab",
+            command: r#"echo "{host}" && train"#,
+            exit_code: 2,
+            connection_lost: false,
+            duration: Duration::from_secs(252),
+            run_id: "r1",
+        };
+        assert_eq!(
+            run.render(DEFAULT_BODY, json_escape),
+            r#"{"text": "Run on gpu failed: `echo \"{host}\" && train` (exit code 2, took 4m 12s)"}"#
+        );
+        assert_eq!(
+            run.render("https://ci/{host}?cmd={command}", percent_encode),
+            "https://ci/gpu?cmd=echo%20%22%7Bhost%7D%22%20%26%26%20train"
+        );
+
+        let mut config = NotificationsConfig { webhook: Some("https://ci".to_string()), ..Default::default() };
+        assert!(wants_webhook(&config, &run));
+        config.on_failure = false;
+        assert!(!wants_webhook(&config, &run));
+        config.min_duration = Some(300);
+        assert!(!wants_webhook(&config, &run));
+        config.min_duration = Some(60);
+        assert!(wants_webhook(&config, &run));
+    }
+}
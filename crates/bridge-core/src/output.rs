@@ -0,0 +1,200 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// Set by `--quiet`: only errors and warnings are printed.
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Suppress status messages for the rest of the invocation.
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+/// Whether `--quiet` was given.
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// `eprintln!` for status messages ("Waiting for lock", "Reconnected"), which
+/// `--quiet` suppresses. Errors, warnings, and the output a command was run for
+/// are printed directly.
+#[macro_export]
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if !$crate::output::is_quiet() {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+/// Output format for commands that support machine-readable output.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// Timing and size of a single file transfer.
+#[derive(Debug, Serialize)]
+pub struct TransferReport {
+    /// "upload" or "download"
+    pub direction: &'static str,
+    pub host: String,
+    pub source: String,
+    pub dest: String,
+    pub bytes: u64,
+    pub duration_ms: u128,
+    /// Effective throughput in bytes per second
+    pub bytes_per_sec: u64,
+    /// Times the transfer was retried after losing the connection
+    pub retries: u32,
+    /// Whether the SHA-256 of the copy matches the original
+    pub checksum: ChecksumStatus,
+}
+
+/// Outcome of comparing a transferred file's SHA-256 on both ends.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChecksumStatus {
+    Match,
+    Mismatch,
+    /// Not compared: a directory, a stream, or a host without a hashing tool
+    Skipped,
+}
+
+impl TransferReport {
+    pub fn new(
+        direction: &'static str,
+        host: &str,
+        source: &str,
+        dest: &str,
+        bytes: u64,
+        duration: Duration,
+        retries: u32,
+    ) -> Self {
+        let secs = duration.as_secs_f64();
+        let bytes_per_sec = if secs > 0.0 { (bytes as f64 / secs) as u64 } else { bytes };
+
+        TransferReport {
+            direction,
+            host: host.to_string(),
+            source: source.to_string(),
+            dest: dest.to_string(),
+            bytes,
+            duration_ms: duration.as_millis(),
+            bytes_per_sec,
+            retries,
+            checksum: ChecksumStatus::Skipped,
+        }
+    }
+
+    pub fn with_checksum(self, checksum: ChecksumStatus) -> Self {
+        TransferReport { checksum, ..self }
+    }
+}
+
+/// Print transfer reports as a JSON document on stdout.
+pub fn print_transfers(reports: &[TransferReport]) -> Result<()> {
+    #[derive(Serialize)]
+    struct Transfers<'a> {
+        transfers: &'a [TransferReport],
+    }
+
+    println!("{}", serde_json::to_string_pretty(&Transfers { transfers: reports })?);
+    Ok(())
+}
+
+/// Total size in bytes of a file or directory tree. Unreadable entries count as zero.
+pub fn path_size(path: &Path) -> u64 {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return 0;
+    };
+
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+
+    fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| path_size(&entry.path()))
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/// A byte count for people: `512 B`, `3.4 MiB`, `1.2 GiB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// A duration for people, to the second: `45s`, `4m 12s`, `2h 5m`.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m {}s", secs / 60, secs % 60),
+        _ => format!("{}h {}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn path_size_sums_directory_tree() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("a.bin"), [0u8; 10]).unwrap();
+        fs::create_dir(dir.path().join("nested")).unwrap();
+        fs::write(dir.path().join("nested/b.bin"), [0u8; 5]).unwrap();
+
+        assert_eq!(path_size(&dir.path().join("a.bin")), 10);
+        assert_eq!(path_size(dir.path()), 15);
+        assert_eq!(path_size(&dir.path().join("missing")), 0);
+    }
+
+    #[test]
+    fn sizes_format_in_binary_units() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(3 * 1024 * 1024 + 400 * 1024), "3.4 MiB");
+        assert_eq!(format_size(5 * 1024 * 1024 * 1024), "5.0 GiB");
+    }
+
+    #[test]
+    fn durations_format_to_the_second() {
+        assert_eq!(format_duration(Duration::from_millis(45_900)), "45s");
+        assert_eq!(format_duration(Duration::from_secs(252)), "4m 12s");
+        assert_eq!(format_duration(Duration::from_secs(7500)), "2h 5m");
+    }
+
+    #[test]
+    fn transfer_report_computes_throughput() {
+        let report = TransferReport::new("upload", "dev", "a", "b", 2000, Duration::from_secs(2), 1);
+        assert_eq!(report.bytes_per_sec, 1000);
+        assert_eq!(report.duration_ms, 2000);
+        assert_eq!(report.retries, 1);
+        assert_eq!(report.checksum, ChecksumStatus::Skipped);
+
+        let json = serde_json::to_value(report.with_checksum(ChecksumStatus::Mismatch)).unwrap();
+        assert_eq!(json["checksum"], "mismatch");
+    }
+}
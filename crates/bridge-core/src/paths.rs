@@ -0,0 +1,147 @@
+//! Paths on the remote host. They follow the remote's conventions rather than the
+//! local machine's: Windows hosts have drive letters and accept either separator,
+//! WSL sees Windows drives under `/mnt`, and everything else is a Unix path.
+//!
+//! Bridge builds remote paths with `/`, which Windows accepts too, and converts only
+//! where a tool needs another form: cmd wants backslashes (see [`crate::ssh::quote_path`]),
+//! rsync on Windows wants Cygwin paths, and Windows' SFTP server spells drives `/C:/`.
+
+use crate::config::Shell;
+
+/// The drive letter and the rest of a Windows drive path (`C:`, `C:/src`, `C:\src`).
+fn drive(path: &str) -> Option<(char, &str)> {
+    let mut chars = path.chars();
+    match (chars.next(), chars.next()) {
+        (Some(letter), Some(':')) if letter.is_ascii_alphabetic() => Some((letter, &path[2..])),
+        _ => None,
+    }
+}
+
+/// Whether a remote path stands on its own: rooted (with either separator),
+/// home-relative, or drive-qualified.
+pub fn is_absolute(path: &str) -> bool {
+    path.starts_with(['/', '\\', '~']) || drive(path).is_some()
+}
+
+/// Resolve a user-supplied remote path against the remote project root.
+/// Absolute, home-relative, and drive-qualified paths are used as-is.
+pub fn resolve(root: &str, path: &str) -> String {
+    if is_absolute(path) {
+        path.to_string()
+    } else {
+        join(root, path)
+    }
+}
+
+/// `path` under `base`, with a single `/` between them however `base` ends.
+pub fn join(base: &str, path: &str) -> String {
+    format!("{}/{}", base.trim_end_matches(['/', '\\']), path)
+}
+
+/// Last component of a remote path, accepting either separator.
+pub fn file_name(path: &str) -> &str {
+    let trimmed = path.trim_end_matches(['/', '\\']);
+    trimmed.rsplit(['/', '\\']).next().unwrap_or(trimmed)
+}
+
+/// The directory containing a remote path, accepting either separator. `None` for
+/// a bare name.
+pub fn parent(path: &str) -> Option<&str> {
+    let (dir, _) = path.rsplit_once(['/', '\\'])?;
+    Some(if dir.is_empty() { &path[..1] } else { dir })
+}
+
+/// `path` relative to `root`, with `/` separators, if it is inside it. Paths that
+/// climb out with `..` don't count.
+pub fn relative_to(root: &str, path: &str) -> Option<String> {
+    let root = root.replace('\\', "/");
+    let path = path.replace('\\', "/");
+    path.strip_prefix(root.trim_end_matches('/'))
+        .and_then(|rest| rest.strip_prefix('/'))
+        .filter(|rest| !rest.is_empty() && !rest.split('/').any(|part| part == ".."))
+        .map(|rest| rest.trim_start_matches("./").to_string())
+}
+
+/// A Windows drive path (`C:/src` or `C:\src`) as WSL mounts it (`/mnt/c/src`).
+/// Other paths are returned unchanged.
+pub fn wsl_path(path: &str) -> String {
+    match drive(path) {
+        Some((letter, rest)) => {
+            format!("/mnt/{}{}", letter.to_ascii_lowercase(), rest.replace('\\', "/").trim_end_matches('/'))
+        }
+        None => path.to_string(),
+    }
+}
+
+/// A WSL drive path (`/mnt/c/src`) as Windows sees it (`C:/src`). Other paths are
+/// returned unchanged.
+pub fn windows_path(path: &str) -> String {
+    let Some(rest) = path.strip_prefix("/mnt/") else {
+        return path.to_string();
+    };
+    let mut chars = rest.chars();
+    match (chars.next(), chars.as_str()) {
+        (Some(letter), tail) if letter.is_ascii_alphabetic() && (tail.is_empty() || tail.starts_with('/')) => {
+            format!("{}:{}", letter.to_ascii_uppercase(), if tail.is_empty() { "/" } else { tail })
+        }
+        _ => path.to_string(),
+    }
+}
+
+/// A Windows drive path (`C:/foo` or `C:\foo`) in Cygwin form (`/cygdrive/c/foo`),
+/// as rsync on Windows expects. Other paths are returned unchanged.
+pub fn cygwin_path(path: &str) -> String {
+    match drive(path) {
+        Some((letter, rest)) => format!("/cygdrive/{}{}", letter.to_ascii_lowercase(), rest.replace('\\', "/")),
+        None => path.to_string(),
+    }
+}
+
+/// A path as the host's SFTP server expects it. Windows OpenSSH spells drive paths
+/// `/C:/...`; WSL paths should already be converted with [`windows_path`].
+pub fn sftp_path(shell: &Shell, path: &str) -> String {
+    match shell {
+        Shell::Powershell | Shell::Cmd | Shell::Wsl if !path.starts_with('/') => format!("/{}", path.replace('\\', "/")),
+        _ => path.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_keeps_absolute_paths() {
+        assert_eq!(resolve("/remote/project", "out.log"), "/remote/project/out.log");
+        assert_eq!(resolve("/remote/project/", "logs/out.log"), "/remote/project/logs/out.log");
+        assert_eq!(resolve("/remote/project", "/tmp/out.log"), "/tmp/out.log");
+        assert_eq!(resolve("/remote/project", "~/out.log"), "~/out.log");
+        assert_eq!(resolve("/remote/project", "C:/out.log"), "C:/out.log");
+        assert_eq!(resolve(r"C:\dev\app\", "bin"), r"C:\dev\app/bin");
+    }
+
+    #[test]
+    fn windows_drive_paths_convert_between_forms() {
+        assert_eq!(wsl_path(r"D:\work\app"), "/mnt/d/work/app");
+        assert_eq!(wsl_path("C:/"), "/mnt/c");
+        assert_eq!(wsl_path("/home/me/app"), "/home/me/app");
+        assert_eq!(windows_path("/mnt/c/Users/me/app"), "C:/Users/me/app");
+        assert_eq!(windows_path("/mnt/d"), "D:/");
+        assert_eq!(windows_path("/mnt/data/x"), "/mnt/data/x");
+        assert_eq!(cygwin_path(r"C:\dev\app"), "/cygdrive/c/dev/app");
+        assert_eq!(sftp_path(&Shell::Powershell, r"C:\dev\app"), "/C:/dev/app");
+        assert_eq!(sftp_path(&Shell::Bash, "/srv/app"), "/srv/app");
+    }
+
+    #[test]
+    fn names_and_relative_paths_accept_either_separator() {
+        assert_eq!(file_name("/srv/app/dist/"), "dist");
+        assert_eq!(file_name(r"C:\dev\app.bin"), "app.bin");
+        assert_eq!(parent(r"C:\dev\app.bin"), Some(r"C:\dev"));
+        assert_eq!(parent("/app.bin"), Some("/"));
+        assert_eq!(parent("app.bin"), None);
+        assert_eq!(relative_to(r"C:\dev\app", "C:/dev/app/./bin/x"), Some("bin/x".to_string()));
+        assert_eq!(relative_to("/srv/app", "/srv/app/../etc"), None);
+        assert_eq!(relative_to("/srv/app", "/srv/application"), None);
+    }
+}
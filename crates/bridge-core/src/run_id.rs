@@ -0,0 +1,69 @@
+use anyhow::Result;
+use std::path::Path;
+
+use crate::state;
+
+/// Placeholder substituted with the run ID in commands, wrappers, and remote paths.
+pub const PLACEHOLDER: &str = "${BRIDGE_RUN_ID}";
+
+/// Environment variable name behind [`PLACEHOLDER`].
+pub const VAR: &str = "BRIDGE_RUN_ID";
+
+/// Generate a run ID unique to this invocation: `<unix time>-<pid>`.
+pub fn generate() -> String {
+    format!("{}-{}", state::now_secs(), std::process::id())
+}
+
+/// Run IDs end up in local and remote paths, so keep them to path-safe characters.
+pub fn validate(run_id: &str) -> Result<()> {
+    let valid = !run_id.is_empty()
+        && !run_id.starts_with('.')
+        && run_id.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c));
+    if !valid {
+        anyhow::bail!("Invalid run ID '{}': use letters, digits, '-', '_' and '.'", run_id);
+    }
+    Ok(())
+}
+
+/// The run ID for commands that act on a previous run's outputs: the explicit
+/// `--run-id`, or else the ID of the project's last recorded run.
+pub fn resolve(explicit: Option<&str>, project_root: &Path) -> Result<Option<String>> {
+    match explicit {
+        Some(id) => {
+            validate(id)?;
+            Ok(Some(id.to_string()))
+        }
+        None => Ok(state::load(project_root).last_run.and_then(|r| r.run_id)),
+    }
+}
+
+/// Replace `${BRIDGE_RUN_ID}` in a path, failing if it is used without a run ID.
+pub fn substitute(path: &str, run_id: Option<&str>) -> Result<String> {
+    if !path.contains(PLACEHOLDER) {
+        return Ok(path.to_string());
+    }
+    match run_id {
+        Some(id) => Ok(path.replace(PLACEHOLDER, id)),
+        None => anyhow::bail!("{} is used in '{}' but no run ID is known (pass --run-id)", PLACEHOLDER, path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn substitute_replaces_placeholder() {
+        assert_eq!(substitute("out/${BRIDGE_RUN_ID}/model.bin", Some("exp-1")).unwrap(), "out/exp-1/model.bin");
+        assert_eq!(substitute("out/model.bin", None).unwrap(), "out/model.bin");
+        assert!(substitute("out/${BRIDGE_RUN_ID}", None).is_err());
+    }
+
+    #[test]
+    fn validate_rejects_path_separators() {
+        assert!(validate("lr-0.01_seed.3").is_ok());
+        assert!(validate("../etc").is_err());
+        assert!(validate("a/b").is_err());
+        assert!(validate("").is_err());
+    }
+}
@@ -0,0 +1,74 @@
+//! The async runtime shared by everything bridge does concurrently: probing several
+//! hosts, checking them, holding master connections, watch mode, and `bridge serve`.
+//! Work that waits on more than one process or timer at once runs as tasks on this
+//! runtime and is cancelled by dropping it; child processes are spawned with
+//! `kill_on_drop`, so cancelling a task never leaves its process behind.
+//!
+//! A single blocking operation (one ssh or rsync call) stays a plain
+//! `std::process` call. Such calls may be made from inside a task: [`block_on`]
+//! notices it is already on the runtime and lets the worker block in place.
+
+use std::future::Future;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::process::Child;
+use tokio::runtime::{Builder, Handle, Runtime};
+
+/// How long a stopped process gets to exit before it is killed.
+pub const STOP_GRACE: Duration = Duration::from_secs(2);
+
+fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        Builder::new_multi_thread()
+            .thread_name("bridge")
+            .enable_all()
+            .build()
+            .expect("Failed to start the async runtime")
+    })
+}
+
+/// Run `future` to completion on the shared runtime. Safe to call from code that
+/// is itself running on the runtime.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    match Handle::try_current() {
+        Ok(handle) => tokio::task::block_in_place(|| handle.block_on(future)),
+        Err(_) => runtime().block_on(future),
+    }
+}
+
+/// Stop a child process: ask it to exit first (SIGTERM on Unix, so `sshpass` can pass
+/// the signal on to ssh), and kill it if it is still running after [`STOP_GRACE`].
+pub async fn stop(child: &mut Child) {
+    if cfg!(unix) {
+        if let Some(pid) = child.id() {
+            let _ = tokio::process::Command::new("kill").arg(pid.to_string()).status().await;
+            if tokio::time::timeout(STOP_GRACE, child.wait()).await.is_ok() {
+                return;
+            }
+        }
+    }
+    let _ = child.kill().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_on_nests_inside_tasks() {
+        let answer = block_on(async { tokio::spawn(async { block_on(async { 42 }) }).await.unwrap() });
+        assert_eq!(answer, 42);
+        assert_eq!(block_on(async { block_on(async { 7 }) }), 7);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stop_ends_the_process() {
+        block_on(async {
+            let mut child = tokio::process::Command::new("sleep").arg("30").spawn().unwrap();
+            stop(&mut child).await;
+            assert!(child.try_wait().unwrap().is_some());
+        });
+    }
+}
@@ -0,0 +1,2554 @@
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use std::collections::HashMap;
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn, Level};
+
+use crate::audit;
+use crate::config::{AddressFamily, Host, HostKeyChecking, KubernetesTarget, ReconnectBackoff, Shell};
+use crate::env_subst::substitute_env_vars;
+use crate::manifest;
+use crate::output;
+use crate::paths;
+use crate::runtime;
+use crate::state;
+use crate::status;
+
+/// SSH client options derived from per-host settings.
+/// Shared by ssh, scp, and the ssh transport used by rsync. While `bridge daemon`
+/// holds a master connection to the host, they also route through its socket.
+fn ssh_options(host: &Host) -> Vec<String> {
+    let mut args = connection_options(host);
+    // ssh's own notices, such as "Permanently added ... to the list of known hosts"
+    if output::is_quiet() {
+        args.extend(["-o".to_string(), "LogLevel=ERROR".to_string()]);
+    }
+    if let Some(socket) = live_control_socket(host) {
+        args.extend(["-o".to_string(), format!("ControlPath={}", socket.display())]);
+    }
+    args
+}
+
+/// Options that decide where and how to connect (the host's login settings).
+fn connection_options(host: &Host) -> Vec<String> {
+    let mut args = Vec::new();
+
+    match host.address_family {
+        AddressFamily::Any => {}
+        AddressFamily::Inet => args.extend(["-o".to_string(), "AddressFamily=inet".to_string()]),
+        AddressFamily::Inet6 => args.extend(["-o".to_string(), "AddressFamily=inet6".to_string()]),
+    }
+
+    // Connect to a fixed address while keeping the alias for ~/.ssh/config and known_hosts
+    if let Some(ref address) = host.resolve {
+        args.extend(["-o".to_string(), format!("HostName={}", address)]);
+    }
+
+    // Spelled as -o options so the same arguments work for ssh, scp (which uses -P
+    // for the port), and rsync's remote shell
+    if let Some(ref user) = host.user {
+        args.extend(["-o".to_string(), format!("User={}", user)]);
+    }
+    if let Some(port) = host.port {
+        args.extend(["-o".to_string(), format!("Port={}", port)]);
+    }
+    if let Some(ref identity_file) = host.identity_file {
+        args.extend(["-o".to_string(), format!("IdentityFile={}", identity_file)]);
+    }
+    if let Some(ref jump) = host.proxy_jump {
+        args.extend(["-o".to_string(), format!("ProxyJump={}", jump)]);
+    }
+    // ssh keeps the first value it's given for an option, so these override the
+    // defaults callers add after them
+    if let Some(secs) = host.connect_timeout {
+        args.extend(["-o".to_string(), format!("ConnectTimeout={}", secs)]);
+    }
+    if let Some(secs) = host.server_alive_interval {
+        args.extend(["-o".to_string(), format!("ServerAliveInterval={}", secs)]);
+    }
+    if let Some(count) = host.server_alive_count {
+        args.extend(["-o".to_string(), format!("ServerAliveCountMax={}", count)]);
+    }
+    match host.host_key_checking {
+        None => {}
+        Some(HostKeyChecking::Strict) => args.extend(["-o".to_string(), "StrictHostKeyChecking=yes".to_string()]),
+        Some(HostKeyChecking::AcceptNew) => {
+            args.extend(["-o".to_string(), "StrictHostKeyChecking=accept-new".to_string()])
+        }
+        // Without a known_hosts file, a changed key can't be detected (or recorded)
+        Some(HostKeyChecking::Off) => args.extend([
+            "-o".to_string(),
+            "StrictHostKeyChecking=no".to_string(),
+            "-o".to_string(),
+            "UserKnownHostsFile=/dev/null".to_string(),
+            "-o".to_string(),
+            "LogLevel=ERROR".to_string(),
+        ]),
+    }
+
+    args.extend(host.ssh_options.iter().cloned());
+
+    args
+}
+
+/// Control socket for a master connection to `host` (`<state dir>/control/<key>`).
+/// Hosts with identical connection settings share one, whatever project they're in.
+pub fn control_socket(host: &Host) -> Option<PathBuf> {
+    let dir = state::state_dir()?.join("control");
+    Some(dir.join(format!("{:016x}", control_key(host))))
+}
+
+/// The control socket, if a master connection is currently listening on it.
+fn live_control_socket(host: &Host) -> Option<PathBuf> {
+    if cfg!(windows) {
+        // The Windows OpenSSH client doesn't support connection multiplexing
+        return None;
+    }
+    control_socket(host).filter(|socket| socket.exists())
+}
+
+/// Hash of the hostname and connection options, stable across builds.
+fn control_key(host: &Host) -> u64 {
+    let identity = std::iter::once(host.hostname.clone())
+        .chain(connection_options(host))
+        .collect::<Vec<_>>()
+        .join("\0");
+    state::fnv1a(identity.as_bytes())
+}
+
+/// Start a master connection to `host` that runs until it is told to exit or the
+/// connection drops. Other invocations reuse it through [`control_socket`].
+pub fn start_master(host: &Host) -> Result<Child> {
+    master_process(host)?.spawn().context("Failed to spawn SSH master connection")
+}
+
+/// The ssh invocation behind [`start_master`], for callers that spawn it themselves.
+pub fn master_process(host: &Host) -> Result<Command> {
+    let socket = control_socket(host).context("Cannot determine state directory (HOME is not set)")?;
+    let dir = socket.parent().context("Control socket has no parent directory")?;
+    std::fs::create_dir_all(dir).with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+
+    // A socket left behind by a master that was killed would block the new one
+    if socket.exists() && !master_command(host, "check") {
+        let _ = std::fs::remove_file(&socket);
+    }
+
+    let mut cmd = transport(host, "ssh")?;
+    cmd.args(["-M", "-N", "-o", "ControlPersist=no"])
+        .args(unattended_options(host))
+        .arg("-S")
+        .arg(&socket)
+        .args(connection_options(host))
+        .args(["-o", "ServerAliveInterval=15", "-o", "ServerAliveCountMax=3", "-o", "ConnectTimeout=10"])
+        .arg(&host.hostname)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::inherit());
+    Ok(cmd)
+}
+
+/// How long [`share_connection`] waits for its master connection to log in.
+const SHARED_CONNECTION_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// A master connection held for part of one invocation, so the ssh, scp, and rsync
+/// calls made meanwhile share a single login. Closed when dropped.
+pub struct SharedConnection {
+    host: Host,
+    master: Child,
+}
+
+impl Drop for SharedConnection {
+    fn drop(&mut self) {
+        if !master_command(&self.host, "exit") {
+            let _ = self.master.kill();
+        }
+        let _ = self.master.wait();
+    }
+}
+
+/// Open a [`SharedConnection`] to `host`, unless a master connection (such as
+/// `bridge daemon`'s) is already up or the host doesn't go over SSH. `None` only
+/// means each call logs in separately, as it otherwise would.
+pub fn share_connection(host: &Host) -> Option<SharedConnection> {
+    // The Windows OpenSSH client can't multiplex
+    if cfg!(windows) || host.kubernetes.is_some() {
+        return None;
+    }
+    // A socket left behind by a master that died is replaced by start_master
+    if live_control_socket(host).is_some() && master_command(host, "check") {
+        return None;
+    }
+    let socket = control_socket(host)?;
+    let mut master = start_master(host).ok()?;
+    let start = Instant::now();
+    while !socket.exists() {
+        if start.elapsed() > SHARED_CONNECTION_TIMEOUT || master.try_wait().ok().flatten().is_some() {
+            let _ = master.kill();
+            let _ = master.wait();
+            info!("Could not open a shared connection to {}; connecting separately", host.hostname);
+            return None;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    info!("Sharing one connection to {}", host.hostname);
+    Some(SharedConnection { host: host.clone(), master })
+}
+
+/// Send a control command (`check` or `exit`) to the host's master connection.
+/// Returns whether it succeeded, i.e. whether a master was there to answer.
+pub fn master_command(host: &Host, command: &str) -> bool {
+    let Some(socket) = control_socket(host).filter(|s| s.exists()) else {
+        return false;
+    };
+    Command::new("ssh")
+        .args(["-O", command, "-S"])
+        .arg(&socket)
+        .args(connection_options(host))
+        .arg(&host.hostname)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+/// Start an ssh, scp, rsync, or mosh invocation for `host`. Hosts with
+/// `password_auth` run it under `sshpass`, which answers the password prompt,
+/// unless a master connection is already logged in.
+fn transport(host: &Host, program: &str) -> Result<Command> {
+    if !uses_sshpass(host) {
+        return Ok(Command::new(program));
+    }
+
+    let mut cmd = Command::new("sshpass");
+    cmd.args(["-e", program]).env("SSHPASS", password_for(host)?);
+    Ok(cmd)
+}
+
+/// Whether [`transport`] runs programs for `host` under sshpass.
+fn uses_sshpass(host: &Host) -> bool {
+    host.password_auth && live_control_socket(host).is_none()
+}
+
+/// Options for connections that must never stop to prompt. Password hosts can't
+/// use BatchMode (it disables password auth), so they get a single attempt instead.
+fn unattended_options(host: &Host) -> [&'static str; 2] {
+    if host.password_auth {
+        ["-o", "NumberOfPasswordPrompts=1"]
+    } else {
+        ["-o", "BatchMode=yes"]
+    }
+}
+
+/// The SSH password for a `password_auth` host: `$SSH_PASSWORD`, or asked for once
+/// per hostname and kept in memory for the rest of the invocation.
+fn password_for(host: &Host) -> Result<String> {
+    static PASSWORDS: Mutex<Option<HashMap<String, String>>> = Mutex::new(None);
+
+    if let Some(password) = env::var("SSH_PASSWORD").ok().filter(|p| !p.is_empty()) {
+        return Ok(password);
+    }
+
+    // Held across the prompt so concurrent checks don't ask twice
+    let mut passwords = PASSWORDS.lock().unwrap_or_else(|e| e.into_inner());
+    let passwords = passwords.get_or_insert_with(HashMap::new);
+    if let Some(password) = passwords.get(&host.hostname) {
+        return Ok(password.clone());
+    }
+
+    let has_sshpass = Command::new("sshpass")
+        .arg("-V")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success());
+    if !has_sshpass {
+        anyhow::bail!("password_auth requires sshpass, which was not found in PATH");
+    }
+
+    let password = rpassword::prompt_password(format!("Password for {}: ", host.hostname))
+        .context("Failed to read password (set SSH_PASSWORD when not running in a terminal)")?;
+    passwords.insert(host.hostname.clone(), password.clone());
+    Ok(password)
+}
+
+/// Run a command on a remote host via SSH, streaming output in real-time.
+/// Changes to the remote path and uses the configured shell to execute the command.
+///
+/// Processing order:
+/// 1. Substitute local environment variables in command
+/// 2. Substitute local environment variables in wrapper (if present)
+/// 3. Apply wrapper template (command replaces {} placeholder)
+/// 4. Prefix with statements exporting `exports` to the remote environment
+/// 5. Wrap with shell-specific cd to remote path (base64-encoded with `encode_command`),
+///    or with `docker exec` into the host's `container`
+/// 6. Execute via SSH
+///
+/// ssh exits with 255 when the connection fails, and so may the command; [`Exit`]
+/// tells the two apart by what ssh printed last.
+pub fn run_remote_command(
+    host: &Host,
+    remote_path: &str,
+    command: &str,
+    env_vars: &HashMap<String, String>,
+    exports: &[(String, String)],
+    interactive: bool,
+) -> Result<Exit> {
+    let full_cmd = prepare_remote_command(host, remote_path, command, env_vars, exports, interactive)?;
+
+    info!("Running: ssh {} {}", host.hostname, full_cmd);
+
+    // Step 6: Execute
+    let exit = wait_remote(host, remote_command(host, &full_cmd, interactive.then_some("-t"))?)?;
+    debug!(host = %host.hostname, ?exit, "Remote command finished");
+    Ok(exit)
+}
+
+/// How a remote command ended.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Exit {
+    /// The command exited with this code, which may be 255 like ssh's own failures
+    Code(i32),
+    /// ssh couldn't connect, or lost the connection before the command finished
+    ConnectionLost,
+}
+
+impl Exit {
+    /// The exit code to pass on: the command's, or 255 for a lost connection as ssh
+    /// itself would.
+    pub fn code(self) -> i32 {
+        match self {
+            Exit::Code(code) => code,
+            Exit::ConnectionLost => 255,
+        }
+    }
+}
+
+/// How the lines ssh prints when it fails to connect or loses the connection start.
+/// A remote command that exits with 255 doesn't print these itself, so they tell the
+/// two apart; matching the start of the line keeps out the command's own messages,
+/// such as `curl: (7) ... Connection refused`.
+const CONNECTION_LOST_PREFIXES: [&str; 6] = [
+    "ssh: connect to host ",
+    "ssh: Could not resolve hostname ",
+    "client_loop: ",
+    "packet_write_wait: ",
+    "kex_exchange_identification: ",
+    "Timeout, server ",
+];
+
+/// Bytes of ssh's stderr kept for [`connection_lost`]; its messages come last.
+const STDERR_TAIL: usize = 2048;
+
+/// Run an ssh (or kubectl) invocation with stdout inherited and stderr passed through
+/// as it arrives, and tell a lost connection from the command's own exit code.
+fn wait_remote(host: &Host, mut cmd: Command) -> Result<Exit> {
+    let mut child = cmd
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to spawn SSH process")?;
+
+    let mut stderr = child.stderr.take().context("Failed to get SSH stderr")?;
+    let relay = thread::spawn(move || {
+        let mut tail = Vec::new();
+        let mut buf = [0u8; 4096];
+        while let Ok(n @ 1..) = io::Read::read(&mut stderr, &mut buf) {
+            let mut out = io::stderr().lock();
+            let _ = out.write_all(&buf[..n]).and_then(|()| out.flush());
+            tail.extend_from_slice(&buf[..n]);
+            tail.drain(..tail.len().saturating_sub(STDERR_TAIL));
+        }
+        tail
+    });
+
+    let status = child.wait().context("Failed to wait for SSH process")?;
+    let tail = relay.join().unwrap_or_default();
+
+    Ok(match status.code() {
+        // kubectl exec passes the command's exit code on, and has no code of its own
+        Some(255) if host.kubernetes.is_none() && connection_lost(&String::from_utf8_lossy(&tail)) => {
+            Exit::ConnectionLost
+        }
+        code => Exit::Code(code.unwrap_or(1)),
+    })
+}
+
+/// Whether ssh's stderr ends with one of its connection failure messages.
+fn connection_lost(stderr: &str) -> bool {
+    // Only ssh's last lines count, not something the command printed earlier
+    let last_lines: Vec<&str> = stderr.lines().rev().map(str::trim).filter(|line| !line.is_empty()).take(2).collect();
+    last_lines.iter().any(|line| {
+        CONNECTION_LOST_PREFIXES.iter().any(|prefix| line.starts_with(prefix))
+            || (line.starts_with("Connection to ") && line.ends_with(" closed by remote host."))
+            || ((line.starts_with("Connection closed by ") || line.starts_with("Connection reset by "))
+                && line.contains(" port "))
+            || is_host_key_failure(line)
+    })
+}
+
+/// Start `command` like [`run_remote_command`] without waiting for it. The remote
+/// side gets a terminal of its own (stdin is not forwarded), so it is hung up on,
+/// and exits, when the returned ssh process is stopped. Must be called on the
+/// [`runtime`]; the process is killed if the handle is dropped.
+pub fn spawn_remote_command(
+    host: &Host,
+    remote_path: &str,
+    command: &str,
+    env_vars: &HashMap<String, String>,
+    exports: &[(String, String)],
+) -> Result<tokio::process::Child> {
+    let full_cmd = prepare_remote_command(host, remote_path, command, env_vars, exports, true)?;
+
+    info!("Running: ssh {} {}", host.hostname, full_cmd);
+
+    let mut cmd = tokio::process::Command::from(remote_command(host, &full_cmd, Some("-tt"))?);
+    cmd.stdin(Stdio::null())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .kill_on_drop(true)
+        .spawn()
+        .context("Failed to spawn SSH process")
+}
+
+/// The ssh invocation that runs a prepared command line on the host, with an
+/// optional terminal allocation flag (`-t` or `-tt`).
+fn remote_command(host: &Host, full_cmd: &str, tty: Option<&str>) -> Result<Command> {
+    let invocation = remote_invocation(host, full_cmd, tty);
+    if host.kubernetes.is_some() {
+        audit::record(host, "kubectl", full_cmd)?;
+        return Ok(invocation);
+    }
+
+    audit::record(host, "ssh", &login_command(host, full_cmd))?;
+    let mut cmd = transport(host, "ssh")?;
+    cmd.args(invocation.get_args());
+    Ok(cmd)
+}
+
+/// [`remote_command`] without sshpass or the audit log, so it can be shown as well as run.
+fn remote_invocation(host: &Host, full_cmd: &str, tty: Option<&str>) -> Command {
+    if let Some(ref target) = host.kubernetes {
+        let mut cmd = kubectl(target);
+        cmd.args(kubectl_exec_args(host, target, full_cmd, tty.is_some()));
+        return cmd;
+    }
+
+    // Keepalive settings ensure SSH detects dead connections quickly (~15s by
+    // default, or as the host's server_alive_* say) rather than waiting for TCP
+    // timeout (can be minutes).
+    let mut cmd = Command::new("ssh");
+    if let Some(flag) = tty {
+        cmd.arg(flag);
+    }
+    if host.forward_agent {
+        cmd.arg("-A");
+    }
+    if host.forward_x11 {
+        cmd.arg(if host.x11_trusted { "-Y" } else { "-X" });
+    }
+    cmd.args(ssh_options(host))
+        .args(["-o", "ServerAliveInterval=5", "-o", "ServerAliveCountMax=3"])
+        .arg(&host.hostname)
+        .arg(login_command(host, full_cmd));
+    cmd
+}
+
+/// The invocation that runs a command line on the host: ssh, or `kubectl exec` for
+/// Kubernetes hosts. Stdin is forwarded either way.
+fn remote_shell(host: &Host, command: &str) -> Result<Command> {
+    let invocation = remote_shell_invocation(host, command);
+    if host.kubernetes.is_some() {
+        audit::record(host, "kubectl", command)?;
+        return Ok(invocation);
+    }
+
+    audit::record(host, "ssh", &login_command(host, command))?;
+    let mut cmd = transport(host, "ssh")?;
+    cmd.args(invocation.get_args());
+    Ok(cmd)
+}
+
+/// [`remote_shell`] without sshpass or the audit log, so it can be shown as well as run.
+fn remote_shell_invocation(host: &Host, command: &str) -> Command {
+    if let Some(ref target) = host.kubernetes {
+        let mut cmd = kubectl(target);
+        cmd.args(kubectl_exec_args(host, target, command, false));
+        return cmd;
+    }
+
+    let mut cmd = Command::new("ssh");
+    cmd.args(ssh_options(host)).arg(&host.hostname).arg(login_command(host, command));
+    cmd
+}
+
+/// A non-interactive invocation for checks, which gives up connecting after
+/// `timeout` seconds and never stops to prompt. Without a `command`, it only checks
+/// that the host can be reached.
+fn probe_command(host: &Host, timeout: u64, command: Option<&str>) -> Result<Command> {
+    if let Some(ref target) = host.kubernetes {
+        if let Some(command) = command {
+            audit::record(host, "kubectl", command)?;
+        }
+        let mut cmd = kubectl(target);
+        cmd.arg(format!("--request-timeout={}s", host.connect_timeout.unwrap_or(timeout)))
+            .args(kubectl_exec_args(host, target, command.unwrap_or("exit 0"), false));
+        return Ok(cmd);
+    }
+
+    // Reachability checks send only `exit 0`, which isn't worth logging
+    let login = match command {
+        Some(command) => {
+            let login = login_command(host, command);
+            audit::record(host, "ssh", &login)?;
+            login
+        }
+        None => "exit 0".to_string(),
+    };
+    let mut cmd = transport(host, "ssh")?;
+    cmd.args(unattended_options(host))
+        .args(ssh_options(host))
+        .args(["-o", &format!("ConnectTimeout={}", timeout)])
+        .arg(&host.hostname)
+        .arg(login);
+    Ok(cmd)
+}
+
+/// `kubectl`, pointed at the target's context and namespace.
+fn kubectl(target: &KubernetesTarget) -> Command {
+    let mut cmd = Command::new("kubectl");
+    if let Some(ref context) = target.context {
+        cmd.args(["--context", context]);
+    }
+    if let Some(ref namespace) = target.namespace {
+        cmd.args(["-n", namespace]);
+    }
+    cmd
+}
+
+/// `kubectl exec` arguments running `command` with the host's shell in the pod.
+/// Like ssh, stdin is always forwarded; `tty` also allocates a terminal.
+fn kubectl_exec_args(host: &Host, target: &KubernetesTarget, command: &str, tty: bool) -> Vec<String> {
+    let mut args = vec!["exec".to_string(), if tty { "-it" } else { "-i" }.to_string(), target.pod.clone()];
+    if let Some(ref container) = target.container {
+        args.extend(["-c".to_string(), container.clone()]);
+    }
+    args.extend(["--".to_string(), host.shell.program().to_string(), "-c".to_string(), command.to_string()]);
+    args
+}
+
+/// Copy between the local machine and a Kubernetes host with `kubectl cp`, which needs
+/// tar in the pod. `upload` copies `local_path` to `remote_path`; otherwise the reverse.
+fn kubectl_cp(
+    host: &Host,
+    target: &KubernetesTarget,
+    local_path: &str,
+    remote_path: &str,
+    upload: bool,
+    dry_run: bool,
+) -> Result<()> {
+    let remote = format!("{}:{}", target.pod, remote_path);
+    let (from, to) = if upload { (local_path, remote.as_str()) } else { (remote.as_str(), local_path) };
+
+    if dry_run {
+        eprintln!("Would copy {} to {} ({})", from, to, host.hostname);
+        return Ok(());
+    }
+
+    info!("Running: kubectl cp {} {}", from, to);
+
+    let mut cmd = kubectl(target);
+    cmd.args(["cp", from, to]);
+    if let Some(ref container) = target.container {
+        cmd.args(["-c", container]);
+    }
+    let status = cmd
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .status()
+        .context("Failed to run kubectl")?;
+
+    if !status.success() {
+        anyhow::bail!("kubectl cp failed with exit code: {}", status.code().unwrap_or(1));
+    }
+
+    Ok(())
+}
+
+/// Fails for Kubernetes hosts, which `program` can't reach since they have no SSH.
+fn require_ssh(host: &Host, program: &str) -> Result<()> {
+    if host.kubernetes.is_some() {
+        anyhow::bail!("{} needs SSH, which Kubernetes hosts don't have", program);
+    }
+    Ok(())
+}
+
+/// A host command line running `command` with sh inside `container`, from the
+/// host's `container_path` (or `remote_path`).
+fn container_command(host: &Host, container: &str, remote_path: &str, command: &str, tty: bool) -> Result<String> {
+    if host.shell.is_windows() {
+        anyhow::bail!("container is only supported on Unix hosts (shell = \"{}\")", host.shell);
+    }
+    let workdir = host.container_path.as_deref().unwrap_or(remote_path);
+    Ok(format!(
+        "docker exec -i{} -w {} {} sh -c {}",
+        if tty { "t" } else { "" },
+        quote_path(&host.shell, workdir),
+        quote_path(&host.shell, container),
+        quote_path(&host.shell, command)
+    ))
+}
+
+/// Copy the synced project at `remote_path` into the host's container (at
+/// `container_path`, or the same path), for containers without a bind mount.
+pub fn copy_into_container(host: &Host, container: &str, remote_path: &str, dry_run: bool) -> Result<()> {
+    if host.shell.is_windows() {
+        anyhow::bail!("container is only supported on Unix hosts (shell = \"{}\")", host.shell);
+    }
+    let dest = host.container_path.as_deref().unwrap_or(remote_path);
+    let script = format!(
+        "docker exec {c} mkdir -p {d} && docker cp {s} {c}:{d}",
+        c = quote_path(&Shell::Sh, container),
+        d = quote_path(&Shell::Sh, dest),
+        s = quote_path(&Shell::Sh, &paths::join(remote_path, "."))
+    );
+
+    if dry_run {
+        eprintln!("Would copy {}:{} into container {} at {}", host.hostname, remote_path, container, dest);
+        return Ok(());
+    }
+
+    let exit_code = exec_remote(host, &sh_command(&host.shell, &script))?.code();
+    if exit_code != 0 {
+        anyhow::bail!("Copying into container {} failed with exit code: {}", container, exit_code);
+    }
+    Ok(())
+}
+
+/// The command line handed to the host's login shell. For WSL hosts, that is a
+/// `wsl.exe` invocation running `command` in the distribution's bash. The command
+/// travels base64-encoded, since the Windows login shell (cmd or PowerShell) would
+/// mangle its quoting; process substitution leaves stdin free for the command.
+fn login_command(host: &Host, command: &str) -> String {
+    if host.shell != Shell::Wsl {
+        return command.to_string();
+    }
+    format!(r#"{} -e bash -lc "source <(echo {} | base64 -d)""#, wsl_exe(host), BASE64.encode(command))
+}
+
+/// `wsl.exe`, selecting the host's distribution if one is configured.
+fn wsl_exe(host: &Host) -> String {
+    match host.wsl_distro {
+        Some(ref distro) => format!("wsl.exe -d {}", distro),
+        None => "wsl.exe".to_string(),
+    }
+}
+
+/// A remote path as the Windows side of a WSL host sees it, for transfers that go
+/// through Windows (scp and SFTP). Paths on other hosts are returned unchanged.
+fn windows_side_path(host: &Host, path: &str) -> String {
+    if host.shell == Shell::Wsl {
+        paths::windows_path(path)
+    } else {
+        path.to_string()
+    }
+}
+
+/// Steps 1-5 of [`run_remote_command`]: the full command line to send to the remote.
+/// `tty` says whether the command gets a terminal, which `docker exec` must be told.
+fn prepare_remote_command(
+    host: &Host,
+    remote_path: &str,
+    command: &str,
+    env_vars: &HashMap<String, String>,
+    exports: &[(String, String)],
+    tty: bool,
+) -> Result<String> {
+    Ok(explain_remote_command(host, remote_path, command, env_vars, exports, tty)?.remote)
+}
+
+/// Each step [`run_remote_command`] takes to turn a command into what it runs, for
+/// `run --dry-run`.
+#[derive(Debug)]
+pub struct Explained {
+    /// The command after `${VAR}` substitution
+    pub command: String,
+    /// The host's `wrapper` after substitution, wrapped around the command
+    pub wrapped: Option<String>,
+    /// Names of the variables exported to the remote
+    pub exports: Vec<String>,
+    /// The command line the remote shell receives
+    pub remote: String,
+    /// The local invocation that sends it, as a shell command line
+    pub invocation: String,
+}
+
+/// Steps 1-5 of [`run_remote_command`], and the ssh (or kubectl) invocation it would
+/// make, without connecting or writing to the audit log.
+pub fn explain_remote_command(
+    host: &Host,
+    remote_path: &str,
+    command: &str,
+    env_vars: &HashMap<String, String>,
+    exports: &[(String, String)],
+    tty: bool,
+) -> Result<Explained> {
+    // Step 1: Substitute environment variables in the user command
+    let command = substitute_env_vars(command, host.strict_env, env_vars)
+        .context("Failed to substitute environment variables in command")?;
+
+    // Step 2 & 3: Apply wrapper if configured
+    let wrapped_command = apply_wrapper(&command, host.wrapper.as_deref(), host.strict_env, env_vars)?;
+
+    // Step 4: Export variables ahead of the wrapper, so it sees them too
+    let exports = remote_exports(host, env_vars, exports)?;
+    // In a container, the command runs under sh rather than the host's shell
+    let shell = if host.container.is_some() { &Shell::Sh } else { &host.shell };
+    let exported_command = format!("{}{}", export_statements(shell, &exports), wrapped_command);
+
+    // Step 5: Wrap with cd to remote path, based on shell type
+    let remote = if let Some(ref container) = host.container {
+        container_command(host, container, remote_path, &exported_command, tty)?
+    } else if host.encode_command {
+        encoded_remote_command(&host.shell, remote_path, &exported_command)?
+    } else {
+        build_remote_command(&host.shell, remote_path, &exported_command)
+    };
+
+    let invocation = shown_invocation(host, &remote_invocation(host, &remote, tty.then_some("-t")));
+    Ok(Explained {
+        wrapped: host.wrapper.is_some().then_some(wrapped_command),
+        command,
+        exports: exports.into_iter().map(|(name, _)| name).collect(),
+        remote,
+        invocation,
+    })
+}
+
+/// `cmd` as a shell command line, prefixed with sshpass where [`transport`] adds it.
+fn shown_invocation(host: &Host, cmd: &Command) -> String {
+    let mut words = Vec::new();
+    if host.kubernetes.is_none() && uses_sshpass(host) {
+        words.extend(["sshpass".to_string(), "-e".to_string()]);
+    }
+    words.push(cmd.get_program().to_string_lossy().into_owned());
+    words.extend(cmd.get_args().map(|arg| arg.to_string_lossy().into_owned()));
+    command_line(&words)
+}
+
+/// Words as a POSIX shell command line, quoting the ones a shell would split or expand.
+pub fn command_line(words: &[String]) -> String {
+    let quoted: Vec<String> = words
+        .iter()
+        .map(|word| {
+            let plain = !word.is_empty()
+                && word.chars().all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%^".contains(c));
+            if plain {
+                word.clone()
+            } else {
+                format!("'{}'", word.replace('\'', r"'\''"))
+            }
+        })
+        .collect();
+    quoted.join(" ")
+}
+
+/// Variables to set in the remote environment: all env file variables with
+/// `export_env`, then the host's `remote_env`, then `exports` (from `--env`), later
+/// ones overriding earlier ones of the same name. Like `${VAR}`, values come from
+/// the process environment before the env files.
+fn remote_exports(
+    host: &Host,
+    env_vars: &HashMap<String, String>,
+    exports: &[(String, String)],
+) -> Result<Vec<(String, String)>> {
+    let lookup = |name: &str| env::var(name).ok().or_else(|| env_vars.get(name).cloned());
+    let mut result: Vec<(String, String)> = Vec::new();
+    let mut set = |name: &str, value: String| {
+        result.retain(|(key, _)| key != name);
+        result.push((name.to_string(), value));
+    };
+
+    if host.export_env {
+        let mut names: Vec<&String> = env_vars.keys().collect();
+        names.sort();
+        for name in names {
+            set(name, lookup(name).unwrap_or_default());
+        }
+    }
+
+    for name in &host.remote_env {
+        match lookup(name) {
+            Some(value) => set(name, value),
+            None if host.strict_env => {
+                anyhow::bail!("remote_env variable '{}' is not set in the environment or env files", name)
+            }
+            None => {}
+        }
+    }
+
+    for (name, value) in exports {
+        set(name, value.clone());
+    }
+
+    Ok(result)
+}
+
+/// Statements that set environment variables in the remote shell, ready to prefix a
+/// command with. Values are quoted so the remote shell takes them literally (except
+/// `%VAR%` references under cmd, which has no way to escape them).
+fn export_statements(shell: &Shell, exports: &[(String, String)]) -> String {
+    if exports.is_empty() {
+        return String::new();
+    }
+    match shell {
+        Shell::Bash | Shell::Zsh | Shell::Sh | Shell::Wsl => {
+            let assignments: Vec<String> =
+                exports.iter().map(|(key, value)| format!("{}={}", key, quote_path(shell, value))).collect();
+            format!("export {} && ", assignments.join(" "))
+        }
+        Shell::Fish => exports.iter().map(|(key, value)| format!("set -gx {} {}; ", key, quote_path(shell, value))).collect(),
+        Shell::Nushell => exports.iter().map(|(key, value)| format!("$env.{} = {}; ", key, quote_path(shell, value))).collect(),
+        // build_remote_command escapes `"` for the -Command argument
+        Shell::Powershell => exports
+            .iter()
+            .map(|(key, value)| format!("$env:{} = '{}'; ", key, value.replace('\'', "''")))
+            .collect(),
+        Shell::Cmd => exports.iter().map(|(key, value)| format!(r#"set "{}={}" && "#, key, value)).collect(),
+    }
+}
+
+/// Whether an interactive session can use mosh: it must be installed locally and on
+/// the remote, which must be a Unix host. Explains the fallback to ssh otherwise.
+pub fn mosh_available(host: &Host) -> bool {
+    let reason = if host.kubernetes.is_some() {
+        Some("mosh can't reach Kubernetes pods")
+    } else if host.shell.is_windows() || host.shell == Shell::Wsl {
+        Some("mosh needs a Unix remote")
+    } else if host.forward_x11 {
+        Some("mosh can't forward X11")
+    } else if !Command::new("mosh")
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success())
+    {
+        Some("mosh is not installed locally")
+    } else if !capture_remote(host, &sh_command(&host.shell, "command -v mosh-server >/dev/null && echo yes"))
+        .is_ok_and(|out| out.trim() == "yes")
+    {
+        Some("mosh-server is not installed on the remote")
+    } else {
+        None
+    };
+
+    match reason {
+        Some(reason) => {
+            status!("{}; using ssh", reason);
+            false
+        }
+        None => true,
+    }
+}
+
+/// Like [`run_remote_command`] with a PTY, but over mosh, which survives roaming and
+/// flaky links. mosh doesn't relay the remote exit code; a normal exit returns 0.
+pub fn run_remote_command_mosh(
+    host: &Host,
+    remote_path: &str,
+    command: &str,
+    env_vars: &HashMap<String, String>,
+    exports: &[(String, String)],
+) -> Result<i32> {
+    let full_cmd = prepare_remote_command(host, remote_path, command, env_vars, exports, true)?;
+    audit::record(host, "mosh", &full_cmd)?;
+
+    // mosh bootstraps over ssh, so it gets the same options
+    let mut args = Vec::new();
+    let options = ssh_options(host);
+    if !options.is_empty() {
+        args.push(format!("--ssh={}", ssh_command_line(&options)));
+    }
+    args.extend([host.hostname.clone(), "--".to_string(), host.shell.program().to_string(), "-c".to_string(), full_cmd]);
+
+    info!("Running: mosh {}", args.join(" "));
+
+    let status = transport(host, "mosh")?
+        .args(&args)
+        .status()
+        .context("Failed to run mosh")?;
+
+    Ok(status.code().unwrap_or(1))
+}
+
+/// Wrap a command so it runs from the remote path using the given shell.
+fn build_remote_command(shell: &Shell, remote_path: &str, command: &str) -> String {
+    let path = quote_path(shell, remote_path);
+    match shell {
+        Shell::Bash | Shell::Zsh | Shell::Fish | Shell::Sh | Shell::Wsl => format!("cd {} && {}", path, command),
+        // A failing cd raises an error, which stops nushell before the command
+        Shell::Nushell => format!("cd {}; {}", path, command),
+        Shell::Powershell => powershell_command(&format!(
+            "cd -LiteralPath {}; {}",
+            path,
+            command.replace('"', r#"\""#)
+        )),
+        Shell::Cmd => cmd_command(&format!("cd /d {} && {}", path, command)),
+    }
+}
+
+/// Like [`build_remote_command`], but ships the script base64-encoded, so nothing in
+/// it needs escaping on the way to the remote shell.
+fn encoded_remote_command(shell: &Shell, remote_path: &str, command: &str) -> Result<String> {
+    match shell {
+        Shell::Bash | Shell::Zsh | Shell::Fish | Shell::Sh | Shell::Wsl => {
+            let script = build_remote_command(shell, remote_path, command);
+            Ok(format!(r#"{} -c "$(echo {} | base64 -d)""#, shell.program(), BASE64.encode(script)))
+        }
+        Shell::Nushell => {
+            let script = build_remote_command(shell, remote_path, command);
+            Ok(format!("nu -c ('{}' | decode base64 | decode utf-8)", BASE64.encode(script)))
+        }
+        // -EncodedCommand takes base64 of the UTF-16LE script. Unlike in -Command, the
+        // path isn't inside a double-quoted argument, so only `'` needs escaping.
+        Shell::Powershell => {
+            let path = format!("'{}'", remote_path.replace('\'', "''"));
+            let script = format!("{}; cd -LiteralPath {}; {}", POWERSHELL_UTF8, path, command);
+            let utf16: Vec<u8> = script.encode_utf16().flat_map(u16::to_le_bytes).collect();
+            Ok(format!("powershell -EncodedCommand {}", BASE64.encode(utf16)))
+        }
+        Shell::Cmd => anyhow::bail!("encode_command is not supported for cmd hosts (use shell = \"powershell\")"),
+    }
+}
+
+/// Switches PowerShell's console input/output and the encoding used for piping to
+/// native programs to UTF-8 (without BOM), instead of the host's legacy code page.
+/// Setting the console encoding throws when there is no console, hence the `try`.
+const POWERSHELL_UTF8: &str = "$u = New-Object Text.UTF8Encoding $false; \
+    try { [Console]::OutputEncoding = $u; [Console]::InputEncoding = $u } catch {}; \
+    $OutputEncoding = $u";
+
+/// Wrap a PowerShell script as a `powershell -Command` invocation with UTF-8 I/O.
+/// The script must already have `"` escaped as `\"`.
+pub fn powershell_command(script: &str) -> String {
+    format!(r#"powershell -Command "{}; {}""#, POWERSHELL_UTF8, script)
+}
+
+/// Prefix a cmd command line with a switch to the UTF-8 code page.
+pub fn cmd_command(command: &str) -> String {
+    format!("chcp 65001 >nul && {}", command)
+}
+
+/// Run a POSIX sh script under a Unix host's login shell. bash, zsh and sh take it as
+/// is; fish and nushell have their own syntax, so they hand it to `sh -c`. Paths in
+/// the script must be quoted for sh.
+pub fn sh_command(shell: &Shell, script: &str) -> String {
+    match shell {
+        Shell::Fish | Shell::Nushell => format!("sh -c {}", quote_path(shell, script)),
+        _ => script.to_string(),
+    }
+}
+
+/// Decode captured remote output. Windows programs may emit UTF-16 (with or without
+/// a BOM) or prefix UTF-8 with a BOM; everything else is treated as UTF-8.
+pub fn decode_output(bytes: &[u8]) -> String {
+    let utf16 = |bytes: &[u8], little_endian: bool| {
+        let units: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|pair| match little_endian {
+                true => u16::from_le_bytes([pair[0], pair[1]]),
+                false => u16::from_be_bytes([pair[0], pair[1]]),
+            })
+            .collect();
+        String::from_utf16_lossy(&units)
+    };
+
+    match bytes {
+        [0xEF, 0xBB, 0xBF, rest @ ..] => String::from_utf8_lossy(rest).into_owned(),
+        [0xFF, 0xFE, rest @ ..] => utf16(rest, true),
+        [0xFE, 0xFF, rest @ ..] => utf16(rest, false),
+        // BOM-less UTF-16LE ASCII text has a NUL in every odd byte
+        _ if bytes.len() >= 2 && bytes.len().is_multiple_of(2) && bytes.iter().skip(1).step_by(2).all(|b| *b == 0) => {
+            utf16(bytes, true)
+        }
+        _ => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+/// Quote a remote path for interpolation into a command line for the given shell.
+///
+/// Unix shells and PowerShell use single quotes, so `$`, backticks, and other
+/// metacharacters in the path are never expanded by the remote shell. Nushell's
+/// single-quoted strings can't contain `'`, so it gets an escaped double-quoted string
+/// (which nushell doesn't interpolate). cmd has no single-quote form; paths are
+/// double-quoted with backslash separators.
+pub fn quote_path(shell: &Shell, path: &str) -> String {
+    match shell {
+        Shell::Bash | Shell::Zsh | Shell::Sh | Shell::Wsl => format!("'{}'", path.replace('\'', r"'\''")),
+        Shell::Fish => format!("'{}'", path.replace('\\', r"\\").replace('\'', r"\'")),
+        Shell::Nushell => format!(r#""{}""#, path.replace('\\', r"\\").replace('"', r#"\""#)),
+        // Embedded in a double-quoted -Command argument, so `"` must be escaped too
+        Shell::Powershell => format!("'{}'", path.replace('\'', "''").replace('"', r#"\""#)),
+        Shell::Cmd => format!(r#""{}""#, path.replace('/', "\\")),
+    }
+}
+
+/// Run a raw command on the remote host without cd, wrapper, or env substitution.
+/// Output is streamed to the local stdout/stderr. Returns the remote exit code.
+pub fn exec_remote(host: &Host, command: &str) -> Result<Exit> {
+    info!("Running: ssh {} {}", host.hostname, command);
+    wait_remote(host, remote_shell(host, command)?)
+}
+
+/// Run a raw command on the remote host and capture its stdout.
+/// Fails if the command exits non-zero; stderr is passed through.
+pub fn capture_remote(host: &Host, command: &str) -> Result<String> {
+    info!("Running: ssh {} {}", host.hostname, command);
+
+    let output = remote_shell(host, command)?
+        .stderr(Stdio::inherit())
+        .output()
+        .context("Failed to spawn SSH process")?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "Remote command failed with exit code {}: {}",
+            output.status.code().unwrap_or(1),
+            command
+        );
+    }
+
+    Ok(decode_output(&output.stdout))
+}
+
+/// Run a raw command on the remote host with `input` as its stdin. Returns its exit code.
+pub fn feed_remote(host: &Host, command: &str, input: &[u8]) -> Result<i32> {
+    info!("Running: ssh {} {}", host.hostname, command);
+
+    let mut child = remote_shell(host, command)?
+        .stdin(Stdio::piped())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .context("Failed to spawn SSH process")?;
+
+    child
+        .stdin
+        .take()
+        .context("Failed to get SSH stdin")?
+        .write_all(input)
+        .context("Failed to send input over SSH")?;
+
+    let status = child.wait().context("Failed to wait for SSH process")?;
+    Ok(status.code().unwrap_or(1))
+}
+
+/// Write contents to a file on the remote host via SSH stdin.
+/// On Unix hosts the file is created with owner-only (600) permissions.
+pub fn write_remote_file(host: &Host, remote_path: &str, contents: &str) -> Result<()> {
+    let write_cmd = match host.shell {
+        Shell::Powershell | Shell::Cmd => powershell_command(&format!(
+            "[Console]::In.ReadToEnd() | Set-Content -NoNewline -LiteralPath {}",
+            quote_path(&Shell::Powershell, remote_path)
+        )),
+        _ => {
+            let path = quote_path(&Shell::Sh, remote_path);
+            sh_command(&host.shell, &format!("umask 077 && cat > {} && chmod 600 {}", path, path))
+        }
+    };
+
+    info!("Running: ssh {} {}", host.hostname, write_cmd);
+
+    let mut child = remote_shell(host, &write_cmd)?
+        .stdin(Stdio::piped())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .context("Failed to spawn SSH process")?;
+
+    child
+        .stdin
+        .take()
+        .context("Failed to get SSH stdin")?
+        .write_all(contents.as_bytes())
+        .context("Failed to send file contents over SSH")?;
+
+    let status = child.wait().context("Failed to wait for SSH process")?;
+    if !status.success() {
+        anyhow::bail!("Failed to write remote file: {}", remote_path);
+    }
+
+    Ok(())
+}
+
+/// Stream local stdin into a file on the remote host. Returns the number of bytes sent.
+pub fn upload_stdin(host: &Host, remote_path: &str) -> Result<u64> {
+    let write_cmd = match host.shell {
+        Shell::Powershell | Shell::Cmd => format!(
+            r#"powershell -Command "$out = [IO.File]::Create({}); [Console]::OpenStandardInput().CopyTo($out); $out.Close()""#,
+            quote_path(&Shell::Powershell, remote_path)
+        ),
+        _ => sh_command(&host.shell, &format!("cat > {}", quote_path(&Shell::Sh, remote_path))),
+    };
+
+    info!("Uploading stdin to {}:{}", host.hostname, remote_path);
+    info!("Running: ssh {} {}", host.hostname, write_cmd);
+
+    let mut child = remote_shell(host, &write_cmd)?
+        .stdin(Stdio::piped())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .context("Failed to spawn SSH process")?;
+
+    let mut ssh_stdin = child.stdin.take().context("Failed to get SSH stdin")?;
+    let bytes = io::copy(&mut io::stdin().lock(), &mut ssh_stdin).context("Failed to stream stdin over SSH")?;
+    drop(ssh_stdin);
+
+    let status = child.wait().context("Failed to wait for SSH process")?;
+    if !status.success() {
+        anyhow::bail!("Upload from stdin failed with exit code: {}", status.code().unwrap_or(1));
+    }
+
+    Ok(bytes)
+}
+
+/// Stream a remote file to local stdout. Returns the number of bytes received.
+pub fn download_stdout(host: &Host, remote_path: &str) -> Result<u64> {
+    let read_cmd = match host.shell {
+        Shell::Powershell | Shell::Cmd => format!(
+            r#"powershell -Command "$in = [IO.File]::OpenRead({}); $in.CopyTo([Console]::OpenStandardOutput()); $in.Close()""#,
+            quote_path(&Shell::Powershell, remote_path)
+        ),
+        _ => sh_command(&host.shell, &format!("cat {}", quote_path(&Shell::Sh, remote_path))),
+    };
+
+    info!("Downloading {}:{} to stdout", host.hostname, remote_path);
+    info!("Running: ssh {} {}", host.hostname, read_cmd);
+
+    let mut child = remote_shell(host, &read_cmd)?
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .context("Failed to spawn SSH process")?;
+
+    let mut ssh_stdout = child.stdout.take().context("Failed to get SSH stdout")?;
+    let bytes = io::copy(&mut ssh_stdout, &mut io::stdout().lock()).context("Failed to stream SSH output to stdout")?;
+
+    let status = child.wait().context("Failed to wait for SSH process")?;
+    if !status.success() {
+        anyhow::bail!("Download to stdout failed with exit code: {}", status.code().unwrap_or(1));
+    }
+
+    Ok(bytes)
+}
+
+/// Apply wrapper template to command, with environment variable substitution.
+fn apply_wrapper(
+    command: &str,
+    wrapper: Option<&str>,
+    strict_env: bool,
+    env_vars: &HashMap<String, String>,
+) -> Result<String> {
+    let Some(wrapper_template) = wrapper else {
+        return Ok(command.to_string());
+    };
+
+    // Validate wrapper has placeholder before substitution
+    if !wrapper_template.contains("{}") {
+        anyhow::bail!(
+            "Wrapper template must contain '{{}}' placeholder for command. Got: {}",
+            wrapper_template
+        );
+    }
+
+    // Substitute environment variables in wrapper
+    let wrapper = substitute_env_vars(wrapper_template, strict_env, env_vars)
+        .context("Failed to substitute environment variables in wrapper")?;
+
+    // Replace placeholder with command
+    Ok(wrapper.replace("{}", command))
+}
+
+/// Check if an SSH connection to the host can be established.
+/// Returns true if the host is reachable, false otherwise.
+pub fn check_connection(host: &Host) -> bool {
+    connect_error(host).is_none()
+}
+
+/// Why a connection to the host can't be established (ssh's last line of stderr), or
+/// `None` if it can.
+fn connect_error(host: &Host) -> Option<String> {
+    let output = match probe_command(host, 5, None) {
+        Ok(mut cmd) => cmd.stdin(Stdio::null()).stdout(Stdio::null()).output(),
+        Err(e) => return Some(format!("{:#}", e)),
+    };
+    let error = match output {
+        Ok(output) if output.status.success() => None,
+        Ok(output) => Some(
+            String::from_utf8_lossy(&output.stderr)
+                .lines()
+                .map(str::trim)
+                .rfind(|line| !line.is_empty())
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("ssh exited with code {}", output.status.code().unwrap_or(1))),
+        ),
+        Err(e) => Some(format!("failed to run ssh: {}", e)),
+    };
+    debug!(host = %host.hostname, reachable = error.is_none(), ?error, "Connection check");
+    error
+}
+
+/// Run a command non-interactively with a short connect timeout, returning its stdout
+/// if it succeeds. Errors (including the remote's stderr) are swallowed, for probes.
+pub fn probe_remote(host: &Host, command: &str) -> Option<String> {
+    let output = probe_command(host, 5, Some(command))
+        .ok()?
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+
+    debug!(host = %host.hostname, command, exit_code = ?output.status.code(), "Probe finished");
+    output.status.success().then(|| decode_output(&output.stdout))
+}
+
+/// A transfer that failed because its connection did (ssh exit 255, rsync exit 12 or
+/// 30) rather than at the remote end. [`with_retries`] retries these.
+#[derive(Debug)]
+pub struct ConnectionFailure(String);
+
+impl std::fmt::Display for ConnectionFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ConnectionFailure {}
+
+/// The error for a `program` that exited with `code`: a [`ConnectionFailure`] if the
+/// code is one of `connection_codes`.
+fn exit_failure(program: &str, code: Option<i32>, connection_codes: &[i32]) -> anyhow::Error {
+    let message = format!("{} failed with exit code: {}", program, code.unwrap_or(1));
+    match code {
+        Some(code) if connection_codes.contains(&code) => ConnectionFailure(message).into(),
+        _ => anyhow::anyhow!(message),
+    }
+}
+
+/// Retries made by [`with_retries`] in this process.
+static RETRIES: AtomicU32 = AtomicU32::new(0);
+
+/// How many times [`with_retries`] has retried so far. The difference across a
+/// transfer is how many retries it took, for [`output::TransferReport`].
+pub fn retry_count() -> u32 {
+    RETRIES.load(Ordering::Relaxed)
+}
+
+/// Run `op`, retrying it up to `host.retries` times while it fails with a
+/// [`ConnectionFailure`]. The first retry waits `host.retry_delay` seconds, and each
+/// one after that twice as long as the last.
+pub fn with_retries<T>(host: &Host, mut op: impl FnMut() -> Result<T>) -> Result<T> {
+    let mut delay = Duration::from_secs(host.retry_delay);
+    let mut attempt = 0;
+    loop {
+        match op() {
+            Err(e) if attempt < host.retries && e.is::<ConnectionFailure>() => {
+                attempt += 1;
+                RETRIES.fetch_add(1, Ordering::Relaxed);
+                status!("{}; retrying in {}s ({}/{})", e, delay.as_secs(), attempt, host.retries);
+                thread::sleep(delay);
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// After an SSH failure (exit 255), check whether it was host key verification and
+/// return advice if so. Costs one extra connection attempt.
+pub fn host_key_failure_hint(host: &Host) -> Option<String> {
+    if host.kubernetes.is_some() {
+        return None;
+    }
+    let output = probe_command(host, 5, None)
+        .ok()?
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .output()
+        .ok()?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !is_host_key_failure(&stderr) {
+        return None;
+    }
+
+    Some(format!(
+        "Host key verification failed for {h}. If the machine was reinstalled, remove its old key with \
+         `ssh-keygen -R {h}`, or set host_key_checking = \"off\" for hosts that are reimaged often.",
+        h = host.hostname
+    ))
+}
+
+fn is_host_key_failure(stderr: &str) -> bool {
+    stderr.contains("Host key verification failed") || stderr.contains("REMOTE HOST IDENTIFICATION HAS CHANGED")
+}
+
+/// Shared deadline for [`probe_hosts`] when checking hosts before use.
+pub const PROBE_DEADLINE: Duration = Duration::from_secs(5);
+
+/// Seconds a failed probe is remembered, so back-to-back fan-out commands don't
+/// wait on the same dead hosts again.
+const PROBE_FAILURE_TTL: u64 = 30;
+
+/// Check several hosts at once. Probes run concurrently and share one deadline,
+/// extended to the longest `connect_timeout` among them; hosts that haven't answered
+/// by then count as unreachable. Hosts that failed a probe within the last
+/// [`PROBE_FAILURE_TTL`] seconds are reported unreachable without probing. Results
+/// are in the order of `hosts`.
+pub fn probe_hosts(hosts: &[&Host], deadline: Duration) -> Vec<bool> {
+    let now = state::now_secs();
+    let mut failures = state::load_probe_failures();
+    failures.retain(|_, failed_at| now.saturating_sub(*failed_at) < PROBE_FAILURE_TTL);
+
+    let deadline =
+        hosts.iter().filter_map(|host| host.connect_timeout).map(Duration::from_secs).fold(deadline, Duration::max);
+
+    let results: Vec<bool> = runtime::block_on(async {
+        let mut children: Vec<Option<tokio::process::Child>> = hosts
+            .iter()
+            .map(|host| {
+                if failures.contains_key(&host.hostname) {
+                    return None;
+                }
+                let mut cmd = tokio::process::Command::from(probe_command(host, deadline.as_secs().max(1), None).ok()?);
+                cmd.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null()).kill_on_drop(true).spawn().ok()
+            })
+            .collect();
+
+        // Anything still connecting at the deadline is unreachable, and killed when dropped
+        let deadline = tokio::time::Instant::now() + deadline;
+        let mut results = Vec::with_capacity(children.len());
+        for child in children.iter_mut() {
+            let reachable = match child {
+                Some(child) => matches!(tokio::time::timeout_at(deadline, child.wait()).await, Ok(Ok(s)) if s.success()),
+                None => false,
+            };
+            results.push(reachable);
+        }
+        results
+    });
+    for (host, reachable) in hosts.iter().zip(&results) {
+        if *reachable {
+            failures.remove(&host.hostname);
+        } else {
+            failures.entry(host.hostname.clone()).or_insert(now);
+        }
+    }
+    let _ = state::store_probe_failures(&failures);
+
+    results
+}
+
+/// Longest wait between checks with `reconnect_backoff = "exponential"`.
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// The wait before reconnection check number `attempt` (from 1).
+fn reconnect_delay(host: &Host, attempt: u32) -> Duration {
+    let interval = Duration::from_secs(host.reconnect_interval.max(1));
+    match host.reconnect_backoff {
+        ReconnectBackoff::Fixed => interval,
+        ReconnectBackoff::Exponential => {
+            let doubled = interval.saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)));
+            // An interval already past the cap is kept as it is
+            doubled.min(RECONNECT_MAX_DELAY.max(interval))
+        }
+    }
+}
+
+/// Check the host every `reconnect_interval` seconds (or with backoff) until an SSH
+/// connection succeeds, showing how long it has been, how long is left, and why the
+/// last check failed. Returns false, having said why, if `timeout` passes or
+/// `reconnect_attempts` checks fail first.
+pub fn wait_for_reconnect(host: &Host, timeout: Duration) -> bool {
+    let start = Instant::now();
+    // On a terminal the progress line is rewritten in place
+    let in_place = io::stderr().is_terminal();
+    let mut shown = false;
+    let mut attempt = 0;
+
+    loop {
+        attempt += 1;
+        let remaining = timeout.saturating_sub(start.elapsed());
+        thread::sleep(reconnect_delay(host, attempt).min(remaining));
+
+        let error = match connect_error(host) {
+            None => {
+                if shown && in_place {
+                    eprintln!();
+                }
+                return true;
+            }
+            Some(error) => error,
+        };
+
+        let elapsed = start.elapsed();
+        let out_of_attempts = host.reconnect_attempts.is_some_and(|max| attempt >= max);
+        if !output::is_quiet() {
+            let line = format!(
+                "Still unreachable after {} (attempt {}, {} left): {}",
+                output::format_duration(elapsed),
+                attempt,
+                output::format_duration(timeout.saturating_sub(elapsed)),
+                error
+            );
+            if in_place {
+                eprint!("\r\x1b[K{}", line);
+            } else {
+                eprintln!("{}", line);
+            }
+            shown = true;
+        }
+
+        if out_of_attempts || elapsed >= timeout {
+            if shown && in_place {
+                eprintln!();
+            }
+            let reason = if out_of_attempts { "Gave up reconnecting" } else { "Timed out waiting for reconnection" };
+            eprintln!("{} after {} ({} attempts): {}", reason, output::format_duration(elapsed), attempt, error);
+            return false;
+        }
+    }
+}
+
+/// Transfers smaller than this skip the free space check, sparing a round trip.
+pub const FREE_SPACE_CHECK_MIN: u64 = 64 * 1024 * 1024;
+
+/// Fail before a transfer of `needed` bytes into `path` if the filesystem holding it
+/// doesn't have room, rather than partway through. With `replaces`, what `path`
+/// already holds counts as free, since the transfer overwrites it. Small transfers
+/// aren't checked, and neither are hosts that can't report their free space.
+pub fn check_free_space(host: &Host, path: &str, needed: u64, replaces: bool) -> Result<()> {
+    if needed < FREE_SPACE_CHECK_MIN {
+        return Ok(());
+    }
+
+    let available = capture_remote(host, &free_space_command(&host.shell, path, replaces))
+        .map(|output| parse_free_space(&output));
+    let available = match available {
+        Ok(Some(available)) => available,
+        Ok(None) | Err(_) => {
+            warn!("Could not check free space on {}:{}", host.hostname, path);
+            return Ok(());
+        }
+    };
+
+    info!("Free space on remote: {} ({} to send)", output::format_size(available), output::format_size(needed));
+    if available < needed {
+        anyhow::bail!(
+            "Not enough space on {}:{}: the transfer needs about {} but only {} is free",
+            host.hostname,
+            path,
+            output::format_size(needed),
+            output::format_size(available)
+        );
+    }
+    Ok(())
+}
+
+/// A remote command printing the KiB free on the filesystem holding `path` (or its
+/// nearest existing parent), and with `replaces` the KiB already under `path`.
+fn free_space_command(shell: &Shell, path: &str, replaces: bool) -> String {
+    match shell {
+        Shell::Powershell | Shell::Cmd => {
+            let used = if replaces {
+                "; if (Test-Path -LiteralPath $t) { [math]::Floor((Get-ChildItem -LiteralPath $t -Recurse -File -Force | Measure-Object Length -Sum).Sum / 1KB) }"
+            } else {
+                ""
+            };
+            powershell_command(&format!(
+                "$t = {}; $p = $t; while ($p -and -not (Test-Path -LiteralPath $p)) {{ $p = Split-Path -Parent $p }}; \
+                 [math]::Floor((Get-Item -LiteralPath $p).PSDrive.Free / 1KB){}",
+                quote_path(&Shell::Powershell, path),
+                used
+            ))
+        }
+        _ => {
+            let used = if replaces { r#"; [ -d "$t" ] && du -sk "$t" | cut -f1"# } else { "" };
+            sh_command(
+                shell,
+                &format!(
+                    r#"t={}; p="$t"; while [ ! -e "$p" ]; do p=$(dirname "$p"); done; df -Pk "$p" | awk 'NR==2 {{ print $4 }}'{}; true"#,
+                    quote_path(&Shell::Sh, path),
+                    used
+                ),
+            )
+        }
+    }
+}
+
+/// Bytes available from [`free_space_command`]'s output: the free KiB plus any KiB
+/// being replaced.
+fn parse_free_space(output: &str) -> Option<u64> {
+    let mut numbers = output.lines().filter_map(|line| line.trim().parse::<u64>().ok());
+    let free = numbers.next()?;
+    Some((free + numbers.next().unwrap_or(0)) * 1024)
+}
+
+/// Seconds a directory [`ensure_remote_dir_cached`] made is trusted to still exist.
+const REMOTE_DIR_TTL: u64 = 60 * 60;
+
+/// [`ensure_remote_dir`], skipped if it succeeded for this host and path within the
+/// last [`REMOTE_DIR_TTL`] seconds, which spares a round trip on repeated transfers.
+/// `force` always creates it, and so do container and Kubernetes hosts, whose
+/// filesystem goes away with the container. Returns whether the directory was only
+/// assumed to exist; pass that to [`retry_in_remote_dir`].
+pub fn ensure_remote_dir_cached(host: &Host, remote_path: &str, force: bool) -> Result<bool> {
+    if host.container.is_some() || host.kubernetes.is_some() {
+        ensure_remote_dir(host, remote_path)?;
+        return Ok(false);
+    }
+
+    let now = state::now_secs();
+    let key = remote_dir_key(host, remote_path);
+    let mut dirs = state::load_remote_dirs();
+    dirs.retain(|_, checked_at| now.saturating_sub(*checked_at) < REMOTE_DIR_TTL);
+    if !force && dirs.contains_key(&key) {
+        info!("Remote directory {} was created recently, not checking again", remote_path);
+        return Ok(true);
+    }
+
+    ensure_remote_dir(host, remote_path)?;
+    dirs.insert(key, now);
+    let _ = state::store_remote_dirs(&dirs);
+    Ok(false)
+}
+
+/// Run `transfer` into `remote_path`, which [`ensure_remote_dir_cached`] made sure of.
+/// If it fails where the directory was only `cached`, the directory may have been
+/// removed since, so it is created again and the transfer retried once. Either way a
+/// failure stops the cache trusting the directory.
+pub fn retry_in_remote_dir<T>(
+    host: &Host,
+    remote_path: &str,
+    cached: bool,
+    mut transfer: impl FnMut() -> Result<T>,
+) -> Result<T> {
+    match transfer() {
+        Ok(value) => Ok(value),
+        // A host that can't be reached hasn't lost the directory
+        Err(e) if cached && !e.is::<ConnectionFailure>() => {
+            status!("{:#}; creating {} again and retrying", e, remote_path);
+            ensure_remote_dir_cached(host, remote_path, true)?;
+            transfer().inspect_err(|_| forget_remote_dir(host, remote_path))
+        }
+        Err(e) => {
+            forget_remote_dir(host, remote_path);
+            Err(e)
+        }
+    }
+}
+
+/// Stop trusting that a remote directory exists, after a transfer into it failed, so
+/// the next one creates it again.
+pub fn forget_remote_dir(host: &Host, remote_path: &str) {
+    let mut dirs = state::load_remote_dirs();
+    if dirs.remove(&remote_dir_key(host, remote_path)).is_some() {
+        let _ = state::store_remote_dirs(&dirs);
+    }
+}
+
+fn remote_dir_key(host: &Host, remote_path: &str) -> String {
+    let user = host.user.as_deref().map(|user| format!("{}@", user)).unwrap_or_default();
+    let port = host.port.map(|port| format!(":{}", port)).unwrap_or_default();
+    format!("{}{}{}:{}", user, host.hostname, port, remote_path)
+}
+
+/// Ensure remote directory exists
+pub fn ensure_remote_dir(host: &Host, remote_path: &str) -> Result<()> {
+    let path = quote_path(&host.shell, remote_path);
+    let mkdir_cmd = match host.shell {
+        Shell::Powershell => format!(
+            r#"powershell -Command "New-Item -ItemType Directory -Force -Path {} | Out-Null""#,
+            path
+        ),
+        Shell::Cmd => format!("mkdir {} 2>nul || echo.", path),
+        _ => sh_command(&host.shell, &format!("mkdir -p {}", quote_path(&Shell::Sh, remote_path))),
+    };
+
+    info!("Ensuring remote directory exists: {}", remote_path);
+    info!("Running: ssh {} {}", host.hostname, mkdir_cmd);
+
+    with_retries(host, || {
+        let status = remote_shell(host, &mkdir_cmd)?
+            .status()
+            .context("Failed to create remote directory")?;
+
+        match status.code() {
+            Some(0) => Ok(()),
+            Some(255) => Err(ConnectionFailure(format!("Failed to create remote directory: {}", remote_path)).into()),
+            _ => anyhow::bail!("Failed to create remote directory: {}", remote_path),
+        }
+    })
+}
+
+/// Sync local directory to remote using tar over SSH
+pub fn sync_to_remote(
+    source: &str,
+    host: &Host,
+    remote_path: &str,
+    excludes: &[String],
+    dry_run: bool,
+) -> Result<()> {
+    // tar can't re-include what it excludes, so with `!` patterns it's given the
+    // list of files to send instead of exclude arguments. The list lives in a private
+    // temp file, removed when it is dropped.
+    let uses_file_list = !manifest::negations(excludes).is_empty();
+    let file_list = if uses_file_list && !dry_run {
+        let mut list = tempfile::Builder::new()
+            .prefix("bridge-files-")
+            .tempfile()
+            .context("Failed to create the file list for tar")?;
+        for file in manifest::collect_files(Path::new(source), excludes)? {
+            list.write_all(file.as_bytes())?;
+            list.write_all(&[0])?;
+        }
+        list.flush().with_context(|| format!("Failed to write {}", list.path().display()))?;
+        Some(list)
+    } else {
+        None
+    };
+    let mut tar_args = vec!["-czf".to_string(), "-".to_string()];
+    if uses_file_list {
+        let list = file_list.as_ref().map_or("<file list>".to_string(), |list| list.path().display().to_string());
+        tar_args.extend(["--null".to_string(), "-T".to_string(), list]);
+    } else {
+        tar_args.extend(excludes.iter().map(|exclude| format!("--exclude={}", exclude)));
+        tar_args.push(".".to_string());
+    }
+
+    // Build the extract command based on shell type
+    let extract_cmd = if host.shell.is_windows() {
+        build_remote_command(&host.shell, remote_path, "tar -xzf -")
+    } else {
+        sh_command(&host.shell, &build_remote_command(&Shell::Sh, remote_path, "tar -xzf -"))
+    };
+
+    if dry_run {
+        let tar: Vec<String> = std::iter::once("tar".to_string()).chain(tar_args.iter().cloned()).collect();
+        eprintln!("Would sync {} to {}:{}", source, host.hostname, remote_path);
+        eprintln!("  cd {} && {}", command_line(&[source.to_string()]), command_line(&tar));
+        eprintln!("  | {}", shown_invocation(host, &remote_shell_invocation(host, &extract_cmd)));
+        return Ok(());
+    }
+
+    info!("Syncing {} to {}:{}", source, host.hostname, remote_path);
+    with_retries(host, || tar_to_remote(source, host, &tar_args, &extract_cmd))
+}
+
+/// Pipe `tar` run in `source` into `extract_cmd` on the host
+fn tar_to_remote(source: &str, host: &Host, tar_args: &[String], extract_cmd: &str) -> Result<()> {
+    // Create tar process
+    // COPYFILE_DISABLE prevents macOS from creating ._* AppleDouble files in the archive
+    let mut tar = Command::new("tar")
+        .args(tar_args)
+        .current_dir(source)
+        .env("COPYFILE_DISABLE", "1")
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("Failed to spawn tar process")?;
+
+    let tar_stdout = tar.stdout.take().context("Failed to get tar stdout")?;
+
+    let mut ssh = remote_shell(host, extract_cmd)?
+        .stdin(tar_stdout)
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .context("Failed to spawn SSH process")?;
+
+    let tar_status = tar.wait().context("Failed to wait for tar")?;
+    let ssh_status = ssh.wait().context("Failed to wait for SSH")?;
+    debug!(tar = ?tar_status.code(), ssh = ?ssh_status.code(), "Archive transfer finished");
+
+    // When ssh can't connect, tar fails writing to it; the connection is what to report
+    if ssh_status.code() == Some(255) {
+        if let Some(hint) = host_key_failure_hint(host) {
+            anyhow::bail!(hint);
+        }
+        return Err(exit_failure("SSH/extract", ssh_status.code(), &[255]));
+    }
+
+    if !tar_status.success() {
+        anyhow::bail!("tar failed with exit code: {}", tar_status.code().unwrap_or(1));
+    }
+
+    if !ssh_status.success() {
+        return Err(exit_failure("SSH/extract", ssh_status.code(), &[255]));
+    }
+
+    Ok(())
+}
+
+/// Sync local directory to remote using rsync (incremental, deletes removed files).
+/// `filters` are rsync filter rules, checked before the excludes.
+pub fn rsync_to_remote(
+    source: &str,
+    host: &Host,
+    remote_path: &str,
+    excludes: &[String],
+    filters: &[String],
+    delete_excluded: bool,
+    dry_run: bool,
+) -> Result<()> {
+    require_ssh(host, "rsync")?;
+
+    let mut args = Vec::new();
+    if tracing::enabled!(Level::INFO) {
+        args.push("-v".to_string());
+    } else if output::is_quiet() {
+        args.push("--quiet".to_string());
+    }
+    if dry_run {
+        args.push("--dry-run".to_string());
+    }
+    args.extend(rsync_push_args(source, host, remote_path, excludes, filters, delete_excluded)?);
+    let (source_path, dest) = (&args[args.len() - 2], &args[args.len() - 1]);
+
+    if dry_run {
+        let mut rsync = Command::new("rsync");
+        rsync.args(&args);
+        eprintln!("Would rsync {} to {}", source_path, dest);
+        eprintln!("  {}", shown_invocation(host, &rsync));
+    }
+
+    info!("Running: rsync {}", args.join(" "));
+
+    with_retries(host, || run_rsync(host, &args))
+}
+
+/// Remote paths (relative to `remote_path`) that [`rsync_to_remote`] with the same
+/// arguments would delete, from an itemized dry run.
+pub fn rsync_deletions(
+    source: &str,
+    host: &Host,
+    remote_path: &str,
+    excludes: &[String],
+    filters: &[String],
+    delete_excluded: bool,
+) -> Result<Vec<String>> {
+    require_ssh(host, "rsync")?;
+
+    let mut args = vec!["--dry-run".to_string(), "--itemize-changes".to_string()];
+    args.extend(rsync_push_args(source, host, remote_path, excludes, filters, delete_excluded)?);
+    let output = with_retries(host, || {
+        let output = transport(host, "rsync")?
+            .args(&args)
+            .stderr(Stdio::inherit())
+            .output()
+            .context("Failed to run rsync")?;
+
+        if !output.status.success() {
+            if matches!(output.status.code(), Some(12 | 255)) {
+                if let Some(hint) = host_key_failure_hint(host) {
+                    anyhow::bail!(hint);
+                }
+            }
+            return Err(exit_failure("rsync dry run", output.status.code(), &[12, 30, 255]));
+        }
+        Ok(output)
+    })?;
+
+    Ok(parse_deletions(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// The `*deleting` entries of rsync's `--itemize-changes` output.
+fn parse_deletions(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| line.strip_prefix("*deleting"))
+        .map(|path| path.trim_start().to_string())
+        .collect()
+}
+
+/// Arguments for pushing `source` into `remote_path`, ending with the source and
+/// destination.
+fn rsync_push_args(
+    source: &str,
+    host: &Host,
+    remote_path: &str,
+    excludes: &[String],
+    filters: &[String],
+    delete_excluded: bool,
+) -> Result<Vec<String>> {
+    let mut args = vec![
+        "-az".to_string(),      // archive mode + compress
+        "--delete".to_string(), // delete files on remote that don't exist locally
+    ];
+
+    if delete_excluded {
+        args.push("--delete-excluded".to_string());
+    }
+
+    args.extend(rsync_shell_args(host));
+    args.extend(filters.iter().map(|filter| format!("--filter={}", filter)));
+    args.extend(manifest::rsync_filter_args(excludes, "")?);
+
+    // Source must end with / to sync contents, not the directory itself
+    let source_path = if source.ends_with('/') {
+        source.to_string()
+    } else {
+        format!("{}/", source)
+    };
+    args.push(source_path);
+
+    // Destination: host:path, with Windows paths in the Cygwin form rsync expects
+    args.push(format!("{}:{}", host.hostname, paths::cygwin_path(remote_path)));
+    Ok(args)
+}
+
+/// Download file or directory from remote using scp
+pub fn download_from_remote(
+    host: &Host,
+    remote_path: &str,
+    local_path: &str,
+    dry_run: bool,
+) -> Result<()> {
+    if let Some(ref target) = host.kubernetes {
+        return kubectl_cp(host, target, local_path, remote_path, false, dry_run);
+    }
+
+    let source = format!("{}:{}", host.hostname, windows_side_path(host, remote_path));
+
+    if dry_run {
+        eprintln!("Would download {} to {}", source, local_path);
+        return Ok(());
+    }
+
+    info!("Downloading {} to {}", source, local_path);
+    with_retries(host, || run_scp(host, &source, local_path))
+}
+
+/// Copy `from` to `to` with scp, one side being `host:path`.
+fn run_scp(host: &Host, from: &str, to: &str) -> Result<()> {
+    let status = transport(host, "scp")?
+        .arg("-r")
+        .args(ssh_options(host))
+        .arg(from)
+        .arg(to)
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .status()
+        .context("Failed to run scp")?;
+
+    if !status.success() {
+        return Err(scp_failure(status.code()));
+    }
+    Ok(())
+}
+
+/// scp exits 255 when its ssh connection fails, and 1 for everything else.
+fn scp_failure(code: Option<i32>) -> anyhow::Error {
+    exit_failure("scp", code, &[255])
+}
+
+/// Download file or directory from remote using rsync, transferring only what changed.
+/// Excludes apply inside the downloaded tree; `delete` removes local files that no
+/// longer exist on the remote.
+pub fn rsync_from_remote(
+    host: &Host,
+    remote_path: &str,
+    local_path: &str,
+    excludes: &[String],
+    delete: bool,
+    dry_run: bool,
+) -> Result<()> {
+    require_ssh(host, "rsync")?;
+
+    let mut args = vec!["-az".to_string()];
+
+    if delete {
+        args.push("--delete".to_string());
+    }
+
+    args.extend(rsync_shell_args(host));
+
+    if tracing::enabled!(Level::INFO) {
+        args.push("-v".to_string());
+    } else if output::is_quiet() {
+        args.push("--quiet".to_string());
+    }
+
+    if dry_run {
+        args.push("--dry-run".to_string());
+    }
+
+    // Always include the requested path itself, so downloading e.g. `target`
+    // isn't swallowed by an exclude of the same name
+    let name = paths::file_name(remote_path);
+    if !name.is_empty() {
+        args.push(format!("--include=/{}", name));
+    }
+
+    args.extend(manifest::rsync_filter_args(excludes, name)?);
+
+    let source = format!("{}:{}", host.hostname, paths::cygwin_path(remote_path));
+    args.push(source.clone());
+    args.push(local_path.to_string());
+
+    if dry_run {
+        let mut rsync = Command::new("rsync");
+        rsync.args(&args);
+        eprintln!("Would rsync {} to {}", source, local_path);
+        eprintln!("  {}", shown_invocation(host, &rsync));
+    }
+
+    info!("Running: rsync {}", args.join(" "));
+
+    with_retries(host, || run_rsync(host, &args))
+}
+
+/// Run rsync with `args`. A failed connection is reported as a [`ConnectionFailure`],
+/// unless it was host key verification.
+fn run_rsync(host: &Host, args: &[String]) -> Result<()> {
+    let status = transport(host, "rsync")?
+        .args(args)
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .status()
+        .context("Failed to run rsync")?;
+    debug!(exit_code = ?status.code(), "rsync finished");
+
+    if !status.success() {
+        // rsync reports a failed remote shell as a protocol error (12) or passes on 255
+        if matches!(status.code(), Some(12 | 255)) {
+            if let Some(hint) = host_key_failure_hint(host) {
+                anyhow::bail!(hint);
+            }
+        }
+        return Err(exit_failure("rsync", status.code(), &[12, 30, 255]));
+    }
+
+    Ok(())
+}
+
+/// rsync arguments that route its remote shell through ssh with the host's options
+fn rsync_shell_args(host: &Host) -> Vec<String> {
+    let mut args = Vec::new();
+
+    // Disable permission preservation for Windows to avoid DENY ACL issues
+    if host.shell.is_windows() {
+        args.push("--no-perms".to_string());
+    }
+
+    // The remote rsync is the one inside WSL
+    if host.shell == Shell::Wsl {
+        args.push(format!("--rsync-path={} -e rsync", wsl_exe(host)));
+    }
+
+    // Pass host-specific SSH options through rsync's remote shell
+    let options = ssh_options(host);
+    if !options.is_empty() {
+        args.push("-e".to_string());
+        args.push(ssh_command_line(&options));
+    }
+
+    args
+}
+
+/// An `ssh ...` command line for rsync's `-e` and mosh's `--ssh`. Both split it on
+/// whitespace, honoring quotes, so options containing spaces (e.g. an identity file
+/// path) are double-quoted.
+fn ssh_command_line(options: &[String]) -> String {
+    let mut shell = String::from("ssh");
+    for option in options {
+        shell.push(' ');
+        if option.contains(char::is_whitespace) {
+            shell.push_str(&format!("\"{}\"", option.replace('\\', "\\\\").replace('"', "\\\"")));
+        } else {
+            shell.push_str(option);
+        }
+    }
+    shell
+}
+
+/// Upload file to remote using scp
+pub fn upload_to_remote(
+    local_path: &str,
+    host: &Host,
+    remote_path: &str,
+    dry_run: bool,
+) -> Result<()> {
+    if let Some(ref target) = host.kubernetes {
+        return kubectl_cp(host, target, local_path, remote_path, true, dry_run);
+    }
+
+    let dest = format!("{}:{}", host.hostname, windows_side_path(host, remote_path));
+
+    if dry_run {
+        eprintln!("Would upload {} to {}", local_path, dest);
+        return Ok(());
+    }
+
+    info!("Uploading {} to {}", local_path, dest);
+    with_retries(host, || run_scp(host, local_path, &dest))
+}
+
+/// Compare the SHA-256 of a transferred file on both ends. Directories, and hosts that
+/// can't hash the file (no `sha256sum` or `shasum`), are skipped.
+pub fn verify_transfer(host: &Host, local_path: &Path, remote_path: &str) -> output::ChecksumStatus {
+    if !local_path.is_file() {
+        return output::ChecksumStatus::Skipped;
+    }
+    let Ok(local) = local_sha256(local_path) else {
+        return output::ChecksumStatus::Skipped;
+    };
+    let remote = capture_remote(host, &sha256_command(&host.shell, remote_path))
+        .ok()
+        .and_then(|output| parse_sha256(&output));
+    match remote {
+        Some(remote) if remote == local => output::ChecksumStatus::Match,
+        Some(_) => output::ChecksumStatus::Mismatch,
+        None => output::ChecksumStatus::Skipped,
+    }
+}
+
+fn local_sha256(path: &Path) -> io::Result<String> {
+    use sha2::{Digest, Sha256};
+
+    let mut hasher = Sha256::new();
+    io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// A command printing the SHA-256 of a remote file, for the host's shell.
+fn sha256_command(shell: &Shell, remote_path: &str) -> String {
+    let path = quote_path(shell, remote_path);
+    match shell {
+        Shell::Powershell => powershell_command(&format!("(Get-FileHash -Algorithm SHA256 -LiteralPath {}).Hash", path)),
+        Shell::Cmd => format!("certutil -hashfile {} SHA256", path),
+        _ => {
+            let path = quote_path(&Shell::Sh, remote_path);
+            sh_command(shell, &format!("sha256sum {p} 2>/dev/null || shasum -a 256 {p} 2>/dev/null", p = path))
+        }
+    }
+}
+
+/// The hash in `sha256sum`, `shasum`, `Get-FileHash`, or `certutil` output, lowercased.
+/// Older certutil versions separate the hex bytes with spaces.
+fn parse_sha256(output: &str) -> Option<String> {
+    let is_sha256 = |s: &str| s.len() == 64 && s.bytes().all(|b| b.is_ascii_hexdigit());
+    output.lines().find_map(|line| {
+        let first = line.split_whitespace().next()?;
+        let hash = if is_sha256(first) { first.to_string() } else { line.split_whitespace().collect() };
+        is_sha256(&hash).then(|| hash.to_lowercase())
+    })
+}
+
+/// Mount the remote directory at a local mountpoint with sshfs, using the host's
+/// SSH settings. Returns once the filesystem is mounted (sshfs daemonizes).
+pub fn mount_remote(host: &Host, remote_path: &str, mountpoint: &str, dry_run: bool) -> Result<()> {
+    require_ssh(host, "sshfs")?;
+    let args = sshfs_args(host, remote_path, mountpoint);
+
+    if dry_run {
+        eprintln!("Would run: sshfs {}", args.join(" "));
+        return Ok(());
+    }
+
+    info!("Running: sshfs {}", args.join(" "));
+
+    let status = match transport(host, "sshfs")?.args(&args).stdin(Stdio::inherit()).status() {
+        Ok(status) => status,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            anyhow::bail!("sshfs is not installed (install sshfs, or macFUSE and sshfs on macOS)")
+        }
+        Err(e) => return Err(e).context("Failed to run sshfs"),
+    };
+
+    if !status.success() {
+        anyhow::bail!("sshfs failed with exit code: {}", status.code().unwrap_or(1));
+    }
+
+    Ok(())
+}
+
+/// sshfs arguments for mounting `remote_path` at `mountpoint`.
+fn sshfs_args(host: &Host, remote_path: &str, mountpoint: &str) -> Vec<String> {
+    let remote_path = paths::sftp_path(&host.shell, &windows_side_path(host, remote_path));
+
+    let mut args = vec![
+        format!("{}:{}", host.hostname, remote_path),
+        mountpoint.to_string(),
+        "-o".to_string(),
+        format!(
+            "reconnect,ServerAliveInterval={},ServerAliveCountMax={}",
+            host.server_alive_interval.unwrap_or(15),
+            host.server_alive_count.unwrap_or(3)
+        ),
+    ];
+
+    // sshfs splits ssh_command on whitespace, honoring backslash escapes only
+    let options = ssh_options(host);
+    if !options.is_empty() {
+        let mut command = String::from("ssh");
+        for option in &options {
+            command.push(' ');
+            for c in option.chars() {
+                if c.is_whitespace() || c == '\\' {
+                    command.push('\\');
+                }
+                command.push(c);
+            }
+        }
+        args.extend(["-o".to_string(), format!("ssh_command={}", command)]);
+    }
+
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sshfs_args_carry_host_settings() {
+        let host: Host = toml::from_str(
+            "hostname = \"gpu\"\npath = \"/srv\"\nport = 2222\nssh_options = [\"-o\", \"ProxyCommand=nc -X 5 %h %p\"]\n",
+        )
+        .unwrap();
+        assert_eq!(
+            sshfs_args(&host, "/srv/app", "/mnt/app"),
+            [
+                "gpu:/srv/app",
+                "/mnt/app",
+                "-o",
+                "reconnect,ServerAliveInterval=15,ServerAliveCountMax=3",
+                "-o",
+                r"ssh_command=ssh -o Port=2222 -o ProxyCommand=nc\ -X\ 5\ %h\ %p",
+            ]
+        );
+
+        let windows: Host = toml::from_str("hostname = \"win\"\npath = \"C:/dev\"\nshell = \"powershell\"\n").unwrap();
+        assert_eq!(sshfs_args(&windows, "C:/dev/app", "/mnt/win")[0], "win:/C:/dev/app");
+    }
+
+    #[test]
+    fn only_connection_failures_are_retried() {
+        let host: Host = toml::from_str("hostname = \"box\"\npath = \"/srv\"\nretries = 2\nretry_delay = 0\n").unwrap();
+        let rsync_failure = |code| exit_failure("rsync", Some(code), &[12, 30, 255]);
+
+        let mut attempts = 0;
+        let result = with_retries(&host, || {
+            attempts += 1;
+            Err::<(), _>(rsync_failure(12))
+        });
+        assert_eq!(result.unwrap_err().to_string(), "rsync failed with exit code: 12");
+        assert_eq!(attempts, 3);
+
+        attempts = 0;
+        let result = with_retries(&host, || {
+            attempts += 1;
+            if attempts < 2 { Err(rsync_failure(30)) } else { Ok(attempts) }
+        });
+        assert_eq!(result.unwrap(), 2);
+
+        attempts = 0;
+        let result = with_retries(&host, || {
+            attempts += 1;
+            Err::<(), _>(rsync_failure(23))
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn scp_is_retried_only_when_its_connection_fails() {
+        let host: Host = toml::from_str("hostname = \"box\"\npath = \"/srv\"\nretries = 2\nretry_delay = 0\n").unwrap();
+
+        let mut attempts = 0;
+        let result = with_retries(&host, || {
+            attempts += 1;
+            if attempts < 3 { Err(scp_failure(Some(255))) } else { Ok(()) }
+        });
+        assert!(result.is_ok());
+        assert_eq!(attempts, 3);
+
+        attempts = 0;
+        let result = with_retries(&host, || {
+            attempts += 1;
+            Err::<(), _>(scp_failure(Some(1)))
+        });
+        assert_eq!(result.unwrap_err().to_string(), "scp failed with exit code: 1");
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn sha256_is_read_from_each_hashing_tool() {
+        let abc = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        assert_eq!(parse_sha256(&format!("{}  /srv/app/abc.txt\n", abc)).as_deref(), Some(abc));
+        assert_eq!(parse_sha256(&format!("{}\r\n", abc.to_uppercase())).as_deref(), Some(abc));
+
+        let spaced: Vec<String> = abc.as_bytes().chunks(2).map(|b| String::from_utf8_lossy(b).into_owned()).collect();
+        let certutil = format!(
+            "SHA256 hash of C:\\dev\\abc.txt:\r\n{}\r\nCertUtil: -hashfile command completed successfully.\r\n",
+            spaced.join(" ")
+        );
+        assert_eq!(parse_sha256(&certutil).as_deref(), Some(abc));
+        assert_eq!(parse_sha256("sh: sha256sum: not found\n"), None);
+
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("abc.txt");
+        std::fs::write(&file, "abc").unwrap();
+        assert_eq!(local_sha256(&file).unwrap(), abc);
+    }
+
+    #[test]
+    fn control_key_follows_connection_settings() {
+        let host: Host = toml::from_str("hostname = \"box\"\npath = \"/srv/a\"\n").unwrap();
+        let same_box: Host = toml::from_str("hostname = \"box\"\npath = \"/srv/b\"\nwrapper = \"{}\"\n").unwrap();
+        let other_user: Host = toml::from_str("hostname = \"box\"\npath = \"/srv/a\"\nuser = \"ci\"\n").unwrap();
+
+        assert_eq!(control_key(&host), control_key(&same_box));
+        assert_ne!(control_key(&host), control_key(&other_user));
+        assert_eq!(
+            control_socket(&host).unwrap().file_name().unwrap().len(),
+            16,
+            "socket names stay short enough for sun_path"
+        );
+    }
+
+    #[test]
+    fn host_key_checking_maps_to_ssh_options() {
+        let host: Host = toml::from_str("hostname = \"vm\"\npath = \"/srv\"\nhost_key_checking = \"accept-new\"\n").unwrap();
+        assert_eq!(ssh_options(&host), ["-o", "StrictHostKeyChecking=accept-new"]);
+
+        let host: Host = toml::from_str("hostname = \"vm\"\npath = \"/srv\"\nhost_key_checking = \"off\"\n").unwrap();
+        assert!(ssh_options(&host).contains(&"UserKnownHostsFile=/dev/null".to_string()));
+
+        assert!(is_host_key_failure("@@@ WARNING: REMOTE HOST IDENTIFICATION HAS CHANGED! @@@\nHost key verification failed.\n"));
+        assert!(!is_host_key_failure("ssh: connect to host vm port 22: Connection refused\n"));
+    }
+
+    #[test]
+    fn login_settings_become_ssh_options() {
+        let host: Host = toml::from_str(
+            "hostname = \"box\"\npath = \"/srv\"\nuser = \"deploy\"\nport = 2222\nidentity_file = \"~/keys/dev key\"\nproxy_jump = \"bastion\"\nssh_options = [\"-o\", \"Compression=yes\"]\n",
+        )
+        .unwrap();
+        let options = ssh_options(&host);
+        assert_eq!(
+            options,
+            [
+                "-o",
+                "User=deploy",
+                "-o",
+                "Port=2222",
+                "-o",
+                "IdentityFile=~/keys/dev key",
+                "-o",
+                "ProxyJump=bastion",
+                "-o",
+                "Compression=yes"
+            ]
+        );
+        assert_eq!(
+            ssh_command_line(&options),
+            r#"ssh -o User=deploy -o Port=2222 -o "IdentityFile=~/keys/dev key" -o ProxyJump=bastion -o Compression=yes"#
+        );
+    }
+
+    const ADVERSARIAL_PATHS: &[&str] = &[
+        "/home/user/$HOME/project",
+        "/home/user/`whoami`/project",
+        "/home/user/$(id -u)/project",
+        "/home/user/it's here",
+        "/home/user/\"quoted\"",
+        "/home/user/back\\slash",
+    ];
+
+    #[test]
+    fn bash_quoted_paths_are_not_expanded() {
+        for path in ADVERSARIAL_PATHS {
+            let output = Command::new("sh")
+                .arg("-c")
+                .arg(format!("printf %s {}", quote_path(&Shell::Bash, path)))
+                .output()
+                .unwrap();
+            assert_eq!(String::from_utf8_lossy(&output.stdout), *path);
+        }
+    }
+
+    #[test]
+    fn powershell_paths_use_single_quotes() {
+        assert_eq!(
+            quote_path(&Shell::Powershell, "C:/Users/$PROFILE/`n"),
+            "'C:/Users/$PROFILE/`n'"
+        );
+        assert_eq!(quote_path(&Shell::Powershell, "C:/it's"), "'C:/it''s'");
+        assert_eq!(quote_path(&Shell::Powershell, r#"C:/a"b"#), r#"'C:/a\"b'"#);
+    }
+
+    #[test]
+    fn cmd_paths_use_backslashes() {
+        assert_eq!(quote_path(&Shell::Cmd, "C:/Users/$name"), r#""C:\Users\$name""#);
+    }
+
+    #[test]
+    fn remote_exports_combine_env_files_and_flags() {
+        let mut host: Host = toml::from_str("hostname = \"vm\"\npath = \"/srv\"\n").unwrap();
+        let env_vars: HashMap<String, String> = [("BRIDGE_TEST_EXPORT_A", "1"), ("BRIDGE_TEST_EXPORT_B", "2")]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let pair = |k: &str, v: &str| (k.to_string(), v.to_string());
+
+        assert!(remote_exports(&host, &env_vars, &[]).unwrap().is_empty());
+
+        host.remote_env = vec!["BRIDGE_TEST_EXPORT_B".to_string()];
+        let flags = vec![pair("MODE", "ci")];
+        assert_eq!(
+            remote_exports(&host, &env_vars, &flags).unwrap(),
+            vec![pair("BRIDGE_TEST_EXPORT_B", "2"), pair("MODE", "ci")]
+        );
+
+        host.export_env = true;
+        let flags = vec![pair("BRIDGE_TEST_EXPORT_A", "override")];
+        assert_eq!(
+            remote_exports(&host, &env_vars, &flags).unwrap(),
+            vec![pair("BRIDGE_TEST_EXPORT_B", "2"), pair("BRIDGE_TEST_EXPORT_A", "override")]
+        );
+
+        host.remote_env = vec!["BRIDGE_TEST_EXPORT_MISSING".to_string()];
+        assert!(remote_exports(&host, &env_vars, &[]).is_err());
+        host.strict_env = false;
+        assert_eq!(remote_exports(&host, &env_vars, &[]).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn exports_are_quoted_per_shell() {
+        let exports = vec![("MODE".to_string(), "it's $HOME".to_string()), ("N".to_string(), "1".to_string())];
+        assert_eq!(export_statements(&Shell::Bash, &exports), r"export MODE='it'\''s $HOME' N='1' && ");
+        assert_eq!(export_statements(&Shell::Powershell, &exports), "$env:MODE = 'it''s $HOME'; $env:N = '1'; ");
+        assert_eq!(export_statements(&Shell::Cmd, &exports[1..]), r#"set "N=1" && "#);
+        assert_eq!(export_statements(&Shell::Fish, &exports[1..]), "set -gx N '1'; ");
+        assert_eq!(export_statements(&Shell::Nushell, &exports[1..]), r#"$env.N = "1"; "#);
+        assert_eq!(export_statements(&Shell::Bash, &[]), "");
+    }
+
+    #[test]
+    fn remote_command_quotes_path_per_shell() {
+        assert_eq!(
+            build_remote_command(&Shell::Bash, "/srv/$APP", "make"),
+            "cd '/srv/$APP' && make"
+        );
+        assert_eq!(
+            build_remote_command(&Shell::Powershell, "C:/dev/$x", r#"echo "hi""#),
+            powershell_command(r#"cd -LiteralPath 'C:/dev/$x'; echo \"hi\""#)
+        );
+        assert_eq!(
+            build_remote_command(&Shell::Cmd, "C:/dev/app", "build.bat"),
+            r#"chcp 65001 >nul && cd /d "C:\dev\app" && build.bat"#
+        );
+    }
+
+    #[test]
+    fn encoded_commands_decode_to_the_plain_script() {
+        let command = r#"echo "it's $HOME" `date`"#;
+        let bash = encoded_remote_command(&Shell::Bash, "/srv/app", command).unwrap();
+        let encoded = bash.strip_prefix("bash -c \"$(echo ").unwrap().strip_suffix(" | base64 -d)\"").unwrap();
+        assert_eq!(BASE64.decode(encoded).unwrap(), format!("cd '/srv/app' && {}", command).into_bytes());
+
+        let powershell_script = |path: &str| {
+            let powershell = encoded_remote_command(&Shell::Powershell, path, command).unwrap();
+            let encoded = powershell.strip_prefix("powershell -EncodedCommand ").unwrap();
+            let utf16: Vec<u16> =
+                BASE64.decode(encoded).unwrap().chunks(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect();
+            String::from_utf16(&utf16).unwrap()
+        };
+        assert!(powershell_script("C:/dev").ends_with(&format!("; cd -LiteralPath 'C:/dev'; {}", command)));
+        // `"` is an ordinary character inside '...'
+        let script = powershell_script(r#"C:/it's "dev""#);
+        assert!(script.ends_with(&format!(r#"; cd -LiteralPath 'C:/it''s "dev"'; {}"#, command)));
+
+        assert!(encoded_remote_command(&Shell::Cmd, "C:/dev", command).is_err());
+    }
+
+    #[test]
+    fn fish_and_nushell_quote_their_own_way() {
+        assert_eq!(quote_path(&Shell::Fish, r"/srv/it's\$x"), r"'/srv/it\'s\\$x'");
+        assert_eq!(quote_path(&Shell::Nushell, r#"/srv/"a"\b'c"#), r#""/srv/\"a\"\\b'c""#);
+        assert_eq!(build_remote_command(&Shell::Nushell, "/srv/app", "make"), r#"cd "/srv/app"; make"#);
+        assert_eq!(build_remote_command(&Shell::Zsh, "/srv/app", "make"), "cd '/srv/app' && make");
+    }
+
+    #[test]
+    fn helper_scripts_go_through_sh_for_non_posix_shells() {
+        assert_eq!(sh_command(&Shell::Zsh, "mkdir -p '/srv'"), "mkdir -p '/srv'");
+        assert_eq!(sh_command(&Shell::Fish, "mkdir -p '/srv'"), r"sh -c 'mkdir -p \'/srv\''");
+        assert_eq!(sh_command(&Shell::Nushell, "mkdir -p '/srv'"), r#"sh -c "mkdir -p '/srv'""#);
+    }
+
+    #[test]
+    fn container_commands_run_under_docker_exec() {
+        let host: Host = toml::from_str("hostname = \"box\"\npath = \"/srv/app\"\ncontainer = \"dev\"\n").unwrap();
+        assert_eq!(
+            prepare_remote_command(&host, "/srv/app", "echo it's", &HashMap::new(), &[("MODE".to_string(), "ci".to_string())], false)
+                .unwrap(),
+            r"docker exec -i -w '/srv/app' 'dev' sh -c 'export MODE='\''ci'\'' && echo it'\''s'"
+        );
+
+        let windows: Host =
+            toml::from_str("hostname = \"win\"\npath = \"C:/app\"\nshell = \"powershell\"\ncontainer = \"dev\"\n").unwrap();
+        assert!(prepare_remote_command(&windows, "C:/app", "dir", &HashMap::new(), &[], false).is_err());
+    }
+
+    #[test]
+    fn explained_commands_show_each_step() {
+        let host: Host =
+            toml::from_str("hostname = \"box\"\npath = \"/srv/app\"\nwrapper = \"nix develop -c {}\"\nstrict_env = true\n").unwrap();
+        let env_vars = HashMap::from([("TARGET".to_string(), "all".to_string())]);
+        let explained = explain_remote_command(&host, "/srv/app", "make ${TARGET}", &env_vars, &[], false).unwrap();
+        assert_eq!(explained.command, "make all");
+        assert_eq!(explained.wrapped.as_deref(), Some("nix develop -c make all"));
+        assert_eq!(explained.remote, "cd '/srv/app' && nix develop -c make all");
+        assert_eq!(
+            explained.invocation,
+            r"ssh -o ServerAliveInterval=5 -o ServerAliveCountMax=3 box 'cd '\''/srv/app'\'' && nix develop -c make all'"
+        );
+        assert!(explain_remote_command(&host, "/srv/app", "make ${MISSING}", &env_vars, &[], false).is_err());
+    }
+
+    #[test]
+    fn kubernetes_commands_run_through_kubectl() {
+        let host: Host = toml::from_str(
+            "path = \"/app\"\nshell = \"sh\"\n[kubernetes]\npod = \"api-0\"\nnamespace = \"dev\"\ncontainer = \"app\"\n",
+        )
+        .unwrap();
+        let cmd = remote_command(&host, "cd '/app' && make", Some("-t")).unwrap();
+        assert_eq!(cmd.get_program(), "kubectl");
+        let args: Vec<_> = cmd.get_args().map(|a| a.to_str().unwrap()).collect();
+        assert_eq!(args, ["-n", "dev", "exec", "-it", "api-0", "-c", "app", "--", "sh", "-c", "cd '/app' && make"]);
+
+        let target = host.kubernetes.as_ref().unwrap();
+        assert_eq!(kubectl_exec_args(&host, target, "true", false)[1], "-i");
+        assert!(rsync_to_remote(".", &host, "/app", &[], &[], false, true).is_err());
+    }
+
+    #[test]
+    fn wsl_commands_run_through_wsl_exe() {
+        let windows: Host = toml::from_str("hostname = \"win\"\npath = \"C:/app\"\nshell = \"powershell\"\n").unwrap();
+        assert_eq!(login_command(&windows, "make"), "make");
+
+        let host: Host =
+            toml::from_str("hostname = \"win\"\npath = \"C:/app\"\nshell = \"wsl\"\nwsl_distro = \"Ubuntu\"\n").unwrap();
+        let command = login_command(&host, "cd '/mnt/c/app' && echo \"$HOME\"");
+        let encoded = command
+            .strip_prefix(r#"wsl.exe -d Ubuntu -e bash -lc "source <(echo "#)
+            .and_then(|rest| rest.strip_suffix(r#" | base64 -d)""#))
+            .unwrap();
+        assert_eq!(BASE64.decode(encoded).unwrap(), b"cd '/mnt/c/app' && echo \"$HOME\"");
+
+        assert_eq!(windows_side_path(&host, "/mnt/c/Users/me/app"), "C:/Users/me/app");
+        assert_eq!(windows_side_path(&windows, "/mnt/c/app"), "/mnt/c/app");
+    }
+
+    #[test]
+    fn powershell_commands_switch_to_utf8() {
+        let cmd = powershell_command("Get-Date");
+        assert!(cmd.starts_with(r#"powershell -Command "$u = New-Object Text.UTF8Encoding $false;"#));
+        assert!(cmd.ends_with(r#"$OutputEncoding = $u; Get-Date""#));
+    }
+
+    #[test]
+    fn decode_output_handles_windows_encodings() {
+        assert_eq!(decode_output("héllo\n".as_bytes()), "héllo\n");
+        assert_eq!(decode_output(b"\xEF\xBB\xBFok"), "ok");
+        assert_eq!(decode_output(b"\xFF\xFEo\x00k\x00"), "ok");
+        assert_eq!(decode_output(b"o\x00k\x00"), "ok");
+        assert_eq!(decode_output(b"\xFE\xFF\x00o\x00k"), "ok");
+    }
+
+    #[test]
+    fn deletions_come_from_itemized_output() {
+        let output = "*deleting   old/notes.txt\n*deleting   old/\n<f+++++++++ src/new.rs\n.d..t...... src/\n";
+        assert_eq!(parse_deletions(output), ["old/notes.txt", "old/"]);
+    }
+
+    #[test]
+    fn free_space_counts_replaced_files() {
+        assert_eq!(parse_free_space("2048\n1024\n"), Some(3 * 1024 * 1024));
+        assert_eq!(parse_free_space("Welcome!\r\n2048\r\n"), Some(2048 * 1024));
+        assert_eq!(parse_free_space(""), None);
+        assert_eq!(
+            free_space_command(&Shell::Bash, "/srv/app", false),
+            r#"t='/srv/app'; p="$t"; while [ ! -e "$p" ]; do p=$(dirname "$p"); done; df -Pk "$p" | awk 'NR==2 { print $4 }'; true"#
+        );
+    }
+
+    #[test]
+    fn remote_dir_cache_is_keyed_by_login() {
+        let host: Host = toml::from_str("hostname = \"box\"\npath = \"/srv\"\nuser = \"deploy\"\nport = 2222\n").unwrap();
+        assert_eq!(remote_dir_key(&host, "/srv/app"), "deploy@box:2222:/srv/app");
+    }
+
+    #[test]
+    fn only_ssh_failures_count_as_a_lost_connection() {
+        assert!(connection_lost("Connection to gpu closed by remote host.\n"));
+        assert!(connection_lost("client_loop: send disconnect: Broken pipe\r\n"));
+        assert!(connection_lost("ssh: connect to host gpu port 22: Connection refused\n"));
+        assert!(connection_lost("Host key verification failed.\n"));
+        // The command exiting 255, whatever it printed earlier
+        assert!(!connection_lost("Error: Connection refused by the database\nretrying...\ngave up\n"));
+        assert!(!connection_lost("fatal: bad config\n"));
+        assert!(!connection_lost("Connection to gpu closed.\n"));
+        assert!(connection_lost("kex_exchange_identification: read: Connection reset by peer\r\n"));
+        assert!(connection_lost("Connection closed by 10.0.0.7 port 22\n"));
+        // The command's own errors, however much they sound like ssh's
+        assert!(!connection_lost("curl: (7) Failed to connect to localhost port 8080: Connection refused\n"));
+        assert!(!connection_lost("psql: error: connection to server failed: No route to host\n"));
+        // Failing to log in isn't losing the connection
+        assert!(!connection_lost("user@gpu: Permission denied (publickey).\n"));
+        assert!(!connection_lost(""));
+    }
+
+    #[test]
+    fn configured_timeouts_come_before_the_defaults() {
+        let host: Host = toml::from_str(
+            "hostname = \"sat\"\npath = \"/srv\"\nconnect_timeout = 60\nserver_alive_interval = 30\nserver_alive_count = 10\n",
+        )
+        .unwrap();
+        let args: Vec<String> =
+            remote_invocation(&host, "make", None).get_args().map(|a| a.to_string_lossy().into_owned()).collect();
+        let position = |option: &str| args.iter().position(|arg| arg == option).unwrap();
+        // ssh uses the first value given for an option
+        assert!(position("ServerAliveInterval=30") < position("ServerAliveInterval=5"));
+        assert!(position("ServerAliveCountMax=10") < position("ServerAliveCountMax=3"));
+        assert!(args.contains(&"ConnectTimeout=60".to_string()));
+        assert_eq!(sshfs_args(&host, "/srv", "/mnt")[3], "reconnect,ServerAliveInterval=30,ServerAliveCountMax=10");
+    }
+
+    #[test]
+    fn reconnect_checks_back_off_up_to_a_minute() {
+        let mut host: Host = toml::from_str("hostname = \"gpu\"\npath = \"/srv\"\n").unwrap();
+        assert_eq!(reconnect_delay(&host, 1), Duration::from_secs(5));
+        assert_eq!(reconnect_delay(&host, 9), Duration::from_secs(5));
+
+        host.reconnect_backoff = ReconnectBackoff::Exponential;
+        let delays: Vec<u64> = (1..=6).map(|attempt| reconnect_delay(&host, attempt).as_secs()).collect();
+        assert_eq!(delays, [5, 10, 20, 40, 60, 60]);
+        assert_eq!(reconnect_delay(&host, 200), RECONNECT_MAX_DELAY);
+
+        host.reconnect_interval = 120;
+        assert_eq!(reconnect_delay(&host, 3), Duration::from_secs(120));
+    }
+}
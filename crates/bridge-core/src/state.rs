@@ -0,0 +1,298 @@
+use anyhow::{Context, Result};
+use fs2::FileExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::Host;
+
+/// Local per-project record of recent bridge activity, used by `prompt-status` and `artifacts`.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct ProjectState {
+    pub last_run: Option<RunRecord>,
+    pub last_sync: Option<SyncRecord>,
+    /// Artifacts offloaded to an object store, oldest first
+    #[serde(default)]
+    pub artifacts: Vec<ArtifactRecord>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RunRecord {
+    pub host: String,
+    pub command: String,
+    pub exit_code: i32,
+    /// `${BRIDGE_RUN_ID}` the command ran with
+    #[serde(default)]
+    pub run_id: Option<String>,
+    /// Unix timestamp (seconds) when the command finished
+    pub finished_at: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SyncRecord {
+    pub host: String,
+    /// Unix timestamp (seconds) when the sync finished
+    pub finished_at: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ArtifactRecord {
+    pub host: String,
+    /// Remote path the artifact was uploaded from
+    pub source: String,
+    /// Object URL in the artifact store
+    pub url: String,
+    /// Whether the artifact is a directory (stored as a prefix)
+    pub recursive: bool,
+    /// Unix timestamp (seconds) when the upload finished
+    pub stored_at: u64,
+}
+
+/// Directory for bridge's local state: `$XDG_STATE_HOME/bridge` or `~/.local/state/bridge`.
+pub fn state_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("XDG_STATE_HOME").filter(|d| !d.is_empty()) {
+        return Some(PathBuf::from(dir).join("bridge"));
+    }
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state/bridge"))
+}
+
+/// Current time as a Unix timestamp in seconds.
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Load the state for a project. Missing or unreadable state yields the default.
+pub fn load(project_root: &Path) -> ProjectState {
+    state_file(project_root)
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Apply `f` to the project's state and persist it.
+pub fn update(project_root: &Path, f: impl FnOnce(&mut ProjectState)) -> Result<()> {
+    let path = state_file(project_root).context("Cannot determine state directory (HOME is not set)")?;
+    let mut state = load(project_root);
+    f(&mut state);
+
+    let dir = path.parent().context("State file has no parent directory")?;
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create state directory: {}", dir.display()))?;
+
+    // Write to a temp file and rename so concurrent readers never see a partial file
+    let temp = path.with_extension(format!("json.{}", std::process::id()));
+    fs::write(&temp, serde_json::to_string_pretty(&state)?)
+        .with_context(|| format!("Failed to write state file: {}", temp.display()))?;
+    fs::rename(&temp, &path).with_context(|| format!("Failed to write state file: {}", path.display()))?;
+
+    Ok(())
+}
+
+/// Hosts that recently failed a connection probe: SSH hostname to the Unix time of
+/// the failure. Shared by all projects.
+pub fn load_probe_failures() -> HashMap<String, u64> {
+    state_dir()
+        .and_then(|dir| fs::read_to_string(dir.join("unreachable.json")).ok())
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+pub fn store_probe_failures(failures: &HashMap<String, u64>) -> Result<()> {
+    store_shared("unreachable.json", failures)
+}
+
+/// Remote directories bridge created or found, as `host:path` keys to the Unix time
+/// they were last known to exist. Shared by all projects.
+pub fn load_remote_dirs() -> HashMap<String, u64> {
+    state_dir()
+        .and_then(|dir| fs::read_to_string(dir.join("remote-dirs.json")).ok())
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+pub fn store_remote_dirs(dirs: &HashMap<String, u64>) -> Result<()> {
+    store_shared("remote-dirs.json", dirs)
+}
+
+/// A sync or run queued by `--queue` while its host was unreachable, to be replayed by
+/// `bridge queue flush`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct QueuedCommand {
+    pub id: u64,
+    /// Name of the host in the config
+    pub host_name: String,
+    /// The host's settings when the command was queued, for checking whether it is back
+    pub host: Host,
+    /// Directory the command was run in
+    pub dir: PathBuf,
+    /// Active config profile
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// Arguments to `bridge`
+    pub args: Vec<String>,
+    /// Unix timestamp (seconds) when the command was queued
+    pub queued_at: u64,
+}
+
+/// Commands waiting for their host, oldest first. Shared by all projects.
+pub fn load_queue() -> Vec<QueuedCommand> {
+    state_dir()
+        .and_then(|dir| fs::read_to_string(dir.join("queue.json")).ok())
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Change the queue under an exclusive lock, so a `--queue` and a `bridge queue flush`
+/// running at the same time don't drop each other's changes.
+pub fn update_queue<T>(change: impl FnOnce(&mut Vec<QueuedCommand>) -> T) -> Result<T> {
+    let dir = state_dir().context("Cannot determine state directory (HOME is not set)")?;
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create state directory: {}", dir.display()))?;
+    let lock_path = dir.join("queue.lock");
+    let lock = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .with_context(|| format!("Failed to open lock file: {}", lock_path.display()))?;
+    lock.lock_exclusive()
+        .with_context(|| format!("Failed to lock {}", lock_path.display()))?;
+
+    let mut queue = load_queue();
+    let result = change(&mut queue);
+    store_shared("queue.json", &queue)?;
+    Ok(result)
+}
+
+/// History grows until it passes this size, then the older half is dropped.
+const HISTORY_MAX_BYTES: u64 = 4 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum HistoryKind {
+    Sync,
+    Run,
+}
+
+/// A finished sync or run, for `bridge history`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct HistoryEntry {
+    pub kind: HistoryKind,
+    /// Project root the command ran from
+    pub project: PathBuf,
+    /// Name of the host in the config
+    pub host: String,
+    /// The remote command, for runs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// Unix timestamp (seconds) when the command started
+    pub started_at: u64,
+    pub duration_ms: u64,
+    /// Exit code of a run; 0 or 1 for a sync that succeeded or failed
+    pub exit_code: i32,
+    /// The run's SSH connection was lost, so `exit_code` is ssh's 255 rather than the
+    /// command's own
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub connection_lost: bool,
+    /// Size of the files a tar sync sent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<String>,
+}
+
+/// Every recorded sync and run, oldest first. Shared by all projects; lines that
+/// can't be read are skipped.
+pub fn load_history() -> Vec<HistoryEntry> {
+    state_dir()
+        .and_then(|dir| fs::read_to_string(dir.join("history.jsonl")).ok())
+        .map(|content| content.lines().filter_map(|line| serde_json::from_str(line).ok()).collect())
+        .unwrap_or_default()
+}
+
+/// Add an entry to the history. Appends are a single write, so concurrent bridge
+/// processes don't interleave their lines.
+pub fn append_history(entry: &HistoryEntry) -> Result<()> {
+    let dir = state_dir().context("Cannot determine state directory (HOME is not set)")?;
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create state directory: {}", dir.display()))?;
+
+    let path = dir.join("history.jsonl");
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open history file: {}", path.display()))?;
+    file.write_all(line.as_bytes())
+        .with_context(|| format!("Failed to write history file: {}", path.display()))?;
+
+    if file.metadata().map(|m| m.len()).unwrap_or(0) > HISTORY_MAX_BYTES {
+        let history = load_history();
+        let kept: Vec<String> = history[history.len() / 2..]
+            .iter()
+            .map(|entry| serde_json::to_string(entry).map(|line| line + "\n"))
+            .collect::<Result<_, _>>()?;
+        let temp = path.with_extension(format!("jsonl.{}", std::process::id()));
+        fs::write(&temp, kept.concat()).with_context(|| format!("Failed to write history file: {}", temp.display()))?;
+        fs::rename(&temp, &path).with_context(|| format!("Failed to write history file: {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Write a state file shared by all projects. The queue keeps command lines and host
+/// settings, which may hold secrets, so these files are private to their owner.
+fn store_shared(name: &str, value: &impl Serialize) -> Result<()> {
+    let dir = state_dir().context("Cannot determine state directory (HOME is not set)")?;
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create state directory: {}", dir.display()))?;
+
+    let path = dir.join(name);
+    let temp = path.with_extension(format!("json.{}", std::process::id()));
+    let content = serde_json::to_string(value)?;
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+        .open(&temp)
+        .and_then(|mut file| file.write_all(content.as_bytes()))
+        .with_context(|| format!("Failed to write state file: {}", temp.display()))?;
+    fs::rename(&temp, &path).with_context(|| format!("Failed to write state file: {}", path.display()))?;
+
+    Ok(())
+}
+
+/// State file for a project, keyed by a hash of its root path.
+fn state_file(project_root: &Path) -> Option<PathBuf> {
+    let key = fnv1a(project_root.to_string_lossy().as_bytes());
+    state_dir().map(|dir| dir.join("projects").join(format!("{:016x}.json", key)))
+}
+
+/// Socket `bridge serve` listens on by default, next to the project's state file.
+pub fn socket_path(project_root: &Path) -> Option<PathBuf> {
+    state_file(project_root).map(|path| path.with_extension("sock"))
+}
+
+/// FNV-1a: a stable hash, so state file names don't change between builds.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fnv1a_is_stable() {
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+    }
+}
@@ -0,0 +1,231 @@
+use anyhow::{Context, Result};
+use std::process::{Command, Stdio};
+use tracing::info;
+
+use crate::config::{self, Config, Shell};
+use crate::output::{self, OutputFormat};
+use crate::paths;
+use crate::run_id;
+use crate::ssh;
+use crate::state::{self, ArtifactRecord};
+
+/// Artifact references kept per project; older ones are dropped.
+const MAX_ARTIFACTS: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum StoreKind {
+    S3,
+    Gcs,
+}
+
+/// Have the remote copy `paths` straight to the host's `artifact_store`, then record
+/// the resulting object URLs locally.
+pub fn push(paths: &[String], run_id: Option<&str>, host: Option<&str>, dry_run: bool) -> Result<()> {
+    let (config, config_path) = Config::find_and_load()?;
+    let (host_name, host_config) = super::select_host(&config, host, dry_run)?;
+    let project_root = Config::project_root(&config_path);
+    let remote_root = config::effective_remote_path(host_config, &project_root);
+
+    let store = host_config
+        .artifact_store
+        .as_deref()
+        .with_context(|| format!("Host '{}' has no artifact_store configured", host_name))?;
+    let kind = store_kind(store)?;
+    let resolved_run_id = run_id::resolve(run_id, &project_root)?;
+
+    // Objects are grouped under the explicit run ID, or the upload time
+    let prefix = match run_id {
+        Some(id) => id.to_string(),
+        None => state::now_secs().to_string(),
+    };
+
+    info!("Offloading from host: {} ({})", host_name, host_config.hostname);
+    info!("Artifact store: {}", store);
+
+    for path in paths {
+        let path = run_id::substitute(path, resolved_run_id.as_deref())?;
+        let remote_path = paths::resolve(&remote_root, &path);
+        let url = artifact_url(store, &prefix, &remote_path);
+        let push_cmd = push_command(&host_config.shell, kind, &remote_path, &url);
+
+        if dry_run {
+            eprintln!("Would upload {}:{} to {}", host_config.hostname, remote_path, url);
+            continue;
+        }
+
+        // The command prints whether it copied a directory, so `pull` knows how to fetch it
+        let copied = ssh::capture_remote(host_config, &push_cmd)
+            .with_context(|| format!("Failed to upload {} to {}", remote_path, url))?;
+        let recursive = copied.lines().any(|line| line.trim() == "dir");
+
+        state::update(&project_root, |s| {
+            s.artifacts.push(ArtifactRecord {
+                host: host_name.clone(),
+                source: remote_path.clone(),
+                url: url.clone(),
+                recursive,
+                stored_at: state::now_secs(),
+            });
+            let excess = s.artifacts.len().saturating_sub(MAX_ARTIFACTS);
+            s.artifacts.drain(..excess);
+        })?;
+
+        if !output::is_quiet() {
+            println!("Stored: {} -> {}", remote_path, url);
+        }
+    }
+
+    Ok(())
+}
+
+/// Print the recorded artifact references, newest first.
+pub fn list(format: OutputFormat) -> Result<()> {
+    let (_, config_path) = Config::find_and_load()?;
+    let project_root = Config::project_root(&config_path);
+    let artifacts: Vec<ArtifactRecord> = state::load(&project_root).artifacts.into_iter().rev().collect();
+
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&artifacts)?),
+        OutputFormat::Text => {
+            if artifacts.is_empty() {
+                println!("No artifacts recorded.");
+            }
+            for artifact in &artifacts {
+                println!("{}  {}:{}", artifact.url, artifact.host, artifact.source);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Download a recorded artifact from the store to this machine, bypassing the remote.
+/// `reference` is the artifact URL or the remote path it was stored from.
+pub fn pull(reference: &str, dest: Option<&str>, dry_run: bool) -> Result<()> {
+    let (_, config_path) = Config::find_and_load()?;
+    let project_root = Config::project_root(&config_path);
+    let project_state = state::load(&project_root);
+
+    let artifact = project_state
+        .artifacts
+        .iter()
+        .rev()
+        .find(|a| a.url == reference || a.source == reference || a.source.ends_with(&format!("/{}", reference)))
+        .with_context(|| format!("No recorded artifact matches: {}", reference))?;
+
+    let local_path = match dest {
+        Some(d) => d.to_string(),
+        None => paths::file_name(&artifact.source).to_string(),
+    };
+
+    let mut cmd = pull_command(store_kind(&artifact.url)?, &artifact.url, &local_path, artifact.recursive);
+    let program = cmd.remove(0);
+
+    if dry_run {
+        eprintln!("Would run: {} {}", program, cmd.join(" "));
+        return Ok(());
+    }
+
+    info!("Running: {} {}", program, cmd.join(" "));
+
+    let status = Command::new(&program)
+        .args(&cmd)
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .status()
+        .with_context(|| format!("Failed to run {}", program))?;
+
+    if !status.success() {
+        anyhow::bail!("{} failed with exit code: {}", program, status.code().unwrap_or(1));
+    }
+
+    if !output::is_quiet() {
+        println!("Download complete: {} -> {}", artifact.url, local_path);
+    }
+    Ok(())
+}
+
+fn store_kind(url: &str) -> Result<StoreKind> {
+    if url.starts_with("s3://") {
+        Ok(StoreKind::S3)
+    } else if url.starts_with("gs://") {
+        Ok(StoreKind::Gcs)
+    } else {
+        anyhow::bail!("Unsupported artifact store: {} (expected s3:// or gs://)", url)
+    }
+}
+
+/// Object URL for an artifact: `<store>/<run ID or unix time>/<file name>`.
+fn artifact_url(store: &str, prefix: &str, remote_path: &str) -> String {
+    format!("{}/{}/{}", store.trim_end_matches('/'), prefix, paths::file_name(remote_path))
+}
+
+/// Build a remote command that copies a file or directory to the store and prints
+/// `dir` or `file` to say which it was.
+fn push_command(shell: &Shell, kind: StoreKind, remote_path: &str, url: &str) -> String {
+    let (copy_file, copy_dir) = match kind {
+        StoreKind::S3 => ("aws s3 cp --only-show-errors", "aws s3 cp --only-show-errors --recursive"),
+        StoreKind::Gcs => ("gsutil -q cp", "gsutil -q -m cp -r"),
+    };
+
+    match shell {
+        Shell::Powershell | Shell::Cmd => {
+            let path = ssh::quote_path(&Shell::Powershell, remote_path);
+            let url = ssh::quote_path(&Shell::Powershell, url);
+            ssh::powershell_command(&format!(
+                "if (Test-Path -LiteralPath {p} -PathType Container) {{ {dir} {p} {u}; if ($?) {{ 'dir' }} }} else {{ {file} {p} {u}; if ($?) {{ 'file' }} }}; exit $LASTEXITCODE",
+                p = path,
+                u = url,
+                dir = copy_dir,
+                file = copy_file
+            ))
+        }
+        _ => {
+            let path = ssh::quote_path(&Shell::Sh, remote_path);
+            let url = ssh::quote_path(&Shell::Sh, url);
+            ssh::sh_command(shell, &format!(
+                "if [ -d {p} ]; then {dir} {p} {u} && echo dir; else {file} {p} {u} && echo file; fi",
+                p = path,
+                u = url,
+                dir = copy_dir,
+                file = copy_file
+            ))
+        }
+    }
+}
+
+/// Local command (program first) that downloads an artifact from the store.
+fn pull_command(kind: StoreKind, url: &str, local_path: &str, recursive: bool) -> Vec<String> {
+    let mut cmd: Vec<String> = match kind {
+        StoreKind::S3 => vec!["aws".into(), "s3".into(), "cp".into()],
+        StoreKind::Gcs if recursive => vec!["gsutil".into(), "-m".into(), "cp".into()],
+        StoreKind::Gcs => vec!["gsutil".into(), "cp".into()],
+    };
+    if recursive {
+        cmd.push(if kind == StoreKind::S3 { "--recursive" } else { "-r" }.into());
+    }
+    cmd.push(url.to_string());
+    cmd.push(local_path.to_string());
+    cmd
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn artifact_url_uses_stamp_and_name() {
+        assert_eq!(artifact_url("s3://bucket/runs/", "42", "/srv/app/out/model.bin"), "s3://bucket/runs/42/model.bin");
+        assert_eq!(artifact_url("gs://bucket", "exp-7", r"C:\dev\app\dist\"), "gs://bucket/exp-7/dist");
+        assert!(store_kind("https://example.com").is_err());
+    }
+
+    #[test]
+    fn pull_command_recurses_for_directories() {
+        assert_eq!(
+            pull_command(StoreKind::S3, "s3://b/1/dist", "dist", true),
+            vec!["aws", "s3", "cp", "--recursive", "s3://b/1/dist", "dist"]
+        );
+        assert_eq!(pull_command(StoreKind::Gcs, "gs://b/1/a.bin", "a.bin", false), vec!["gsutil", "cp", "gs://b/1/a.bin", "a.bin"]);
+    }
+}
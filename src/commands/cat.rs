@@ -0,0 +1,36 @@
+use anyhow::Result;
+use tracing::info;
+
+use crate::config::{self, Config, Shell};
+use crate::paths;
+use crate::ssh;
+
+pub fn run(file: &str, host: Option<&str>, dry_run: bool) -> Result<i32> {
+    let (config, config_path) = Config::find_and_load()?;
+    let (host_name, host_config) = super::select_host(&config, host, dry_run)?;
+    let project_root = Config::project_root(&config_path);
+    let remote_root = config::effective_remote_path(host_config, &project_root);
+
+    let remote_path = paths::resolve(&remote_root, file);
+    let cat_cmd = cat_command(&host_config.shell, &remote_path);
+
+    info!("Reading from host: {} ({})", host_name, host_config.hostname);
+    info!("Remote path: {}", remote_path);
+
+    if dry_run {
+        eprintln!("Would run: ssh {} {}", host_config.hostname, cat_cmd);
+        return Ok(0);
+    }
+
+    ssh::exec_remote(host_config, &cat_cmd).map(ssh::Exit::code)
+}
+
+/// Build a command that writes a remote file to stdout for the remote shell.
+fn cat_command(shell: &Shell, remote_path: &str) -> String {
+    let path = ssh::quote_path(shell, remote_path);
+    match shell {
+        Shell::Powershell => ssh::powershell_command(&format!("Get-Content -Raw -LiteralPath {}", path)),
+        Shell::Cmd => format!("type {}", path),
+        _ => ssh::sh_command(shell, &format!("cat {}", ssh::quote_path(&Shell::Sh, remote_path))),
+    }
+}
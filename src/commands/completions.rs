@@ -0,0 +1,75 @@
+use anyhow::{Context, Result};
+use clap_complete::engine::CompletionCandidate;
+use clap_complete::env::Shells;
+use clap_complete::Shell;
+use std::io::{self, Write};
+
+use crate::config::Config;
+
+/// Environment variable the registration script sets when asking bridge for completions.
+const COMPLETE_VAR: &str = "COMPLETE";
+
+/// Print a completion script for `shell` to stdout. By default the script calls back
+/// into bridge, which completes host, group, tag, and profile names from the config of
+/// the directory being completed in; `static_script` prints a standalone script instead.
+pub fn run(shell: Shell, static_script: bool, command: &mut clap::Command) -> Result<()> {
+    let name = command.get_name().to_string();
+    // Generated into a buffer first: clap_complete panics if a write to stdout fails
+    let mut script = Vec::new();
+    if static_script {
+        clap_complete::generate(shell, command, name, &mut script);
+    } else {
+        let shells = Shells::builtins();
+        let completer = shells
+            .completer(&shell.to_string())
+            .with_context(|| format!("Dynamic completion is not available for {}; use --static", shell))?;
+        completer.write_registration(COMPLETE_VAR, &name, &name, &name, &mut script)?;
+    }
+    io::stdout().write_all(&script).context("Failed to write completion script")
+}
+
+/// Configured host names, described by their hostname.
+pub fn host_names() -> Vec<CompletionCandidate> {
+    let Some(config) = load() else {
+        return Vec::new();
+    };
+    let mut hosts: Vec<_> = config.hosts.iter().collect();
+    hosts.sort_by_key(|(name, _)| *name);
+    hosts
+        .into_iter()
+        .map(|(name, host)| CompletionCandidate::new(name).help(Some(host.hostname.clone().into())))
+        .collect()
+}
+
+pub fn group_names() -> Vec<CompletionCandidate> {
+    let Some(config) = load() else {
+        return Vec::new();
+    };
+    sorted(config.groups.keys().cloned())
+}
+
+pub fn tags() -> Vec<CompletionCandidate> {
+    let Some(config) = load() else {
+        return Vec::new();
+    };
+    sorted(config.hosts.values().flat_map(|h| h.tags.iter().cloned()))
+}
+
+pub fn profile_names() -> Vec<CompletionCandidate> {
+    let Some(config) = load() else {
+        return Vec::new();
+    };
+    sorted(config.profiles.keys().cloned())
+}
+
+/// The config for the current directory; completion quietly offers nothing without one.
+fn load() -> Option<Config> {
+    Config::find_and_load().ok().map(|(config, _)| config)
+}
+
+fn sorted(names: impl Iterator<Item = String>) -> Vec<CompletionCandidate> {
+    let mut names: Vec<String> = names.collect();
+    names.sort();
+    names.dedup();
+    names.into_iter().map(CompletionCandidate::new).collect()
+}
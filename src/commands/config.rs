@@ -0,0 +1,249 @@
+use anyhow::{Context, Result};
+use std::fs;
+use tracing::info;
+
+use crate::config::{self, Config};
+use crate::output::OutputFormat;
+
+/// Print the fully resolved config: every layer merged, the profile applied, hosts'
+/// inherited settings filled in, and `${VAR}` expanded.
+pub fn show(format: OutputFormat) -> Result<()> {
+    let (config, _) = Config::find_and_load()?;
+    // Going through a TOML value sorts the keys (hosts are a HashMap)
+    let effective = toml::Value::try_from(&config).context("Failed to serialize effective config")?;
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&effective)?),
+        OutputFormat::Text => print!("{}", toml::to_string_pretty(&effective).context("Failed to render config")?),
+    }
+    Ok(())
+}
+
+/// Print the effective value of a dotted key. Strings are printed bare, for scripts.
+pub fn get(key: &str) -> Result<()> {
+    let (config, _) = Config::find_and_load()?;
+    let effective = toml::Value::try_from(&config).context("Failed to serialize effective config")?;
+    let path = split_key(key)?;
+    let value = lookup(&effective, &path).with_context(|| format!("'{}' is not set and has no default", key))?;
+
+    match value {
+        toml::Value::String(s) => println!("{}", s),
+        toml::Value::Table(_) => println!("{}", render(key, value)?),
+        _ => println!("{}", value),
+    }
+    Ok(())
+}
+
+/// Set a dotted key in the project config file, keeping its comments and formatting.
+/// `value` is read as a TOML value (`true`, `22`, `["a", "b"]`) and otherwise as a string.
+pub fn set(key: &str, value: &str) -> Result<()> {
+    let path = split_key(key)?;
+    let parsed: toml_edit::Value = value.parse().unwrap_or_else(|_| toml_edit::Value::from(value));
+    let shown = parsed.to_string().trim().to_string();
+
+    edit_config_checked(
+        |doc| set_key(doc, &path, parsed),
+        |config| {
+            let effective = toml::Value::try_from(config).context("Failed to serialize effective config")?;
+            match lookup(&effective, &path) {
+                Some(_) => Ok(()),
+                None => anyhow::bail!("'{}' is not a recognized setting", key),
+            }
+        },
+    )?;
+
+    println!("{} = {}", key, shown);
+    Ok(())
+}
+
+/// Load the project config as an editable document, apply `f`, and write it back. The
+/// result is parsed as a config first, so an edit can't leave the file unloadable.
+pub(super) fn edit_config<F>(f: F) -> Result<()>
+where
+    F: FnOnce(&mut toml_edit::DocumentMut) -> Result<()>,
+{
+    edit_config_checked(f, |_| Ok(()))
+}
+
+/// As `edit_config`, additionally running `check` on the edited config before saving.
+fn edit_config_checked<F, C>(f: F, check: C) -> Result<()>
+where
+    F: FnOnce(&mut toml_edit::DocumentMut) -> Result<()>,
+    C: FnOnce(&Config) -> Result<()>,
+{
+    let (_, config_path) = Config::find_and_load()?;
+    if config_path.extension().and_then(|e| e.to_str()) != Some("toml") {
+        anyhow::bail!(
+            "Editing the config is only supported for bridge.toml; edit {} by hand",
+            config_path.display()
+        );
+    }
+    let content = fs::read_to_string(&config_path)
+        .with_context(|| format!("Failed to read config file: {}", config_path.display()))?;
+    let mut doc: toml_edit::DocumentMut = content
+        .parse()
+        .with_context(|| format!("Failed to parse config file: {}", config_path.display()))?;
+
+    f(&mut doc)?;
+
+    let updated = doc.to_string();
+    let mut layers = config::config_layers(&config_path)?;
+    layers[0].1 = toml::from_str(&updated).context("Edited config is invalid")?;
+    let edited = config::from_layers(&layers).context("Edited config is invalid")?;
+    check(&edited)?;
+    fs::write(&config_path, updated).with_context(|| format!("Failed to write {}", config_path.display()))?;
+    Ok(())
+}
+
+/// Set `path` in the document, creating any missing tables after the existing ones.
+fn set_key(doc: &mut toml_edit::DocumentMut, path: &[&str], value: toml_edit::Value) -> Result<()> {
+    let (last, parents) = path.split_last().context("Empty config key")?;
+    let mut next_position = max_position(doc.as_table()).map_or(0, |p| p + 1);
+
+    let mut table: &mut dyn toml_edit::TableLike = doc.as_table_mut();
+    for (depth, part) in parents.iter().enumerate() {
+        let item = table.entry(part).or_insert_with(|| {
+            let mut child = toml_edit::Table::new();
+            if depth + 1 < parents.len() {
+                // Only the innermost new table gets a header of its own
+                child.set_implicit(true);
+            } else {
+                child.set_position(next_position);
+                if next_position > 0 {
+                    child.decor_mut().set_prefix("\n");
+                }
+                next_position += 1;
+            }
+            toml_edit::Item::Table(child)
+        });
+        table = item
+            .as_table_like_mut()
+            .with_context(|| format!("'{}' is not a table", path[..=depth].join(".")))?;
+    }
+
+    match table.get_mut(last) {
+        Some(existing) if existing.is_table_like() => {
+            anyhow::bail!("'{}' is a table; set one of its keys instead", path.join("."))
+        }
+        Some(toml_edit::Item::Value(existing)) => {
+            // Keep the existing spacing and trailing comment
+            let decor = existing.decor().clone();
+            *existing = value;
+            *existing.decor_mut() = decor;
+        }
+        _ => {
+            table.insert(last, toml_edit::Item::Value(value));
+        }
+    }
+    Ok(())
+}
+
+pub(super) fn max_position(table: &toml_edit::Table) -> Option<usize> {
+    table
+        .iter()
+        .filter_map(|(_, item)| item.as_table())
+        .flat_map(|child| [child.position(), max_position(child)])
+        .flatten()
+        .max()
+}
+
+/// Print the effective value of a dotted config key (e.g. `hosts.dev.lock_timeout`)
+/// and the layer that supplied it.
+pub fn explain(key: &str, verbose: bool) -> Result<()> {
+    let (config, config_path) = Config::find_and_load()?;
+    let layers = config::config_layers(&config_path)?;
+    let effective = toml::Value::try_from(&config).context("Failed to serialize effective config")?;
+
+    info!("Config loaded from: {}", config_path.display());
+
+    let path = split_key(key)?;
+    // Host settings may come from the host itself, a host it extends, or [host_defaults]
+    let candidates = config::inherited_key_paths(&config, &path);
+    let source = candidates.iter().find_map(|candidate| {
+        let candidate: Vec<&str> = candidate.iter().map(String::as_str).collect();
+        layers
+            .iter()
+            .find(|(_, value)| lookup(value, &candidate).is_some())
+            .map(|layer| (layer, candidate))
+    });
+
+    let Some(value) = lookup(&effective, &path) else {
+        match source {
+            Some(((layer, _), _)) => anyhow::bail!("'{}' is set in {} but is not a recognized setting", key, layer),
+            None => anyhow::bail!("'{}' is not set in any layer and has no default", key),
+        }
+    };
+
+    println!("{}", render(key, value)?);
+    match source {
+        Some(((layer, _), ref found)) if *found == path => println!("  source: {}", layer),
+        Some(((layer, _), ref found)) => println!("  source: {} ({})", layer, found.join(".")),
+        None => println!("  source: built-in default"),
+    }
+
+    if verbose {
+        if let Some((source_layer, found)) = source {
+            // Lower layers whose value was overridden
+            for (layer, raw) in layers.iter().skip_while(|l| *l != source_layer).skip(1) {
+                if let Some(shadowed) = lookup(raw, &found) {
+                    println!("  overrides {}: {}", layer, render(key, shadowed)?);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn split_key(key: &str) -> Result<Vec<&str>> {
+    let path: Vec<&str> = key.split('.').collect();
+    if path.iter().any(|part| part.is_empty()) {
+        anyhow::bail!("Invalid config key: '{}' (expected dotted form, e.g. hosts.dev.path)", key);
+    }
+    Ok(path)
+}
+
+fn lookup<'a>(value: &'a toml::Value, path: &[&str]) -> Option<&'a toml::Value> {
+    path.iter().try_fold(value, |current, part| current.get(part))
+}
+
+/// `key = value` for scalars and arrays; tables are printed as a TOML section.
+fn render(key: &str, value: &toml::Value) -> Result<String> {
+    match value {
+        toml::Value::Table(table) => {
+            let body = toml::to_string(table).context("Failed to render config value")?;
+            Ok(format!("[{}]\n{}", key, body.trim_end()))
+        }
+        _ => Ok(format!("{} = {}", key, value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_key_preserves_formatting() {
+        let mut doc: toml_edit::DocumentMut = "# Team config\ndefault_host = \"dev\"\n\n[hosts.dev]\nhostname = \"dev\"\nshell = \"bash\"   # login shell\n"
+            .parse()
+            .unwrap();
+
+        set_key(&mut doc, &["hosts", "dev", "shell"], "\"powershell\"".parse().unwrap()).unwrap();
+        set_key(&mut doc, &["hosts", "dev", "lock_timeout"], "30".parse().unwrap()).unwrap();
+        set_key(&mut doc, &["sync", "exclude"], "[\".git\"]".parse().unwrap()).unwrap();
+        assert_eq!(
+            doc.to_string(),
+            "# Team config\ndefault_host = \"dev\"\n\n[hosts.dev]\nhostname = \"dev\"\nshell = \"powershell\"   # login shell\nlock_timeout = 30\n\n[sync]\nexclude = [\".git\"]\n"
+        );
+        assert!(set_key(&mut doc, &["hosts", "dev"], "1".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn lookup_follows_dotted_keys() {
+        let value: toml::Value = toml::from_str("[hosts.dev]\nlock_timeout = 30\ntags = [\"gpu\"]\n").unwrap();
+        let path = split_key("hosts.dev.lock_timeout").unwrap();
+        assert_eq!(render("hosts.dev.lock_timeout", lookup(&value, &path).unwrap()).unwrap(), "hosts.dev.lock_timeout = 30");
+        assert_eq!(render("hosts.dev.tags", &value["hosts"]["dev"]["tags"]).unwrap(), r#"hosts.dev.tags = ["gpu"]"#);
+        assert!(lookup(&value, &["hosts", "prod", "path"]).is_none());
+        assert!(split_key("hosts..path").is_err());
+    }
+}
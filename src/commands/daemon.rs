@@ -0,0 +1,172 @@
+use anstyle::Style;
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::task::JoinSet;
+use tokio::time;
+use tracing::info;
+
+use crate::config::{Config, Host};
+use crate::output;
+use crate::runtime;
+use crate::ssh;
+use crate::status;
+use crate::style;
+
+/// How often a connecting master is checked for its control socket.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Wait before reconnecting after a master connection drops.
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+/// A master connection the daemon keeps open.
+struct Master {
+    name: String,
+    host: Host,
+    socket: PathBuf,
+}
+
+/// Keep authenticated SSH master connections open to the selected hosts (`--host`,
+/// or `--group`/`--tag`, or every configured host) until stopped. Other bridge
+/// invocations route through them and skip the connection handshake.
+pub fn run(host: Option<&str>, groups: &[String], tags: &[String], status: bool, stop: bool) -> Result<i32> {
+    if cfg!(windows) {
+        anyhow::bail!("bridge daemon is not supported on Windows (OpenSSH there has no connection multiplexing)");
+    }
+
+    let (config, _) = Config::find_and_load()?;
+    let names: Vec<&String> = match host {
+        Some(name) if groups.is_empty() && tags.is_empty() => vec![config.get_host(Some(name))?.0],
+        _ => config.select_hosts(groups, tags)?,
+    };
+
+    // Hosts that only differ in path share a connection
+    let mut masters: Vec<Master> = Vec::new();
+    for name in names {
+        let host = &config.hosts[name];
+        if host.kubernetes.is_some() {
+            status!("{}: Kubernetes host, no SSH connection to hold", name);
+            continue;
+        }
+        if let Some(ref error) = host.expand_error {
+            eprintln!("Warning: skipping {}: {}", name, error);
+            continue;
+        }
+        let Some(socket) = ssh::control_socket(host) else {
+            anyhow::bail!("Cannot determine state directory (HOME is not set)");
+        };
+        if masters.iter().any(|m| m.socket == socket) {
+            continue;
+        }
+        masters.push(Master { name: name.clone(), host: host.clone(), socket });
+    }
+    if masters.is_empty() {
+        anyhow::bail!("No SSH hosts to connect to");
+    }
+
+    if status {
+        let mut exit_code = 0;
+        let mut rows = Vec::new();
+        for master in &masters {
+            let state = if ssh::master_command(&master.host, "check") {
+                ("connected".to_string(), style::GOOD)
+            } else {
+                exit_code = 1;
+                ("not connected".to_string(), style::BAD)
+            };
+            rows.push(vec![(master.name.clone(), Style::new()), state, (master.host.hostname.clone(), style::DIM)]);
+        }
+        for line in style::table(&rows) {
+            println!("{}", line);
+        }
+        return Ok(exit_code);
+    }
+
+    if stop {
+        for master in &masters {
+            if ssh::master_command(&master.host, "exit") {
+                if !output::is_quiet() {
+                    println!("Closed connection to {}", master.name);
+                }
+            } else {
+                info!("No connection to {}", master.name);
+            }
+        }
+        return Ok(0);
+    }
+
+    supervise(masters)?;
+    Ok(0)
+}
+
+/// Start the master connections and restart any that drop, until every one of them
+/// has been closed with `bridge daemon --stop`.
+fn supervise(masters: Vec<Master>) -> Result<()> {
+    let masters: Vec<Master> = masters
+        .into_iter()
+        .filter(|master| {
+            // Already served by another daemon; leave it alone
+            let served = ssh::master_command(&master.host, "check");
+            if served {
+                status!("{}: already connected", master.name);
+            }
+            !served
+        })
+        .collect();
+
+    status!("Keeping connections open (stop with `bridge daemon --stop` or Ctrl-C)");
+
+    runtime::block_on(async {
+        let mut tasks = JoinSet::new();
+        for master in masters {
+            tasks.spawn(hold(master));
+        }
+        while let Some(joined) = tasks.join_next().await {
+            joined?;
+        }
+        Ok(())
+    })
+}
+
+/// Keep one master connection open, reconnecting whenever it drops, until it is
+/// closed with `bridge daemon --stop`.
+async fn hold(master: Master) {
+    loop {
+        info!("Connecting to {} ({})", master.name, master.host.hostname);
+        let started = ssh::master_process(&master.host).and_then(|cmd| {
+            tokio::process::Command::from(cmd)
+                .kill_on_drop(true)
+                .spawn()
+                .context("Failed to spawn SSH master connection")
+        });
+        let mut child = match started {
+            Ok(child) => child,
+            Err(e) => {
+                eprintln!("Error on {}: {:#}", master.name, e);
+                time::sleep(RECONNECT_DELAY).await;
+                continue;
+            }
+        };
+
+        let mut connected = false;
+        let status = loop {
+            tokio::select! {
+                status = child.wait() => break status,
+                () = time::sleep(POLL_INTERVAL), if !connected => {
+                    if master.socket.exists() {
+                        connected = true;
+                        status!("Connected to {}", master.name);
+                    }
+                }
+            }
+        };
+
+        // A clean exit means the master was told to close (`ssh -O exit`)
+        if status.is_ok_and(|status| status.success()) {
+            status!("Closed connection to {}", master.name);
+            return;
+        }
+        eprintln!("Lost connection to {}; reconnecting in {}s", master.name, RECONNECT_DELAY.as_secs());
+        time::sleep(RECONNECT_DELAY).await;
+    }
+}
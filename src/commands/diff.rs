@@ -0,0 +1,66 @@
+use anyhow::{Context, Result};
+use std::process::{Command, Stdio};
+use tempfile::TempDir;
+use tracing::info;
+
+use crate::config::{self, Config};
+use crate::paths;
+use crate::ssh;
+
+/// Diff the remote copy of `file` against the local one. Relative paths name the same
+/// file on both sides: they are resolved against the project root, locally and on
+/// the remote.
+pub fn run(file: &str, host: Option<&str>, dry_run: bool) -> Result<i32> {
+    let (config, config_path) = Config::find_and_load()?;
+    let (host_name, host_config) = super::select_host(&config, host, dry_run)?;
+    let project_root = Config::project_root(&config_path);
+    let remote_root = config::effective_remote_path(host_config, &project_root);
+
+    let remote_path = paths::resolve(&remote_root, file);
+    let local_path = project_root.join(file);
+
+    if !local_path.exists() && !dry_run {
+        anyhow::bail!("Local file does not exist: {}", local_path.display());
+    }
+
+    info!("Diffing against host: {} ({})", host_name, host_config.hostname);
+    info!("Remote path: {}", remote_path);
+    info!("Local path: {}", local_path.display());
+
+    if dry_run {
+        eprintln!(
+            "Would diff {} against {}:{}",
+            local_path.display(),
+            host_config.hostname,
+            remote_path
+        );
+        return Ok(0);
+    }
+
+    // Fetch the remote copy into a private temp directory, removed (with whatever was
+    // fetched) when it is dropped
+    let temp_dir = TempDir::with_prefix("bridge-diff-").context("Failed to create temp directory")?;
+    let file_name = local_path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("remote");
+    let temp_path = temp_dir.path().join(file_name);
+    let temp_str = temp_path.to_str().context("Temp path contains invalid UTF-8")?;
+    ssh::download_from_remote(host_config, &remote_path, temp_str, false)?;
+
+    // diff exits 0 when identical, 1 when different, 2 on trouble
+    let status = Command::new("diff")
+        .arg("-u")
+        .arg("-L")
+        .arg(format!("{}:{}", host_config.hostname, remote_path))
+        .arg("-L")
+        .arg(file)
+        .arg(&temp_path)
+        .arg(&local_path)
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .status()
+        .context("Failed to run diff")?;
+
+    Ok(status.code().unwrap_or(2))
+}
@@ -0,0 +1,364 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use tracing::info;
+
+use crate::config::{self, Config, Host, Shell, SyncMethod};
+use crate::ssh;
+use crate::style;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Level {
+    Ok,
+    Warn,
+    Fail,
+}
+
+/// Tallies and prints diagnostic results.
+#[derive(Default)]
+struct Report {
+    failures: usize,
+    warnings: usize,
+}
+
+impl Report {
+    fn section(&self, title: &str) {
+        println!("{}", style::out(style::HEADER, title));
+    }
+
+    fn check(&mut self, level: Level, message: &str, fix: Option<&str>) {
+        let label = match level {
+            Level::Ok => style::out(style::GOOD, "ok  "),
+            Level::Warn => {
+                self.warnings += 1;
+                style::out(style::WARN, "warn")
+            }
+            Level::Fail => {
+                self.failures += 1;
+                style::out(style::BAD, "FAIL")
+            }
+        };
+        println!("  {}  {}", label, message);
+        if let Some(fix) = fix {
+            println!("        -> {}", fix);
+        }
+    }
+
+    fn ok(&mut self, message: &str) {
+        self.check(Level::Ok, message, None);
+    }
+}
+
+/// Diagnose the local setup and the selected host (or every host with `all`): config
+/// validity, local tools, reachability, remote tools, the remote path, and the shell.
+/// Returns 1 if any check failed.
+pub fn run(host: Option<&str>, all: bool) -> Result<i32> {
+    let mut report = Report::default();
+
+    report.section("Config");
+    let (config, config_path) = match Config::find_and_load() {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            let message = format!("{:#}", e);
+            let fix = if e.downcast_ref::<config::ConfigNotFound>().is_some() {
+                "Run `bridge init` in the project root"
+            } else {
+                "Fix the error above; `bridge config explain <key>` shows where a value comes from"
+            };
+            report.check(Level::Fail, &message, Some(fix));
+            return Ok(1);
+        }
+    };
+    report.ok(&format!("Loaded {}", config_path.display()));
+    let project_root = Config::project_root(&config_path);
+    check_config(&mut report, &config, &project_root);
+
+    report.section("Local tools");
+    check_local_tools(&mut report, &config);
+
+    let names: Vec<&String> = if all {
+        let mut names: Vec<&String> = config.hosts.keys().collect();
+        names.sort();
+        names
+    } else {
+        match config.get_host(host) {
+            Ok((name, _)) => vec![name],
+            Err(e) => {
+                report.check(Level::Fail, &format!("{:#}", e), Some("Pass --host <name>, or --all to check every host"));
+                vec![]
+            }
+        }
+    };
+
+    for name in names {
+        let host_config = &config.hosts[name];
+        // Reported with the config checks; there is no hostname to connect to
+        if host_config.expand_error.is_some() {
+            continue;
+        }
+        report.section(&format!("Host {} ({})", name, host_config.hostname));
+        let remote_path = config::effective_remote_path(host_config, &project_root);
+        check_host(&mut report, name, host_config, &remote_path);
+    }
+
+    println!();
+    let summary = match (report.failures, report.warnings) {
+        (0, 0) => style::out(style::GOOD, "No problems found."),
+        (0, warnings) => style::out(style::WARN, &format!("0 failure(s), {} warning(s).", warnings)),
+        (failures, warnings) => style::out(style::BAD, &format!("{} failure(s), {} warning(s).", failures, warnings)),
+    };
+    println!("{}", summary);
+    Ok(if report.failures > 0 { 1 } else { 0 })
+}
+
+/// Cross-references the loader doesn't check: host names, wrappers, and env files.
+fn check_config(report: &mut Report, config: &Config, project_root: &Path) {
+    let mut problems = 0;
+    let mut problem = |report: &mut Report, level, message: String, fix: &str| {
+        problems += 1;
+        report.check(level, &message, Some(fix));
+    };
+
+    match config.default_host.as_deref() {
+        None => problem(report, Level::Warn, "No default_host set".to_string(), "Set one with `bridge hosts set-default <name>`, or pass --host"),
+        Some(name) if !config.hosts.contains_key(name) => problem(
+            report,
+            Level::Fail,
+            format!("default_host '{}' is not a configured host", name),
+            "Fix default_host, or add the host with `bridge hosts add`",
+        ),
+        Some(_) => {}
+    }
+
+    let mut hosts: Vec<(&String, &Host)> = config.hosts.iter().collect();
+    hosts.sort_by_key(|(name, _)| *name);
+    for (name, host) in hosts {
+        if let Some(ref error) = host.expand_error {
+            problem(report, Level::Fail, error.clone(), "Set the variable, or set strict_env = false on the host");
+        }
+        for fallback in host.fallback_hosts.iter().filter(|f| !config.hosts.contains_key(*f)) {
+            problem(
+                report,
+                Level::Fail,
+                format!("hosts.{}.fallback_hosts names unknown host '{}'", name, fallback),
+                "Remove it from fallback_hosts or add the host",
+            );
+        }
+        if let Some(wrapper) = host.wrapper.as_deref().filter(|w| !w.contains("{}")) {
+            problem(
+                report,
+                Level::Fail,
+                format!("hosts.{}.wrapper has no {{}} placeholder: {}", name, wrapper),
+                "Add {} where the command should go, e.g. \"source ~/.profile && {}\"",
+            );
+        }
+        for file in host.env_files.iter().filter(|f| !project_root.join(f).exists()) {
+            problem(
+                report,
+                Level::Warn,
+                format!("hosts.{}.env_files entry not found locally: {}", name, file),
+                "Create the file, or remove it from env_files",
+            );
+        }
+    }
+
+    let mut groups: Vec<(&String, &Vec<String>)> = config.groups.iter().collect();
+    groups.sort_by_key(|(name, _)| *name);
+    for (group, members) in groups {
+        for member in members.iter().filter(|m| !config.hosts.contains_key(*m)) {
+            problem(
+                report,
+                Level::Fail,
+                format!("groups.{} names unknown host '{}'", group, member),
+                "Remove it from the group or add the host",
+            );
+        }
+    }
+
+    if problems == 0 {
+        report.ok("Host references, wrappers, and env files are consistent");
+    }
+}
+
+fn check_local_tools(report: &mut Report, config: &Config) {
+    let uses = |f: fn(&Host) -> bool| config.hosts.values().any(f);
+
+    let mut tools = vec![
+        ("ssh", "-V", true, "Install OpenSSH (e.g. `apt install openssh-client`)"),
+        ("scp", "-V", true, "Install OpenSSH (scp ships with it)"),
+    ];
+    if uses(|h| h.sync_method == SyncMethod::Tar) {
+        tools.push(("tar", "--version", true, "Install tar"));
+    }
+    if uses(|h| h.sync_method == SyncMethod::Rsync) {
+        tools.push(("rsync", "--version", true, "Install rsync (e.g. `brew install rsync`), or use sync_method = \"tar\""));
+    }
+    if uses(|h| h.password_auth) {
+        tools.push(("sshpass", "-V", true, "Install sshpass, or switch to key-based login"));
+    }
+    if uses(|h| h.kubernetes.is_some()) {
+        tools.push(("kubectl", "version", true, "Install kubectl (https://kubernetes.io/docs/tasks/tools/)"));
+    }
+    if uses(|h| h.mosh) {
+        tools.push(("mosh", "--version", false, "Install mosh; until then sessions fall back to ssh"));
+    }
+
+    for (tool, version_arg, required, fix) in tools {
+        if local_tool_exists(tool, version_arg) {
+            report.ok(&format!("{} found", tool));
+        } else {
+            let level = if required { Level::Fail } else { Level::Warn };
+            report.check(level, &format!("{} not found in PATH", tool), Some(fix));
+        }
+    }
+}
+
+/// Whether `tool` can be started. Only a missing binary counts; some tools exit
+/// non-zero for their version flag.
+fn local_tool_exists(tool: &str, version_arg: &str) -> bool {
+    match Command::new(tool).arg(version_arg).stdout(Stdio::null()).stderr(Stdio::null()).status() {
+        Ok(_) => true,
+        Err(e) => e.kind() != ErrorKind::NotFound,
+    }
+}
+
+fn check_host(report: &mut Report, name: &str, host: &Host, remote_path: &str) {
+    info!("Connecting to {} ...", host.hostname);
+    if !ssh::check_connection(host) {
+        if let Some(ref target) = host.kubernetes {
+            let fix = format!(
+                "Check that the pod is running (`kubectl get pod {}`) in the host's context and namespace",
+                target.pod
+            );
+            report.check(Level::Fail, "Could not exec into the pod", Some(&fix));
+            return;
+        }
+        let fix = ssh::host_key_failure_hint(host).unwrap_or_else(|| {
+            format!(
+                "Check that `ssh {}` works from this machine (address, ~/.ssh/config, keys); `bridge -v` shows the ssh commands used",
+                host.hostname
+            )
+        });
+        report.check(Level::Fail, "SSH connection failed", Some(&fix));
+        return;
+    }
+    report.ok(if host.kubernetes.is_some() { "kubectl exec works" } else { "SSH connection works" });
+
+    let facts = match ssh::probe_remote(host, &probe_command(&host.shell, remote_path)) {
+        Some(output) => parse_probe(&output),
+        None => {
+            // A probe in the configured shell failed; see if the remote speaks the other kind
+            let other = if host.shell.is_windows() { Shell::Bash } else { Shell::Powershell };
+            let detected = ssh::probe_remote(host, &probe_command(&other, remote_path)).is_some();
+            // `sh -c '...'` works from every Unix login shell, so it can tell which one it is
+            let login_shell = (!host.shell.is_windows() && host.shell != Shell::Wsl)
+                .then(|| ssh::probe_remote(host, &ssh::sh_command(&Shell::Fish, "echo $SHELL")))
+                .flatten()
+                .and_then(|output| shell_from_path(output.trim()));
+            let fix = match (detected, &other, login_shell) {
+                (true, _, _) if host.shell == Shell::Wsl => format!(
+                    "The remote is Windows, but WSL didn't run the command; check `wsl.exe --list` on it (and hosts.{}.wsl_distro)",
+                    name
+                ),
+                (true, Shell::Bash, _) => format!("The remote looks like a Unix host; run `bridge config set hosts.{}.shell bash`", name),
+                (true, _, _) => format!("The remote looks like Windows; run `bridge config set hosts.{}.shell powershell`", name),
+                (false, _, Some(shell)) if shell != host.shell => format!(
+                    "The remote login shell is {}; run `bridge config set hosts.{}.shell {}`",
+                    shell, name, shell
+                ),
+                (false, _, _) => "Check that the remote login shell works non-interactively (no prompts in .bashrc/profile)".to_string(),
+            };
+            report.check(Level::Fail, &format!("Remote did not accept {} commands", host.shell), Some(&fix));
+            return;
+        }
+    };
+    report.ok(&format!("Remote shell matches shell = \"{}\"", host.shell));
+
+    let (tool, fix) = match host.sync_method {
+        SyncMethod::Tar => ("tar", "Install tar on the remote (Windows 10+ ships tar.exe)"),
+        SyncMethod::Rsync => ("rsync", "Install rsync on the remote, or use sync_method = \"tar\""),
+    };
+    if facts.get(tool).map(String::as_str) == Some("yes") {
+        report.ok(&format!("{} found on the remote", tool));
+    } else {
+        report.check(Level::Fail, &format!("{} not found on the remote (needed for sync_method = \"{}\")", tool, tool), Some(fix));
+    }
+
+    match (facts.get("dir").map(String::as_str), facts.get("writable").map(String::as_str)) {
+        (Some("yes"), Some("yes")) => report.ok(&format!("{} exists and is writable", remote_path)),
+        (Some("yes"), _) => report.check(
+            Level::Fail,
+            &format!("{} is not writable", remote_path),
+            Some(&format!("Fix its ownership on the remote, or point hosts.{}.path somewhere writable", name)),
+        ),
+        _ => report.check(
+            Level::Warn,
+            &format!("{} does not exist yet", remote_path),
+            Some("It is created on the first `bridge sync`"),
+        ),
+    }
+}
+
+/// One remote command that reports `key=value` lines: `tar`/`rsync` availability and
+/// whether `remote_path` exists (`dir`) and is writable.
+fn probe_command(shell: &Shell, remote_path: &str) -> String {
+    match shell {
+        // Writability is tested by creating a file, since ACLs make it hard to tell otherwise
+        Shell::Powershell | Shell::Cmd => ssh::powershell_command(&format!(
+            "foreach ($t in 'tar','rsync') {{ if (Get-Command $t -ErrorAction SilentlyContinue) {{ $t + '=yes' }} else {{ $t + '=no' }} }}; \
+             $p = {}; if (Test-Path -LiteralPath $p -PathType Container) {{ 'dir=yes'; \
+             try {{ $f = Join-Path $p ('.bridge-doctor-' + [Guid]::NewGuid().ToString('N')); New-Item -ItemType File -Path $f -ErrorAction Stop | Out-Null; Remove-Item -LiteralPath $f; 'writable=yes' }} catch {{ 'writable=no' }} }} else {{ 'dir=no' }}",
+            ssh::quote_path(&Shell::Powershell, remote_path)
+        )),
+        _ => ssh::sh_command(shell, &format!(
+            "for t in tar rsync; do if command -v $t >/dev/null 2>&1; then echo $t=yes; else echo $t=no; fi; done; \
+             p={}; if [ -d \"$p\" ]; then echo dir=yes; if [ -w \"$p\" ]; then echo writable=yes; else echo writable=no; fi; else echo dir=no; fi",
+            ssh::quote_path(&Shell::Sh, remote_path)
+        )),
+    }
+}
+
+/// The `shell` setting for a login shell path such as `/usr/bin/fish`.
+fn shell_from_path(path: &str) -> Option<Shell> {
+    match path.rsplit('/').next()? {
+        "bash" => Some(Shell::Bash),
+        "zsh" => Some(Shell::Zsh),
+        "fish" => Some(Shell::Fish),
+        "sh" | "dash" | "ash" | "ksh" => Some(Shell::Sh),
+        "nu" => Some(Shell::Nushell),
+        _ => None,
+    }
+}
+
+fn parse_probe(output: &str) -> HashMap<String, String> {
+    output
+        .lines()
+        .filter_map(|line| line.trim().split_once('='))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn probe_output_is_parsed_per_key() {
+        let facts = parse_probe("tar=yes\r\nrsync=no\nbanner text\ndir=yes\nwritable=no\n");
+        assert_eq!(facts["tar"], "yes");
+        assert_eq!(facts["rsync"], "no");
+        assert_eq!(facts["writable"], "no");
+        assert_eq!(facts.len(), 4);
+        assert!(probe_command(&Shell::Bash, "/srv/it's").contains(r"p='/srv/it'\''s'"));
+    }
+
+    #[test]
+    fn login_shell_paths_map_to_settings() {
+        assert_eq!(shell_from_path("/usr/bin/fish"), Some(Shell::Fish));
+        assert_eq!(shell_from_path("/bin/dash"), Some(Shell::Sh));
+        assert_eq!(shell_from_path("/opt/nu/bin/nu"), Some(Shell::Nushell));
+        assert_eq!(shell_from_path("/usr/bin/tcsh"), None);
+    }
+}
@@ -1,28 +1,85 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
+use std::fs;
 use std::path::Path;
+use std::time::Instant;
+use tracing::info;
 
-use crate::config::{self, Config};
+use crate::config::{self, Config, Host, Shell, SyncMethod};
+use crate::output::{self, OutputFormat, TransferReport};
+use crate::paths;
+use crate::run_id;
 use crate::ssh;
 
 pub fn run(
-    file: &str,
+    files: &[String],
     dest: Option<&str>,
+    format: OutputFormat,
+    delete: bool,
+    run_id: Option<&str>,
     host: Option<&str>,
     dry_run: bool,
-    verbose: bool,
 ) -> Result<()> {
     let (config, config_path) = Config::find_and_load()?;
-    let (host_name, host_config) = config.get_host(host)?;
+    let (host_name, host_config) = super::select_host(&config, host, dry_run)?;
     let project_root = Config::project_root(&config_path);
     let remote_root = config::effective_remote_path(host_config, &project_root);
 
-    // Build remote path
-    let remote_path = if file.starts_with('/') || file.starts_with('~') || file.contains(':') {
-        file.to_string()
-    } else {
-        format!("{}/{}", remote_root, file)
+    if delete && host_config.sync_method != SyncMethod::Rsync {
+        anyhow::bail!("--delete requires sync_method = \"rsync\" for host '{}'", host_name);
+    }
+
+    // rsync downloads honor the same excludes as sync
+    let excludes = super::sync::excludes(&config, host_config, false);
+
+    // Remote paths may refer to a run's outputs via ${BRIDGE_RUN_ID}
+    let resolved_run_id = run_id::resolve(run_id, &project_root)?;
+    let files = files
+        .iter()
+        .map(|f| run_id::substitute(f, resolved_run_id.as_deref()))
+        .collect::<Result<Vec<_>>>()?;
+    let files = files.as_slice();
+
+    // An explicit --run-id namespaces the download under `<dest>/<run-id>/`,
+    // so outputs of parallel runs never overwrite each other locally
+    let namespace = match (run_id, dest) {
+        (Some(_), Some("-")) | (None, _) => None,
+        (Some(id), dest) => Some(format!("{}/{}", dest.unwrap_or(".").trim_end_matches('/'), id)),
     };
 
+    // Multiple paths or remote globs download into a directory, preserving structure
+    if namespace.is_some() || files.len() > 1 || files.iter().any(|f| is_glob(f)) {
+        if dest == Some("-") {
+            anyhow::bail!("--dest - can only be used with a single remote file");
+        }
+        let remote = Remote { host_name, host: host_config, root: &remote_root, excludes: &excludes };
+        return download_many(files, namespace.as_deref().or(dest).unwrap_or("."), &remote, format, delete, dry_run);
+    }
+
+    let file = files[0].as_str();
+
+    // Build remote path
+    let remote_path = paths::resolve(&remote_root, file);
+
+    // `--dest -` streams the remote file to stdout, so nothing else may be printed there
+    if dest == Some("-") {
+        if format == OutputFormat::Json {
+            anyhow::bail!("--format json cannot be used with --dest - (stdout carries the file contents)");
+        }
+
+        if delete {
+            anyhow::bail!("--delete cannot be used with --dest -");
+        }
+
+        if dry_run {
+            eprintln!("Would download {}:{} to stdout", host_config.hostname, remote_path);
+            return Ok(());
+        }
+
+        ssh::download_stdout(host_config, &remote_path)
+            .with_context(|| format!("Failed to download {} from {}", remote_path, host_name))?;
+        return Ok(());
+    }
+
     // Determine local destination
     let local_path = match dest {
         Some(d) => d.to_string(),
@@ -35,23 +92,299 @@ pub fn run(
         }
     };
 
-    if verbose {
-        eprintln!("Downloading from host: {} ({})", host_name, host_config.hostname);
-        eprintln!("Remote path: {}", remote_path);
-        eprintln!("Local path: {}", local_path);
-    }
+    info!("Downloading from host: {} ({})", host_name, host_config.hostname);
+    info!("Remote path: {}", remote_path);
+    info!("Local path: {}", local_path);
+
+    // scp places the download inside an existing destination directory
+    let landed_path = if Path::new(&local_path).is_dir() {
+        let remote_name = Path::new(&remote_path)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("");
+        Path::new(&local_path).join(remote_name)
+    } else {
+        Path::new(&local_path).to_path_buf()
+    };
+
+    let start = Instant::now();
+    let retries = ssh::retry_count();
 
-    ssh::download_from_remote(
-        &host_config.hostname,
+    fetch(
+        host_config,
         &remote_path,
         &local_path,
+        &excludes,
+        delete,
         dry_run,
-        verbose,
     )?;
 
-    if !dry_run {
-        println!("Download complete: {} -> {}", remote_path, local_path);
+    if dry_run {
+        return Ok(());
+    }
+
+    match format {
+        OutputFormat::Text => {
+            if !output::is_quiet() {
+                println!("Download complete: {} -> {}", remote_path, local_path);
+            }
+        }
+        OutputFormat::Json => output::print_transfers(&[TransferReport::new(
+            "download",
+            host_name,
+            &remote_path,
+            &local_path,
+            output::path_size(&landed_path),
+            start.elapsed(),
+            ssh::retry_count() - retries,
+        )
+        .with_checksum(ssh::verify_transfer(host_config, &landed_path, &remote_path))])?,
     }
 
     Ok(())
 }
+
+/// The host files are downloaded from, its remote project root, and the excludes
+/// rsync downloads honor.
+struct Remote<'a> {
+    host_name: &'a str,
+    host: &'a Host,
+    root: &'a str,
+    excludes: &'a [String],
+}
+
+/// Download several remote paths (globs expanded on the remote) into `dest_dir`,
+/// recreating each file's path relative to the remote project root.
+fn download_many(
+    files: &[String],
+    dest_dir: &str,
+    remote: &Remote,
+    format: OutputFormat,
+    delete: bool,
+    dry_run: bool,
+) -> Result<()> {
+    let Remote { host_name, host, root: remote_root, excludes } = *remote;
+    let remote_paths = expand_remote_paths(files, host, remote_root, dry_run)?;
+
+    info!("Downloading from host: {} ({})", host_name, host.hostname);
+    info!("Local directory: {}", dest_dir);
+
+    let mut reports = Vec::new();
+    for remote_path in &remote_paths {
+        let local_path = Path::new(dest_dir).join(relative_download_path(remote_root, remote_path));
+        let local_str = local_path.to_str().context("Local path contains invalid UTF-8")?;
+
+        if !dry_run {
+            if let Some(parent) = local_path.parent() {
+                fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+            }
+        }
+
+        let start = Instant::now();
+        let retries = ssh::retry_count();
+        fetch(host, remote_path, local_str, excludes, delete, dry_run)?;
+
+        if dry_run {
+            continue;
+        }
+
+        match format {
+            OutputFormat::Text => {
+                if !output::is_quiet() {
+                    println!("Download complete: {} -> {}", remote_path, local_str);
+                }
+            }
+            OutputFormat::Json => reports.push(TransferReport::new(
+                "download",
+                host_name,
+                remote_path,
+                local_str,
+                output::path_size(&local_path),
+                start.elapsed(),
+                ssh::retry_count() - retries,
+            )
+            .with_checksum(ssh::verify_transfer(host, &local_path, remote_path))),
+        }
+    }
+
+    if format == OutputFormat::Json && !dry_run {
+        output::print_transfers(&reports)?;
+    }
+
+    Ok(())
+}
+
+/// Resolve remote paths against the project root, expanding globs on the remote.
+fn expand_remote_paths(files: &[String], host: &Host, remote_root: &str, dry_run: bool) -> Result<Vec<String>> {
+    let mut remote_paths = Vec::new();
+    for file in files {
+        if !is_glob(file) {
+            remote_paths.push(paths::resolve(remote_root, file));
+            continue;
+        }
+
+        if dry_run {
+            eprintln!("Would expand {} on {}", file, host.hostname);
+            continue;
+        }
+
+        let listing = ssh::capture_remote(host, &glob_command(&host.shell, remote_root, file))?;
+        let before = remote_paths.len();
+        remote_paths.extend(
+            listing
+                .lines()
+                .map(|line| line.trim())
+                .filter(|line| !line.is_empty())
+                .map(|line| paths::resolve(remote_root, line)),
+        );
+
+        if remote_paths.len() == before {
+            anyhow::bail!("No remote files match pattern: {}", file);
+        }
+    }
+
+    Ok(remote_paths)
+}
+
+/// Copy remote paths (globs expanded on the remote) back into the local project, each
+/// to its place relative to the project root, merging into existing directories.
+/// Used by `run --sync-back`.
+pub(super) fn sync_back(
+    files: &[String],
+    host: &Host,
+    project_root: &Path,
+    remote_root: &str,
+    excludes: &[String],
+    dry_run: bool,
+) -> Result<()> {
+    for remote_path in expand_remote_paths(files, host, remote_root, dry_run)? {
+        let local_path = project_root.join(relative_download_path(remote_root, &remote_path));
+        let local_str = local_path.to_str().context("Local path contains invalid UTF-8")?;
+
+        if !dry_run {
+            if let Some(parent) = local_path.parent() {
+                fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+            }
+        }
+
+        match host.sync_method {
+            // scp would nest a directory inside an existing local copy, so target its parent
+            SyncMethod::Tar => {
+                let target = rsync_target(&remote_path, local_str);
+                ssh::download_from_remote(host, &remote_path, &target, dry_run)?;
+            }
+            SyncMethod::Rsync => fetch(host, &remote_path, local_str, excludes, false, dry_run)?,
+        }
+
+        if !dry_run && !output::is_quiet() {
+            println!("Synced back: {} -> {}", remote_path, local_path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Transfer one remote path with the host's sync method: scp for tar hosts,
+/// incremental rsync for rsync hosts.
+fn fetch(
+    host: &Host,
+    remote_path: &str,
+    local_path: &str,
+    excludes: &[String],
+    delete: bool,
+    dry_run: bool,
+) -> Result<()> {
+    match host.sync_method {
+        SyncMethod::Tar => ssh::download_from_remote(host, remote_path, local_path, dry_run),
+        SyncMethod::Rsync => {
+            let target = rsync_target(remote_path, local_path);
+            ssh::rsync_from_remote(host, remote_path, &target, excludes, delete, dry_run)
+        }
+    }
+}
+
+/// rsync copies a directory *into* its destination, so when the local path is just
+/// the remote name in some directory, hand rsync that directory instead.
+fn rsync_target(remote_path: &str, local_path: &str) -> String {
+    let remote_name = paths::file_name(remote_path);
+    let local = Path::new(local_path);
+
+    if local.file_name().and_then(|n| n.to_str()) != Some(remote_name) {
+        return local_path.to_string();
+    }
+
+    match local.parent().and_then(|p| p.to_str()) {
+        Some("") | None => "./".to_string(),
+        Some(parent) => format!("{}/", parent),
+    }
+}
+
+fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
+
+/// Build a command that prints the remote paths matching a glob pattern, one per line.
+/// Relative patterns are expanded from the remote project root.
+fn glob_command(shell: &Shell, remote_root: &str, pattern: &str) -> String {
+    match shell {
+        Shell::Powershell | Shell::Cmd => ssh::powershell_command(&format!(
+            "Set-Location -LiteralPath {}; Resolve-Path -Path {} | ForEach-Object {{ $_.Path }}",
+            ssh::quote_path(&Shell::Powershell, remote_root),
+            ssh::quote_path(&Shell::Powershell, pattern)
+        )),
+        _ => ssh::sh_command(shell, &format!(
+            r#"cd {} && for f in {}; do [ -e "$f" ] && printf '%s\n' "$f"; done; true"#,
+            ssh::quote_path(&Shell::Sh, remote_root),
+            escape_glob(pattern)
+        )),
+    }
+}
+
+/// Backslash-escape everything in a glob pattern except wildcards and ordinary path
+/// characters, so the remote shell expands the glob but nothing else.
+fn escape_glob(pattern: &str) -> String {
+    let mut escaped = String::with_capacity(pattern.len());
+    for (i, c) in pattern.chars().enumerate() {
+        let literal = c.is_ascii_alphanumeric() || "*?[]!^/._-+=,:@%".contains(c) || (c == '~' && i == 0);
+        if !literal {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Local path for a downloaded file: its path under the remote root (Windows
+/// separators normalized), or just the file name for paths outside the root.
+fn relative_download_path(remote_root: &str, remote_path: &str) -> String {
+    paths::relative_to(remote_root, remote_path).unwrap_or_else(|| paths::file_name(remote_path).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_download_path_preserves_structure_under_root() {
+        assert_eq!(relative_download_path("/srv/app", "/srv/app/logs/a.log"), "logs/a.log");
+        assert_eq!(relative_download_path("/srv/app/", "/srv/app/out.txt"), "out.txt");
+        assert_eq!(relative_download_path("C:/dev/app", r"C:\dev\app\bin\a.exe"), "bin/a.exe");
+        assert_eq!(relative_download_path("/srv/app", "/var/log/syslog"), "syslog");
+    }
+
+    #[test]
+    fn rsync_target_uses_parent_when_names_match() {
+        assert_eq!(rsync_target("/srv/app/dist", "dist"), "./");
+        assert_eq!(rsync_target("/srv/app/logs/a.log", "out/logs/a.log"), "out/logs/");
+        assert_eq!(rsync_target(r"C:\dev\app\bin", "bin"), "./");
+        assert_eq!(rsync_target("/srv/app/app.bin", "renamed.bin"), "renamed.bin");
+    }
+
+    #[test]
+    fn escape_glob_keeps_wildcards_only() {
+        assert_eq!(escape_glob("logs/*.log"), "logs/*.log");
+        assert_eq!(escape_glob("my dir/$(rm -rf)*"), r"my\ dir/\$\(rm\ -rf\)*");
+        assert_eq!(escape_glob("~/out/[ab]?.txt"), "~/out/[ab]?.txt");
+    }
+}
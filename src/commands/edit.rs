@@ -0,0 +1,125 @@
+use anyhow::{Context, Result};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use tempfile::TempDir;
+use tracing::info;
+
+use crate::config::{self, Config, Shell};
+use crate::output;
+use crate::paths;
+use crate::ssh;
+
+pub fn run(file: &str, host: Option<&str>, dry_run: bool) -> Result<()> {
+    let (config, config_path) = Config::find_and_load()?;
+    let (host_name, host_config) = super::select_host(&config, host, dry_run)?;
+    let project_root = Config::project_root(&config_path);
+    let remote_root = config::effective_remote_path(host_config, &project_root);
+
+    let remote_path = paths::resolve(&remote_root, file);
+    let editor = editor_command();
+
+    info!("Editing on host: {} ({})", host_name, host_config.hostname);
+    info!("Remote path: {}", remote_path);
+    info!("Editor: {}", editor);
+
+    if dry_run {
+        eprintln!("Would edit {}:{} with {}", host_config.hostname, remote_path, editor);
+        return super::upload::gate(host_name, host_config, &[], &remote_root, &project_root, true);
+    }
+
+    let file_name = Path::new(&remote_path)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("remote");
+    // A private temp directory, removed when dropped unless the user's copy has to be
+    // kept for them
+    let temp_dir = TempDir::with_prefix("bridge-edit-").context("Failed to create temp directory")?;
+    let temp_path = temp_dir.path().join(file_name);
+    let temp_str = temp_path.to_str().context("Temp path contains invalid UTF-8")?;
+
+    // Fingerprint the remote file so concurrent modifications can be detected before upload
+    let fingerprint_cmd = fingerprint_command(&host_config.shell, &remote_path);
+    let before = ssh::capture_remote(host_config, &fingerprint_cmd)?;
+
+    ssh::download_from_remote(host_config, &remote_path, temp_str, false)?;
+    let original = fs::read(&temp_path)
+        .with_context(|| format!("Failed to read {}", temp_path.display()))?;
+
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!(r#"{} "$1""#, editor))
+        .arg("sh")
+        .arg(&temp_path)
+        .status()
+        .with_context(|| format!("Failed to launch editor: {}", editor))?;
+
+    if !status.success() {
+        let _ = temp_dir.keep();
+        anyhow::bail!(
+            "Editor exited with code {}. Your copy is kept at {}",
+            status.code().unwrap_or(1),
+            temp_path.display()
+        );
+    }
+
+    let edited = fs::read(&temp_path)
+        .with_context(|| format!("Failed to read {}", temp_path.display()))?;
+
+    if edited == original {
+        if !output::is_quiet() {
+            println!("No changes.");
+        }
+        return Ok(());
+    }
+
+    let after = ssh::capture_remote(host_config, &fingerprint_cmd)?;
+    if before != after {
+        let _ = temp_dir.keep();
+        anyhow::bail!(
+            "Remote file {} changed while it was being edited. Not overwriting; your copy is kept at {}",
+            remote_path,
+            temp_path.display()
+        );
+    }
+
+    let saved = super::upload::gate(host_name, host_config, &[&temp_path], &remote_root, &project_root, false)
+        .with_context(|| format!("Not saved. Your copy is kept at {}", temp_path.display()))
+        .and_then(|()| {
+            ssh::upload_to_remote(temp_str, host_config, &remote_path, false)
+                .with_context(|| format!("Upload failed. Your copy is kept at {}", temp_path.display()))
+        });
+    if saved.is_err() {
+        let _ = temp_dir.keep();
+    }
+    saved?;
+
+    if !output::is_quiet() {
+        println!("Saved: {}", remote_path);
+    }
+
+    Ok(())
+}
+
+/// Editor to launch: $VISUAL, then $EDITOR, falling back to vi.
+fn editor_command() -> String {
+    env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .ok()
+        .filter(|e| !e.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string())
+}
+
+/// Build a command that prints a content fingerprint of a remote file for the remote shell.
+fn fingerprint_command(shell: &Shell, remote_path: &str) -> String {
+    let path = ssh::quote_path(shell, remote_path);
+    match shell {
+        Shell::Powershell => ssh::powershell_command(&format!(
+            "(Get-FileHash -Algorithm SHA256 -LiteralPath {}).Hash",
+            path
+        )),
+        Shell::Cmd => format!("certutil -hashfile {} SHA256", path),
+        _ => ssh::sh_command(shell, &format!("cksum {}", ssh::quote_path(&Shell::Sh, remote_path))),
+    }
+}
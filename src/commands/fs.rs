@@ -0,0 +1,141 @@
+use anyhow::Result;
+use tracing::info;
+
+use crate::config::{self, Config, Shell};
+use crate::paths;
+use crate::ssh;
+use super::ls;
+
+/// A remote filesystem operation. Relative paths resolve against the project path.
+pub enum FsOp<'a> {
+    Ls(Option<&'a str>),
+    Rm { paths: &'a [String], recursive: bool },
+    Mv { from: &'a str, to: &'a str },
+    Mkdir(&'a [String]),
+    Stat(&'a str),
+}
+
+pub fn run(op: FsOp, host: Option<&str>, dry_run: bool) -> Result<i32> {
+    let (config, config_path) = Config::find_and_load()?;
+    let (host_name, host_config) = super::select_host(&config, host, dry_run)?;
+    let project_root = Config::project_root(&config_path);
+    let remote_root = config::effective_remote_path(host_config, &project_root);
+
+    let resolve = |path: &str| paths::resolve(&remote_root, path);
+    let resolve_all = |paths: &[String]| paths.iter().map(|p| resolve(p)).collect::<Vec<_>>();
+
+    let shell = &host_config.shell;
+    let fs_cmd = match op {
+        FsOp::Ls(path) => ls::list_command(shell, &path.map(resolve).unwrap_or_else(|| remote_root.clone())),
+        FsOp::Rm { paths, recursive } => rm_command(shell, &resolve_all(paths), recursive),
+        FsOp::Mv { from, to } => mv_command(shell, &resolve(from), &resolve(to)),
+        FsOp::Mkdir(paths) => mkdir_command(shell, &resolve_all(paths)),
+        FsOp::Stat(path) => stat_command(shell, &resolve(path)),
+    };
+
+    info!("Running on host: {} ({})", host_name, host_config.hostname);
+    info!("Remote path: {}", remote_root);
+
+    if dry_run {
+        eprintln!("Would run: ssh {} {}", host_config.hostname, fs_cmd);
+        return Ok(0);
+    }
+
+    ssh::exec_remote(host_config, &fs_cmd).map(ssh::Exit::code)
+}
+
+/// PowerShell array literal of quoted paths, e.g. `'a','b'`.
+fn powershell_paths(paths: &[String]) -> String {
+    paths
+        .iter()
+        .map(|p| ssh::quote_path(&Shell::Powershell, p))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// sh-quoted paths separated by spaces.
+fn sh_paths(paths: &[String]) -> String {
+    paths
+        .iter()
+        .map(|p| ssh::quote_path(&Shell::Sh, p))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// cmd's del/rmdir/move have awkward quoting and error codes, so Windows hosts use
+// PowerShell either way.
+
+fn rm_command(shell: &Shell, paths: &[String], recursive: bool) -> String {
+    match shell {
+        Shell::Powershell | Shell::Cmd => {
+            let list = powershell_paths(paths);
+            if recursive {
+                ssh::powershell_command(&format!("Remove-Item -Force -Recurse -LiteralPath {}", list))
+            } else {
+                // Without -Recurse, Remove-Item prompts for non-empty directories; refuse instead
+                ssh::powershell_command(&format!(
+                    "foreach ($p in @({l})) {{ if (Test-Path -LiteralPath $p -PathType Container) {{ Write-Error ('Is a directory (use -r): ' + $p); exit 1 }} }}; Remove-Item -Force -LiteralPath {l}",
+                    l = list
+                ))
+            }
+        }
+        _ => ssh::sh_command(shell, &format!("rm -f{} -- {}", if recursive { "r" } else { "" }, sh_paths(paths))),
+    }
+}
+
+fn mv_command(shell: &Shell, from: &str, to: &str) -> String {
+    match shell {
+        Shell::Powershell | Shell::Cmd => ssh::powershell_command(&format!(
+            "Move-Item -LiteralPath {} -Destination {}",
+            ssh::quote_path(&Shell::Powershell, from),
+            ssh::quote_path(&Shell::Powershell, to)
+        )),
+        _ => ssh::sh_command(
+            shell,
+            &format!("mv -- {} {}", ssh::quote_path(&Shell::Sh, from), ssh::quote_path(&Shell::Sh, to)),
+        ),
+    }
+}
+
+fn mkdir_command(shell: &Shell, paths: &[String]) -> String {
+    match shell {
+        Shell::Powershell | Shell::Cmd => ssh::powershell_command(&format!(
+            "New-Item -ItemType Directory -Force -Path {} | Out-Null",
+            powershell_paths(paths)
+        )),
+        _ => ssh::sh_command(shell, &format!("mkdir -p -- {}", sh_paths(paths))),
+    }
+}
+
+fn stat_command(shell: &Shell, path: &str) -> String {
+    match shell {
+        Shell::Powershell | Shell::Cmd => ssh::powershell_command(&format!(
+            "Get-Item -Force -LiteralPath {} | Format-List FullName, Mode, Length, CreationTime, LastWriteTime",
+            ssh::quote_path(&Shell::Powershell, path)
+        )),
+        _ => ssh::sh_command(shell, &format!("stat -- {}", ssh::quote_path(&Shell::Sh, path))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bash_commands_quote_every_path() {
+        let paths = vec!["/srv/a b".to_string(), "/srv/$c".to_string()];
+        assert_eq!(rm_command(&Shell::Bash, &paths, true), "rm -fr -- '/srv/a b' '/srv/$c'");
+        assert_eq!(mkdir_command(&Shell::Bash, &paths), "mkdir -p -- '/srv/a b' '/srv/$c'");
+        assert_eq!(mv_command(&Shell::Bash, "/srv/x", "/srv/it's"), r"mv -- '/srv/x' '/srv/it'\''s'");
+    }
+
+    #[test]
+    fn windows_commands_use_powershell() {
+        let paths = vec!["C:/a".to_string(), "C:/b".to_string()];
+        assert_eq!(
+            rm_command(&Shell::Cmd, &paths, true),
+            ssh::powershell_command("Remove-Item -Force -Recurse -LiteralPath 'C:/a','C:/b'")
+        );
+        assert!(rm_command(&Shell::Powershell, &paths, false).contains("Is a directory"));
+    }
+}
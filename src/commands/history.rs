@@ -0,0 +1,124 @@
+use anyhow::Result;
+use chrono::{Local, TimeZone};
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::output::{self, OutputFormat};
+use crate::state::{self, HistoryEntry, HistoryKind};
+use crate::status;
+
+/// Which recorded syncs and runs `bridge history` shows.
+pub struct Filter<'a> {
+    pub host: Option<&'a str>,
+    pub kind: Option<HistoryKind>,
+    /// Only entries that started at most this long ago
+    pub since: Option<Duration>,
+    pub failed: bool,
+    /// Only runs whose command contains this text
+    pub grep: Option<&'a str>,
+    /// Entries from every project, not just the current one
+    pub all: bool,
+    /// Show at most this many, the most recent
+    pub limit: usize,
+}
+
+/// Print past syncs and runs, oldest first, ending with the most recent. Outside a
+/// project, entries from every project are shown.
+pub fn run(filter: &Filter, format: OutputFormat) -> Result<()> {
+    let project = if filter.all {
+        None
+    } else {
+        Config::find_and_load().ok().map(|(_, path)| Config::project_root(&path))
+    };
+    let since = filter.since.map(|since| state::now_secs().saturating_sub(since.as_secs()));
+
+    let history = state::load_history();
+    let mut entries: Vec<&HistoryEntry> = history
+        .iter()
+        .filter(|entry| project.as_ref().is_none_or(|project| entry.project == *project))
+        .filter(|entry| filter.host.is_none_or(|host| entry.host == host))
+        .filter(|entry| filter.kind.is_none_or(|kind| entry.kind == kind))
+        .filter(|entry| since.is_none_or(|since| entry.started_at >= since))
+        .filter(|entry| !filter.failed || entry.exit_code != 0)
+        .filter(|entry| filter.grep.is_none_or(|text| entry.command.as_deref().is_some_and(|c| c.contains(text))))
+        .collect();
+    entries.drain(..entries.len().saturating_sub(filter.limit));
+
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&entries)?),
+        OutputFormat::Text => {
+            if entries.is_empty() {
+                status!("No matching history");
+            }
+            for entry in entries {
+                println!("{}", format_entry(entry, project.is_none()));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// e.g. `2026-06-10 14:02  run   gpu  4m 12s  exit 0  python train.py`
+fn format_entry(entry: &HistoryEntry, with_project: bool) -> String {
+    let started = Local
+        .timestamp_opt(entry.started_at as i64, 0)
+        .single()
+        .map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| "-".to_string());
+    let duration = output::format_duration(Duration::from_millis(entry.duration_ms));
+
+    let mut line = match entry.kind {
+        HistoryKind::Run => format!(
+            "{}  run   {}  {}  {}  {}",
+            started,
+            entry.host,
+            duration,
+            if entry.connection_lost { "disconnected".to_string() } else { format!("exit {}", entry.exit_code) },
+            entry.command.as_deref().unwrap_or("")
+        ),
+        HistoryKind::Sync => {
+            let result = if entry.exit_code == 0 { "ok" } else { "failed" };
+            let mut line = format!("{}  sync  {}  {}  {}", started, entry.host, duration, result);
+            if let Some(bytes) = entry.bytes {
+                line.push_str(&format!("  {}", output::format_size(bytes)));
+            }
+            line
+        }
+    };
+    if with_project {
+        line.push_str(&format!("  (in {})", entry.project.display()));
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn entries_show_what_ran_and_how_long() {
+        let mut entry = HistoryEntry {
+            kind: HistoryKind::Run,
+            project: PathBuf::from("/src/app"),
+            host: "gpu".to_string(),
+            command: Some("python train.py".to_string()),
+            started_at: Local.with_ymd_and_hms(2026, 6, 10, 14, 2, 0).unwrap().timestamp() as u64,
+            duration_ms: 252_000,
+            exit_code: 0,
+            connection_lost: false,
+            bytes: None,
+            run_id: None,
+        };
+        assert_eq!(format_entry(&entry, false), "2026-06-10 14:02  run   gpu  4m 12s  exit 0  python train.py");
+        entry.connection_lost = true;
+        assert_eq!(format_entry(&entry, false), "2026-06-10 14:02  run   gpu  4m 12s  disconnected  python train.py");
+        entry.connection_lost = false;
+
+        entry.kind = HistoryKind::Sync;
+        entry.command = None;
+        entry.duration_ms = 3_400;
+        entry.bytes = Some(2048);
+        assert_eq!(format_entry(&entry, true), "2026-06-10 14:02  sync  gpu  3s  ok  2.0 KiB  (in /src/app)");
+    }
+}
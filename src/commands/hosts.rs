@@ -1,12 +1,77 @@
-use anyhow::Result;
+use anstyle::Style;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::Semaphore;
+use tracing::info;
 
-use crate::config::Config;
+use crate::config::{self, Config, Host, Shell, SyncMethod};
+use super::config::{edit_config, max_position};
+use crate::output::{self, OutputFormat};
+use crate::runtime;
+use crate::ssh;
+use crate::style;
 
-pub fn run(verbose: bool) -> Result<()> {
+/// Hosts checked at the same time by `--check`.
+const CHECK_CONCURRENCY: usize = 8;
+
+/// Result of checking one host with `--check`.
+#[derive(Debug, Serialize)]
+struct HostStatus {
+    reachable: bool,
+    /// Time taken by the SSH connection check
+    latency_ms: u64,
+    /// Whether the remote project path exists; None if unreachable or the check failed
+    path_exists: Option<bool>,
+}
+
+/// One host in `--format json` output.
+#[derive(Debug, Serialize)]
+struct HostEntry<'a> {
+    name: &'a str,
+    hostname: &'a str,
+    path: &'a str,
+    shell: &'a Shell,
+    sync_method: &'a SyncMethod,
+    default: bool,
+    tags: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<&'a HostStatus>,
+}
+
+pub fn run(check: bool, format: OutputFormat) -> Result<()> {
     let (config, config_path) = Config::find_and_load()?;
 
-    if verbose {
-        eprintln!("Config loaded from: {}", config_path.display());
+    info!("Config loaded from: {}", config_path.display());
+
+    let default_host = config.default_host.as_deref();
+    let project_root = Config::project_root(&config_path);
+    let statuses = if check {
+        check_hosts(&config, &project_root)
+    } else {
+        Vec::new()
+    };
+
+    if format == OutputFormat::Json {
+        let mut entries: Vec<HostEntry> = config
+            .hosts
+            .iter()
+            .enumerate()
+            .map(|(i, (name, host))| HostEntry {
+                name,
+                hostname: &host.hostname,
+                path: &host.path,
+                shell: &host.shell,
+                sync_method: &host.sync_method,
+                default: default_host == Some(name.as_str()),
+                tags: &host.tags,
+                status: statuses.get(i),
+            })
+            .collect();
+        entries.sort_by(|a, b| a.name.cmp(b.name));
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
     }
 
     if config.hosts.is_empty() {
@@ -15,18 +80,381 @@ pub fn run(verbose: bool) -> Result<()> {
         return Ok(());
     }
 
-    let default_host = config.default_host.as_deref();
-
-    for (name, host) in &config.hosts {
+    let mut header = vec!["NAME", "HOSTNAME", "PATH", "SHELL", "TAGS"];
+    if check {
+        header.push("STATUS");
+    }
+    let mut hosts: Vec<_> = config.hosts.iter().zip(0..).collect();
+    hosts.sort_by_key(|((name, _), _)| *name);
+    let mut rows = vec![header.into_iter().map(|title| (title.to_string(), style::HEADER)).collect()];
+    for ((name, host), i) in hosts {
         let is_default = default_host == Some(name.as_str());
         let default_marker = if is_default { " (default)" } else { "" };
 
-        println!("{}{}", name, default_marker);
-        println!("  hostname: {}", host.hostname);
-        println!("  path: {}", host.path);
-        println!("  shell: {}", host.shell);
+        let mut row = vec![
+            (format!("{}{}", name, default_marker), Style::new()),
+            (host.hostname.clone(), Style::new()),
+            (host.path.clone(), Style::new()),
+            (host.shell.to_string(), Style::new()),
+            if host.tags.is_empty() {
+                ("-".to_string(), style::DIM)
+            } else {
+                (host.tags.join(","), Style::new())
+            },
+        ];
+        if let Some(status) = statuses.get(i) {
+            row.push((describe(status), status_style(status)));
+        }
+        rows.push(row);
+    }
+    for line in style::table(&rows) {
+        println!("{}", line);
+    }
+
+    if !config.groups.is_empty() {
+        let mut groups: Vec<_> = config.groups.iter().collect();
+        groups.sort();
         println!();
+        println!("{}", style::out(style::HEADER, "Groups:"));
+        for (name, members) in groups {
+            println!("  {}: {}", name, members.join(", "));
+        }
     }
 
     Ok(())
 }
+
+/// Add `[hosts.<name>]` to bridge.toml, keeping the rest of the file as written.
+/// The host becomes the default if `make_default` is set or no default exists yet.
+pub fn add(name: &str, hostname: &str, path: &str, shell: Option<Shell>, make_default: bool) -> Result<()> {
+    edit_config(|doc| {
+        add_host(doc, name, hostname, path, shell.as_ref(), make_default)?;
+        if !output::is_quiet() {
+            println!("Added host '{}'", name);
+        }
+        Ok(())
+    })
+}
+
+/// Remove `[hosts.<name>]` from bridge.toml, along with references to it from
+/// `default_host`, `[groups]`, and other hosts' `fallback_hosts`.
+pub fn remove(name: &str) -> Result<()> {
+    edit_config(|doc| {
+        let notes = remove_host(doc, name)?;
+        if !output::is_quiet() {
+            for note in notes {
+                println!("{}", note);
+            }
+            println!("Removed host '{}'", name);
+        }
+        Ok(())
+    })
+}
+
+pub fn set_default(name: &str) -> Result<()> {
+    edit_config(|doc| {
+        if !has_host(doc, name) {
+            anyhow::bail!("Host '{}' not found in configuration", name);
+        }
+        doc["default_host"] = toml_edit::value(name);
+        if !output::is_quiet() {
+            println!("Default host set to '{}'", name);
+        }
+        Ok(())
+    })
+}
+
+fn has_host(doc: &toml_edit::DocumentMut, name: &str) -> bool {
+    doc.get("hosts").and_then(|h| h.get(name)).is_some()
+}
+
+fn add_host(
+    doc: &mut toml_edit::DocumentMut,
+    name: &str,
+    hostname: &str,
+    path: &str,
+    shell: Option<&Shell>,
+    make_default: bool,
+) -> Result<()> {
+    if has_host(doc, name) {
+        anyhow::bail!("Host '{}' already exists", name);
+    }
+
+    // Place the new table after the last host (or at the end of the file)
+    let last_host = doc
+        .get("hosts")
+        .and_then(|h| h.as_table())
+        .and_then(|h| h.iter().filter_map(|(_, t)| t.as_table()?.position()).max());
+    let position = match last_host {
+        Some(last) => {
+            shift_positions(doc.as_table_mut(), last);
+            last + 1
+        }
+        None => max_position(doc.as_table()).map_or(0, |p| p + 1),
+    };
+
+    let mut host = toml_edit::Table::new();
+    host.set_position(position);
+    if position > 0 {
+        host.decor_mut().set_prefix("\n");
+    }
+    host["hostname"] = toml_edit::value(hostname);
+    host["path"] = toml_edit::value(path);
+    if let Some(shell) = shell.filter(|s| **s != Shell::Bash) {
+        host["shell"] = toml_edit::value(shell.to_string());
+    }
+
+    let hosts = doc
+        .entry("hosts")
+        .or_insert_with(|| {
+            let mut table = toml_edit::Table::new();
+            table.set_implicit(true);
+            toml_edit::Item::Table(table)
+        })
+        .as_table_mut()
+        .context("'hosts' in bridge.toml is not a table")?;
+    hosts.insert(name, toml_edit::Item::Table(host));
+
+    if make_default || doc.get("default_host").is_none() {
+        doc["default_host"] = toml_edit::value(name);
+    }
+    Ok(())
+}
+
+/// Move every table written after `after` one place later.
+fn shift_positions(table: &mut toml_edit::Table, after: usize) {
+    for (_, item) in table.iter_mut() {
+        if let Some(child) = item.as_table_mut() {
+            if let Some(position) = child.position().filter(|p| *p > after) {
+                child.set_position(position + 1);
+            }
+            shift_positions(child, after);
+        }
+    }
+}
+
+/// Remove a host and every reference to it; returns a note per cleaned-up reference.
+fn remove_host(doc: &mut toml_edit::DocumentMut, name: &str) -> Result<Vec<String>> {
+    let hosts = doc
+        .get_mut("hosts")
+        .and_then(|h| h.as_table_mut())
+        .filter(|h| h.contains_key(name))
+        .with_context(|| format!("Host '{}' not found in configuration", name))?;
+    hosts.remove(name);
+
+    let mut notes = Vec::new();
+    for (other, host) in hosts.iter_mut() {
+        if let Some(fallbacks) = host.get_mut("fallback_hosts").and_then(|f| f.as_array_mut()) {
+            if remove_from_array(fallbacks, name) {
+                notes.push(format!("Removed from fallback_hosts of '{}'", other));
+            }
+        }
+    }
+
+    if let Some(groups) = doc.get_mut("groups").and_then(|g| g.as_table_mut()) {
+        for (group, members) in groups.iter_mut() {
+            if let Some(members) = members.as_array_mut() {
+                if remove_from_array(members, name) {
+                    notes.push(format!("Removed from group '{}'", group));
+                }
+            }
+        }
+    }
+
+    if doc.get("default_host").and_then(|d| d.as_str()) == Some(name) {
+        remove_root_key(doc, "default_host");
+        notes.push("Cleared default_host; set a new one with `bridge hosts set-default`".to_string());
+    }
+
+    Ok(notes)
+}
+
+/// Remove a top-level key, handing any comment above it to whatever now follows,
+/// so a file header comment isn't lost with the key.
+fn remove_root_key(doc: &mut toml_edit::DocumentMut, key: &str) {
+    let root = doc.as_table_mut();
+    let Some(index) = root.iter().position(|(k, _)| k == key) else {
+        return;
+    };
+    let comment = root
+        .key(key)
+        .and_then(|k| k.leaf_decor().prefix())
+        .and_then(|p| p.as_str())
+        .filter(|p| p.contains('#'))
+        .map(str::to_string);
+    root.remove(key);
+
+    let Some(comment) = comment else {
+        return;
+    };
+    let prepend = |decor: &mut toml_edit::Decor| {
+        let existing = decor.prefix().and_then(|p| p.as_str()).unwrap_or("").to_string();
+        decor.set_prefix(format!("{}{}", comment, existing.trim_start_matches('\n')));
+    };
+
+    // Top-level values are written before any table
+    if let Some((mut next, _)) = root.iter_mut().skip(index).find(|(_, item)| item.is_value()) {
+        prepend(next.leaf_decor_mut());
+        return;
+    }
+
+    let mut tables: Vec<&mut toml_edit::Table> = Vec::new();
+    for (_, item) in root.iter_mut() {
+        if let Some(table) = item.as_table_mut() {
+            if table.is_implicit() {
+                tables.extend(table.iter_mut().filter_map(|(_, child)| child.as_table_mut()));
+            } else {
+                tables.push(table);
+            }
+        }
+    }
+    if let Some(first) = tables.into_iter().min_by_key(|t| t.position().unwrap_or(usize::MAX)) {
+        prepend(first.decor_mut());
+    }
+}
+
+fn remove_from_array(array: &mut toml_edit::Array, name: &str) -> bool {
+    let before = array.len();
+    array.retain(|v| v.as_str() != Some(name));
+    array.len() != before
+}
+
+/// Check every host concurrently (at most [`CHECK_CONCURRENCY`] at a time), in the
+/// order of `config.hosts`.
+fn check_hosts(config: &Config, project_root: &std::path::Path) -> Vec<HostStatus> {
+    let slots = Arc::new(Semaphore::new(CHECK_CONCURRENCY));
+    runtime::block_on(async {
+        let checks: Vec<_> = config
+            .hosts
+            .values()
+            .map(|host| {
+                let host = host.clone();
+                let remote_path = config::effective_remote_path(&host, project_root);
+                let slots = Arc::clone(&slots);
+                tokio::spawn(async move {
+                    let _slot = slots.acquire_owned().await.expect("the semaphore is never closed");
+                    tokio::task::spawn_blocking(move || check_host(&host, &remote_path)).await
+                })
+            })
+            .collect();
+
+        let mut statuses = Vec::with_capacity(checks.len());
+        for check in checks {
+            statuses.push(check.await.ok().and_then(Result::ok).expect("every host is checked"));
+        }
+        statuses
+    })
+}
+
+fn check_host(host: &Host, remote_path: &str) -> HostStatus {
+    let start = Instant::now();
+    let reachable = ssh::check_connection(host);
+    let latency_ms = start.elapsed().as_millis() as u64;
+
+    let path_exists = if reachable {
+        ssh::capture_remote(host, &path_exists_command(&host.shell, remote_path))
+            .ok()
+            .map(|out| out.trim() == "yes")
+    } else {
+        None
+    };
+
+    HostStatus {
+        reachable,
+        latency_ms,
+        path_exists,
+    }
+}
+
+/// Remote command printing `yes` if the directory exists, `no` otherwise.
+fn path_exists_command(shell: &Shell, remote_path: &str) -> String {
+    match shell {
+        Shell::Powershell | Shell::Cmd => ssh::powershell_command(&format!(
+            "if (Test-Path -LiteralPath {} -PathType Container) {{ 'yes' }} else {{ 'no' }}",
+            ssh::quote_path(&Shell::Powershell, remote_path)
+        )),
+        _ => ssh::sh_command(
+            shell,
+            &format!("if [ -d {} ]; then echo yes; else echo no; fi", ssh::quote_path(&Shell::Sh, remote_path)),
+        ),
+    }
+}
+
+/// Green for a host that's ready, yellow if its project path is missing, red if it
+/// can't be reached.
+fn status_style(status: &HostStatus) -> Style {
+    match (status.reachable, status.path_exists) {
+        (false, _) => style::BAD,
+        (true, Some(false)) => style::WARN,
+        (true, _) => style::GOOD,
+    }
+}
+
+fn describe(status: &HostStatus) -> String {
+    if !status.reachable {
+        return "unreachable".to_string();
+    }
+    let path = match status.path_exists {
+        Some(true) => "path exists",
+        Some(false) => "path missing",
+        None => "path unknown",
+    };
+    format!("reachable ({} ms), {}", status.latency_ms, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_and_remove_keep_comments() {
+        let mut doc: toml_edit::DocumentMut = r#"# Team config
+default_host = "dev"
+
+[hosts.dev]
+hostname = "dev-1"   # Lab box
+path = "/srv/app"
+fallback_hosts = ["win"]
+
+[hosts.win]
+hostname = "win-1"
+path = "C:/app"
+
+[groups]
+all = ["dev", "win"]
+"#
+        .parse()
+        .unwrap();
+
+        add_host(&mut doc, "gpu", "gpu-1", "/srv/app", Some(&Shell::Bash), false).unwrap();
+        assert!(add_host(&mut doc, "gpu", "x", "y", None, false).is_err());
+        let text = doc.to_string();
+        assert!(text.contains("# Lab box"));
+        assert!(text.contains("path = \"C:/app\"\n\n[hosts.gpu]\nhostname = \"gpu-1\"\npath = \"/srv/app\"\n\n[groups]"));
+        assert!(text.contains(r#"default_host = "dev""#));
+
+        let notes = remove_host(&mut doc, "win").unwrap();
+        assert_eq!(notes.len(), 2);
+        let config: Config = toml::from_str(&doc.to_string()).unwrap();
+        assert!(config.hosts["dev"].fallback_hosts.is_empty());
+        assert_eq!(config.groups["all"], vec!["dev"]);
+
+        remove_host(&mut doc, "dev").unwrap();
+        assert!(doc.get("default_host").is_none());
+        assert!(doc.to_string().starts_with("# Team config"));
+    }
+
+    #[test]
+    fn describe_reports_latency_and_path() {
+        let status = HostStatus {
+            reachable: true,
+            latency_ms: 42,
+            path_exists: Some(false),
+        };
+        assert_eq!(describe(&status), "reachable (42 ms), path missing");
+        assert_eq!(
+            describe(&HostStatus { reachable: false, latency_ms: 0, path_exists: None }),
+            "unreachable"
+        );
+    }
+}
@@ -1,12 +1,14 @@
 use anyhow::{Context, Result};
 use std::env;
 use std::fs;
+use tracing::info;
 
-use crate::config;
+use crate::config::{self, ProjectTemplate};
+use crate::output;
 
 const CONFIG_FILENAME: &str = "bridge.toml";
 
-pub fn run(verbose: bool) -> Result<()> {
+pub fn run(template: Option<ProjectTemplate>) -> Result<()> {
     let current_dir = env::current_dir().context("Failed to get current directory")?;
     let config_path = current_dir.join(CONFIG_FILENAME);
 
@@ -16,17 +18,17 @@ pub fn run(verbose: bool) -> Result<()> {
         );
     }
 
-    let template = config::generate_template();
+    let template = config::generate_template(template);
 
-    if verbose {
-        eprintln!("Creating {} in {}", CONFIG_FILENAME, current_dir.display());
-    }
+    info!("Creating {} in {}", CONFIG_FILENAME, current_dir.display());
 
     fs::write(&config_path, &template)
         .with_context(|| format!("Failed to write {}", config_path.display()))?;
 
-    println!("Created bridge.toml");
-    println!("Edit it to configure your remote hosts.");
+    if !output::is_quiet() {
+        println!("Created bridge.toml");
+        println!("Edit it to configure your remote hosts.");
+    }
 
     Ok(())
 }
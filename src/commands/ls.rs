@@ -0,0 +1,43 @@
+use anyhow::Result;
+use tracing::info;
+
+use crate::config::{self, Config, Shell};
+use crate::paths;
+use crate::ssh;
+
+pub fn run(path: Option<&str>, host: Option<&str>, dry_run: bool) -> Result<i32> {
+    let (config, config_path) = Config::find_and_load()?;
+    let (host_name, host_config) = super::select_host(&config, host, dry_run)?;
+    let project_root = Config::project_root(&config_path);
+    let remote_root = config::effective_remote_path(host_config, &project_root);
+
+    let remote_path = match path {
+        Some(p) => paths::resolve(&remote_root, p),
+        None => remote_root,
+    };
+
+    let list_cmd = list_command(&host_config.shell, &remote_path);
+
+    info!("Listing on host: {} ({})", host_name, host_config.hostname);
+    info!("Remote path: {}", remote_path);
+
+    if dry_run {
+        eprintln!("Would run: ssh {} {}", host_config.hostname, list_cmd);
+        return Ok(0);
+    }
+
+    ssh::exec_remote(host_config, &list_cmd).map(ssh::Exit::code)
+}
+
+/// Build a directory listing command (with sizes and modification times) for the remote shell.
+pub fn list_command(shell: &Shell, remote_path: &str) -> String {
+    let path = ssh::quote_path(shell, remote_path);
+    match shell {
+        Shell::Powershell => ssh::powershell_command(&format!(
+            "Get-ChildItem -Force -LiteralPath {} | Format-Table Mode, LastWriteTime, Length, Name -AutoSize",
+            path
+        )),
+        Shell::Cmd => ssh::cmd_command(&format!("dir /a {}", path)),
+        _ => ssh::sh_command(shell, &format!("ls -la {}", ssh::quote_path(&Shell::Sh, remote_path))),
+    }
+}
@@ -1,7 +1,161 @@
+pub mod artifacts;
+pub mod cat;
+pub mod completions;
+pub mod config;
+pub mod daemon;
+pub mod diff;
+pub mod doctor;
 pub mod download;
+pub mod edit;
+pub mod fs;
+pub mod history;
 pub mod hosts;
 pub mod init;
+pub mod ls;
+pub mod mount;
+pub mod plugin;
+pub mod prompt_status;
+pub mod queue;
 pub mod run;
+pub mod script;
+pub mod serve;
 pub mod ssh;
 pub mod sync;
+pub mod tail;
 pub mod upload;
+pub mod watch;
+
+use anyhow::Result;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::config::{Config, Host};
+use crate::hooks;
+use crate::status;
+use crate::style;
+
+/// Resolve `--host` (or the default host). If it has `fallback_hosts`, it and its
+/// fallbacks are probed concurrently and the first reachable one, in order, is used.
+/// Hosts without fallbacks, and all hosts in dry-run mode, are returned unchecked.
+pub fn select_host<'a>(config: &'a Config, name: Option<&str>, dry_run: bool) -> Result<(&'a String, &'a Host)> {
+    let (primary_name, primary) = config.get_host(name)?;
+    if dry_run || primary.fallback_hosts.is_empty() {
+        return Ok((primary_name, primary));
+    }
+
+    let mut candidates = vec![(primary_name, primary)];
+    for fallback in &primary.fallback_hosts {
+        let candidate = config
+            .get_host(Some(fallback))
+            .map_err(|e| e.context(format!("Invalid fallback host for '{}'", primary_name)))?;
+        candidates.push(candidate);
+    }
+
+    let hosts: Vec<&Host> = candidates.iter().map(|(_, host)| *host).collect();
+    let reachable = crate::ssh::probe_hosts(&hosts, crate::ssh::PROBE_DEADLINE);
+
+    match candidates.iter().zip(&reachable).position(|(_, ok)| *ok) {
+        Some(0) => Ok((primary_name, primary)),
+        Some(i) => {
+            let (fallback_name, fallback_host) = candidates[i];
+            status!("Host '{}' is unreachable; using fallback '{}'", primary_name, fallback_name);
+            Ok((fallback_name, fallback_host))
+        }
+        None => anyhow::bail!(
+            "Host '{}' and its fallbacks ({}) are all unreachable",
+            primary_name,
+            primary.fallback_hosts.join(", ")
+        ),
+    }
+}
+
+/// The host's settings with session flags from the command line applied on top.
+pub fn with_overrides(host: &Host, forward_agent: bool, forward_x11: bool, container: Option<&str>) -> Host {
+    let mut host = host.clone();
+    host.forward_agent |= forward_agent;
+    host.forward_x11 |= forward_x11;
+    if let Some(container) = container {
+        host.container = Some(container.to_string());
+    }
+    host
+}
+
+/// Wait up to `timeout` for a host whose connection was lost during `operation`, with
+/// its `on_disconnect_local` hook run first and `on_reconnect_local` once it is back.
+/// A failing hook is only a warning. Returns whether the host came back.
+pub fn await_reconnect(host_name: &str, host: &Host, operation: &str, project_root: &Path, timeout: Duration) -> bool {
+    eprintln!("SSH connection lost. Waiting for reconnection (timeout: {}s)...", timeout.as_secs());
+    let run_hook = |hook: &Option<String>, event| {
+        if let Some(hook) = hook {
+            if let Err(e) = hooks::run_connection_hook(hook, event, operation, host_name, project_root) {
+                eprintln!("Warning: {:#}", e);
+            }
+        }
+    };
+
+    run_hook(&host.on_disconnect_local, "disconnect");
+    if !crate::ssh::wait_for_reconnect(host, timeout) {
+        return false;
+    }
+    run_hook(&host.on_reconnect_local, "reconnect");
+    true
+}
+
+/// Run `f` once per target host and combine the results.
+///
+/// With no groups or tags, `f` runs once for `--host` (or the default host). Otherwise
+/// it runs for every selected host (see [`Config::select_hosts`]), continuing past
+/// failures; the first non-zero exit code is returned, and any errors fail the whole
+/// invocation at the end. Selected hosts are probed concurrently first, so
+/// unreachable ones fail fast instead of each waiting out an SSH timeout.
+pub fn for_each_host<F>(host: Option<&str>, groups: &[String], tags: &[String], dry_run: bool, mut f: F) -> Result<i32>
+where
+    F: FnMut(Option<&str>) -> Result<i32>,
+{
+    if groups.is_empty() && tags.is_empty() {
+        return f(host);
+    }
+
+    let (config, _) = Config::find_and_load()?;
+    let names = config.select_hosts(groups, tags)?;
+
+    let reachable = if dry_run {
+        vec![true; names.len()]
+    } else {
+        let hosts: Vec<&Host> = names.iter().map(|name| &config.hosts[*name]).collect();
+        crate::ssh::probe_hosts(&hosts, crate::ssh::PROBE_DEADLINE)
+    };
+
+    let mut exit_code = 0;
+    let mut failed = Vec::new();
+    for (name, reachable) in names.iter().zip(reachable) {
+        eprintln!("{}", style::err(style::HEADER, &format!("==> {} <==", name)));
+        if let Some(ref error) = config.hosts[*name].expand_error {
+            eprintln!("{} {}", style::err(style::BAD, &format!("Error on {}:", name)), error);
+            failed.push(name.as_str());
+            continue;
+        }
+        if !reachable {
+            eprintln!("{} host is unreachable", style::err(style::BAD, &format!("Error on {}:", name)));
+            failed.push(name.as_str());
+            continue;
+        }
+        match f(Some(name.as_str())) {
+            Ok(code) => {
+                if code != 0 && exit_code == 0 {
+                    exit_code = code;
+                }
+            }
+            Err(e) => {
+                eprintln!("{} {:#}", style::err(style::BAD, &format!("Error on {}:", name)), e);
+                failed.push(name.as_str());
+            }
+        }
+    }
+
+    if !failed.is_empty() {
+        anyhow::bail!("{} of {} hosts failed: {}", failed.len(), names.len(), failed.join(", "));
+    }
+
+    Ok(exit_code)
+}
@@ -0,0 +1,100 @@
+use anyhow::{Context, Result};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::info;
+
+use crate::config::{self, Config};
+use crate::output;
+use crate::ssh;
+use crate::state;
+
+/// Mount the host's remote project path locally with sshfs.
+pub fn mount(mountpoint: Option<&str>, host: Option<&str>, dry_run: bool) -> Result<()> {
+    let (config, config_path) = Config::find_and_load()?;
+    let (host_name, host_config) = super::select_host(&config, host, dry_run)?;
+    let project_root = Config::project_root(&config_path);
+    let remote_path = config::effective_remote_path(host_config, &project_root);
+    let mountpoint = resolve_mountpoint(mountpoint, &project_root, host_name)?;
+
+    info!("Mounting from host: {} ({})", host_name, host_config.hostname);
+    info!("Remote path: {}", remote_path);
+
+    if !dry_run {
+        std::fs::create_dir_all(&mountpoint)
+            .with_context(|| format!("Failed to create mountpoint: {}", mountpoint.display()))?;
+    }
+
+    let mountpoint_str = mountpoint.to_str().context("Mountpoint contains invalid UTF-8")?;
+    ssh::mount_remote(host_config, &remote_path, mountpoint_str, dry_run)?;
+
+    if !dry_run && !output::is_quiet() {
+        println!("Mounted {}:{} at {}", host_config.hostname, remote_path, mountpoint.display());
+    }
+    Ok(())
+}
+
+/// Unmount a directory mounted with `bridge mount`.
+pub fn umount(mountpoint: Option<&str>, host: Option<&str>, dry_run: bool) -> Result<()> {
+    let (config, config_path) = Config::find_and_load()?;
+    let (host_name, _) = super::select_host(&config, host, dry_run)?;
+    let project_root = Config::project_root(&config_path);
+    let mountpoint = resolve_mountpoint(mountpoint, &project_root, host_name)?;
+
+    // FUSE filesystems are unmounted by their owner with fusermount on Linux
+    let candidates: &[&[&str]] = if cfg!(target_os = "linux") {
+        &[&["fusermount3", "-u"], &["fusermount", "-u"]]
+    } else {
+        &[&["umount"]]
+    };
+
+    for candidate in candidates {
+        let (program, args) = candidate.split_first().expect("candidate has a program");
+        if dry_run {
+            eprintln!("Would run: {} {} {}", program, args.join(" "), mountpoint.display());
+            return Ok(());
+        }
+        info!("Running: {} {} {}", program, args.join(" "), mountpoint.display());
+
+        let status = match Command::new(program).args(args.iter()).arg(&mountpoint).status() {
+            Ok(status) => status,
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(e).with_context(|| format!("Failed to run {}", program)),
+        };
+        if !status.success() {
+            anyhow::bail!("{} failed with exit code: {}", program, status.code().unwrap_or(1));
+        }
+
+        if !output::is_quiet() {
+            println!("Unmounted {}", mountpoint.display());
+        }
+        return Ok(());
+    }
+
+    anyhow::bail!("No unmount tool found (tried {})", candidates.iter().map(|c| c[0]).collect::<Vec<_>>().join(", "))
+}
+
+/// The given mountpoint, or `<state dir>/mounts/<project>-<host>` by default.
+fn resolve_mountpoint(mountpoint: Option<&str>, project_root: &Path, host_name: &str) -> Result<PathBuf> {
+    if let Some(path) = mountpoint {
+        return Ok(PathBuf::from(path));
+    }
+    let dir = state::state_dir().context("Cannot determine state directory (HOME is not set)")?;
+    Ok(dir.join("mounts").join(default_mount_name(project_root, host_name)))
+}
+
+fn default_mount_name(project_root: &Path, host_name: &str) -> String {
+    let project = project_root.file_name().and_then(|n| n.to_str()).unwrap_or("project");
+    format!("{}-{}", project, host_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_mount_is_named_after_project_and_host() {
+        assert_eq!(default_mount_name(Path::new("/home/me/app"), "gpu"), "app-gpu");
+        assert_eq!(default_mount_name(Path::new("/"), "gpu"), "project-gpu");
+    }
+}
@@ -0,0 +1,67 @@
+use anyhow::{Context, Result};
+use std::io::ErrorKind;
+use std::process::{Command, Stdio};
+use tracing::info;
+
+use crate::config::{self, Config};
+use crate::output;
+
+/// Run `bridge-<name>` from PATH with the remaining arguments, like cargo and git
+/// subcommands. The plugin learns about the project through environment variables:
+///
+/// - `BRIDGE_CONFIG`, `BRIDGE_PROJECT_ROOT`: the config file found from the current directory
+/// - `BRIDGE_HOST`, `BRIDGE_HOSTNAME`, `BRIDGE_REMOTE_PATH`, `BRIDGE_SHELL`: the selected
+///   host (`--host` or the default), if any
+/// - `BRIDGE_PROFILE`, `BRIDGE_RUN_ID`, `BRIDGE_DRY_RUN`, `BRIDGE_VERBOSE`: global flags
+///
+/// Config values are left out when there is no config; a broken config is an error.
+/// Returns the plugin's exit code.
+pub fn run(args: &[String], host: Option<&str>, run_id: Option<&str>, dry_run: bool, verbosity: u8) -> Result<i32> {
+    let (name, plugin_args) = args.split_first().context("Missing command")?;
+    let program = format!("bridge-{}", name);
+
+    let mut cmd = Command::new(&program);
+    cmd.args(plugin_args)
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit());
+
+    match Config::find_and_load() {
+        Ok((config, config_path)) => {
+            let project_root = Config::project_root(&config_path);
+            cmd.env("BRIDGE_CONFIG", &config_path).env("BRIDGE_PROJECT_ROOT", &project_root);
+
+            // A plugin may not need a host, so a missing default isn't an error here
+            if host.is_some() || config.default_host.is_some() {
+                let (host_name, host_config) = config.get_host(host)?;
+                cmd.env("BRIDGE_HOST", host_name)
+                    .env("BRIDGE_HOSTNAME", &host_config.hostname)
+                    .env("BRIDGE_REMOTE_PATH", config::effective_remote_path(host_config, &project_root))
+                    .env("BRIDGE_SHELL", host_config.shell.to_string());
+            }
+        }
+        Err(e) if host.is_none() && e.downcast_ref::<config::ConfigNotFound>().is_some() => {}
+        Err(e) => return Err(e),
+    }
+
+    if let Some(profile) = config::active_profile() {
+        cmd.env("BRIDGE_PROFILE", profile);
+    }
+    if let Some(run_id) = run_id {
+        cmd.env("BRIDGE_RUN_ID", run_id);
+    }
+    cmd.env("BRIDGE_DRY_RUN", if dry_run { "1" } else { "0" })
+        .env("BRIDGE_VERBOSE", verbosity.to_string())
+        .env("BRIDGE_QUIET", if output::is_quiet() { "1" } else { "0" });
+
+    info!("Running plugin: {} {}", program, plugin_args.join(" "));
+
+    let status = match cmd.status() {
+        Ok(status) => status,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            anyhow::bail!("Unknown command '{}' (no {} found in PATH). See `bridge --help`.", name, program)
+        }
+        Err(e) => return Err(e).with_context(|| format!("Failed to run {}", program)),
+    };
+    Ok(status.code().unwrap_or(1))
+}
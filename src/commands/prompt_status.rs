@@ -0,0 +1,101 @@
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::config::{Config, LockSetting};
+use crate::lock;
+use crate::output::OutputFormat;
+use crate::state;
+
+#[derive(Serialize)]
+struct PromptStatus {
+    host: String,
+    last_run_exit: Option<i32>,
+    last_run_age_secs: Option<u64>,
+    last_sync_age_secs: Option<u64>,
+    lock_held: bool,
+}
+
+/// Print a compact status line for shell prompts. Reads only local files and never
+/// fails: outside a bridge project it prints nothing.
+pub fn run(host: Option<&str>, format: OutputFormat) -> Result<()> {
+    let Ok((config, config_path)) = Config::find_and_load() else {
+        return Ok(());
+    };
+    let Ok((host_name, host_config)) = config.get_host(host) else {
+        return Ok(());
+    };
+
+    let project_root = Config::project_root(&config_path);
+    let project_state = state::load(&project_root);
+    let now = state::now_secs();
+
+    let lock_name = match host_config.lock {
+        LockSetting::Off | LockSetting::Default => "default",
+        LockSetting::Named(ref name) => name.as_str(),
+    };
+
+    let status = PromptStatus {
+        host: host_name.clone(),
+        last_run_exit: project_state.last_run.as_ref().map(|r| r.exit_code),
+        last_run_age_secs: project_state.last_run.as_ref().map(|r| now.saturating_sub(r.finished_at)),
+        last_sync_age_secs: project_state.last_sync.as_ref().map(|s| now.saturating_sub(s.finished_at)),
+        lock_held: lock::is_held(&host_config.hostname, lock_name),
+    };
+
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string(&status)?),
+        OutputFormat::Text => println!("{}", format_status(&status)),
+    }
+
+    Ok(())
+}
+
+/// e.g. `dev run:0 sync:5m locked`
+fn format_status(status: &PromptStatus) -> String {
+    let mut parts = vec![status.host.clone()];
+    if let Some(code) = status.last_run_exit {
+        parts.push(format!("run:{}", code));
+    }
+    if let Some(age) = status.last_sync_age_secs {
+        parts.push(format!("sync:{}", format_age(age)));
+    }
+    if status.lock_held {
+        parts.push("locked".to_string());
+    }
+    parts.join(" ")
+}
+
+/// Coarse human-readable age: 45s, 3m, 2h, 5d.
+pub(super) fn format_age(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_age_uses_largest_unit() {
+        assert_eq!(format_age(45), "45s");
+        assert_eq!(format_age(180), "3m");
+        assert_eq!(format_age(7200), "2h");
+        assert_eq!(format_age(5 * 86400 + 10), "5d");
+    }
+
+    #[test]
+    fn format_status_omits_unknown_fields() {
+        let status = PromptStatus {
+            host: "dev".to_string(),
+            last_run_exit: Some(1),
+            last_run_age_secs: Some(10),
+            last_sync_age_secs: None,
+            lock_held: true,
+        };
+        assert_eq!(format_status(&status), "dev run:1 locked");
+    }
+}
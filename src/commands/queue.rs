@@ -0,0 +1,221 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::io::{self, IsTerminal, Write};
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+use tracing::info;
+
+use crate::config::{self, Config};
+use crate::ssh;
+use crate::state::{self, QueuedCommand};
+use crate::status;
+use crate::style;
+use super::prompt_status::format_age;
+
+/// How often `bridge queue flush --wait` checks whether waiting hosts are back.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Exit code of a sync or run that was queued instead of done (`EX_TEMPFAIL` in
+/// sysexits.h), so scripts can tell it apart from both success and failure.
+pub const QUEUED: i32 = 75;
+
+/// The `result` of a sync or run, or [`QUEUED`] if it failed because its host couldn't
+/// be reached (a connection failure, or `connection_lost` for a run) and was queued
+/// for `bridge queue flush`. The failure is still reported.
+pub fn or_queue(result: Result<i32>, connection_lost: bool, host: Option<&str>, always: bool) -> Result<i32> {
+    let unreachable = match result {
+        Ok(_) => connection_lost,
+        Err(ref e) => e.chain().any(|cause| cause.is::<ssh::ConnectionFailure>()),
+    };
+    if !unreachable || !offer(host, always) {
+        return result;
+    }
+    if let Err(e) = result {
+        eprintln!("{} {:#}", style::err(style::BAD, "Error:"), e);
+    }
+    Ok(QUEUED)
+}
+
+/// Queue a sync or run whose host couldn't be reached: straight away with `--queue`,
+/// or if the user agrees when asked. Returns whether it was queued.
+fn offer(host: Option<&str>, always: bool) -> bool {
+    let queued = (|| {
+        let (config, _) = Config::find_and_load()?;
+        let (host_name, host_config) = config.get_host(host)?;
+        if !always && !confirm(host_name)? {
+            return Ok(false);
+        }
+
+        // Replays go to the same host even if the default changes meanwhile. The
+        // arguments are kept as they are, so a replay with `--queue` that finds the host
+        // gone again is queued again.
+        let mut args: Vec<String> = std::env::args().skip(1).collect();
+        if host.is_none() {
+            args.splice(0..0, ["--host".to_string(), host_name.clone()]);
+        }
+
+        let dir = std::env::current_dir().context("Cannot determine the current directory")?;
+        let id = state::update_queue(|queue| {
+            let id = queue.iter().map(|c| c.id).max().unwrap_or(0) + 1;
+            queue.push(QueuedCommand {
+                id,
+                host_name: host_name.clone(),
+                host: host_config.clone(),
+                dir,
+                profile: config::active_profile(),
+                args,
+                queued_at: state::now_secs(),
+            });
+            id
+        })?;
+        eprintln!("Host '{}' is unreachable; queued as #{} (run `bridge queue flush` when it is back)", host_name, id);
+        Ok(true)
+    })();
+
+    queued.unwrap_or_else(|e: anyhow::Error| {
+        eprintln!("Warning: could not queue the command: {:#}", e);
+        false
+    })
+}
+
+/// Ask whether to queue a command for an unreachable host. No terminal means no.
+fn confirm(host_name: &str) -> Result<bool> {
+    if !io::stdin().is_terminal() {
+        return Ok(false);
+    }
+    eprint!("Host '{}' is unreachable. Queue the command to run when it is back? [y/N] ", host_name);
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).context("Failed to read answer")?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Print the queued commands, oldest first.
+pub fn list() -> Result<()> {
+    let queue = state::load_queue();
+    if queue.is_empty() {
+        status!("No queued commands");
+        return Ok(());
+    }
+
+    let now = state::now_secs();
+    for command in &queue {
+        println!(
+            "#{}  {} ago  {}  {}  (in {})",
+            command.id,
+            format_age(now.saturating_sub(command.queued_at)),
+            command.host_name,
+            command_line(&command.args),
+            command.dir.display()
+        );
+    }
+    Ok(())
+}
+
+/// Run the queued commands whose host is reachable, each in the directory it was
+/// queued from, and drop them from the queue. With `wait`, keep checking on the
+/// remaining hosts every [`FLUSH_INTERVAL`] until the queue is empty. Returns the
+/// first non-zero exit code of the commands run.
+pub fn flush(wait: bool) -> Result<i32> {
+    let mut exit_code = 0;
+    let mut ran = 0;
+    loop {
+        let queue = state::load_queue();
+        if queue.is_empty() {
+            if ran == 0 {
+                status!("No queued commands");
+            }
+            return Ok(exit_code);
+        }
+
+        let mut reachable: HashMap<&str, bool> = HashMap::new();
+        let mut waiting = 0;
+        for command in &queue {
+            let up = *reachable
+                .entry(&command.host.hostname)
+                .or_insert_with(|| ssh::check_connection(&command.host));
+            if !up {
+                waiting += 1;
+                continue;
+            }
+
+            // Dropped first, so an interrupted flush doesn't run it twice
+            remove(command.id)?;
+            eprintln!("==> #{} on {}: {} <==", command.id, command.host_name, command_line(&command.args));
+            let code = replay(command)?;
+            if code != 0 && exit_code == 0 {
+                exit_code = code;
+            }
+            ran += 1;
+        }
+
+        if waiting == 0 {
+            return Ok(exit_code);
+        }
+        if !wait {
+            status!("{} queued command(s) still waiting for their host", waiting);
+            return Ok(exit_code);
+        }
+        info!("{} queued command(s) waiting; checking again in {}s", waiting, FLUSH_INTERVAL.as_secs());
+        thread::sleep(FLUSH_INTERVAL);
+    }
+}
+
+/// Drop every queued command.
+pub fn clear() -> Result<()> {
+    let count = state::update_queue(|queue| queue.drain(..).count())?;
+    status!("Removed {} queued command(s)", count);
+    Ok(())
+}
+
+fn remove(id: u64) -> Result<()> {
+    state::update_queue(|queue| queue.retain(|c| c.id != id))
+}
+
+/// Run a queued command as a fresh `bridge` invocation.
+fn replay(command: &QueuedCommand) -> Result<i32> {
+    let exe = std::env::current_exe().context("Cannot locate the bridge executable")?;
+    let mut cmd = Command::new(exe);
+    match command.profile {
+        Some(ref profile) => cmd.env("BRIDGE_PROFILE", profile),
+        None => cmd.env_remove("BRIDGE_PROFILE"),
+    };
+    let status = cmd
+        .args(&command.args)
+        .current_dir(&command.dir)
+        .status()
+        .with_context(|| format!("Failed to run queued command #{}", command.id))?;
+    Ok(status.code().unwrap_or(1))
+}
+
+/// Arguments as a `bridge` command line, quoting the ones a shell would split or expand.
+fn command_line(args: &[String]) -> String {
+    let quoted = args.iter().map(|arg| {
+        if arg.is_empty() || arg.contains(|c: char| c.is_whitespace() || "'\"$`\\".contains(c)) {
+            format!("'{}'", arg.replace('\'', r"'\''"))
+        } else {
+            arg.clone()
+        }
+    });
+    std::iter::once("bridge".to_string()).chain(quoted).collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_connection_failures_are_queued() {
+        // Neither asks about queueing, so no config or terminal is needed
+        assert_eq!(or_queue(Ok(255), false, None, true).unwrap(), 255);
+        let err = or_queue(Err(anyhow::anyhow!("Config file not found")), false, None, true).unwrap_err();
+        assert_eq!(err.to_string(), "Config file not found");
+    }
+
+    #[test]
+    fn command_lines_quote_arguments_a_shell_would_split() {
+        let args = ["--host", "dev", "run", "--", "cargo test", "it's"].map(String::from);
+        assert_eq!(command_line(&args), r"bridge --host dev run -- 'cargo test' 'it'\''s'");
+    }
+}
@@ -1,37 +1,97 @@
+use std::collections::HashMap;
+use std::io::IsTerminal;
+use std::path::Path;
 use std::time::{Duration, Instant};
-use std::thread;
+use tracing::{debug_span, info, warn, Level};
 
+use anstyle::Style;
 use anyhow::Result;
+use chrono::{DateTime, Local, NaiveTime};
 
-use crate::config::{self, Config, LockSetting};
+use crate::config::{self, Config, Host, LockSetting, ReconnectAction};
+use crate::credentials;
 use crate::env_loader;
+use crate::env_subst;
+use crate::hooks;
 use crate::lock;
+use crate::notify::{self, Finished};
+use crate::output;
+use crate::run_id;
 use crate::ssh;
-use super::sync;
+use crate::state::{self, HistoryEntry, HistoryKind, RunRecord};
+use crate::status;
+use crate::style;
+use super::{download, sync};
 
-pub fn run(
-    host: Option<&str>,
-    command: &str,
-    do_sync: bool,
-    interactive: bool,
-    dry_run: bool,
-    verbose: bool,
-    reconnect_command_override: Option<&str>,
-    reconnect_timeout_override: Option<u64>,
-    lock_override: Option<String>,
-    lock_timeout_override: Option<u64>,
-) -> Result<i32> {
-    // Sync first if requested
+/// How to run, beyond which host and what. These are the `run` flags; where the host
+/// has a setting of the same name, a flag that is set overrides it.
+#[derive(Debug, Clone, Copy)]
+pub struct Options<'a> {
+    /// Sync before running
+    pub sync: bool,
+    pub interactive: bool,
+    pub dry_run: bool,
+    pub reconnect_command: Option<&'a str>,
+    pub reconnect_action: Option<ReconnectAction>,
+    pub max_reruns: Option<u32>,
+    pub reconnect_timeout: Option<u64>,
+    pub lock: Option<&'a str>,
+    pub lock_timeout: Option<u64>,
+    pub run_id: &'a str,
+    pub mosh: bool,
+    pub forward_agent: bool,
+    pub x11: bool,
+    pub container: Option<&'a str>,
+    /// Remote paths copied back into the project afterwards
+    pub sync_back: &'a [String],
+    /// Variables exported to the remote command's environment (`--env`)
+    pub exports: &'a [(String, String)],
+    pub notify: bool,
+}
+
+/// Run `commands` on the host one after another, each once the one before it has
+/// succeeded.
+pub fn run(host: Option<&str>, commands: &[String], options: &Options) -> Result<ssh::Exit> {
+    let Options {
+        sync: do_sync,
+        interactive,
+        dry_run,
+        reconnect_command: reconnect_command_override,
+        reconnect_action: reconnect_action_override,
+        max_reruns: max_reruns_override,
+        reconnect_timeout: reconnect_timeout_override,
+        lock: lock_override,
+        lock_timeout: lock_timeout_override,
+        run_id,
+        mosh,
+        forward_agent,
+        x11,
+        container,
+        sync_back,
+        exports,
+        notify,
+    } = *options;
+    let started_at = state::now_secs();
+    let started = Instant::now();
+    let (config, config_path) = Config::find_and_load()?;
+    let (host_name, host) = super::select_host(&config, host, dry_run)?;
+    let _span = debug_span!("run", host = %host_name, run_id).entered();
+    // How history, notifications, and `prompt-status` show a run of several commands
+    let command_line = commands.join(" && ");
+
+    // Sync first if requested. A master connection held until the command finishes
+    // lets the sync's transfers and the command share one login.
+    let _connection = if do_sync && !dry_run { ssh::share_connection(host) } else { None };
     if do_sync {
-        sync::run(host, false, false, dry_run, verbose)?;
+        sync::run(Some(host_name), &sync::Options { dry_run, ..Default::default() })?;
     }
 
-    let (config, config_path) = Config::find_and_load()?;
-    let (host_name, host) = config.get_host(host)?;
+    let host = &super::with_overrides(host, forward_agent, x11, container);
 
     // Load environment variables from .env files
     let project_root = Config::project_root(&config_path);
-    let env_vars = env_loader::load_env_files(&project_root, &host.env_files)?;
+    let mut env_vars = env_loader::load_env_files(&project_root, &host.env_files)?;
+    env_vars.insert(run_id::VAR.to_string(), run_id.to_string());
     let remote_path = config::effective_remote_path(host, &project_root);
 
     // Resolve reconnect settings: CLI flags override config
@@ -39,99 +99,506 @@ pub fn run(
         .map(|s| s.to_string())
         .or_else(|| host.reconnect_command.clone());
     let reconnect_timeout = reconnect_timeout_override.unwrap_or(host.reconnect_timeout);
+    // --reconnect-command asks for that command, whatever the config's action
+    let reconnect_action = reconnect_action_override
+        .or(reconnect_command_override.map(|_| ReconnectAction::Command))
+        .or(host.reconnect_action)
+        .unwrap_or(if reconnect_command.is_some() { ReconnectAction::Command } else { ReconnectAction::None });
+    if reconnect_action == ReconnectAction::Command && reconnect_command.is_none() {
+        anyhow::bail!("reconnect_action = \"command\" needs a reconnect_command (or --reconnect-command)");
+    }
+    let max_reruns = max_reruns_override.unwrap_or(host.max_reruns);
 
-    // Resolve lock settings: CLI overrides config
-    let lock_name = match lock_override {
-        Some(name) => Some(name),
-        None => match host.lock {
-            LockSetting::Off => None,
-            LockSetting::Default => Some("default".to_string()),
-            LockSetting::Named(ref n) => Some(n.clone()),
-        },
-    };
+    // Secrets referenced in the command or wrapper come from the credential helper
+    if !dry_run {
+        let texts: Vec<&str> = commands
+            .iter()
+            .map(String::as_str)
+            .chain([host.wrapper.as_deref(), reconnect_command.as_deref()].into_iter().flatten())
+            .collect();
+        credentials::fill_missing(host_name, host, &texts, &mut env_vars)?;
+    }
+
+    let lock_name = lock_name(host, lock_override);
 
-    if verbose {
-        eprintln!("Running on host: {} ({})", host_name, host.hostname);
-        eprintln!("Remote path: {}", remote_path);
+    if tracing::enabled!(Level::INFO) {
+        info!("Running on host: {} ({})", host_name, host.hostname);
+        info!("Remote path: {}", remote_path);
+        if let Some(ref container) = host.container {
+            info!("Container: {}", container);
+        }
+        info!("Run ID: {}", run_id);
         if let Some(ref wrapper) = host.wrapper {
-            eprintln!("Wrapper: {}", wrapper);
+            info!("Wrapper: {}", wrapper);
         }
         if !env_vars.is_empty() {
-            eprintln!("Loaded {} env vars from .env files", env_vars.len());
+            info!("Loaded {} env vars from .env files", env_vars.len());
+        }
+        if !exports.is_empty() {
+            let keys: Vec<&str> = exports.iter().map(|(key, _)| key.as_str()).collect();
+            info!("Exporting: {}", keys.join(", "));
         }
-        if let Some(ref rc) = reconnect_command {
-            eprintln!("Reconnect command: {} (timeout: {}s)", rc, reconnect_timeout);
+        match (reconnect_action, &reconnect_command) {
+            (ReconnectAction::Rerun, _) => {
+                info!("On reconnect: run again, up to {} times (timeout: {}s)", max_reruns, reconnect_timeout)
+            }
+            (ReconnectAction::Command, Some(rc)) => info!("Reconnect command: {} (timeout: {}s)", rc, reconnect_timeout),
+            _ => {}
         }
         if let Some(ref name) = lock_name {
-            eprintln!("Lock: {} (timeout: {}s)", name, lock_timeout_override.unwrap_or(host.lock_timeout));
+            info!("Lock: {} (timeout: {}s)", name, lock_timeout_override.unwrap_or(host.lock_timeout));
+        }
+        for command in commands {
+            info!("Command: {}", command);
+        }
+    }
+
+    if let Some(ref hook) = host.pre_run_local {
+        if dry_run {
+            eprintln!("Would run local hook: {}", hook);
+        } else {
+            hooks::run_local_hook(hook, "run", host_name, &project_root)?;
         }
-        eprintln!("Command: {}", command);
     }
 
     // Acquire lock if configured
     let _lock_guard = if let Some(ref name) = lock_name {
         let timeout = lock_timeout_override.unwrap_or(host.lock_timeout);
-        Some(lock::acquire_lock(&host.hostname, name, Duration::from_secs(timeout), verbose)?)
+        Some(lock::acquire_lock(&host.hostname, name, Duration::from_secs(timeout))?)
     } else {
         None
     };
 
+    // Sync-back paths may name this run's outputs via ${BRIDGE_RUN_ID}
+    let sync_back = sync_back
+        .iter()
+        .map(|path| run_id::substitute(path, Some(run_id)))
+        .collect::<Result<Vec<_>>>()?;
+    let fetch_results = || {
+        let excludes = sync::excludes(&config, host, false);
+        download::sync_back(&sync_back, host, &project_root, &remote_path, &excludes, dry_run)
+    };
+
     if dry_run {
-        eprintln!("Would run: ssh {} cd \"{}\" && {}", host.hostname, remote_path, command);
-        return Ok(0);
-    }
-
-    let exit_code = ssh::run_remote_command(
-        &host.hostname,
-        &remote_path,
-        command,
-        &host.shell,
-        host.wrapper.as_deref(),
-        host.strict_env,
-        &env_vars,
-        interactive,
-        verbose,
-    )?;
-
-    // Check for unexpected SSH disconnect with reconnect configured
-    if exit_code == 255 {
-        if let Some(ref reconnect_cmd) = reconnect_command {
-            eprintln!("SSH connection lost. Waiting for reconnection (timeout: {}s)...", reconnect_timeout);
-
-            let start = Instant::now();
-            let timeout = Duration::from_secs(reconnect_timeout);
-            let poll_interval = Duration::from_secs(5);
-
-            loop {
-                if start.elapsed() >= timeout {
-                    eprintln!("Timed out waiting for reconnection after {}s", reconnect_timeout);
-                    return Ok(255);
+        for command in commands {
+            explain(host_name, host, &project_root, &remote_path, command, &env_vars, options)?;
+        }
+        if !sync_back.is_empty() {
+            fetch_results()?;
+        }
+        return Ok(ssh::Exit::Code(0));
+    }
+
+    // mosh is a terminal session, so it is only used when attached to one
+    let use_mosh = (mosh || host.mosh)
+        && std::io::stdout().is_terminal()
+        && std::io::stdin().is_terminal()
+        && ssh::mosh_available(host);
+
+    let execute = |command: &str| {
+        if use_mosh {
+            ssh::run_remote_command_mosh(host, &remote_path, command, &env_vars, exports).map(ssh::Exit::Code)
+        } else {
+            ssh::run_remote_command(host, &remote_path, command, &env_vars, exports, interactive)
+        }
+    };
+
+    // Run one command, waiting out a lost connection as `reconnect_action` says. None
+    // if the host didn't come back in time.
+    let run_step = |command: &str| -> Result<Option<ssh::Exit>> {
+        let mut exit = execute(command)?;
+
+        // A host key mismatch isn't a disconnect; say so instead of waiting to reconnect
+        if exit == ssh::Exit::ConnectionLost {
+            if let Some(hint) = ssh::host_key_failure_hint(host) {
+                anyhow::bail!(hint);
+            }
+        }
+
+        // A command that exits 255 by itself is a failure like any other; only a lost
+        // connection waits to reconnect. A rerun that loses its connection too waits again,
+        // until `max_reruns` have been used up.
+        let mut reruns = 0;
+        while exit == ssh::Exit::ConnectionLost && reconnect_action != ReconnectAction::None {
+            if reconnect_action == ReconnectAction::Rerun && reruns == max_reruns {
+                match reruns {
+                    0 => eprintln!("SSH connection lost; not rerunning (max_reruns = 0)"),
+                    1 => eprintln!("SSH connection lost again after 1 rerun; giving up"),
+                    n => eprintln!("SSH connection lost again after {} reruns; giving up", n),
+                }
+                return Ok(None);
+            }
+            if !super::await_reconnect(host_name, host, "run", &project_root, Duration::from_secs(reconnect_timeout)) {
+                return Ok(None);
+            }
+
+            exit = match (reconnect_action, &reconnect_command) {
+                (ReconnectAction::Rerun, _) => {
+                    reruns += 1;
+                    status!("Reconnected. Running the command again ({}/{})...", reruns, max_reruns);
+                    // A rebooted host may have lost the synced files
+                    if do_sync {
+                        sync::run(Some(host_name), &sync::Options::default())?;
+                    }
+                    execute(command)?
+                }
+                (_, Some(reconnect_cmd)) => {
+                    status!("Reconnected. Running reconnect command...");
+                    ssh::run_remote_command(host, &remote_path, reconnect_cmd, &env_vars, exports, false)?
                 }
+                (_, None) => break,
+            };
+            if reconnect_action != ReconnectAction::Rerun {
+                break;
+            }
+        }
+        Ok(Some(exit))
+    };
+
+    // Each command runs once the one before it has succeeded
+    let mut steps: Vec<Step> = Vec::new();
+    let mut gave_up = false;
+    for command in commands {
+        let step_started = Instant::now();
+        let exit = run_step(command)?;
+        gave_up = exit.is_none();
+        let exit = exit.unwrap_or(ssh::Exit::ConnectionLost);
+        steps.push(Step { command, exit, duration: step_started.elapsed() });
+        if exit != ssh::Exit::Code(0) {
+            break;
+        }
+    }
+    if commands.len() > 1 {
+        report_steps(commands, &steps, started.elapsed());
+    }
+
+    let last = steps.last().map_or(ssh::Exit::Code(0), |step| step.exit);
+    let connection_lost = last == ssh::Exit::ConnectionLost;
+    let exit_code = last.code();
+    record_run(&project_root, host_name, &command_line, exit_code, run_id);
+
+    // The host never came back, so there are no results to fetch
+    if gave_up {
+        let run = Finished {
+            host: host_name,
+            hostname: &host.hostname,
+            command: &command_line,
+            exit_code,
+            connection_lost,
+            duration: started.elapsed(),
+            run_id,
+        };
+        record_history(&project_root, &run, started_at);
+        announce(&config, &run, &env_vars, notify || host.notify);
+        return Ok(last);
+    }
+
+    // Results are fetched whether or not the command succeeded; the command's own
+    // failure takes precedence over a failed fetch
+    let mut result = Ok(last);
+    if !sync_back.is_empty() {
+        if let Err(e) = fetch_results() {
+            if exit_code == 0 {
+                result = Err(e.context("Failed to sync back results"));
+            } else {
+                eprintln!("Error: failed to sync back results: {:#}", e);
+            }
+        }
+    }
+
+    let exit_code = result.as_ref().map_or(1, |exit| exit.code());
+    let duration = started.elapsed();
+    let run = Finished {
+        host: host_name,
+        hostname: &host.hostname,
+        command: &command_line,
+        exit_code,
+        connection_lost,
+        duration,
+        run_id,
+    };
+    record_history(&project_root, &run, started_at);
+    announce(&config, &run, &env_vars, notify || host.notify);
+    result
+}
+
+/// How one command of a run went.
+struct Step<'a> {
+    command: &'a str,
+    exit: ssh::Exit,
+    duration: Duration,
+}
 
-                thread::sleep(poll_interval);
-
-                eprint!(".");
-                if ssh::check_connection(&host.hostname) {
-                    eprintln!();
-                    eprintln!("Reconnected. Running reconnect command...");
-
-                    let rc_exit = ssh::run_remote_command(
-                        &host.hostname,
-                        &remote_path,
-                        reconnect_cmd,
-                        &host.shell,
-                        host.wrapper.as_deref(),
-                        host.strict_env,
-                        &env_vars,
-                        false,
-                        verbose,
-                    )?;
-
-                    return Ok(rc_exit);
+/// After a run of several commands, show each one's exit code and time, the ones
+/// skipped after a failure, and the time in all.
+fn report_steps(commands: &[String], steps: &[Step], total: Duration) {
+    let mut rows = vec![vec![
+        ("EXIT".to_string(), style::HEADER),
+        ("TIME".to_string(), style::HEADER),
+        ("COMMAND".to_string(), style::HEADER),
+    ]];
+    for step in steps {
+        let exit = match step.exit {
+            ssh::Exit::Code(code) => (code.to_string(), if code == 0 { style::GOOD } else { style::BAD }),
+            ssh::Exit::ConnectionLost => ("disconnected".to_string(), style::BAD),
+        };
+        let time = output::format_duration(step.duration);
+        rows.push(vec![exit, (time, Style::new()), (step.command.to_string(), Style::new())]);
+    }
+    for command in &commands[steps.len()..] {
+        rows.push(vec![
+            ("-".to_string(), style::DIM),
+            ("-".to_string(), style::DIM),
+            (format!("{} (skipped)", command), style::DIM),
+        ]);
+    }
+    rows.push(vec![
+        (String::new(), Style::new()),
+        (output::format_duration(total), style::HEADER),
+        ("total".to_string(), style::HEADER),
+    ]);
+
+    if !output::is_quiet() {
+        for line in style::err_table(&rows) {
+            eprintln!("{}", line);
+        }
+    }
+}
+
+/// Print each step from `command` to the invocation that would run it, for `--dry-run`:
+/// the env files loaded, the command after `${VAR}` substitution, the wrapper around
+/// it, the exported variables, the command line the remote shell gets, and the local
+/// ssh (or kubectl) invocation. Secrets the credential helper would supply are shown
+/// as their `${VAR}` reference, since it isn't asked.
+fn explain(
+    host_name: &str,
+    host: &Host,
+    project_root: &Path,
+    remote_path: &str,
+    command: &str,
+    env_vars: &HashMap<String, String>,
+    options: &Options,
+) -> Result<()> {
+    let Options { exports, interactive, .. } = *options;
+    let mut env_vars = env_vars.clone();
+    if host.credential_helper.is_some() {
+        for text in [Some(command), host.wrapper.as_deref()].into_iter().flatten() {
+            for name in env_subst::referenced_vars(text) {
+                if std::env::var_os(&name).is_none() && !env_vars.contains_key(&name) {
+                    env_vars.insert(name.clone(), format!("${{{}}}", name));
                 }
             }
         }
     }
+    let explained = ssh::explain_remote_command(host, remote_path, command, &env_vars, exports, interactive)?;
+
+    let env_files: Vec<String> = env_loader::env_file_paths(project_root, &host.env_files)
+        .iter()
+        .map(|path| path.strip_prefix(project_root).unwrap_or(path).display().to_string())
+        .collect();
+
+    eprintln!("Would run on {} ({}) in {}", host_name, host.hostname, remote_path);
+    eprintln!("  env files: {}", if env_files.is_empty() { "none".to_string() } else { env_files.join(", ") });
+    eprintln!("  command:   {}", explained.command);
+    if let Some(ref wrapped) = explained.wrapped {
+        eprintln!("  wrapped:   {}", wrapped);
+    }
+    if !explained.exports.is_empty() {
+        eprintln!("  exports:   {}", explained.exports.join(", "));
+    }
+    eprintln!("  remote:    {}", explained.remote);
+    eprintln!("  local:     {}", explained.invocation);
+    Ok(())
+}
+
+/// Announce a finished run: on the desktop for `--notify`, and to the `[notifications]`
+/// webhook if the run was long enough or failed. Announcements that can't be made are
+/// only warnings.
+fn announce(config: &Config, run: &Finished, env_vars: &HashMap<String, String>, desktop: bool) {
+    if desktop {
+        let title = match run.exit_code {
+            0 => format!("Run on {} succeeded", run.host),
+            code => format!("Run on {} failed (exit code {})", run.host, code),
+        };
+        let body = format!("{}\nTook {}", run.command, output::format_duration(run.duration));
+        if let Err(e) = notify::desktop(&title, &body) {
+            eprintln!("Warning: could not show a notification: {:#}", e);
+        }
+    }
+
+    let notifications = &config.notifications;
+    if let (Some(url), true) = (&notifications.webhook, notify::wants_webhook(notifications, run)) {
+        let posted = env_subst::substitute_env_vars(url, true, env_vars)
+            .and_then(|url| notify::webhook(notifications, &url, run));
+        if let Err(e) = posted {
+            eprintln!("Warning: could not post the webhook notification: {:#}", e);
+        }
+    }
+}
+
+/// Parse a `--env KEY=VALUE` argument.
+pub fn parse_env_arg(arg: &str) -> Result<(String, String), String> {
+    let (key, value) = arg.split_once('=').ok_or_else(|| format!("expected KEY=VALUE, got '{}'", arg))?;
+    let valid = key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(format!("invalid variable name '{}'", key));
+    }
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// Parse a `--in` delay: a number and a unit (`s`, `m`, `h`, or `d`), or several,
+/// as in `1h30m`.
+pub fn parse_delay(arg: &str) -> Result<Duration, String> {
+    let invalid = || format!("expected a delay such as 90s, 30m, or 1h30m, got '{}'", arg);
+    let too_long = || format!("delay '{}' is too long", arg);
+    let mut total = 0u64;
+    let mut rest = arg.trim();
+    if rest.is_empty() {
+        return Err(invalid());
+    }
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+        let amount: u64 = rest[..digits].parse().map_err(|_| invalid())?;
+        let mut units = rest[digits..].chars();
+        let unit = match units.next() {
+            Some('s') => 1,
+            Some('m') => 60,
+            Some('h') => 3600,
+            Some('d') => 86400,
+            _ => return Err(invalid()),
+        };
+        total = amount.checked_mul(unit).and_then(|secs| total.checked_add(secs)).ok_or_else(too_long)?;
+        rest = units.as_str();
+    }
+    let delay = Duration::from_secs(total);
+    // The start time has to be a date chrono can represent
+    start_after(Local::now(), delay).ok_or_else(too_long)?;
+    Ok(delay)
+}
+
+/// `now` plus `delay`, unless that is past the latest representable time.
+fn start_after(now: DateTime<Local>, delay: Duration) -> Option<DateTime<Local>> {
+    now.checked_add_signed(chrono::Duration::from_std(delay).ok()?)
+}
+
+/// Parse an `--at` time of day: `HH:MM` in 24-hour local time.
+pub fn parse_time_of_day(arg: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(arg.trim(), "%H:%M").map_err(|_| format!("expected a time such as 02:00 or 18:30, got '{}'", arg))
+}
+
+/// The next time the clock reads `time`: later today, or else tomorrow.
+fn next_occurrence(now: DateTime<Local>, time: NaiveTime) -> DateTime<Local> {
+    let mut day = now.date_naive();
+    loop {
+        // A time skipped by a daylight saving change falls through to the next day
+        if let Some(start) = day.and_time(time).and_local_timezone(Local).earliest().filter(|start| *start > now) {
+            return start;
+        }
+        day = day.succ_opt().expect("date in range");
+    }
+}
+
+/// For `--at` and `--in`: wait until it is time to start. Dry runs only say when that
+/// would be. Waits by the wall clock, so time spent suspended counts.
+pub fn wait_to_start(at: Option<NaiveTime>, delay: Option<Duration>, dry_run: bool) -> Result<()> {
+    let now = Local::now();
+    let start = match (at, delay) {
+        (Some(time), _) => next_occurrence(now, time),
+        (None, Some(delay)) => start_after(now, delay).ok_or_else(|| anyhow::anyhow!("--in delay is too long"))?,
+        (None, None) => return Ok(()),
+    };
+
+    let when = start.format("%a %H:%M");
+    if dry_run {
+        eprintln!("Would wait until {} before starting", when);
+        return Ok(());
+    }
+    status!("Waiting until {} to start (Ctrl-C to cancel)", when);
+    while let Ok(left) = (start - Local::now()).to_std() {
+        std::thread::sleep(left.min(Duration::from_secs(60)));
+    }
+    Ok(())
+}
 
-    Ok(exit_code)
+/// Lock to hold while running: the `--lock` name, else the host's `lock` setting.
+pub(super) fn lock_name(host: &Host, lock_override: Option<&str>) -> Option<String> {
+    match lock_override {
+        Some(name) => Some(name.to_string()),
+        None => match host.lock {
+            LockSetting::Off => None,
+            LockSetting::Default => Some("default".to_string()),
+            LockSetting::Named(ref n) => Some(n.clone()),
+        },
+    }
+}
+
+/// Remember the finished command for `prompt-status`. Failing to is not an error.
+pub(super) fn record_run(project_root: &Path, host_name: &str, command: &str, exit_code: i32, run_id: &str) {
+    let record = state::update(project_root, |s| {
+        s.last_run = Some(RunRecord {
+            host: host_name.to_string(),
+            command: command.to_string(),
+            exit_code,
+            run_id: Some(run_id.to_string()),
+            finished_at: state::now_secs(),
+        });
+    });
+    if let Err(e) = record {
+        warn!("Failed to record run state: {:#}", e);
+    }
+}
+
+/// Add a finished run to `bridge history`. Failing to is not an error.
+fn record_history(project_root: &Path, run: &Finished, started_at: u64) {
+    let entry = HistoryEntry {
+        kind: HistoryKind::Run,
+        project: project_root.to_path_buf(),
+        host: run.host.to_string(),
+        command: Some(run.command.to_string()),
+        started_at,
+        duration_ms: run.duration.as_millis() as u64,
+        exit_code: run.exit_code,
+        connection_lost: run.connection_lost,
+        bytes: None,
+        run_id: Some(run.run_id.to_string()),
+    };
+    if let Err(e) = state::append_history(&entry) {
+        warn!("Failed to record history: {:#}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn env_args_need_a_valid_name() {
+        assert_eq!(parse_env_arg("MODE=a=b"), Ok(("MODE".to_string(), "a=b".to_string())));
+        assert_eq!(parse_env_arg("EMPTY="), Ok(("EMPTY".to_string(), String::new())));
+        assert!(parse_env_arg("MODE").is_err());
+        assert!(parse_env_arg("1X=y").is_err());
+        assert!(parse_env_arg("A B=y").is_err());
+    }
+
+    #[test]
+    fn delays_and_start_times() {
+        assert_eq!(parse_delay("30m"), Ok(Duration::from_secs(1800)));
+        assert_eq!(parse_delay("1h30m"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_delay("2d"), Ok(Duration::from_secs(172800)));
+        assert!(parse_delay("30").is_err());
+        assert!(parse_delay("m").is_err());
+        assert!(parse_delay("5µs").is_err());
+        // Past what a date can hold, whether the sum or a single amount overflows
+        assert_eq!(parse_delay("9999999999d"), Err("delay '9999999999d' is too long".to_string()));
+        assert!(parse_delay("99999999999999999999s").is_err());
+        assert!(parse_delay("18446744073709551615s1s").is_err());
+
+        use chrono::TimeZone;
+        let evening = Local.with_ymd_and_hms(2026, 6, 10, 23, 0, 0).unwrap();
+        let at = |time: &str| next_occurrence(evening, parse_time_of_day(time).unwrap());
+        assert_eq!(at("02:00"), Local.with_ymd_and_hms(2026, 6, 11, 2, 0, 0).unwrap());
+        assert_eq!(at("23:30"), Local.with_ymd_and_hms(2026, 6, 10, 23, 30, 0).unwrap());
+        assert!(parse_time_of_day("25:00").is_err());
+    }
 }
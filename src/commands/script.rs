@@ -0,0 +1,171 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+use tracing::info;
+
+use crate::config::{self, Config, Host, Shell};
+use crate::credentials;
+use crate::env_loader;
+use crate::ssh;
+
+/// Upload a local script to a temp file on the remote, run it from the project path
+/// with the given arguments (under the configured wrapper), and remove it afterwards.
+pub fn run(script: &str, args: &[String], host: Option<&str>, dry_run: bool) -> Result<i32> {
+    let (config, config_path) = Config::find_and_load()?;
+    let (host_name, host_config) = super::select_host(&config, host, dry_run)?;
+    let project_root = Config::project_root(&config_path);
+    let remote_path = config::effective_remote_path(host_config, &project_root);
+
+    // The script is uploaded to the host, where a container can't see it
+    if host_config.container.is_some() {
+        anyhow::bail!(
+            "bridge script can't run in a container (host '{}' sets container); sync the script and use `bridge run`",
+            host_name
+        );
+    }
+
+    let local_path = Path::new(script);
+    if !local_path.is_file() && !dry_run {
+        anyhow::bail!("Local script does not exist: {}", local_path.display());
+    }
+    let file_name = local_path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .context("Script path has no file name")?;
+
+    info!("Running script on host: {} ({})", host_name, host_config.hostname);
+    info!("Remote path: {}", remote_path);
+
+    if dry_run {
+        eprintln!(
+            "Would upload {} to a temp file on {} and run it with: {}",
+            local_path.display(),
+            host_config.hostname,
+            args.join(" ")
+        );
+    }
+
+    // The script is uploaded, so it goes through the transfer hook like any other upload
+    super::upload::gate(host_name, host_config, &[local_path], &remote_path, &std::env::current_dir()?, dry_run)?;
+    if dry_run {
+        return Ok(0);
+    }
+    let temp_path = remote_temp_file(host_config, file_name)?;
+    info!("Remote script: {}", temp_path);
+
+    let result = upload_and_run(host_name, host_config, local_path, &temp_path, &remote_path, &project_root, args);
+
+    // Clean up even if the upload or the script failed
+    let cleanup = ssh::exec_remote(host_config, &remove_command(&host_config.shell, &temp_path));
+    if !matches!(cleanup, Ok(ssh::Exit::Code(0))) {
+        eprintln!("Warning: failed to remove remote script: {}", temp_path);
+    }
+
+    result
+}
+
+fn upload_and_run(
+    host_name: &str,
+    host: &Host,
+    local_path: &Path,
+    temp_path: &str,
+    remote_path: &str,
+    project_root: &Path,
+    args: &[String],
+) -> Result<i32> {
+    let local_str = local_path.to_str().context("Script path contains invalid UTF-8")?;
+    ssh::upload_to_remote(local_str, host, temp_path, false)?;
+
+    if !host.shell.is_windows() {
+        let chmod = ssh::sh_command(&host.shell, &format!("chmod +x {}", ssh::quote_path(&Shell::Sh, temp_path)));
+        ssh::capture_remote(host, &chmod)?;
+    }
+
+    let mut env_vars = env_loader::load_env_files(project_root, &host.env_files)?;
+    if let Some(ref wrapper) = host.wrapper {
+        credentials::fill_missing(host_name, host, &[wrapper], &mut env_vars)?;
+    }
+
+    let command = script_command(&host.shell, temp_path, args);
+    ssh::run_remote_command(host, remote_path, &command, &env_vars, &[], false).map(ssh::Exit::code)
+}
+
+/// Create a uniquely named temp file on the remote and return its path. Windows
+/// keeps the script's extension, since that decides how the script is run.
+fn remote_temp_file(host: &Host, file_name: &str) -> Result<String> {
+    let command = match host.shell {
+        Shell::Powershell | Shell::Cmd => {
+            let extension = Path::new(file_name)
+                .extension()
+                .and_then(|e| e.to_str())
+                .map(|e| format!(".{}", e))
+                .unwrap_or_default();
+            ssh::powershell_command(&format!(
+                "$p = Join-Path ([IO.Path]::GetTempPath()) ('bridge-script-' + [Guid]::NewGuid().ToString('N') + {}); New-Item -ItemType File -Path $p | Out-Null; $p",
+                ssh::quote_path(&Shell::Powershell, &extension)
+            ))
+        }
+        // The script is uploaded through Windows, so it goes in the Windows temp dir
+        Shell::Wsl => r#"mktemp "$(wslpath "$(cmd.exe /c 'echo %TEMP%' 2>/dev/null | tr -d '\r')")/bridge-script.XXXXXX""#
+            .to_string(),
+        _ => ssh::sh_command(&host.shell, "mktemp /tmp/bridge-script.XXXXXX"),
+    };
+
+    let output = ssh::capture_remote(host, &command).context("Failed to create remote temp file")?;
+    let path = output.trim();
+    if path.is_empty() {
+        anyhow::bail!("Remote did not return a temp file path");
+    }
+    Ok(path.replace('\\', "/"))
+}
+
+/// Command line that invokes the uploaded script with its arguments.
+fn script_command(shell: &Shell, temp_path: &str, args: &[String]) -> String {
+    let mut parts = vec![match shell {
+        // A quoted string on its own is a value to nushell, not a command
+        Shell::Nushell => format!("^{}", ssh::quote_path(shell, temp_path)),
+        Shell::Powershell => format!("& {}", ssh::quote_path(shell, temp_path)),
+        _ => ssh::quote_path(shell, temp_path),
+    }];
+    parts.extend(args.iter().map(|arg| quote_arg(shell, arg)));
+    parts.join(" ")
+}
+
+/// Quote a script argument so the remote shell passes it through verbatim.
+fn quote_arg(shell: &Shell, arg: &str) -> String {
+    match shell {
+        Shell::Cmd => format!(r#""{}""#, arg.replace('"', r#""""#)),
+        _ => ssh::quote_path(shell, arg),
+    }
+}
+
+fn remove_command(shell: &Shell, temp_path: &str) -> String {
+    match shell {
+        Shell::Powershell | Shell::Cmd => ssh::powershell_command(&format!(
+            "Remove-Item -Force -LiteralPath {}",
+            ssh::quote_path(&Shell::Powershell, temp_path)
+        )),
+        _ => ssh::sh_command(shell, &format!("rm -f {}", ssh::quote_path(&Shell::Sh, temp_path))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn script_command_quotes_arguments_per_shell() {
+        let args = vec!["--env".to_string(), "it's $HOME".to_string()];
+        assert_eq!(
+            script_command(&Shell::Bash, "/tmp/bridge-script.abc", &args),
+            r#"'/tmp/bridge-script.abc' '--env' 'it'\''s $HOME'"#
+        );
+        assert_eq!(
+            script_command(&Shell::Powershell, "C:/Temp/s.ps1", &args),
+            "& 'C:/Temp/s.ps1' '--env' 'it''s $HOME'"
+        );
+        assert_eq!(
+            script_command(&Shell::Cmd, "C:/Temp/s.bat", &["a \"b\"".to_string()]),
+            r#""C:\Temp\s.bat" "a ""b""""#
+        );
+    }
+}
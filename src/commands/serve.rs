@@ -0,0 +1,353 @@
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::config::{self, Config};
+use crate::state;
+
+// JSON-RPC 2.0 error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+
+/// A request that couldn't be served, as a JSON-RPC error code and message.
+#[derive(Debug, PartialEq)]
+struct RpcError(i64, String);
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    jsonrpc: String,
+    /// Absent for notifications, which get no response
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HostParams {
+    host: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RunParams {
+    command: String,
+    host: Option<String>,
+    #[serde(default)]
+    sync: bool,
+    run_id: Option<String>,
+}
+
+/// Parse one line of input into a request.
+fn parse_request(line: &str) -> Result<Request, (Value, RpcError)> {
+    let value: Value = serde_json::from_str(line)
+        .map_err(|e| (Value::Null, RpcError(PARSE_ERROR, format!("Parse error: {}", e))))?;
+    let id = value.get("id").cloned().unwrap_or(Value::Null);
+    let request: Request = serde_json::from_value(value)
+        .map_err(|e| (id.clone(), RpcError(INVALID_REQUEST, format!("Invalid request: {}", e))))?;
+    if request.jsonrpc != "2.0" {
+        return Err((id, RpcError(INVALID_REQUEST, "Invalid request: jsonrpc must be \"2.0\"".to_string())));
+    }
+    Ok(request)
+}
+
+fn params<T: for<'de> Deserialize<'de> + Default>(params: &Value) -> Result<T, RpcError> {
+    if params.is_null() {
+        return Ok(T::default());
+    }
+    serde_json::from_value(params.clone()).map_err(|e| RpcError(INVALID_PARAMS, format!("Invalid params: {}", e)))
+}
+
+/// Arguments for the `bridge` child process that carries out a `sync` or `run` request.
+fn child_args(method: &str, raw_params: &Value) -> Result<Vec<String>, RpcError> {
+    let mut args = Vec::new();
+    match method {
+        "sync" => {
+            let p: HostParams = params(raw_params)?;
+            if let Some(host) = p.host {
+                args.extend(["--host".to_string(), host]);
+            }
+            args.push("sync".to_string());
+        }
+        "run" => {
+            let p: RunParams = serde_json::from_value(raw_params.clone())
+                .map_err(|e| RpcError(INVALID_PARAMS, format!("Invalid params: {}", e)))?;
+            if let Some(host) = p.host {
+                args.extend(["--host".to_string(), host]);
+            }
+            if let Some(run_id) = p.run_id {
+                args.extend(["--run-id".to_string(), run_id]);
+            }
+            args.push("run".to_string());
+            if p.sync {
+                args.push("--sync".to_string());
+            }
+            args.extend(["--".to_string(), p.command]);
+        }
+        _ => return Err(RpcError(METHOD_NOT_FOUND, format!("Method not found: {}", method))),
+    }
+    Ok(args)
+}
+
+/// Result of a `status` request: the selected host and recent activity from local state.
+fn status(params_value: &Value) -> Result<Value, RpcError> {
+    let p: HostParams = params(params_value)?;
+    let internal = |e: anyhow::Error| RpcError(INTERNAL_ERROR, format!("{:#}", e));
+
+    let (config, config_path) = Config::find_and_load().map_err(internal)?;
+    let project_root = Config::project_root(&config_path);
+    let (host_name, host_config) = config.get_host(p.host.as_deref()).map_err(internal)?;
+    let project_state = state::load(&project_root);
+    let mut hosts: Vec<&String> = config.hosts.keys().collect();
+    hosts.sort();
+
+    Ok(json!({
+        "config": config_path,
+        "project_root": project_root,
+        "profile": config::active_profile(),
+        "host": host_name,
+        "hostname": host_config.hostname,
+        "remote_path": config::effective_remote_path(host_config, &project_root),
+        "hosts": hosts,
+        "last_run": project_state.last_run,
+        "last_sync": project_state.last_sync,
+    }))
+}
+
+fn response(id: Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(RpcError(code, message)) => json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } }),
+    }
+}
+
+#[cfg(not(unix))]
+pub fn run(_socket: Option<&str>, _verbosity: u8) -> Result<()> {
+    anyhow::bail!("bridge serve needs Unix domain sockets, which this platform doesn't support")
+}
+
+#[cfg(unix)]
+pub use unix::run;
+
+#[cfg(unix)]
+mod unix {
+    use anyhow::{Context, Result};
+    use serde_json::{json, Value};
+    use std::path::{Path, PathBuf};
+    use std::process::Stdio;
+    use std::sync::Arc;
+    use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader};
+    use tokio::net::unix::OwnedWriteHalf;
+    use tokio::net::{UnixListener, UnixStream};
+    use tokio::process::Command;
+    use tokio::sync::Mutex;
+    use tokio::task::JoinSet;
+    use tracing::info;
+
+    use super::{child_args, parse_request, response, status, RpcError, INTERNAL_ERROR};
+    use crate::config::{self, Config};
+    use crate::output;
+    use crate::runtime;
+    use crate::state;
+
+    /// Messages to one client, written a line at a time from several tasks.
+    type Writer = Arc<Mutex<OwnedWriteHalf>>;
+
+    /// Serve JSON-RPC requests from editor plugins on a Unix socket until killed.
+    /// Each connection may send any number of requests; `sync` and `run` execute
+    /// concurrently in child `bridge` processes, streaming their output.
+    pub fn run(socket: Option<&str>, verbosity: u8) -> Result<()> {
+        let (_, config_path) = Config::find_and_load()?;
+        let project_root = Config::project_root(&config_path);
+        let socket = match socket {
+            Some(path) => PathBuf::from(path),
+            None => state::socket_path(&project_root).context("Cannot determine state directory (HOME is not set)")?,
+        };
+
+        if socket.exists() {
+            if std::os::unix::net::UnixStream::connect(&socket).is_ok() {
+                anyhow::bail!("Another bridge serve is already listening on {}", socket.display());
+            }
+            // Left behind by a server that was killed
+            std::fs::remove_file(&socket).with_context(|| format!("Failed to remove stale socket: {}", socket.display()))?;
+        }
+        if let Some(dir) = socket.parent() {
+            std::fs::create_dir_all(dir).with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+        }
+
+        runtime::block_on(async {
+            let listener =
+                UnixListener::bind(&socket).with_context(|| format!("Failed to listen on {}", socket.display()))?;
+            crate::status!("Listening on {}", socket.display());
+
+            loop {
+                let stream = match listener.accept().await {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        eprintln!("Warning: failed to accept connection: {}", e);
+                        continue;
+                    }
+                };
+                let project_root = project_root.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = serve_connection(stream, &project_root, verbosity).await {
+                        info!("Connection closed: {:#}", e);
+                    }
+                });
+            }
+        })
+    }
+
+    async fn serve_connection(stream: UnixStream, project_root: &Path, verbosity: u8) -> Result<()> {
+        let (reader, writer) = stream.into_split();
+        let writer: Writer = Arc::new(Mutex::new(writer));
+        // Dropped if the connection fails, which cancels the requests and kills their processes
+        let mut running = JoinSet::new();
+
+        let mut lines = BufReader::new(reader).lines();
+        while let Some(line) = lines.next_line().await? {
+            while running.try_join_next().is_some() {}
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let request = match parse_request(&line) {
+                Ok(request) => request,
+                Err((id, error)) => {
+                    send(&writer, &response(id, Err(error))).await?;
+                    continue;
+                }
+            };
+            info!("Request: {}", request.method);
+
+            let id = request.id.clone();
+            if request.method == "status" {
+                let result = status(&request.params);
+                if let Some(id) = id {
+                    send(&writer, &response(id, result)).await?;
+                }
+                continue;
+            }
+
+            match child_args(&request.method, &request.params) {
+                Ok(args) => {
+                    let writer = Arc::clone(&writer);
+                    let project_root = project_root.to_path_buf();
+                    running.spawn(async move {
+                        let id = id.unwrap_or(Value::Null);
+                        let result = run_child(&args, &project_root, &id, &writer, verbosity)
+                            .await
+                            .map(|exit_code| json!({ "exit_code": exit_code }))
+                            .map_err(|e| RpcError(INTERNAL_ERROR, format!("{:#}", e)));
+                        let _ = send(&writer, &response(id, result)).await;
+                    });
+                }
+                Err(error) => {
+                    if let Some(id) = id {
+                        send(&writer, &response(id, Err(error))).await?;
+                    }
+                }
+            }
+        }
+
+        // The client may stop sending and still wait for its responses
+        while running.join_next().await.is_some() {}
+        Ok(())
+    }
+
+    /// Run `bridge <args>` in the project, forwarding each line of its output as an
+    /// `output` notification tagged with the request ID. The child is killed if the
+    /// client goes away.
+    async fn run_child(args: &[String], project_root: &Path, id: &Value, writer: &Writer, verbosity: u8) -> Result<i32> {
+        let exe = std::env::current_exe().context("Cannot locate the bridge executable")?;
+        let mut cmd = Command::new(exe);
+        for _ in 0..verbosity {
+            cmd.arg("--verbose");
+        }
+        if output::is_quiet() {
+            cmd.arg("--quiet");
+        }
+        if let Some(profile) = config::active_profile() {
+            cmd.env("BRIDGE_PROFILE", profile);
+        }
+        let mut child = cmd
+            .args(args)
+            .current_dir(project_root)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .context("Failed to start bridge")?;
+
+        let stdout = child.stdout.take().context("Child stdout not captured")?;
+        let stderr = child.stderr.take().context("Child stderr not captured")?;
+        let (stdout_ok, stderr_ok) = tokio::join!(
+            forward_lines(stdout, "stdout", id, writer),
+            forward_lines(stderr, "stderr", id, writer)
+        );
+
+        if !(stdout_ok && stderr_ok) {
+            let _ = child.kill().await;
+        }
+        let status = child.wait().await.context("Failed to wait for bridge")?;
+        Ok(status.code().unwrap_or(1))
+    }
+
+    /// Send each line read from `source` to the client. Returns false if the client
+    /// stopped accepting messages.
+    async fn forward_lines(source: impl AsyncRead + Unpin, stream: &str, id: &Value, writer: &Writer) -> bool {
+        let mut reader = BufReader::new(source);
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            match reader.read_until(b'\n', &mut line).await {
+                Ok(0) | Err(_) => return true,
+                Ok(_) => {}
+            }
+            let notification = json!({
+                "jsonrpc": "2.0",
+                "method": "output",
+                "params": { "id": id, "stream": stream, "text": String::from_utf8_lossy(&line) },
+            });
+            if send(writer, &notification).await.is_err() {
+                return false;
+            }
+        }
+    }
+
+    async fn send(writer: &Writer, message: &Value) -> Result<()> {
+        let mut line = serde_json::to_vec(message)?;
+        line.push(b'\n');
+        writer.lock().await.write_all(&line).await.context("Failed to write to client")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_map_to_bridge_arguments() {
+        assert_eq!(child_args("sync", &Value::Null).unwrap(), vec!["sync"]);
+        assert_eq!(
+            child_args("run", &json!({ "command": "cargo test", "host": "gpu", "sync": true })).unwrap(),
+            vec!["--host", "gpu", "run", "--sync", "--", "cargo test"]
+        );
+        assert_eq!(child_args("run", &json!({})).unwrap_err().0, INVALID_PARAMS);
+        assert_eq!(child_args("sync", &json!({ "hots": "gpu" })).unwrap_err().0, INVALID_PARAMS);
+        assert_eq!(child_args("deploy", &Value::Null).unwrap_err().0, METHOD_NOT_FOUND);
+    }
+
+    #[test]
+    fn malformed_requests_get_protocol_errors() {
+        assert_eq!(parse_request("{").unwrap_err().1 .0, PARSE_ERROR);
+        let (id, error) = parse_request(r#"{"jsonrpc": "1.0", "id": 7, "method": "status"}"#).unwrap_err();
+        assert_eq!((id, error.0), (json!(7), INVALID_REQUEST));
+        assert_eq!(parse_request(r#"{"jsonrpc": "2.0", "method": "sync"}"#).unwrap().id, None);
+    }
+}
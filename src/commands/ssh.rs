@@ -1,45 +1,57 @@
 use anyhow::Result;
+use tracing::info;
 
-use crate::config::{self, Config, Shell};
+use crate::config::{self, Config};
+use crate::credentials;
 use crate::env_loader;
 use crate::ssh;
 use super::sync;
 
-pub fn run(host: Option<&str>, do_sync: bool, verbose: bool) -> Result<i32> {
+pub fn run(
+    host: Option<&str>,
+    do_sync: bool,
+    mosh: bool,
+    forward_agent: bool,
+    x11: bool,
+    container: Option<&str>,
+) -> Result<i32> {
     if do_sync {
-        sync::run(host, false, false, false, verbose)?;
+        sync::run(host, &sync::Options::default())?;
     }
 
     let (config, config_path) = Config::find_and_load()?;
-    let (host_name, host) = config.get_host(host)?;
+    let (host_name, host) = super::select_host(&config, host, false)?;
+    let host = &super::with_overrides(host, forward_agent, x11, container);
 
     let project_root = Config::project_root(&config_path);
-    let env_vars = env_loader::load_env_files(&project_root, &host.env_files)?;
+    let mut env_vars = env_loader::load_env_files(&project_root, &host.env_files)?;
+    if let Some(ref wrapper) = host.wrapper {
+        credentials::fill_missing(host_name, host, &[wrapper], &mut env_vars)?;
+    }
     let remote_path = config::effective_remote_path(host, &project_root);
 
-    let shell_cmd = match host.shell {
-        Shell::Bash => "bash",
-        Shell::Powershell => "powershell",
-        Shell::Cmd => "cmd",
+    // Containers often lack bash; fall back to sh there
+    let shell_cmd = match host.container {
+        Some(_) => "exec $(command -v bash || echo sh)",
+        None => host.shell.program(),
     };
 
-    if verbose {
-        eprintln!("Opening SSH session on host: {} ({})", host_name, host.hostname);
-        eprintln!("Remote path: {}", remote_path);
-        eprintln!("Shell: {}", shell_cmd);
+    info!("Opening SSH session on host: {} ({})", host_name, host.hostname);
+    info!("Remote path: {}", remote_path);
+    info!("Shell: {}", shell_cmd);
+
+    if (mosh || host.mosh) && ssh::mosh_available(host) {
+        return ssh::run_remote_command_mosh(host, &remote_path, shell_cmd, &env_vars, &[]);
     }
 
-    let exit_code = ssh::run_remote_command(
-        &host.hostname,
+    let exit = ssh::run_remote_command(
+        host,
         &remote_path,
         shell_cmd,
-        &host.shell,
-        host.wrapper.as_deref(),
-        host.strict_env,
         &env_vars,
+        &[],
         true,
-        verbose,
     )?;
 
-    Ok(exit_code)
+    Ok(exit.code())
 }
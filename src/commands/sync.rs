@@ -1,68 +1,637 @@
 use anyhow::{Context, Result};
+use glob::{MatchOptions, Pattern};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+use std::time::Instant;
+use tempfile::TempDir;
+use tracing::{debug_span, info, warn};
 
-use crate::config::{self, Config, SyncMethod};
+use crate::config::{self, Config, Host, LineEndings, Shell, SyncMethod};
+use crate::env_loader;
+use crate::hooks;
+use crate::line_endings;
+use crate::manifest;
+use crate::output;
+use crate::paths;
 use crate::ssh;
+use crate::state::{self, HistoryEntry, HistoryKind, SyncRecord};
+use crate::style;
 
-pub fn run(host: Option<&str>, no_auto_exclude: bool, delete_excluded: bool, dry_run: bool, verbose: bool) -> Result<()> {
+/// How to sync, beyond which host to. The default syncs the whole project with the
+/// configured excludes.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Options<'a> {
+    /// Just this subdirectory of the project
+    pub path: Option<&'a str>,
+    pub no_auto_exclude: bool,
+    pub delete_excluded: bool,
+    /// Make a tar sync mirror the project, as rsync always does
+    pub delete: bool,
+    /// Create the remote directory for tar even if it was created recently
+    pub force_mkdir: bool,
+    /// Added to the configured excludes for this sync only
+    pub exclude: &'a [String],
+    /// Configured excludes to drop for this sync only (see [`apply_includes`])
+    pub include: &'a [String],
+    /// Don't ask before many remote files are deleted
+    pub yes: bool,
+    pub dry_run: bool,
+}
+
+/// Sync the project to the host.
+pub fn run(host: Option<&str>, options: &Options) -> Result<()> {
+    let dry_run = options.dry_run;
     let (config, config_path) = Config::find_and_load()?;
     let project_root = Config::project_root(&config_path);
+    let (host_name, host) = super::select_host(&config, host, dry_run)?;
+    let _span = debug_span!("sync", host = %host_name).entered();
 
-    let (host_name, host) = config.get_host(host)?;
-    let remote_path = config::effective_remote_path(host, &project_root);
+    let started_at = state::now_secs();
+    let started = Instant::now();
+    let synced = sync(&config, &project_root, host_name, host, options);
+    if !dry_run {
+        let entry = HistoryEntry {
+            kind: HistoryKind::Sync,
+            project: project_root.clone(),
+            host: host_name.clone(),
+            command: None,
+            started_at,
+            duration_ms: started.elapsed().as_millis() as u64,
+            exit_code: synced.is_err() as i32,
+            connection_lost: false,
+            bytes: synced.as_ref().ok().copied().flatten(),
+            run_id: None,
+        };
+        if let Err(e) = state::append_history(&entry) {
+            warn!("Failed to record history: {:#}", e);
+        }
+    }
+    synced.map(|_| ())
+}
 
-    // Merge auto-excludes with config excludes (unless --no-auto-exclude)
-    let excludes = if no_auto_exclude {
-        config.sync.exclude.clone()
-    } else {
-        let mut excludes = config::auto_excludes();
-        excludes.extend(config.sync.exclude.clone());
-        excludes
+/// The sync itself, for [`run`]. Returns the size of the files a tar sync sent.
+fn sync(
+    config: &Config,
+    project_root: &Path,
+    host_name: &String,
+    host: &Host,
+    options: &Options,
+) -> Result<Option<u64>> {
+    let Options { path, no_auto_exclude, delete_excluded, delete, force_mkdir, exclude, include, yes, dry_run } =
+        *options;
+    let remote_path = config::effective_remote_path(host, project_root);
+    if !config.sync.filters.is_empty() && host.sync_method != SyncMethod::Rsync {
+        anyhow::bail!(
+            "sync.filters are rsync filter rules, but host '{}' syncs with tar (set sync_method = \"rsync\")",
+            host_name
+        );
+    }
+
+    // Tar mirroring cleans up with a shell script
+    let mirror = delete && host.sync_method == SyncMethod::Tar;
+    if mirror && host.shell.is_windows() {
+        anyhow::bail!("--delete with tar sync isn't supported on Windows hosts; use sync_method = \"rsync\"");
+    }
+
+    let mut excludes = excludes(config, host, no_auto_exclude);
+    excludes.extend(exclude.iter().cloned());
+    apply_includes(&mut excludes, include);
+
+    let subtree = match path {
+        Some(path) => subtree(project_root, path, &excludes)?,
+        None => None,
+    };
+    // Everything the sync ships, relative to the project root
+    let synced_files = || -> Result<Vec<String>> {
+        let mut files = manifest::collect_files(project_root, &excludes)?;
+        if let Some(ref rel) = subtree {
+            files.retain(|file| file.strip_prefix(rel.as_str()).is_some_and(|rest| rest.starts_with('/')));
+        }
+        Ok(files)
+    };
+    // The same files, relative to the directory being synced
+    let local_root = match subtree {
+        Some(ref rel) => project_root.join(rel),
+        None => project_root.to_path_buf(),
+    };
+    let shipped_files = || -> Result<Vec<String>> {
+        let files = synced_files()?;
+        Ok(match subtree {
+            Some(ref rel) => files.into_iter().map(|file| file[rel.len() + 1..].to_string()).collect(),
+            None => files,
+        })
     };
+    let (target, transfer_excludes) = match subtree {
+        Some(ref rel) => (paths::join(&remote_path, rel), subtree_excludes(&excludes, rel)),
+        None => (remote_path.clone(), excludes.clone()),
+    };
+
+    info!("Project root: {}", project_root.display());
+    info!("Syncing to host: {} ({})", host_name, host.hostname);
+    info!("Remote path: {}", target);
+    info!("Sync method: {:?}", host.sync_method);
+    info!("Excludes: {:?}", excludes);
+
+    // Build steps run first, so the transfer hook and sync see fresh outputs
+    if let Some(ref hook) = host.pre_sync_local {
+        if dry_run {
+            eprintln!("Would run local hook: {}", hook);
+        } else {
+            hooks::run_local_hook(hook, "sync", host_name, project_root)?;
+        }
+    }
 
-    if verbose {
-        eprintln!("Project root: {}", project_root.display());
-        eprintln!("Syncing to host: {} ({})", host_name, host.hostname);
-        eprintln!("Remote path: {}", remote_path);
-        eprintln!("Sync method: {:?}", host.sync_method);
-        eprintln!("Excludes: {:?}", excludes);
+    // Gate the transfer on the configured hook, passing it the files that would be shipped
+    if let Some(ref hook) = host.transfer_hook {
+        if dry_run {
+            eprintln!("Would run transfer hook: {}", hook);
+        } else {
+            let files: Vec<String> = synced_files()?
+                .iter()
+                .map(|rel| project_root.join(rel).display().to_string())
+                .collect();
+            hooks::run_transfer_hook(hook, "sync", host_name, &remote_path, project_root, &files)?;
+        }
     }
 
+    // Rules are checked before anything is sent, so a typo doesn't cost a sync
+    let chmod = if config.sync.chmod.is_empty() {
+        None
+    } else if host.shell.is_windows() {
+        info!("Skipping sync.chmod: Windows hosts have no Unix permissions");
+        None
+    } else {
+        chmod_command(&host.shell, &remote_path, &config.sync.chmod, &synced_files()?)?
+    };
+
     // Ensure remote directory exists (skip in dry-run, rsync creates it automatically)
+    let mut sent = None;
+    let mut dir_cached = false;
     if !dry_run && host.sync_method == SyncMethod::Tar {
-        ssh::ensure_remote_dir(&host.hostname, &remote_path, &host.shell, verbose)?;
+        dir_cached = ssh::ensure_remote_dir_cached(host, &target, force_mkdir)?;
+        // tar sends everything, so it all has to fit; rsync only sends what changed
+        let size = synced_files()?
+            .iter()
+            .map(|file| fs::symlink_metadata(project_root.join(file)).map(|m| m.len()).unwrap_or(0))
+            .sum();
+        ssh::check_free_space(host, &target, size, true)?;
+        sent = Some(size);
+    }
+
+    // Converted line endings are shipped from a staged copy of the project, in a
+    // private temp directory removed when it is dropped
+    let endings = config.sync.convert_line_endings;
+    if dry_run && endings != LineEndings::None {
+        eprintln!("Would convert text files to {} line endings", format!("{:?}", endings).to_uppercase());
     }
+    let staging = if endings != LineEndings::None && !dry_run {
+        let staging = TempDir::with_prefix("bridge-sync-").context("Failed to create temp directory")?;
+        let files = synced_files()?;
+        let converted = line_endings::stage(project_root, &files, endings, staging.path())?;
+        info!("Converted line endings in {} of {} files", converted, files.len());
+        Some(staging)
+    } else {
+        None
+    };
 
-    let source = project_root.to_str().context("Invalid project path")?;
+    let mut source = staging.as_ref().map_or(project_root, |staging| staging.path()).to_path_buf();
+    if let Some(ref rel) = subtree {
+        source.push(rel);
+    }
+    let source = source.to_str().context("Invalid project path")?;
 
-    match host.sync_method {
-        SyncMethod::Tar => {
-            ssh::sync_to_remote(
-                source,
-                &host.hostname,
-                &remote_path,
-                &excludes,
-                &host.shell,
-                dry_run,
-                verbose,
-            )?;
-        }
+    let transferred = match host.sync_method {
+        SyncMethod::Tar => ssh::retry_in_remote_dir(host, &target, dir_cached, || {
+            ssh::sync_to_remote(source, host, &target, &transfer_excludes, dry_run)
+        }),
         SyncMethod::Rsync => {
-            ssh::rsync_to_remote(
-                source,
-                &host.hostname,
-                &remote_path,
-                &excludes,
-                &host.shell,
-                delete_excluded,
-                dry_run,
-                verbose,
-            )?;
+            let filters = &config.sync.filters;
+            // Preview deletions first, so a wrong `path` doesn't wipe a directory unasked
+            let confirmed = if host.confirm_delete && !yes && !dry_run {
+                ssh::rsync_deletions(source, host, &target, &transfer_excludes, filters, delete_excluded)
+                    .and_then(|deletions| confirm_deletions(host_name, &target, &deletions))
+            } else {
+                Ok(())
+            };
+            confirmed.and_then(|()| {
+                ssh::rsync_to_remote(source, host, &target, &transfer_excludes, filters, delete_excluded, dry_run)
+            })
+        }
+    };
+    drop(staging);
+    transferred?;
+
+    // tar's dry run would only show the pipeline, so list what it would send
+    if dry_run && host.sync_method == SyncMethod::Tar {
+        let files = shipped_files()?;
+        let size: u64 = files.iter().map(|file| output::path_size(&local_root.join(file))).sum();
+        let plural = if files.len() == 1 { "" } else { "s" };
+        eprintln!("Would send {} file{} ({}):", files.len(), plural, output::format_size(size));
+        for file in &files {
+            eprintln!("  {}", file);
+        }
+    }
+
+    if mirror {
+        if dry_run {
+            eprintln!("Would delete files under {}:{} that don't exist locally", host.hostname, target);
+        } else {
+            let shipped = shipped_files()?;
+            let listing = ssh::capture_remote(host, &listing_command(&host.shell, &target, &transfer_excludes))?;
+            let is_local_dir = |dir: &str| local_root.join(dir).is_dir();
+            let excludes = manifest::Excludes::new(&transfer_excludes)?;
+            let (files, dirs) = stale_entries(&listing, &shipped, is_local_dir, &excludes);
+            if host.confirm_delete && !yes {
+                confirm_deletions(host_name, &target, &files)?;
+            }
+            // The paths go over stdin, so no list is too long for one command line
+            if !files.is_empty() {
+                if ssh::feed_remote(host, &removal_command(&host.shell, &target, false), &nul_separated(&files))? != 0 {
+                    anyhow::bail!("Failed to delete stale files on the remote");
+                }
+                info!("Deleted {} remote files that don't exist locally", files.len());
+            }
+            if !dirs.is_empty() {
+                ssh::feed_remote(host, &removal_command(&host.shell, &target, true), &nul_separated(&dirs))?;
+            }
+        }
+    }
+
+    if let Some(ref chmod) = chmod {
+        if dry_run {
+            eprintln!("Would run: ssh {} {}", host.hostname, chmod);
+        } else if ssh::exec_remote(host, chmod)?.code() != 0 {
+            anyhow::bail!("Failed to apply sync.chmod rules on the remote");
         }
     }
 
+    // Render env vars to a remote file for tools that read env files themselves
+    if let Some(ref render_env) = host.render_env {
+        let env_vars = env_loader::load_env_files(project_root, &host.env_files)?;
+        let rendered = env_loader::render_env_file(&env_vars, host.strict_env)?;
+        let env_path = paths::resolve(&remote_path, render_env);
+
+        if dry_run {
+            eprintln!("Would write {} env vars to {}:{}", env_vars.len(), host.hostname, env_path);
+        } else {
+            info!("Writing {} env vars to {}", env_vars.len(), env_path);
+            ssh::write_remote_file(host, &env_path, &rendered)?;
+        }
+    }
+
+    if let (Some(ref container), true) = (&host.container, host.container_sync) {
+        ssh::copy_into_container(host, container, &remote_path, dry_run)?;
+    }
+
     if !dry_run {
-        println!("Sync complete.");
+        let record = state::update(project_root, |s| {
+            s.last_sync = Some(SyncRecord {
+                host: host_name.clone(),
+                finished_at: state::now_secs(),
+            });
+        });
+        if let Err(e) = record {
+            warn!("Failed to record sync state: {:#}", e);
+        }
+
+        if !output::is_quiet() {
+            println!("{}", style::out(style::GOOD, "Sync complete."));
+        }
+    }
+
+    Ok(sent)
+}
+
+/// A remote command applying `[sync] chmod` rules to the synced files they match, or
+/// `None` if no file matches. Patterns are matched here against the files the sync
+/// ships, with `*` stopping at `/` and `**` crossing directories.
+fn chmod_command(shell: &Shell, remote_path: &str, rules: &[String], files: &[String]) -> Result<Option<String>> {
+    let options = MatchOptions { require_literal_separator: true, ..MatchOptions::new() };
+    let mut steps = Vec::new();
+    for rule in rules {
+        let (mode, pattern) = parse_chmod_rule(rule)?;
+        let matched: Vec<String> = files
+            .iter()
+            .filter(|file| pattern.matches_with(file, options))
+            .map(|file| ssh::quote_path(&Shell::Sh, file))
+            .collect();
+        if !matched.is_empty() {
+            steps.push(format!("chmod {} -- {}", mode, matched.join(" ")));
+        }
+    }
+
+    if steps.is_empty() {
+        return Ok(None);
+    }
+    let script = format!("cd {} && {}", ssh::quote_path(&Shell::Sh, remote_path), steps.join(" && "));
+    Ok(Some(ssh::sh_command(shell, &script)))
+}
+
+/// Split a chmod rule into its mode (symbolic or octal) and file pattern.
+fn parse_chmod_rule(rule: &str) -> Result<(&str, Pattern)> {
+    let (mode, pattern) = rule.trim().split_once(char::is_whitespace).with_context(|| {
+        format!("Invalid sync.chmod rule '{}' (expected \"<mode> <pattern>\", e.g. \"+x scripts/*.sh\")", rule)
+    })?;
+    if !mode.chars().all(|c| "ugoa+-=rwxXst,01234567".contains(c)) {
+        anyhow::bail!("Invalid mode '{}' in sync.chmod rule '{}'", mode, rule);
+    }
+    let pattern = Pattern::new(pattern.trim()).with_context(|| format!("Invalid pattern in sync.chmod rule '{}'", rule))?;
+    Ok((mode, pattern))
+}
+
+/// The project subdirectory `path` (relative to the cwd) names, as a `/`-separated
+/// path under the project root, or `None` for the root itself.
+fn subtree(project_root: &Path, path: &str, excludes: &[String]) -> Result<Option<String>> {
+    let full = fs::canonicalize(path).with_context(|| format!("No such directory: {}", path))?;
+    if !full.is_dir() {
+        anyhow::bail!("{} is not a directory", path);
+    }
+    let root = fs::canonicalize(project_root)
+        .with_context(|| format!("Failed to resolve project root: {}", project_root.display()))?;
+    let rel = full
+        .strip_prefix(&root)
+        .map_err(|_| anyhow::anyhow!("{} is outside the project ({})", path, project_root.display()))?;
+
+    let rel: Vec<String> = rel.components().map(|c| c.as_os_str().to_string_lossy().into_owned()).collect();
+    if rel.is_empty() {
+        return Ok(None);
+    }
+    let rel = rel.join("/");
+    if manifest::is_within_excluded(&rel, &manifest::compile_patterns(excludes)?) {
+        anyhow::bail!("{} is excluded from sync", path);
+    }
+    Ok(Some(rel))
+}
+
+/// Excludes for syncing the subdirectory `rel` on its own. Patterns match any trailing
+/// run of path components, so most apply as they are; one that spells out a path
+/// through the subdirectory (`src/gen` when syncing `src`) also gets the part below it.
+/// Rooted `!` patterns are re-rooted at the subdirectory, or dropped if outside it.
+fn subtree_excludes(excludes: &[String], rel: &str) -> Vec<String> {
+    let plain: Vec<&String> = excludes.iter().filter(|e| !e.starts_with('!')).collect();
+    let mut adjusted: Vec<String> = plain.iter().map(|e| e.to_string()).collect();
+    let mut suffix = rel;
+    loop {
+        for exclude in &plain {
+            if let Some(rest) = exclude.strip_prefix(suffix).and_then(|rest| rest.strip_prefix('/')) {
+                if !rest.is_empty() {
+                    adjusted.push(rest.to_string());
+                }
+            }
+        }
+        match suffix.split_once('/') {
+            Some((_, rest)) => suffix = rest,
+            None => break,
+        }
+    }
+
+    for negation in manifest::negations(excludes) {
+        if !negation.rooted {
+            adjusted.push(format!("!{}", negation.pattern));
+        } else if let Some(rest) = negation.pattern.strip_prefix(rel).and_then(|rest| rest.strip_prefix('/')) {
+            adjusted.push(format!("!/{}", rest));
+        }
+    }
+    adjusted
+}
+
+/// Drop the excludes each `--include` pattern names, so what they cover is synced
+/// after all. Trailing slashes don't matter when comparing. A pattern that isn't an
+/// exclude is added as a `!` pattern instead, to sync something inside one.
+fn apply_includes(excludes: &mut Vec<String>, includes: &[String]) {
+    for include in includes {
+        let before = excludes.len();
+        excludes.retain(|exclude| exclude.trim_end_matches('/') != include.trim_end_matches('/'));
+        if excludes.len() == before {
+            excludes.push(format!("!{}", include));
+        }
+    }
+}
+
+/// A remote command listing everything under `target`, one `d <path>` or `f <path>`
+/// line per directory or other file. Directories `excludes` leave out are not
+/// descended into, unless a `!` pattern may re-include something inside them.
+fn listing_command(shell: &Shell, target: &str, excludes: &[String]) -> String {
+    let prune = prune_expression(excludes);
+    let script = format!(
+        "cd {} && {{ find . -mindepth 1 {}-type d -print | sed 's/^/d /'; find . {}! -type d -print | sed 's/^/f /'; }}",
+        ssh::quote_path(&Shell::Sh, target),
+        prune,
+        prune
+    );
+    ssh::sh_command(shell, &script)
+}
+
+/// The `find` arguments (ending in ` -o `, or empty) that prune directories matching
+/// an exclude. Like tar's, a pattern without a `/` matches any name and one with a
+/// `/` any trailing run of the path. Directories a `!` pattern may name, or lead to,
+/// are kept; `find`'s `*` also matches `/`, so it errs towards keeping. What gets
+/// listed is still checked against the excludes locally.
+fn prune_expression(excludes: &[String]) -> String {
+    let quote = |pattern: &str| ssh::quote_path(&Shell::Sh, pattern);
+    let name_or_path = |pattern: &str| {
+        if pattern.contains('/') {
+            format!("-path {} -o -path {}", quote(&format!("./{}", pattern)), quote(&format!("*/{}", pattern)))
+        } else {
+            format!("-name {}", quote(pattern))
+        }
+    };
+
+    let pruned: Vec<String> = excludes
+        .iter()
+        .filter(|e| !e.starts_with('!'))
+        .map(|e| e.trim_end_matches('/'))
+        .filter(|e| !e.is_empty())
+        .map(name_or_path)
+        .collect();
+    if pruned.is_empty() {
+        return String::new();
+    }
+
+    let mut kept = Vec::new();
+    for negation in manifest::negations(excludes) {
+        if !negation.rooted {
+            kept.push(format!("-name {}", quote(&negation.pattern)));
+            continue;
+        }
+        let mut path = String::from(".");
+        for part in negation.pattern.split('/') {
+            if part == "**" {
+                kept.push(format!("-path {}", quote(&format!("{}/*", path))));
+                break;
+            }
+            path = format!("{}/{}", path, part);
+            kept.push(format!("-path {}", quote(&path)));
+        }
+    }
+
+    let mut expression = format!("-type d \\( {} \\) ", pruned.join(" -o "));
+    if !kept.is_empty() {
+        expression.push_str(&format!("! \\( {} \\) ", kept.join(" -o ")));
     }
+    expression.push_str("-prune -o ");
+    expression
+}
 
+/// The remote files and directories in `listing` (relative to the sync target) that a
+/// mirroring tar sync removes: those neither shipped nor excluded. Directories are
+/// only stale if `is_local_dir` says they don't exist locally either, and come
+/// deepest first.
+fn stale_entries(
+    listing: &str,
+    shipped: &[String],
+    is_local_dir: impl Fn(&str) -> bool,
+    excludes: &manifest::Excludes,
+) -> (Vec<String>, Vec<String>) {
+    let shipped: HashSet<&str> = shipped.iter().map(String::as_str).collect();
+    let mut files = Vec::new();
+    let mut dirs = Vec::new();
+    for line in listing.lines() {
+        let Some((kind, path)) = line.split_once(' ') else {
+            continue;
+        };
+        let path = path.trim_start_matches("./");
+        if path.is_empty() || excludes.contains(path) {
+            continue;
+        }
+        match kind {
+            "f" if !shipped.contains(path) => files.push(path.to_string()),
+            "d" if !is_local_dir(path) => dirs.push(path.to_string()),
+            _ => {}
+        }
+    }
+    dirs.sort_by_key(|dir| std::cmp::Reverse(dir.matches('/').count()));
+    (files, dirs)
+}
+
+/// A remote command removing the NUL-separated paths it reads from stdin under
+/// `target`: files, or with `dirs` the directories that are empty by then (ones
+/// still holding excluded files are kept). xargs splits a long list over several
+/// runs.
+fn removal_command(shell: &Shell, target: &str, dirs: bool) -> String {
+    let remove = if dirs { "{ xargs -0 rmdir -- 2>/dev/null; true; }" } else { "xargs -0 rm -f --" };
+    ssh::sh_command(shell, &format!("cd {} && {}", ssh::quote_path(&Shell::Sh, target), remove))
+}
+
+/// `paths` as xargs -0 reads them.
+fn nul_separated(paths: &[String]) -> Vec<u8> {
+    paths.iter().flat_map(|path| path.bytes().chain([0])).collect()
+}
+
+/// Syncs deleting up to this many remote files go ahead without asking.
+const CONFIRM_DELETE_THRESHOLD: usize = 20;
+
+/// How many of the files to be deleted are listed when asking.
+const CONFIRM_DELETE_SHOWN: usize = 10;
+
+/// Ask before a sync deletes more than [`CONFIRM_DELETE_THRESHOLD`] remote files.
+/// Without a terminal to ask on, the sync is refused.
+fn confirm_deletions(host_name: &str, target: &str, deletions: &[String]) -> Result<()> {
+    if deletions.len() <= CONFIRM_DELETE_THRESHOLD {
+        return Ok(());
+    }
+
+    eprintln!("Syncing would delete {} files in {}:{} that don't exist locally:", deletions.len(), host_name, target);
+    for path in deletions.iter().take(CONFIRM_DELETE_SHOWN) {
+        eprintln!("  {}", path);
+    }
+    if deletions.len() > CONFIRM_DELETE_SHOWN {
+        eprintln!("  ... and {} more", deletions.len() - CONFIRM_DELETE_SHOWN);
+    }
+
+    if !io::stdin().is_terminal() {
+        anyhow::bail!(
+            "Not deleting remote files without a terminal to confirm on; pass --yes if the deletions are intended \
+             (or set confirm_delete = false)"
+        );
+    }
+    eprint!("Delete them? [y/N] ");
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).context("Failed to read answer")?;
+    if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+        anyhow::bail!("Sync cancelled");
+    }
     Ok(())
 }
+
+/// Auto-excludes (unless `--no-auto-exclude`), then `sync.exclude`, then the host's excludes.
+pub(super) fn excludes(config: &Config, host: &Host, no_auto_exclude: bool) -> Vec<String> {
+    let mut excludes = if no_auto_exclude { Vec::new() } else { config::auto_excludes() };
+    excludes.extend(config.sync.exclude.iter().cloned());
+    excludes.extend(host.exclude.iter().cloned());
+    excludes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chmod_rules_apply_to_matching_synced_files() {
+        let files: Vec<String> = ["build.sh", "scripts/run.sh", "scripts/lib/util.sh", "scripts/README.md"]
+            .iter()
+            .map(|f| f.to_string())
+            .collect();
+        let rules = vec!["+x scripts/*.sh".to_string(), "755 **/util.sh".to_string()];
+        assert_eq!(
+            chmod_command(&Shell::Bash, "/srv/app", &rules, &files).unwrap().unwrap(),
+            "cd '/srv/app' && chmod +x -- 'scripts/run.sh' && chmod 755 -- 'scripts/lib/util.sh'"
+        );
+
+        let unmatched = vec!["+x bin/*".to_string()];
+        assert_eq!(chmod_command(&Shell::Bash, "/srv/app", &unmatched, &files).unwrap(), None);
+        assert!(chmod_command(&Shell::Bash, "/srv/app", &["+x".to_string()], &files).is_err());
+        assert!(chmod_command(&Shell::Bash, "/srv/app", &["rm -rf *".to_string()], &files).is_err());
+    }
+
+    #[test]
+    fn subtree_excludes_reroot_paths_through_the_subtree() {
+        let excludes = vec!["target".to_string(), "src/gen".to_string(), "app/src/vendor/*.js".to_string()];
+        assert_eq!(
+            subtree_excludes(&excludes, "app/src"),
+            ["target", "src/gen", "app/src/vendor/*.js", "vendor/*.js", "gen"]
+        );
+
+        let negations: Vec<String> =
+            ["*.log", "!keep.log", "!app/src/gen/api.rs", "!target/config.json"].iter().map(|e| e.to_string()).collect();
+        assert_eq!(subtree_excludes(&negations, "app/src"), ["*.log", "!keep.log", "!/gen/api.rs"]);
+    }
+
+    #[test]
+    fn tar_mirror_deletes_what_was_not_shipped() {
+        let listing = "d ./src\nd ./old\nd ./old/nested\nd ./target\nf ./src/main.rs\nf ./src/gone.rs\nf ./old/nested/x\nf ./target/app\n";
+        let shipped = vec!["src/main.rs".to_string()];
+        let excludes = manifest::Excludes::new(&["target".to_string()]).unwrap();
+        let (files, dirs) = stale_entries(listing, &shipped, |dir| dir == "src", &excludes);
+        assert_eq!(files, ["src/gone.rs", "old/nested/x"]);
+        assert_eq!(dirs, ["old/nested", "old"]);
+        assert_eq!(nul_separated(&files), b"src/gone.rs\0old/nested/x\0");
+        assert_eq!(removal_command(&Shell::Bash, "/srv/app", false), "cd '/srv/app' && xargs -0 rm -f --");
+    }
+
+    #[test]
+    fn tar_mirror_listing_skips_excluded_directories() {
+        let excludes: Vec<String> =
+            ["target", "web/node_modules/", "!target/config.json", "!keep.d"].iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            prune_expression(&excludes),
+            concat!(
+                r"-type d \( -name 'target' -o -path './web/node_modules' -o -path '*/web/node_modules' \) ",
+                r"! \( -path './target' -o -path './target/config.json' -o -name 'keep.d' \) -prune -o ",
+            )
+        );
+        assert_eq!(prune_expression(&["!keep.log".to_string()]), "");
+    }
+
+    #[test]
+    fn includes_drop_matching_excludes() {
+        let mut excludes = vec![".git".to_string(), "data/".to_string(), "target".to_string()];
+        apply_includes(&mut excludes, &["data".to_string()]);
+        assert_eq!(excludes, [".git", "target"]);
+        apply_includes(&mut excludes, &["target/config.json".to_string()]);
+        assert_eq!(excludes, [".git", "target", "!target/config.json"]);
+    }
+}
@@ -0,0 +1,56 @@
+use anyhow::Result;
+use std::time::Duration;
+use tracing::info;
+
+use crate::config::{self, Config, Shell};
+use crate::paths;
+use crate::run_id;
+use crate::ssh;
+use crate::status;
+
+pub fn run(file: &str, lines: u32, run_id: Option<&str>, host: Option<&str>, dry_run: bool) -> Result<i32> {
+    let (config, config_path) = Config::find_and_load()?;
+    let (host_name, host_config) = super::select_host(&config, host, dry_run)?;
+    let project_root = Config::project_root(&config_path);
+    let remote_root = config::effective_remote_path(host_config, &project_root);
+
+    // Named shortcuts from [hosts.X.logs] take precedence over literal paths
+    let log_path = host_config.logs.get(file).map(String::as_str).unwrap_or(file);
+    let log_path = run_id::substitute(log_path, run_id::resolve(run_id, &project_root)?.as_deref())?;
+    let remote_path = paths::resolve(&remote_root, &log_path);
+    let tail_cmd = tail_command(&host_config.shell, &remote_path, lines);
+
+    info!("Tailing on host: {} ({})", host_name, host_config.hostname);
+    info!("Remote path: {}", remote_path);
+
+    if dry_run {
+        eprintln!("Would run: ssh {} {}", host_config.hostname, tail_cmd);
+        return Ok(0);
+    }
+
+    loop {
+        // Resume once the host is back if the connection dropped
+        if let ssh::Exit::Code(code) = ssh::exec_remote(host_config, &tail_cmd)? {
+            return Ok(code);
+        }
+
+        let timeout = Duration::from_secs(host_config.reconnect_timeout);
+        if !super::await_reconnect(host_name, host_config, "tail", &project_root, timeout) {
+            return Ok(255);
+        }
+        status!("Reconnected. Resuming tail...");
+    }
+}
+
+/// Build a command that follows a remote file for the remote shell.
+/// cmd has no native tail, so Windows hosts use PowerShell either way.
+fn tail_command(shell: &Shell, remote_path: &str, lines: u32) -> String {
+    match shell {
+        Shell::Powershell | Shell::Cmd => ssh::powershell_command(&format!(
+            "Get-Content -LiteralPath {} -Tail {} -Wait",
+            ssh::quote_path(&Shell::Powershell, remote_path),
+            lines
+        )),
+        _ => ssh::sh_command(shell, &format!("tail -n {} -F {}", lines, ssh::quote_path(&Shell::Sh, remote_path))),
+    }
+}
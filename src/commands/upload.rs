@@ -1,64 +1,321 @@
 use anyhow::{Context, Result};
-use std::path::Path;
+use std::collections::BTreeSet;
+use std::path::{Component, Path, PathBuf};
+use std::time::Instant;
+use tracing::info;
 
-use crate::config::{self, Config};
+use crate::config::{self, Config, Host};
+use crate::hooks;
+use crate::manifest;
+use crate::output::{self, OutputFormat, TransferReport};
+use crate::paths;
 use crate::ssh;
 
 pub fn run(
-    file: &str,
+    files: &[String],
     dest: Option<&str>,
+    format: OutputFormat,
+    relative: Option<bool>,
+    force_mkdir: bool,
     host: Option<&str>,
     dry_run: bool,
-    verbose: bool,
 ) -> Result<()> {
     let (config, config_path) = Config::find_and_load()?;
-    let (host_name, host_config) = config.get_host(host)?;
+    let (host_name, host_config) = super::select_host(&config, host, dry_run)?;
     let project_root = Config::project_root(&config_path);
     let remote_root = config::effective_remote_path(host_config, &project_root);
 
-    // Resolve local file path
-    let local_path = if Path::new(file).is_absolute() {
-        Path::new(file).to_path_buf()
+    // `-` streams stdin to the remote file instead of copying a local file
+    if files.iter().any(|f| f == "-") {
+        if files.len() > 1 {
+            anyhow::bail!("`-` (stdin) cannot be combined with other files");
+        }
+
+        let dest = dest.context("Uploading from stdin requires --dest")?;
+        check_stdin_allowed(host_name, host_config)?;
+        let remote_path = paths::resolve(&remote_root, dest);
+
+        info!("Uploading to host: {} ({})", host_name, host_config.hostname);
+        info!("Remote path: {}", remote_path);
+
+        if dry_run {
+            eprintln!("Would upload stdin to {}:{}", host_config.hostname, remote_path);
+            return Ok(());
+        }
+
+        // stdin can't be sent twice, so the directory is always made rather than retried
+        let dir = paths::parent(&remote_path).unwrap_or(&remote_root);
+        ssh::ensure_remote_dir_cached(host_config, dir, true)?;
+
+        let start = Instant::now();
+        let bytes = ssh::upload_stdin(host_config, &remote_path)?;
+
+        match format {
+            OutputFormat::Text => {
+                if !output::is_quiet() {
+                    println!("Upload complete: stdin -> {}", remote_path);
+                }
+            }
+            // stdin can't be replayed, so it is never retried
+            OutputFormat::Json => output::print_transfers(&[TransferReport::new(
+                "upload",
+                host_name,
+                "-",
+                &remote_path,
+                bytes,
+                start.elapsed(),
+                0,
+            )])?,
+        }
+        return Ok(());
+    }
+
+    let current_dir = std::env::current_dir()?;
+    let local_files = expand_globs(files)?;
+
+    // A single literal file keeps the flat `<path>/<name>` layout unless --relative
+    // (or `[upload] relative`) is set; multiple files or glob patterns always
+    // preserve their directory structure relative to the project root, as sync does.
+    let relative = relative.unwrap_or(config.upload.relative);
+    let preserve_structure = relative || files.len() > 1 || files.iter().any(|f| is_glob(f));
+
+    // (local path, path as shown to the user, remote path)
+    let uploads: Vec<(PathBuf, String, String)> = if preserve_structure {
+        let base = match dest {
+            Some(d) => paths::resolve(&remote_root, d.trim_end_matches(['/', '\\'])),
+            None => remote_root.clone(),
+        };
+        local_files
+            .iter()
+            .map(|file| {
+                let remote_path = paths::join(&base, &relative_upload_path(&project_root, &current_dir, file)?);
+                Ok((current_dir.join(file), file.display().to_string(), remote_path))
+            })
+            .collect::<Result<_>>()?
     } else {
-        std::env::current_dir()?.join(file)
+        let file = &files[0];
+        let local_path = current_dir.join(file);
+        let remote_filename = dest.unwrap_or_else(|| {
+            local_path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or(file)
+        });
+        let remote_path = paths::resolve(&remote_root, remote_filename);
+        vec![(local_path, file.clone(), remote_path)]
     };
 
-    if !local_path.exists() && !dry_run {
-        anyhow::bail!("Local file does not exist: {}", local_path.display());
+    for (local_path, _, _) in &uploads {
+        if !local_path.exists() && !dry_run {
+            anyhow::bail!("Local file does not exist: {}", local_path.display());
+        }
     }
 
-    // Determine remote destination
-    let remote_filename = dest.unwrap_or_else(|| {
-        local_path
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or(file)
-    });
+    info!("Uploading to host: {} ({})", host_name, host_config.hostname);
+    for (local_path, _, remote_path) in &uploads {
+        info!("Local file: {}", local_path.display());
+        info!("Remote path: {}", remote_path);
+    }
+
+    let local_paths: Vec<&Path> = uploads.iter().map(|(local_path, _, _)| local_path.as_path()).collect();
+    gate(host_name, host_config, &local_paths, &remote_root, &current_dir, dry_run)?;
 
-    let remote_path = format!("{}/{}", remote_root, remote_filename);
+    // Ensure remote directories exist (skip in dry-run), noting which ones the cache
+    // vouched for
+    let mut cached_dirs = BTreeSet::new();
+    if !dry_run {
+        let remote_dirs: BTreeSet<&str> = uploads
+            .iter()
+            .map(|(_, _, remote_path)| paths::parent(remote_path).unwrap_or(&remote_root))
+            .collect();
+        for dir in &remote_dirs {
+            if ssh::ensure_remote_dir_cached(host_config, dir, force_mkdir)? {
+                cached_dirs.insert(*dir);
+            }
+        }
 
-    if verbose {
-        eprintln!("Uploading to host: {} ({})", host_name, host_config.hostname);
-        eprintln!("Local file: {}", local_path.display());
-        eprintln!("Remote path: {}", remote_path);
+        let size = uploads.iter().map(|(local_path, _, _)| output::path_size(local_path)).sum();
+        let first_dir = remote_dirs.first().copied().unwrap_or(&remote_root);
+        ssh::check_free_space(host_config, first_dir, size, false)?;
     }
 
-    // Ensure remote directory exists (skip in dry-run)
-    if !dry_run {
-        ssh::ensure_remote_dir(&host_config.hostname, &remote_root, &host_config.shell, verbose)?;
+    let mut reports = Vec::new();
+    for (local_path, display, remote_path) in &uploads {
+        let local_str = local_path.to_str().context("Local path contains invalid UTF-8")?;
+        let start = Instant::now();
+        let retries = ssh::retry_count();
+
+        let dir = paths::parent(remote_path).unwrap_or(&remote_root);
+        ssh::retry_in_remote_dir(host_config, dir, cached_dirs.contains(dir), || {
+            ssh::upload_to_remote(local_str, host_config, remote_path, dry_run)
+        })?;
+
+        if dry_run {
+            continue;
+        }
+
+        match format {
+            OutputFormat::Text => {
+                if !output::is_quiet() {
+                    println!("Upload complete: {} -> {}", display, remote_path);
+                }
+            }
+            OutputFormat::Json => reports.push(TransferReport::new(
+                "upload",
+                host_name,
+                local_str,
+                remote_path,
+                output::path_size(local_path),
+                start.elapsed(),
+                ssh::retry_count() - retries,
+            )
+            .with_checksum(ssh::verify_transfer(host_config, local_path, remote_path))),
+        }
     }
 
-    ssh::upload_to_remote(
-        local_path.to_str().context("Local path contains invalid UTF-8")?,
-        &host_config.hostname,
-        &remote_path,
-        dry_run,
-        verbose,
-    )?;
+    if format == OutputFormat::Json && !dry_run {
+        output::print_transfers(&reports)?;
+    }
 
-    if !dry_run {
-        println!("Upload complete: {} -> {}", file, remote_path);
+    Ok(())
+}
+
+/// Gate an upload on the host's `transfer_hook`, passing it every file being uploaded
+/// (the files inside directories). `edit` and `script` upload through here too.
+pub(super) fn gate(
+    host_name: &str,
+    host: &Host,
+    local_paths: &[&Path],
+    remote_root: &str,
+    cwd: &Path,
+    dry_run: bool,
+) -> Result<()> {
+    let Some(ref hook) = host.transfer_hook else {
+        return Ok(());
+    };
+    if dry_run {
+        eprintln!("Would run transfer hook: {}", hook);
+        return Ok(());
     }
 
+    let mut files = Vec::new();
+    for local_path in local_paths {
+        let local_path = cwd.join(local_path);
+        if local_path.is_dir() {
+            files.extend(
+                manifest::collect_files(&local_path, &[])?
+                    .iter()
+                    .map(|rel| local_path.join(rel).display().to_string()),
+            );
+        } else {
+            files.push(local_path.display().to_string());
+        }
+    }
+    hooks::run_transfer_hook(hook, "upload", host_name, remote_root, cwd, &files)
+}
+
+/// Data from stdin never exists as a file the `transfer_hook` could check, so hosts
+/// with a hook don't take it.
+fn check_stdin_allowed(host_name: &str, host: &Host) -> Result<()> {
+    if host.transfer_hook.is_some() {
+        anyhow::bail!(
+            "Host '{}' has a transfer_hook, which can't check an upload from stdin; save it to a file and upload that",
+            host_name
+        );
+    }
     Ok(())
 }
+
+fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
+
+/// Expand glob patterns in the argument list. Literal paths are passed through unchanged.
+fn expand_globs(patterns: &[String]) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+
+    for pattern in patterns {
+        if !is_glob(pattern) {
+            files.push(PathBuf::from(pattern));
+            continue;
+        }
+
+        let before = files.len();
+        for entry in glob::glob(pattern).with_context(|| format!("Invalid glob pattern: {}", pattern))? {
+            files.push(entry.with_context(|| format!("Failed to read path matching {}", pattern))?);
+        }
+
+        if files.len() == before {
+            anyhow::bail!("No files match pattern: {}", pattern);
+        }
+    }
+
+    Ok(files)
+}
+
+/// Remote-relative path for a local file (relative to the cwd, or absolute): its path
+/// relative to the project root using `/` separators, so it lands where sync puts it.
+/// Files outside the project have no such place and are refused.
+fn relative_upload_path(project_root: &Path, current_dir: &Path, file: &Path) -> Result<String> {
+    // Resolve `.` and `..` by hand; the file needn't exist in a dry run
+    let mut absolute = PathBuf::new();
+    for component in current_dir.join(file).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                absolute.pop();
+            }
+            other => absolute.push(other),
+        }
+    }
+
+    let rel = absolute.strip_prefix(project_root).map_err(|_| {
+        anyhow::anyhow!(
+            "{} is outside the project ({}); upload it on its own, without --relative",
+            file.display(),
+            project_root.display()
+        )
+    })?;
+    Ok(rel
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(part) => part.to_str(),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_upload_path_is_taken_from_the_project_root() {
+        let root = Path::new("/work/project");
+        let upload = |cwd: &str, file: &str| relative_upload_path(root, Path::new(cwd), Path::new(file)).unwrap();
+        assert_eq!(upload("/work/project", "build/lib.so"), "build/lib.so");
+        assert_eq!(upload("/work/project", "./config/a.toml"), "config/a.toml");
+        assert_eq!(upload("/work/project/src", "/work/project/src/main.rs"), "src/main.rs");
+        assert_eq!(upload("/work/project/src", "deep/file.rs"), "src/deep/file.rs");
+        assert_eq!(upload("/work/project/src/a", "../b/x.csv"), "src/b/x.csv");
+    }
+
+    #[test]
+    fn stdin_uploads_are_refused_when_a_transfer_hook_gates_the_host() {
+        let mut host: Host = toml::from_str("hostname = \"fw\"\npath = \"/srv\"\n").unwrap();
+        assert!(check_stdin_allowed("fw", &host).is_ok());
+        host.transfer_hook = Some("./scripts/check-signatures.sh".to_string());
+        let err = check_stdin_allowed("fw", &host).unwrap_err().to_string();
+        assert!(err.contains("transfer_hook"), "{}", err);
+    }
+
+    #[test]
+    fn relative_upload_path_refuses_files_outside_the_project() {
+        let root = Path::new("/work/project");
+        let cwd = Path::new("/work/project/src");
+        assert!(relative_upload_path(root, cwd, Path::new("../../other/data.csv")).is_err());
+        assert!(relative_upload_path(root, cwd, Path::new("/tmp/data.csv")).is_err());
+        assert!(relative_upload_path(root, cwd, Path::new("/work/project-old/data.csv")).is_err());
+    }
+}
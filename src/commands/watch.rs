@@ -0,0 +1,206 @@
+use anyhow::{Context, Result};
+use glob::Pattern;
+use notify::event::{EventKind, MetadataKind, ModifyKind};
+use notify::{Event, PollWatcher, RecursiveMode, Watcher};
+use std::io;
+use std::path::Path;
+use std::process::ExitStatus;
+use std::time::Duration;
+use tokio::process::Child;
+use tokio::sync::mpsc;
+use tokio::time::{self, Instant};
+use tracing::info;
+
+use crate::config::{self, Config};
+use crate::credentials;
+use crate::env_loader;
+use crate::hooks;
+use crate::lock;
+use crate::manifest;
+use crate::run_id;
+use crate::runtime;
+use crate::ssh;
+use crate::status;
+use super::{run, sync};
+
+/// Sync and run `command`, then do it again whenever a file that would be synced
+/// changes, stopping the previous invocation first. Runs until interrupted. Of the
+/// `run` options, the lock, run ID, host overrides, and `--env` apply.
+pub fn run(host: Option<&str>, command: &str, options: &run::Options) -> Result<i32> {
+    let run::Options {
+        dry_run,
+        lock: lock_override,
+        lock_timeout: lock_timeout_override,
+        run_id,
+        forward_agent,
+        x11,
+        container,
+        exports,
+        ..
+    } = *options;
+    let (config, config_path) = Config::find_and_load()?;
+    let (host_name, host) = super::select_host(&config, host, dry_run)?;
+    let host = &super::with_overrides(host, forward_agent, x11, container);
+    let project_root = Config::project_root(&config_path);
+    let remote_path = config::effective_remote_path(host, &project_root);
+    let mut ignored = sync::excludes(&config, host, false);
+    ignored.extend(config.watch.ignore.iter().cloned());
+    let patterns = manifest::compile_patterns(&ignored)?;
+    // Wait for a burst of saves to settle, so it causes a single restart
+    let debounce = Duration::from_millis(config.watch.debounce_ms);
+
+    if dry_run {
+        eprintln!(
+            "Would sync and run on {}, then again whenever files under {} change: {}",
+            host.hostname,
+            project_root.display(),
+            command
+        );
+        return Ok(0);
+    }
+
+    let mut env_vars = env_loader::load_env_files(&project_root, &host.env_files)?;
+    env_vars.insert(run_id::VAR.to_string(), run_id.to_string());
+    let texts: Vec<&str> = [Some(command), host.wrapper.as_deref()].into_iter().flatten().collect();
+    credentials::fill_missing(host_name, host, &texts, &mut env_vars)?;
+
+    // One lock for the whole session, so restarts don't queue behind other users
+    let _lock_guard = match run::lock_name(host, lock_override) {
+        Some(name) => {
+            let timeout = lock_timeout_override.unwrap_or(host.lock_timeout);
+            Some(lock::acquire_lock(&host.hostname, &name, Duration::from_secs(timeout))?)
+        }
+        None => None,
+    };
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let handler = move |event| {
+        let _ = tx.send(event);
+    };
+    let mut watcher: Box<dyn Watcher> = if config.watch.poll {
+        let interval = Duration::from_millis(config.watch.poll_interval_ms);
+        Box::new(PollWatcher::new(handler, notify::Config::default().with_poll_interval(interval))?)
+    } else {
+        Box::new(notify::recommended_watcher(handler)?)
+    };
+    watcher
+        .watch(&project_root, RecursiveMode::Recursive)
+        .with_context(|| format!("Failed to watch {}", project_root.display()))?;
+
+    let mode = if config.watch.poll { "polling" } else { "notifications" };
+    info!("Watching: {} ({})", project_root.display(), mode);
+
+    // Every rerun syncs and starts the command, so they all share one login
+    let _connection = ssh::share_connection(host);
+
+    runtime::block_on(async {
+        loop {
+            let started = sync::run(Some(host_name), &sync::Options::default())
+                .and_then(|()| {
+                    if let Some(ref hook) = host.pre_run_local {
+                        hooks::run_local_hook(hook, "run", host_name, &project_root)?;
+                    }
+                    ssh::spawn_remote_command(host, &remote_path, command, &env_vars, exports)
+                });
+            let mut child = match started {
+                Ok(child) => Some(child),
+                Err(e) => {
+                    eprintln!("Error: {:#}", e);
+                    status!("Waiting for changes...");
+                    None
+                }
+            };
+
+            // Wait for a relevant change, reporting when the command finishes meanwhile
+            loop {
+                tokio::select! {
+                    event = rx.recv() => match event {
+                        Some(Ok(event)) if is_relevant(&event, &project_root, &patterns) => break,
+                        Some(Ok(_)) => {}
+                        Some(Err(e)) => eprintln!("Warning: file watcher error: {}", e),
+                        None => anyhow::bail!("File watcher stopped unexpectedly"),
+                    },
+                    status = exited(&mut child) => {
+                        let exit_code = status.context("Failed to wait for SSH process")?.code().unwrap_or(1);
+                        run::record_run(&project_root, host_name, command, exit_code, run_id);
+                        eprintln!("[bridge] Command exited with code {}; waiting for changes...", exit_code);
+                        child = None;
+                    }
+                }
+            }
+
+            // Let the burst of changes settle
+            let mut quiet_until = Instant::now() + debounce;
+            loop {
+                tokio::select! {
+                    event = rx.recv() => match event {
+                        Some(Ok(event)) if is_relevant(&event, &project_root, &patterns) => {
+                            quiet_until = Instant::now() + debounce;
+                        }
+                        Some(_) => {}
+                        None => anyhow::bail!("File watcher stopped unexpectedly"),
+                    },
+                    () = time::sleep_until(quiet_until) => break,
+                }
+            }
+
+            match child {
+                Some(mut running) => {
+                    status!("[bridge] Files changed; restarting...");
+                    runtime::stop(&mut running).await;
+                }
+                None => status!("[bridge] Files changed; running again..."),
+            }
+        }
+    })
+}
+
+/// Wait for the running command to exit. Never completes if nothing is running.
+async fn exited(child: &mut Option<Child>) -> io::Result<ExitStatus> {
+    match child {
+        Some(child) => child.wait().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Whether an event changed a file that sync would ship. Reads (including the ones
+/// sync itself makes) and changes under excluded paths are ignored.
+fn is_relevant(event: &Event, project_root: &Path, patterns: &[Pattern]) -> bool {
+    match event.kind {
+        EventKind::Access(_) => return false,
+        EventKind::Modify(ModifyKind::Metadata(MetadataKind::AccessTime)) => return false,
+        _ => {}
+    }
+
+    event.paths.iter().any(|path| {
+        let Ok(rel) = path.strip_prefix(project_root) else {
+            return false;
+        };
+        let rel = rel.to_string_lossy().replace('\\', "/");
+        !rel.is_empty() && !manifest::is_within_excluded(&rel, patterns)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{AccessKind, AccessMode, CreateKind, DataChange};
+
+    #[test]
+    fn only_changes_to_synced_files_count() {
+        let root = Path::new("/work/app");
+        let patterns = manifest::compile_patterns(&["target".to_string(), ".git".to_string()]).unwrap();
+        let event = |kind, path: &str| Event::new(kind).add_path(root.join(path));
+        let write = EventKind::Modify(ModifyKind::Data(DataChange::Content));
+
+        assert!(is_relevant(&event(write, "src/main.rs"), root, &patterns));
+        assert!(is_relevant(&event(EventKind::Create(CreateKind::File), "new.txt"), root, &patterns));
+        assert!(!is_relevant(&event(write, "target/debug/app"), root, &patterns));
+        assert!(!is_relevant(&event(write, ".git/index"), root, &patterns));
+        assert!(!is_relevant(
+            &event(EventKind::Access(AccessKind::Close(AccessMode::Read)), "src/main.rs"),
+            root,
+            &patterns
+        ));
+    }
+}
@@ -0,0 +1,73 @@
+//! Diagnostics go through `tracing`. `-v` shows bridge's info events on stderr, `-vv`
+//! its debug events, and `-vvv` everything down to trace, including the libraries it
+//! uses; `RUST_LOG` replaces those levels with its own filter. `--log-file` writes
+//! debug events (or what `RUST_LOG` selects) to a file as well, with timestamps,
+//! however many `-v`s were given.
+//!
+//! What a command reports in normal use (progress, results, warnings) is printed
+//! directly and doesn't depend on any of this.
+
+use anyhow::{Context, Result};
+use std::fs::OpenOptions;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+use tracing_subscriber::filter::EnvFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, Layer};
+
+use crate::style;
+
+/// The stderr filter for a number of `-v`s.
+fn level_filter(verbosity: u8) -> &'static str {
+    match verbosity {
+        0 => "off",
+        1 => "bridge=info,bridge_core=info",
+        2 => "bridge=debug,bridge_core=debug",
+        _ => "trace",
+    }
+}
+
+/// `RUST_LOG`'s filter if it is set, otherwise `default`.
+fn filter(default: &str) -> Result<EnvFilter> {
+    match std::env::var("RUST_LOG") {
+        Ok(directives) if !directives.is_empty() => {
+            EnvFilter::try_new(&directives).with_context(|| format!("Invalid RUST_LOG: {}", directives))
+        }
+        _ => Ok(EnvFilter::new(default)),
+    }
+}
+
+/// Install the subscriber for this invocation.
+pub fn init(verbosity: u8, log_file: Option<&Path>) -> Result<()> {
+    let stderr = fmt::layer()
+        .with_writer(io::stderr)
+        .with_ansi(style::stderr_colored())
+        .without_time()
+        .with_target(verbosity >= 2)
+        .with_filter(filter(level_filter(verbosity))?);
+
+    let file = match log_file {
+        Some(path) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("Failed to open log file: {}", path.display()))?;
+            let layer = fmt::layer()
+                .with_writer(Mutex::new(file))
+                .with_ansi(false)
+                .with_filter(filter(level_filter(verbosity.max(2)))?);
+            Some(layer)
+        }
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(stderr)
+        .with(file)
+        .try_init()
+        .context("Failed to set up logging")
+}
+
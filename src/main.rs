@@ -1,12 +1,19 @@
-use clap::{Parser, Subcommand};
+
+use clap::{CommandFactory, Parser, Subcommand};
+use chrono::NaiveTime;
+use clap_complete::engine::ArgValueCandidates;
+use std::path::PathBuf;
 use std::process::ExitCode;
+use std::time::Duration;
+
+use output::OutputFormat;
+use state::HistoryKind;
+
+use bridge_core::{config, credentials, env_loader, env_subst, hooks, line_endings, lock, manifest, notify, output, paths, run_id, runtime, ssh, state, status};
 
 mod commands;
-mod config;
-mod env_loader;
-mod env_subst;
-mod lock;
-mod ssh;
+mod logging;
+mod style;
 
 #[derive(Parser)]
 #[command(name = "bridge")]
@@ -14,12 +21,40 @@ mod ssh;
 #[command(version)]
 struct Cli {
     /// Override default host
-    #[arg(long, global = true)]
+    #[arg(long, global = true, add = ArgValueCandidates::new(commands::completions::host_names))]
     host: Option<String>,
 
-    /// Detailed output
-    #[arg(short, long, global = true)]
-    verbose: bool,
+    /// Target all hosts in this [groups] entry (repeatable). Used by run and sync.
+    #[arg(long, global = true, conflicts_with = "host", add = ArgValueCandidates::new(commands::completions::group_names))]
+    group: Vec<String>,
+
+    /// Target all hosts with this tag (repeatable; hosts must match every tag). Used by run and sync.
+    #[arg(long, global = true, conflicts_with = "host", add = ArgValueCandidates::new(commands::completions::tags))]
+    tag: Vec<String>,
+
+    /// Apply the [profiles.<name>] overlay from the config (default: $BRIDGE_PROFILE)
+    #[arg(long, global = true, add = ArgValueCandidates::new(commands::completions::profile_names))]
+    profile: Option<String>,
+
+    /// Run ID exposed as ${BRIDGE_RUN_ID} (run generates one if absent; download and tail default to the last run's)
+    #[arg(long, global = true)]
+    run_id: Option<String>,
+
+    /// Detailed output (-vv for debug events, -vvv for trace; RUST_LOG overrides)
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Only print errors and warnings (not "Sync complete." and other status messages)
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Color output: auto (when writing to a terminal and NO_COLOR is unset), always, or never
+    #[arg(long, global = true, value_enum, value_name = "WHEN", default_value_t = style::ColorChoice::Auto)]
+    color: style::ColorChoice,
+
+    /// Also write debug events, with timestamps, to this file (appended)
+    #[arg(long, global = true, value_name = "PATH")]
+    log_file: Option<PathBuf>,
 
     /// Preview without executing
     #[arg(long, global = true)]
@@ -33,6 +68,9 @@ struct Cli {
 enum Commands {
     /// Sync current directory to remote
     Sync {
+        /// Sync only this project subdirectory, to the matching remote subdirectory
+        path: Option<String>,
+
         /// Disable auto-exclusion of Mac-specific files (.DS_Store, ._*)
         #[arg(long)]
         no_auto_exclude: bool,
@@ -40,6 +78,30 @@ enum Commands {
         /// Delete excluded files from remote (rsync only)
         #[arg(long)]
         delete_excluded: bool,
+
+        /// Delete remote files that don't exist locally (tar; rsync always does)
+        #[arg(long)]
+        delete: bool,
+
+        /// Create the remote directory even if it was created recently (tar only)
+        #[arg(long)]
+        force_mkdir: bool,
+
+        /// Also exclude this pattern, on top of the configured excludes (repeatable)
+        #[arg(long, value_name = "PATTERN")]
+        exclude: Vec<String>,
+
+        /// Sync what this configured exclude pattern would skip (repeatable)
+        #[arg(long, value_name = "PATTERN")]
+        include: Vec<String>,
+
+        /// Don't ask before a sync deletes many remote files
+        #[arg(short, long)]
+        yes: bool,
+
+        /// If the host is unreachable, queue the sync for `bridge queue flush` instead of failing
+        #[arg(long)]
+        queue: bool,
     },
 
     /// Run command on remote
@@ -47,6 +109,10 @@ enum Commands {
         /// Command to execute
         command: String,
 
+        /// Another command to run after it, if it succeeded (repeatable, in order)
+        #[arg(long, value_name = "COMMAND", conflicts_with = "watch")]
+        then: Vec<String>,
+
         /// Sync before running
         #[arg(short, long)]
         sync: bool,
@@ -59,6 +125,14 @@ enum Commands {
         #[arg(long)]
         reconnect_command: Option<String>,
 
+        /// What to do after reconnecting from an unexpected SSH disconnect (overrides config)
+        #[arg(long, value_name = "ACTION")]
+        reconnect_action: Option<config::ReconnectAction>,
+
+        /// Give up after rerunning this many times with --reconnect-action rerun (overrides config, default: 3)
+        #[arg(long, value_name = "N")]
+        max_reruns: Option<u32>,
+
         /// Seconds to wait for reconnection (overrides config, default: 90)
         #[arg(long)]
         reconnect_timeout: Option<u64>,
@@ -70,87 +144,708 @@ enum Commands {
         /// Seconds to wait for lock (default: 600)
         #[arg(long)]
         lock_timeout: Option<u64>,
+
+        /// Run over mosh when attached to a terminal (falls back to ssh if unavailable)
+        #[arg(long)]
+        mosh: bool,
+
+        /// Forward the local SSH agent (overrides config)
+        #[arg(long)]
+        forward_agent: bool,
+
+        /// Forward X11 so remote GUI windows open locally (overrides config)
+        #[arg(long)]
+        x11: bool,
+
+        /// Run inside this Docker container on the host (overrides config)
+        #[arg(long)]
+        container: Option<String>,
+
+        /// Re-sync and rerun whenever local files change, stopping the previous run first
+        #[arg(short, long, conflicts_with_all = ["sync", "interactive", "mosh"])]
+        watch: bool,
+
+        /// Copy these remote paths back into the local project after the command
+        /// finishes, even if it failed (repeatable or comma-separated; globs allowed)
+        #[arg(long, value_name = "PATH", value_delimiter = ',', conflicts_with = "watch")]
+        sync_back: Vec<String>,
+
+        /// Set an environment variable for the remote command (repeatable)
+        #[arg(short, long = "env", value_name = "KEY=VALUE", value_parser = commands::run::parse_env_arg)]
+        env: Vec<(String, String)>,
+
+        /// If the host is unreachable, queue the command for `bridge queue flush` instead of failing
+        #[arg(long, conflicts_with_all = ["interactive", "watch"])]
+        queue: bool,
+
+        /// Wait until this local time (today, or tomorrow if it has passed) before syncing and running
+        #[arg(long, value_name = "HH:MM", value_parser = commands::run::parse_time_of_day, conflicts_with = "delay")]
+        at: Option<NaiveTime>,
+
+        /// Wait this long (e.g. 30m, 1h30m) before syncing and running
+        #[arg(long = "in", value_name = "DELAY", value_parser = commands::run::parse_delay)]
+        delay: Option<Duration>,
+
+        /// Show a desktop notification when the command finishes (overrides config)
+        #[arg(long, conflicts_with = "watch")]
+        notify: bool,
     },
 
-    /// Upload single file to remote
+    /// Upload files to remote
     Upload {
-        /// File to upload
-        file: String,
+        /// Files or glob patterns to upload (`-` reads from stdin; requires --dest)
+        #[arg(required = true)]
+        files: Vec<String>,
 
-        /// Remote destination filename
+        /// Remote destination filename (or directory when uploading multiple files), relative to the remote project unless absolute
         #[arg(long)]
         dest: Option<String>,
+
+        /// Output format (json reports bytes, duration, and throughput)
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+
+        /// Keep the file's path relative to the project root on the remote
+        #[arg(long, overrides_with = "no_relative")]
+        relative: bool,
+
+        /// Upload a single file to the top of the remote path (overrides `[upload] relative`)
+        #[arg(long, overrides_with = "relative")]
+        no_relative: bool,
+
+        /// Create the remote directories even if they were created recently
+        #[arg(long)]
+        force_mkdir: bool,
     },
 
-    /// Download file from remote
+    /// Upload a local script to the remote, run it with arguments, and remove it
+    Script {
+        /// Local script file (run from the project path on the remote)
+        script: String,
+
+        /// Arguments passed to the script
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+
+    /// Download files from remote
     Download {
-        /// File to download
-        file: String,
+        /// Files or glob patterns to download (globs are expanded on the remote)
+        #[arg(required = true)]
+        files: Vec<String>,
 
-        /// Local destination path
+        /// Local destination path (`-` writes to stdout; a directory for multiple files)
         #[arg(long)]
         dest: Option<String>,
+
+        /// Output format (json reports bytes, duration, and throughput)
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+
+        /// Delete local files that no longer exist on the remote (rsync hosts only)
+        #[arg(long)]
+        delete: bool,
+    },
+
+    /// Print a remote file to stdout
+    Cat {
+        /// File to print (relative to the project path on the remote)
+        file: String,
+    },
+
+    /// Show a unified diff of a remote file against the local copy
+    Diff {
+        /// File to compare (relative to the project path on the remote)
+        file: String,
+    },
+
+    /// Edit a remote file locally with $EDITOR and upload it back if changed
+    Edit {
+        /// File to edit (relative to the project path on the remote)
+        file: String,
+    },
+
+    /// List remote directory contents
+    Ls {
+        /// Directory to list (defaults to the remote project path)
+        path: Option<String>,
+    },
+
+    /// Mount the remote project path locally with sshfs
+    Mount {
+        /// Local directory to mount at (default: ~/.local/state/bridge/mounts/<project>-<host>)
+        mountpoint: Option<String>,
+    },
+
+    /// Unmount a directory mounted with `bridge mount`
+    #[command(alias = "unmount")]
+    Umount {
+        /// Mounted directory (default: the one `bridge mount` uses for the host)
+        mountpoint: Option<String>,
+    },
+
+    /// Remote file operations (relative paths resolve against the project path)
+    Fs {
+        #[command(subcommand)]
+        action: FsAction,
+    },
+
+    /// Follow a remote log file, reconnecting if the connection drops
+    Tail {
+        /// File to follow (relative to the project path) or a name from [hosts.X.logs]
+        file: String,
+
+        /// Number of existing lines to show before following
+        #[arg(short = 'n', long, default_value_t = 10)]
+        lines: u32,
     },
 
     /// Create bridge.toml in current directory
-    Init,
+    Init {
+        /// Tailor excludes and the example wrapper to a project type
+        #[arg(long, value_enum)]
+        template: Option<config::ProjectTemplate>,
+    },
+
+    /// Diagnose the config, local tools, and the remote host, with suggested fixes
+    Doctor {
+        /// Check every configured host instead of just the selected one
+        #[arg(long)]
+        all: bool,
+    },
 
     /// Open interactive SSH session on remote
     Ssh {
         /// Sync before connecting
         #[arg(short, long)]
         sync: bool,
+
+        /// Connect with mosh (falls back to ssh if unavailable)
+        #[arg(long)]
+        mosh: bool,
+
+        /// Forward the local SSH agent (overrides config)
+        #[arg(long)]
+        forward_agent: bool,
+
+        /// Forward X11 so remote GUI windows open locally (overrides config)
+        #[arg(long)]
+        x11: bool,
+
+        /// Open the shell inside this Docker container on the host (overrides config)
+        #[arg(long)]
+        container: Option<String>,
     },
 
     /// List configured hosts
-    Hosts,
+    Hosts {
+        /// Check every host concurrently: reachability, SSH latency, and whether the remote path exists
+        #[arg(long)]
+        check: bool,
+
+        /// Output format (json lists hosts with their settings and, with --check, status)
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+
+        #[command(subcommand)]
+        action: Option<HostsAction>,
+    },
+
+    /// Print a compact project status line for shell prompts (reads local state only)
+    PromptStatus {
+        /// Output format (json for prompt tools such as starship custom modules)
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+
+    /// Offload artifacts from the remote straight to an object store
+    Artifacts {
+        #[command(subcommand)]
+        action: ArtifactsAction,
+    },
+
+    /// Inspect or edit the configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+
+    /// Syncs and runs queued while their host was unreachable
+    Queue {
+        #[command(subcommand)]
+        action: QueueAction,
+    },
+
+    /// Show past syncs and runs of this project, most recent last (`--host` shows one host's)
+    History {
+        /// Only syncs or only runs
+        #[arg(long, value_enum)]
+        kind: Option<HistoryKind>,
+
+        /// Only those started within this long (e.g. 1d, 12h)
+        #[arg(long, value_name = "DELAY", value_parser = commands::run::parse_delay)]
+        since: Option<Duration>,
+
+        /// Only those that failed
+        #[arg(long)]
+        failed: bool,
+
+        /// Only runs whose command contains this text
+        #[arg(long, value_name = "TEXT")]
+        grep: Option<String>,
+
+        /// Include every project, not just this one
+        #[arg(long)]
+        all: bool,
+
+        /// Show at most this many
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+
+    /// Keep SSH connections to hosts open so other commands skip the handshake
+    /// (`--host`, `--group`, or `--tag` select hosts; default: every host)
+    Daemon {
+        /// Report which hosts have an open connection
+        #[arg(long)]
+        status: bool,
+
+        /// Close the connections held by a running daemon
+        #[arg(long, conflicts_with = "status")]
+        stop: bool,
+    },
+
+    /// Serve JSON-RPC requests (status, sync, run) from editor plugins on a Unix socket
+    Serve {
+        /// Socket path (default: one per project in the bridge state directory)
+        #[arg(long)]
+        socket: Option<String>,
+    },
+
+    /// Print a shell completion script (e.g. `bridge completions zsh > ~/.zfunc/_bridge`)
+    Completions {
+        /// Shell to generate completions for
+        shell: clap_complete::Shell,
+
+        /// Standalone script without config-aware completion of host, group, tag, and profile names
+        #[arg(long = "static")]
+        static_script: bool,
+    },
+
+    /// Any other command runs a `bridge-<command>` plugin from PATH
+    #[command(external_subcommand)]
+    External(Vec<String>),
+}
+
+#[derive(Subcommand)]
+enum FsAction {
+    /// List a remote directory (defaults to the project path)
+    Ls {
+        /// Directory to list
+        path: Option<String>,
+    },
+
+    /// Remove remote files
+    Rm {
+        /// Paths to remove
+        #[arg(required = true)]
+        paths: Vec<String>,
+
+        /// Remove directories and their contents
+        #[arg(short, long)]
+        recursive: bool,
+    },
+
+    /// Move or rename a remote file or directory
+    Mv {
+        /// Existing path
+        from: String,
+
+        /// New path
+        to: String,
+    },
+
+    /// Create remote directories, including missing parents
+    Mkdir {
+        /// Directories to create
+        #[arg(required = true)]
+        paths: Vec<String>,
+    },
+
+    /// Show size, permissions, and timestamps of a remote path
+    Stat {
+        /// Path to inspect
+        path: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum ArtifactsAction {
+    /// Upload remote files or directories to the host's artifact_store and record them
+    Push {
+        /// Paths to upload (relative to the project path on the remote)
+        #[arg(required = true)]
+        paths: Vec<String>,
+    },
+
+    /// List recorded artifacts, newest first
+    List {
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+
+    /// Download a recorded artifact from the store to this machine
+    Pull {
+        /// Artifact URL, or the remote path it was pushed from
+        artifact: String,
+
+        /// Local destination path (default: the artifact's name)
+        #[arg(long)]
+        dest: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum HostsAction {
+    /// Add a host to bridge.toml (comments and formatting are preserved)
+    Add {
+        /// Host name used with --host
+        name: String,
+
+        /// SSH alias (from ~/.ssh/config) or address
+        #[arg(long)]
+        hostname: String,
+
+        /// Project path on the remote
+        #[arg(long)]
+        path: String,
+
+        /// Remote login shell (default: bash)
+        #[arg(long, value_enum)]
+        shell: Option<config::Shell>,
+
+        /// Make this the default host (automatic if none is set)
+        #[arg(long)]
+        default: bool,
+    },
+
+    /// Remove a host from bridge.toml, along with references to it in groups and fallback_hosts
+    Remove {
+        /// Host name
+        #[arg(add = ArgValueCandidates::new(commands::completions::host_names))]
+        name: String,
+    },
+
+    /// Set default_host in bridge.toml
+    SetDefault {
+        /// Host name
+        #[arg(add = ArgValueCandidates::new(commands::completions::host_names))]
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum QueueAction {
+    /// List queued commands, oldest first
+    List,
+
+    /// Run the queued commands whose host is reachable again
+    Flush {
+        /// Keep checking the other hosts (every 30s) until everything queued has run
+        #[arg(long)]
+        wait: bool,
+    },
+
+    /// Drop every queued command
+    Clear,
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Print the fully resolved config (all files merged, inheritance and ${VAR} applied)
+    Show {
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+
+    /// Print the effective value of a config key
+    Get {
+        /// Dotted key, e.g. default_host, sync.exclude, or hosts.dev.path
+        key: String,
+    },
+
+    /// Set a key in bridge.toml, preserving comments and formatting
+    Set {
+        /// Dotted key, e.g. hosts.dev.shell
+        key: String,
+
+        /// TOML value (true, 22, ["a", "b"]); anything else is taken as a string
+        value: String,
+    },
+
+    /// Show the effective value of a config key and which layer supplied it
+    Explain {
+        /// Dotted key, e.g. default_host, sync.exclude, or hosts.dev.lock_timeout
+        key: String,
+    },
 }
 
 fn main() -> ExitCode {
+    // Answers completion requests from the scripts printed by `bridge completions`
+    clap_complete::CompleteEnv::with_factory(Cli::command).complete();
+
     let cli = Cli::parse();
+    style::init(cli.color);
+    if let Err(e) = logging::init(cli.verbose, cli.log_file.as_deref()) {
+        eprintln!("{} {:#}", style::err(style::BAD, "Error:"), e);
+        return ExitCode::FAILURE;
+    }
+    output::set_quiet(cli.quiet);
+    config::set_profile(cli.profile.clone());
 
     let result = match cli.command {
-        Commands::Sync { no_auto_exclude, delete_excluded } => {
-            commands::sync::run(cli.host.as_deref(), no_auto_exclude, delete_excluded, cli.dry_run, cli.verbose)
+        Commands::Sync { path, no_auto_exclude, delete_excluded, delete, force_mkdir, exclude, include, yes, queue } => {
+            let single_host = cli.group.is_empty() && cli.tag.is_empty() && !cli.dry_run;
+            let result = commands::for_each_host(cli.host.as_deref(), &cli.group, &cli.tag, cli.dry_run, |host| {
+                let options = commands::sync::Options {
+                    path: path.as_deref(),
+                    no_auto_exclude,
+                    delete_excluded,
+                    delete,
+                    force_mkdir,
+                    exclude: &exclude,
+                    include: &include,
+                    yes,
+                    dry_run: cli.dry_run,
+                };
+                commands::sync::run(host, &options).map(|()| 0)
+            });
+            let result = if single_host { commands::queue::or_queue(result, false, cli.host.as_deref(), queue) } else { result };
+            match result {
+                Ok(exit_code) => return ExitCode::from(exit_code.min(255) as u8),
+                Err(e) => Err(e),
+            }
         }
-        Commands::Run { command, sync, interactive, reconnect_command, reconnect_timeout, lock, lock_timeout } => {
-            match commands::run::run(cli.host.as_deref(), &command, sync, interactive, cli.dry_run, cli.verbose, reconnect_command.as_deref(), reconnect_timeout, lock, lock_timeout) {
+        Commands::Run { command, then, sync, interactive, reconnect_command, reconnect_action, max_reruns, reconnect_timeout, lock, lock_timeout, mosh, forward_agent, x11, container, watch, sync_back, env, queue, at, delay, notify } => {
+            // One ID per invocation, shared by every targeted host
+            let run_id = cli.run_id.clone().unwrap_or_else(run_id::generate);
+            let mut connection_lost = false;
+            let result = run_id::validate(&run_id).and_then(|()| {
+                commands::run::wait_to_start(at, delay, cli.dry_run)?;
+                let options = commands::run::Options {
+                    sync,
+                    interactive,
+                    dry_run: cli.dry_run,
+                    reconnect_command: reconnect_command.as_deref(),
+                    reconnect_action,
+                    max_reruns,
+                    reconnect_timeout,
+                    lock: lock.as_deref(),
+                    lock_timeout,
+                    run_id: &run_id,
+                    mosh,
+                    forward_agent,
+                    x11,
+                    container: container.as_deref(),
+                    sync_back: &sync_back,
+                    exports: &env,
+                    notify,
+                };
+                if watch {
+                    if !cli.group.is_empty() || !cli.tag.is_empty() {
+                        anyhow::bail!("--watch runs on a single host; use --host instead of --group/--tag");
+                    }
+                    return commands::watch::run(cli.host.as_deref(), &command, &options);
+                }
+                let steps: Vec<String> = std::iter::once(command.clone()).chain(then.iter().cloned()).collect();
+                commands::for_each_host(cli.host.as_deref(), &cli.group, &cli.tag, cli.dry_run, |host| {
+                    let exit = commands::run::run(host, &steps, &options)?;
+                    connection_lost = exit == ssh::Exit::ConnectionLost;
+                    Ok(exit.code())
+                })
+            });
+            let single_host = cli.group.is_empty() && cli.tag.is_empty() && !cli.dry_run && !watch && !interactive;
+            let result = if single_host {
+                commands::queue::or_queue(result, connection_lost, cli.host.as_deref(), queue)
+            } else {
+                result
+            };
+            match result {
                 Ok(exit_code) => {
                     return ExitCode::from(exit_code.min(255) as u8);
                 }
                 Err(e) => Err(e),
             }
         }
-        Commands::Upload { file, dest } => commands::upload::run(
-            &file,
+        Commands::Upload { files, dest, format, relative, no_relative, force_mkdir } => commands::upload::run(
+            &files,
             dest.as_deref(),
+            format,
+            match (relative, no_relative) {
+                (true, _) => Some(true),
+                (_, true) => Some(false),
+                _ => None,
+            },
+            force_mkdir,
             cli.host.as_deref(),
             cli.dry_run,
-            cli.verbose,
         ),
-        Commands::Download { file, dest } => commands::download::run(
-            &file,
+        Commands::Download { files, dest, format, delete } => commands::download::run(
+            &files,
             dest.as_deref(),
+            format,
+            delete,
+            cli.run_id.as_deref(),
             cli.host.as_deref(),
             cli.dry_run,
-            cli.verbose,
         ),
-        Commands::Ssh { sync } => {
-            match commands::ssh::run(cli.host.as_deref(), sync, cli.verbose) {
+        Commands::Script { script, args } => {
+            match commands::script::run(&script, &args, cli.host.as_deref(), cli.dry_run) {
+                Ok(exit_code) => {
+                    return ExitCode::from(exit_code.min(255) as u8);
+                }
+                Err(e) => Err(e),
+            }
+        }
+        Commands::Cat { file } => {
+            match commands::cat::run(&file, cli.host.as_deref(), cli.dry_run) {
+                Ok(exit_code) => {
+                    return ExitCode::from(exit_code.min(255) as u8);
+                }
+                Err(e) => Err(e),
+            }
+        }
+        Commands::Diff { file } => {
+            match commands::diff::run(&file, cli.host.as_deref(), cli.dry_run) {
+                Ok(exit_code) => {
+                    return ExitCode::from(exit_code.min(255) as u8);
+                }
+                Err(e) => Err(e),
+            }
+        }
+        Commands::Edit { file } => commands::edit::run(&file, cli.host.as_deref(), cli.dry_run),
+        Commands::Mount { mountpoint } => {
+            commands::mount::mount(mountpoint.as_deref(), cli.host.as_deref(), cli.dry_run)
+        }
+        Commands::Umount { mountpoint } => {
+            commands::mount::umount(mountpoint.as_deref(), cli.host.as_deref(), cli.dry_run)
+        }
+        Commands::Ls { path } => {
+            match commands::ls::run(path.as_deref(), cli.host.as_deref(), cli.dry_run) {
+                Ok(exit_code) => {
+                    return ExitCode::from(exit_code.min(255) as u8);
+                }
+                Err(e) => Err(e),
+            }
+        }
+        Commands::Fs { action } => {
+            let op = match &action {
+                FsAction::Ls { path } => commands::fs::FsOp::Ls(path.as_deref()),
+                FsAction::Rm { paths, recursive } => commands::fs::FsOp::Rm { paths, recursive: *recursive },
+                FsAction::Mv { from, to } => commands::fs::FsOp::Mv { from, to },
+                FsAction::Mkdir { paths } => commands::fs::FsOp::Mkdir(paths),
+                FsAction::Stat { path } => commands::fs::FsOp::Stat(path),
+            };
+            match commands::fs::run(op, cli.host.as_deref(), cli.dry_run) {
                 Ok(exit_code) => {
                     return ExitCode::from(exit_code.min(255) as u8);
                 }
                 Err(e) => Err(e),
             }
         }
-        Commands::Init => commands::init::run(cli.verbose),
-        Commands::Hosts => commands::hosts::run(cli.verbose),
+        Commands::Tail { file, lines } => {
+            match commands::tail::run(&file, lines, cli.run_id.as_deref(), cli.host.as_deref(), cli.dry_run) {
+                Ok(exit_code) => {
+                    return ExitCode::from(exit_code.min(255) as u8);
+                }
+                Err(e) => Err(e),
+            }
+        }
+        Commands::Ssh { sync, mosh, forward_agent, x11, container } => {
+            match commands::ssh::run(cli.host.as_deref(), sync, mosh, forward_agent, x11, container.as_deref()) {
+                Ok(exit_code) => {
+                    return ExitCode::from(exit_code.min(255) as u8);
+                }
+                Err(e) => Err(e),
+            }
+        }
+        Commands::Init { template } => commands::init::run(template),
+        Commands::Doctor { all } => match commands::doctor::run(cli.host.as_deref(), all) {
+            Ok(exit_code) => return ExitCode::from(exit_code.min(255) as u8),
+            Err(e) => Err(e),
+        },
+        Commands::Hosts { check, format, action } => match action {
+            None => commands::hosts::run(check, format),
+            Some(HostsAction::Add { name, hostname, path, shell, default }) => {
+                commands::hosts::add(&name, &hostname, &path, shell, default)
+            }
+            Some(HostsAction::Remove { name }) => commands::hosts::remove(&name),
+            Some(HostsAction::SetDefault { name }) => commands::hosts::set_default(&name),
+        },
+        Commands::PromptStatus { format } => commands::prompt_status::run(cli.host.as_deref(), format),
+        Commands::Artifacts { action } => match action {
+            ArtifactsAction::Push { paths } => {
+                commands::artifacts::push(&paths, cli.run_id.as_deref(), cli.host.as_deref(), cli.dry_run)
+            }
+            ArtifactsAction::List { format } => commands::artifacts::list(format),
+            ArtifactsAction::Pull { artifact, dest } => {
+                commands::artifacts::pull(&artifact, dest.as_deref(), cli.dry_run)
+            }
+        },
+        Commands::Config { action } => match action {
+            ConfigAction::Show { format } => commands::config::show(format),
+            ConfigAction::Get { key } => commands::config::get(&key),
+            ConfigAction::Set { key, value } => commands::config::set(&key, &value),
+            ConfigAction::Explain { key } => commands::config::explain(&key, cli.verbose > 0),
+        },
+        Commands::External(args) => {
+            match commands::plugin::run(&args, cli.host.as_deref(), cli.run_id.as_deref(), cli.dry_run, cli.verbose) {
+                Ok(exit_code) => return ExitCode::from(exit_code.min(255) as u8),
+                Err(e) => Err(e),
+            }
+        }
+        Commands::Queue { action } => match action {
+            QueueAction::List => commands::queue::list(),
+            QueueAction::Flush { wait } => match commands::queue::flush(wait) {
+                Ok(exit_code) => return ExitCode::from(exit_code.min(255) as u8),
+                Err(e) => Err(e),
+            },
+            QueueAction::Clear => commands::queue::clear(),
+        },
+        Commands::History { kind, since, failed, grep, all, limit, format } => {
+            let filter = commands::history::Filter {
+                host: cli.host.as_deref(),
+                kind,
+                since,
+                failed,
+                grep: grep.as_deref(),
+                all,
+                limit,
+            };
+            commands::history::run(&filter, format)
+        }
+        Commands::Daemon { status, stop } => {
+            match commands::daemon::run(cli.host.as_deref(), &cli.group, &cli.tag, status, stop) {
+                Ok(exit_code) => return ExitCode::from(exit_code.min(255) as u8),
+                Err(e) => Err(e),
+            }
+        }
+        Commands::Serve { socket } => commands::serve::run(socket.as_deref(), cli.verbose),
+        Commands::Completions { shell, static_script } => {
+            commands::completions::run(shell, static_script, &mut Cli::command())
+        }
     };
 
     match result {
         Ok(()) => ExitCode::SUCCESS,
         Err(e) => {
-            eprintln!("Error: {:#}", e);
+            eprintln!("{} {:#}", style::err(style::BAD, "Error:"), e);
             ExitCode::FAILURE
         }
     }
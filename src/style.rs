@@ -0,0 +1,128 @@
+//! Colors and columns for terminal output. With `--color auto`, the default, a
+//! stream is colored only when it is a terminal, `NO_COLOR` isn't set, and `TERM`
+//! isn't `dumb`. Machine-readable output (`--format json`) is never colored.
+
+use anstyle::{AnsiColor, Style};
+use std::io::{self, IsTerminal};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// When to color output, for `--color`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+pub enum ColorChoice {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+pub const GOOD: Style = AnsiColor::Green.on_default();
+pub const WARN: Style = AnsiColor::Yellow.on_default();
+pub const BAD: Style = AnsiColor::Red.on_default().bold();
+pub const HEADER: Style = Style::new().bold();
+pub const DIM: Style = Style::new().dimmed();
+
+static STDOUT: AtomicBool = AtomicBool::new(false);
+static STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Decide, once per invocation, which streams get colors.
+pub fn init(choice: ColorChoice) {
+    STDOUT.store(enabled(choice, io::stdout().is_terminal()), Ordering::Relaxed);
+    STDERR.store(enabled(choice, io::stderr().is_terminal()), Ordering::Relaxed);
+}
+
+fn enabled(choice: ColorChoice, terminal: bool) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            terminal
+                && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                && std::env::var_os("TERM").is_none_or(|term| term != "dumb")
+        }
+    }
+}
+
+/// Whether stderr is colored, for the log output.
+pub fn stderr_colored() -> bool {
+    STDERR.load(Ordering::Relaxed)
+}
+
+/// `text` in `style`, for printing to stdout.
+pub fn out(style: Style, text: &str) -> String {
+    paint(STDOUT.load(Ordering::Relaxed), style, text)
+}
+
+/// `text` in `style`, for printing to stderr.
+pub fn err(style: Style, text: &str) -> String {
+    paint(stderr_colored(), style, text)
+}
+
+fn paint(colored: bool, style: Style, text: &str) -> String {
+    if colored {
+        format!("{}{}{:#}", style, text, style)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Rows of `(text, style)` cells as lines for stdout, each column padded to its
+/// widest cell. A header is a first row in [`HEADER`].
+pub fn table(rows: &[Vec<(String, Style)>]) -> Vec<String> {
+    columns(STDOUT.load(Ordering::Relaxed), rows)
+}
+
+/// [`table`], for printing to stderr.
+pub fn err_table(rows: &[Vec<(String, Style)>]) -> Vec<String> {
+    columns(stderr_colored(), rows)
+}
+
+fn columns(colored: bool, rows: &[Vec<(String, Style)>]) -> Vec<String> {
+    let mut widths: Vec<usize> = Vec::new();
+    for row in rows {
+        for (i, (text, _)) in row.iter().enumerate() {
+            let width = text.chars().count();
+            match widths.get_mut(i) {
+                Some(max) => *max = (*max).max(width),
+                None => widths.push(width),
+            }
+        }
+    }
+
+    rows.iter()
+        .map(|row| {
+            let cells: Vec<String> = row
+                .iter()
+                .enumerate()
+                .map(|(i, (text, style))| {
+                    // Padded outside the escape codes, which take no room on screen
+                    let padding = if i + 1 == row.len() { 0 } else { widths[i] - text.chars().count() };
+                    paint(colored, *style, text) + &" ".repeat(padding)
+                })
+                .collect();
+            cells.join("  ")
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn columns_line_up_whether_colored_or_not() {
+        let rows = vec![
+            vec![("NAME".to_string(), HEADER), ("STATUS".to_string(), HEADER)],
+            vec![("gpu (default)".to_string(), Style::new()), ("reachable".to_string(), GOOD)],
+            vec![("cpu".to_string(), Style::new()), ("unreachable".to_string(), BAD)],
+        ];
+        assert_eq!(
+            columns(false, &rows),
+            ["NAME           STATUS", "gpu (default)  reachable", "cpu            unreachable"]
+        );
+
+        let colored = columns(true, &rows);
+        assert_eq!(colored[2], "cpu            \x1b[1m\x1b[31munreachable\x1b[0m");
+        assert!(!enabled(ColorChoice::Never, true));
+        assert!(enabled(ColorChoice::Always, false));
+    }
+}